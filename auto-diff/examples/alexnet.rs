@@ -1,42 +1,101 @@
-//use tensor_rs::tensor::Tensor;
-//use auto_diff::var::{Module, Var, bcewithlogitsloss};
-
-//fn alexnet(x: Var) {
-//      def __init__(self, num_classes=1000):
-//        super(AlexNet, self).__init__()
-//        self.features = nn.Sequential(
-//            nn.Conv2d(3, 64, kernel_size=11, stride=4, padding=2),
-//            nn.ReLU(inplace=True),
-//            nn.MaxPool2d(kernel_size=3, stride=2),
-//            nn.Conv2d(64, 192, kernel_size=5, padding=2),
-//            nn.ReLU(inplace=True),
-//            nn.MaxPool2d(kernel_size=3, stride=2),
-//            nn.Conv2d(192, 384, kernel_size=3, padding=1),
-//            nn.ReLU(inplace=True),
-//            nn.Conv2d(384, 256, kernel_size=3, padding=1),
-//            nn.ReLU(inplace=True),
-//            nn.Conv2d(256, 256, kernel_size=3, padding=1),
-//            nn.ReLU(inplace=True),
-//            nn.MaxPool2d(kernel_size=3, stride=2),
-//        )
-//        self.avgpool = nn.AdaptiveAvgPool2d((6, 6))
-//        self.classifier = nn.Sequential(
-//            nn.Dropout(),
-//            nn.Linear(256 * 6 * 6, 4096),
-//            nn.ReLU(inplace=True),
-//            nn.Dropout(),
-//            nn.Linear(4096, 4096),
-//            nn.ReLU(inplace=True),
-//            nn.Linear(4096, num_classes),
-//        )
-//
-//    def forward(self, x):
-//        x = self.features(x)
-//        x = self.avgpool(x)
-//        x = torch.flatten(x, 1)
-//        x = self.classifier(x)
-//        return x
-//}
+use tensor_rs::tensor::PaddingMode;
+use auto_diff::op::{Linear, OpCall, Conv2d, Dropout};
+use auto_diff::optim::SGD;
+use auto_diff::{Var, AutoDiffError};
+use rand::prelude::*;
+use ::rand::prelude::StdRng;
+extern crate openblas_src;
+
+// AlexNet, as in torchvision.
+struct AlexNet {
+    convs: Vec<Conv2d>,
+    dropouts: Vec<Dropout>,
+    linears: Vec<Linear>,
+}
+
+impl AlexNet {
+    fn new(rng: &mut StdRng, num_classes: usize) -> AlexNet {
+        let conv_config = [(3, 64, 11, 4, 2), (64, 192, 5, 1, 2), (192, 384, 3, 1, 1),
+                           (384, 256, 3, 1, 1), (256, 256, 3, 1, 1)];
+        let mut convs = Vec::new();
+        for (in_channels, out_channels, kernel, stride, padding) in conv_config.iter() {
+            let op = Conv2d::new(*in_channels, *out_channels,
+                                 (*kernel, *kernel), (*stride, *stride),
+                                 (*padding, *padding), (1, 1),
+                                 true, PaddingMode::Zeros);
+            op.set_weight(Var::normal(rng, &op.weight().size(), 0., 0.01));
+            op.set_bias(Var::zeros(&op.bias().size()));
+            convs.push(op);
+        }
+
+        let linear_config = [(256*6*6, 4096), (4096, 4096), (4096, num_classes)];
+        let mut linears = Vec::new();
+        for (in_features, out_features) in linear_config.iter() {
+            let op = Linear::new(Some(*in_features), Some(*out_features), true);
+            op.set_weight(Var::normal(rng, &[*in_features, *out_features], 0., 0.01));
+            op.set_bias(Var::zeros(&[*out_features]));
+            linears.push(op);
+        }
+
+        let dropouts = vec![Dropout::new(0.5, 1), Dropout::new(0.5, 2)];
+
+        AlexNet {
+            convs,
+            dropouts,
+            linears,
+        }
+    }
+
+    fn forward(&mut self, x: &Var) -> Result<Var, AutoDiffError> {
+        // features
+        let x = self.convs[0].call(&[x])?.pop().unwrap().relu()?;
+        let x = x.max_pool2d((3, 3), Some((2, 2)), (0, 0), (1, 1), false)?;
+        let x = self.convs[1].call(&[&x])?.pop().unwrap().relu()?;
+        let x = x.max_pool2d((3, 3), Some((2, 2)), (0, 0), (1, 1), false)?;
+        let x = self.convs[2].call(&[&x])?.pop().unwrap().relu()?;
+        let x = self.convs[3].call(&[&x])?.pop().unwrap().relu()?;
+        let x = self.convs[4].call(&[&x])?.pop().unwrap().relu()?;
+        let x = x.max_pool2d((3, 3), Some((2, 2)), (0, 0), (1, 1), false)?;
+
+        let x = x.adaptive_avg_pool2d((6, 6))?;
+        let n = x.size()[0];
+        let x = x.view(&[n, 256*6*6])?;
+
+        // classifier
+        let x = self.dropouts[0].call(&[&x])?.pop().unwrap();
+        let x = self.linears[0].call(&[&x])?.pop().unwrap().relu()?;
+        let x = self.dropouts[1].call(&[&x])?.pop().unwrap();
+        let x = self.linears[1].call(&[&x])?.pop().unwrap().relu()?;
+        let x = self.linears[2].call(&[&x])?.pop().unwrap();
+        Ok(x)
+    }
+}
 
 fn main() {
+    let mut rng = StdRng::seed_from_u64(671);
+    let num_classes = 10;
+    let mut net = AlexNet::new(&mut rng, num_classes);
+
+    // A small input keeps the run short,
+    // adaptive_avg_pool2d brings the features back to the 6x6 the classifier expects.
+    let input = Var::normal(&mut rng, &[2, 3, 64, 64], 0., 1.);
+    let label = Var::new(&[1., 7.], &[2]);
+
+    let output = net.forward(&input).unwrap();
+    println!("output size: {:?}", output.size());
+
+    let loss = output.cross_entropy_loss(&label).unwrap();
+    println!("loss: {:?}", loss);
+
+    let mut opt = SGD::new(0.01);
+    loss.bp().unwrap();
+    loss.step(&mut opt).unwrap();
+
+    loss.rerun().unwrap();
+    println!("loss after one step: {:?}", loss);
+
+    // Dropout passes everything through from now on.
+    loss.set_training(false);
+    loss.rerun().unwrap();
+    println!("loss in eval mode: {:?}", loss);
 }
@@ -131,7 +131,7 @@ fn main() {
 
     let loss = output.cross_entropy_loss(&label).unwrap();  println!("here8");
     
-    let lr = 0.1;
+    let lr = 0.00625;
     let mut opt = SGD::new(lr);
 
     println!("{:?}", loss);
@@ -29,7 +29,7 @@ fn main() {
 
     let loss = output.mse_loss(&label).unwrap();
     
-    let mut opt = SGD::new(3.);
+    let mut opt = SGD::new(0.1);
 
     for i in 0..200 {
         
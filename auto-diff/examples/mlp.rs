@@ -111,7 +111,7 @@ fn main() {
 
     let loss = output.bce_with_logits_loss(&label).unwrap();
 
-    let mut opt = SGD::new(1.);
+    let mut opt = SGD::new(0.0025);
 
     for i in 0..500 {
 
@@ -20,9 +20,10 @@ pub struct Graph<TData: Ord, TOp: Ord> {
     data: BTreeSet<TData>,
     op: BTreeSet<TOp>,
     forward_dt_op: BTreeMap<TData, BTreeSet<TOp>>,
-    forward_op_dt: BTreeMap<TOp, BTreeSet<TData>>,
+    // the data of an op keep the order they are connected in, repeats included.
+    forward_op_dt: BTreeMap<TOp, Vec<TData>>,
     backward_dt_op: BTreeMap<TData, BTreeSet<TOp>>,
-    backward_op_dt: BTreeMap<TOp, BTreeSet<TData>>,
+    backward_op_dt: BTreeMap<TOp, Vec<TData>>,
 }
 
 impl<TData: Clone + Copy + Ord, TOp: Clone + Copy + Ord> Default for Graph<TData, TOp> {
@@ -54,13 +55,13 @@ impl<TData: Clone + Copy + Ord, TOp: Clone + Copy + Ord> Graph<TData, TOp> {
     /// iterator over data node.
     pub fn iter_data(&self) -> NodeIterator<TData> {
         NodeIterator {
-            iter: self.data.iter(),
+            iter: Box::new(self.data.iter()),
         }
     }
     /// iterator over op node.
     pub fn iter_op(&self) -> NodeIterator<TOp> {
         NodeIterator {
-            iter: self.op.iter(),
+            iter: Box::new(self.op.iter()),
         }
     }
 
@@ -72,7 +73,7 @@ impl<TData: Clone + Copy + Ord, TOp: Clone + Copy + Ord> Graph<TData, TOp> {
             Err("Not a valid variable/data")
         } else {
             Ok(NodeIterator {
-                iter: self.forward_dt_op.get(var).expect("").iter(),
+                iter: Box::new(self.forward_dt_op.get(var).expect("").iter()),
             })
         }
     }
@@ -85,7 +86,7 @@ impl<TData: Clone + Copy + Ord, TOp: Clone + Copy + Ord> Graph<TData, TOp> {
             Err("Not a valid variable/data")
         } else {
             Ok(NodeIterator {
-                iter: self.backward_dt_op.get(var).expect("").iter(),
+                iter: Box::new(self.backward_dt_op.get(var).expect("").iter()),
             })
         }
     }
@@ -98,7 +99,7 @@ impl<TData: Clone + Copy + Ord, TOp: Clone + Copy + Ord> Graph<TData, TOp> {
             Err("Bad func id.")
         } else {
             Ok(NodeIterator {
-                iter: self.backward_op_dt.get(func).expect("").iter(),
+                iter: Box::new(self.backward_op_dt.get(func).expect("").iter()),
             })
         }
     }
@@ -111,7 +112,7 @@ impl<TData: Clone + Copy + Ord, TOp: Clone + Copy + Ord> Graph<TData, TOp> {
             Err("Bad func id.")
         } else {
             Ok(NodeIterator {
-                iter: self.forward_op_dt.get(func).expect("").iter(),
+                iter: Box::new(self.forward_op_dt.get(func).expect("").iter()),
             })
         }
     }
@@ -133,11 +134,11 @@ impl<TData: Clone + Copy + Ord, TOp: Clone + Copy + Ord> Graph<TData, TOp> {
         if self.data.contains(id) {
             self.data.remove(id);
             for i in self.forward_dt_op.get_mut(id).expect("").iter() {
-                self.backward_op_dt.get_mut(i).expect("").remove(id);
+                self.backward_op_dt.get_mut(i).expect("").retain(|x| x != id);
             }
             self.forward_dt_op.remove(id);
             for i in self.backward_dt_op.get_mut(id).expect("").iter() {
-                self.forward_op_dt.get_mut(i).expect("").remove(id);
+                self.forward_op_dt.get_mut(i).expect("").retain(|x| x != id);
             }
             self.backward_dt_op.remove(id);
 
@@ -151,8 +152,8 @@ impl<TData: Clone + Copy + Ord, TOp: Clone + Copy + Ord> Graph<TData, TOp> {
     pub fn add_op(&mut self, id: &TOp) -> Result<TOp, &str> {
         if !self.op.contains(id) {
             self.op.insert(*id);
-            self.forward_op_dt.insert(*id, BTreeSet::new());
-            self.backward_op_dt.insert(*id, BTreeSet::new());
+            self.forward_op_dt.insert(*id, Vec::new());
+            self.backward_op_dt.insert(*id, Vec::new());
             Ok(*id)
         } else {
             Err("op id exists.")
@@ -183,7 +184,7 @@ impl<TData: Clone + Copy + Ord, TOp: Clone + Copy + Ord> Graph<TData, TOp> {
     pub fn decouple_data_func(&mut self, var: &TData, func: &TOp) -> Result<(), AutoDiffError> {
         if self.data.contains(var) && self.op.contains(func) {
             self.forward_dt_op.get_mut(var).expect("").remove(func);
-            self.backward_op_dt.get_mut(func).expect("").remove(var);
+            self.backward_op_dt.get_mut(func).expect("").retain(|x| x != var);
             Ok(())
         } else {
             Err(AutoDiffError::new("invalid var or func"))
@@ -195,7 +196,7 @@ impl<TData: Clone + Copy + Ord, TOp: Clone + Copy + Ord> Graph<TData, TOp> {
     ///
     pub fn decouple_func_data(&mut self, func: &TOp, var: &TData) -> Result<(), AutoDiffError> {
         if self.data.contains(var) && self.op.contains(func) {
-            self.forward_op_dt.get_mut(func).expect("").remove(var);
+            self.forward_op_dt.get_mut(func).expect("").retain(|x| x != var);
             self.backward_dt_op.get_mut(var).expect("").remove(func);
             Ok(())
         } else {
@@ -249,10 +250,10 @@ impl<TData: Clone + Copy + Ord, TOp: Clone + Copy + Ord> Graph<TData, TOp> {
         if valid_ids {
             for i in dti {
                 self.forward_dt_op.get_mut(i).expect("").insert(*op);
-                self.backward_op_dt.get_mut(op).expect("").insert(*i);
+                self.backward_op_dt.get_mut(op).expect("").push(*i);
             }
             for i in dto {
-                self.forward_op_dt.get_mut(op).expect("").insert(*i);
+                self.forward_op_dt.get_mut(op).expect("").push(*i);
                 self.backward_dt_op.get_mut(i).expect("").insert(*op);
             }
             Ok(())
@@ -272,14 +273,14 @@ impl<TData: Clone + Copy + Ord, TOp: Clone + Copy + Ord> Graph<TData, TOp> {
     {
         let mut fdo = &self.forward_dt_op;
         let mut fod = &self.forward_op_dt;
-        //let mut bdo = &self.backward_dt_op;
+        let mut bdo = &self.backward_dt_op;
         let mut bod = &self.backward_op_dt;
         match forward {
             Direction::Forward => {}
             Direction::Backward => {
                 fdo = &self.backward_dt_op;
                 fod = &self.backward_op_dt;
-                //bdo = &self.forward_dt_op;
+                bdo = &self.forward_dt_op;
                 bod = &self.forward_op_dt;
             }
         }
@@ -293,6 +294,18 @@ impl<TData: Clone + Copy + Ord, TOp: Clone + Copy + Ord> Graph<TData, TOp> {
             jobs.insert(*index);
         }
 
+        // ops the walk can get to,
+        // a data waits for all of them that lead to it.
+        let mut reachable = BTreeSet::<TOp>::new();
+        let mut frontier: Vec<TData> = start_set.to_vec();
+        while let Some(dt) = frontier.pop() {
+            for op in &fdo[&dt] {
+                if reachable.insert(*op) {
+                    frontier.extend(fod[op].iter());
+                }
+            }
+        }
+
         loop {
             let mut made_progress = false;
 
@@ -335,7 +348,11 @@ impl<TData: Clone + Copy + Ord, TOp: Clone + Copy + Ord> Graph<TData, TOp> {
                     // add the output back to the jobs.
                     for output in fod[&op_candidate].iter() {
                         // don't add to jobs if it's the final data node.
-                        if !fdo[output].is_empty() {
+                        if !fdo[output].is_empty()
+                            && bdo[output]
+                                .iter()
+                                .all(|op| done.contains(op) || !reachable.contains(op))
+                        {
                             jobs.insert(*output);
                         }
                     }
@@ -429,18 +446,12 @@ impl<TData: Clone + Copy + Ord, TOp: Clone + Copy + Ord> Graph<TData, TOp> {
             self.backward_dt_op.insert(data_key_map[key], new_set);
         }
         for (key, value) in other.forward_op_dt.iter() {
-            let mut new_set = BTreeSet::new();
-            for key in value.iter() {
-                new_set.insert(data_key_map[key]);
-            }
-            self.forward_op_dt.insert(op_key_map[key], new_set);
+            let new_list = value.iter().map(|x| data_key_map[x]).collect();
+            self.forward_op_dt.insert(op_key_map[key], new_list);
         }
         for (key, value) in other.backward_op_dt.iter() {
-            let mut new_set = BTreeSet::new();
-            for key in value.iter() {
-                new_set.insert(data_key_map[key]);
-            }
-            self.backward_op_dt.insert(op_key_map[key], new_set);
+            let new_list = value.iter().map(|x| data_key_map[x]).collect();
+            self.backward_op_dt.insert(op_key_map[key], new_list);
         }
 
         Ok(())
@@ -449,7 +460,7 @@ impl<TData: Clone + Copy + Ord, TOp: Clone + Copy + Ord> Graph<TData, TOp> {
 
 // iterator
 pub struct NodeIterator<'a, TNode> {
-    iter: std::collections::btree_set::Iter<'a, TNode>,
+    iter: Box<dyn Iterator<Item = &'a TNode> + 'a>,
 }
 impl<'a, TNode> Iterator for NodeIterator<'a, TNode> {
     type Item = &'a TNode;
@@ -13,6 +13,18 @@ use tensor_rs::tensor::Tensor;
 #[cfg(feature = "use-serde")]
 use serde::{Deserialize, Serialize};
 
+/// The data behind the over_tick var of a data.
+/// The register keeps the last ticks, shifted by the index at each tick,
+/// at keeps the (position, slice) for each at_tick.
+#[cfg_attr(feature = "use-serde", derive(Serialize, Deserialize))]
+#[derive(Clone)]
+pub(crate) struct TickRegister {
+    pub(crate) register: GenKey,
+    pub(crate) index: GenKey,
+    pub(crate) stack: GenKey,
+    pub(crate) at: BTreeMap<i32, (GenKey, GenKey)>,
+}
+
 /// The computation network.
 /// Connection has duplication.
 #[cfg_attr(feature = "use-serde", derive(Serialize, Deserialize))]
@@ -24,7 +36,11 @@ pub struct Net {
     data_grad: BTreeMap<GenKey, Tensor>,
     label2id: BTreeMap<String, GenKey>, // Give some var a name.
     tick_data: BTreeSet<GenKey>,        // set of data that will be looped.
-    
+    loops: BTreeMap<GenKey, GenKey>,    // loop back, to -> from.
+    #[cfg_attr(feature = "use-serde", serde(skip))]
+    tick_value: BTreeMap<GenKey, Vec<Tensor>>, // data at each tick of the last loop run.
+    tick_register: BTreeMap<GenKey, TickRegister>, // over_tick of a data.
+
     max_bptt_tick: usize,
     max_eval_tick: usize,
     max_connection: usize,
@@ -39,7 +55,10 @@ impl Net {
             data_grad: BTreeMap::new(),
             label2id: BTreeMap::new(),
             tick_data: BTreeSet::new(),
-	    
+            loops: BTreeMap::new(),
+            tick_value: BTreeMap::new(),
+            tick_register: BTreeMap::new(),
+
 	    max_bptt_tick: 128,
 	    max_eval_tick: 128,
 	    max_connection: 128,
@@ -63,6 +82,7 @@ impl Net {
     pub fn add_tensor(&mut self, t: Tensor) -> GenKey {
         let id = self.data.insert(t);
         self.graph.add_data(&id).expect("add tensor always works");
+        self.tick_value.clear();
         id
     }
 
@@ -149,6 +169,98 @@ impl Net {
 	}
     }
 
+    /// The value of from at a tick is the value of to at the next tick.
+    /// to is an input of the net, and holds the value at the first tick.
+    pub fn add_loop(&mut self, from: &GenKey, to: &GenKey) -> Result<(), AutoDiffError> {
+        if !self.data.contains(from) || !self.data.contains(to) {
+            return Err(AutoDiffError::new("unknown id."));
+        }
+        if self.graph.iter_op_given_output(to).expect("").next().is_some() {
+            return Err(AutoDiffError::new("loop back to a data computed by an op."));
+        }
+        if self.tick_data.contains(to) {
+            return Err(AutoDiffError::new("loop back to a ticked data."));
+        }
+        self.loops.insert(*to, *from);
+        self.tick_value.clear();
+        Ok(())
+    }
+
+    pub fn has_loop(&self) -> bool {
+        !self.loops.is_empty()
+    }
+
+    /// The data an op sees, ticked data gives the slice at the tick.
+    pub fn get_tick_tensor(&self, id: GenKey, tick: usize) -> Result<Tensor, AutoDiffError> {
+        let data = self.data.get(&id)?;
+        if !self.tick_data.contains(&id) {
+            return Ok(data.ref_copy());
+        }
+        let size = data.size();
+        if tick >= size[0] {
+            return Err(AutoDiffError::new(&format!(
+                "tick {} is out of {} ticks.",
+                tick, size[0]
+            )));
+        }
+        let mut range: Vec<_> = size.iter().map(|x| (0, *x)).collect();
+        range[0] = (tick, tick + 1);
+        Ok(data.get_patch(&range, None).reshape(&size[1..]))
+    }
+
+    /// The value at each tick, from the last loop run,
+    /// or the slices of ticked data.
+    pub fn get_tick_values(&self, id: GenKey) -> Result<Vec<Tensor>, AutoDiffError> {
+        if let Some(v) = self.tick_value.get(&id) {
+            Ok(v.iter().map(|x| x.ref_copy()).collect())
+        } else if self.tick_data.contains(&id) {
+            let ticks = self.data.get(&id)?.size()[0];
+            (0..ticks).map(|t| self.get_tick_tensor(id, t)).collect()
+        } else {
+            Err(AutoDiffError::new(&format!(
+                "Data {:?} has no value over ticks.",
+                id
+            )))
+        }
+    }
+
+    pub(crate) fn get_tick_register(&self, id: GenKey) -> Option<TickRegister> {
+        self.tick_register.get(&id).cloned()
+    }
+    pub(crate) fn set_tick_register(&mut self, id: GenKey, register: TickRegister) {
+        self.tick_register.insert(id, register);
+    }
+
+    /// Fit the tick registers to the ticks of the coming loop run.
+    fn resize_tick_register(&self, ticks: usize) {
+        for reg in self.tick_register.values() {
+            let register = self.data.get(&reg.register).expect("");
+            let mut size = register.size();
+            if size[0] == ticks + 1 {
+                continue;
+            }
+            size[0] = ticks + 1;
+            register.swap(&Tensor::zeros(&size));
+            let index: Vec<f64> = (1..=ticks).map(|x| x as f64).collect();
+            self.data.get(&reg.index).expect("").swap(&Tensor::from_vec_f64(&index, &[ticks]));
+            for (tick, (position, _)) in &reg.at {
+                let at = if *tick < 0 { ticks as i32 + tick } else { *tick };
+                if at < 0 || at as usize >= ticks {
+                    panic!("tick {} is out of {} ticks.", tick, ticks);
+                }
+                self.data.get(position).expect("").swap(&Tensor::from_vec_f64(&[at as f64], &[1]));
+            }
+        }
+    }
+
+    /// Number of ticks in a loop run, limited by the shortest ticked data.
+    fn loop_ticks(&self) -> usize {
+        self.tick_data
+            .iter()
+            .map(|x| self.data.get(x).expect("").size()[0])
+            .fold(self.max_eval_tick, usize::min)
+    }
+
     pub fn get_input_edge_data(&self) -> BTreeSet<GenKey> {
         self.graph.get_input_edge_data()
     }
@@ -226,7 +338,8 @@ impl Net {
 	if self.graph.iter_output_given_op(&op).expect("").count() > self.max_connection {
 	    return Err(AutoDiffError::new(&format!("op {} has too many output.\n", op)));
 	}
-	
+
+        self.tick_value.clear();
         self.graph.connect(input, output, &op)
     }
 
@@ -237,6 +350,9 @@ impl Net {
         &mut self,
         starting_node: &[GenKey],
     ) -> Result<(), BTreeSet<GenKey>> {
+        if !self.loops.is_empty() {
+            return self.eval_loop(starting_node);
+        }
 	let max_tick: usize = self.max_eval_tick;
 	
         let mut tick = BTreeMap::new();
@@ -309,6 +425,55 @@ impl Net {
         Ok(())
     }
 
+    /// Run the net once for every tick, with loops from assign_to.
+    /// Ops see the slice of ticked data at the tick,
+    /// the value of each data at each tick is kept for bptt.
+    fn eval_loop(&mut self, starting_node: &[GenKey]) -> Result<(), BTreeSet<GenKey>> {
+        let ticks = self.loop_ticks();
+        self.resize_tick_register(ticks);
+        let init: BTreeMap<GenKey, Tensor> = self
+            .loops
+            .keys()
+            .map(|to| (*to, self.data.get(to).expect("").clone()))
+            .collect();
+        self.tick_value.clear();
+
+        for tick in 0..ticks {
+            if tick > 0 {
+                for (to, from) in &self.loops {
+                    let value = self.tick_value[from][tick - 1].clone();
+                    self.data.get(to).expect("").swap(&value);
+                }
+            }
+
+            self.graph
+                .walk(starting_node, Direction::Forward, |input, output, op| {
+                    let inputs: Vec<Tensor> = input
+                        .iter()
+                        .map(|x| self.get_tick_tensor(*x, tick).expect(""))
+                        .collect();
+                    let outputs: Vec<Tensor> = output
+                        .iter()
+                        .map(|x| self.data.get(x).expect("").ref_copy())
+                        .collect();
+                    self.ops.get(op).expect("").apply(&inputs, &outputs);
+                    true
+                })?;
+
+            let ids: Vec<GenKey> = self.graph.iter_data().copied().collect();
+            for id in ids {
+                let value = self.get_tick_tensor(id, tick).expect("").clone();
+                self.tick_value.entry(id).or_default().push(value);
+            }
+        }
+
+        // to keeps the value at the first tick.
+        for (to, value) in init {
+            self.data.get(&to).expect("").swap(&value);
+        }
+        Ok(())
+    }
+
     //    pub fn eval_op(&self, input: &[&Var], func: &Func, output: &[&Var]) {
     //        let mut inputs: Vec<&Tensor> = Vec::new();
     //        for input_var in input {
@@ -339,11 +504,35 @@ impl Net {
     //        self.bptt(&output_grad);
     //    }
 
+    // An op runs backward only when all its outputs have a gradient,
+    // so unused outputs of a multi-output op (qr, chunk, ...) are
+    // seeded with an empty gradient.
+    fn unused_outputs(&self, seeded: &[GenKey]) -> Vec<GenKey> {
+        let mut unused = Vec::new();
+        for dangling in self.graph.get_output_edge_data() {
+            if seeded.contains(&dangling) {
+                continue;
+            }
+            for op in self.graph.iter_op_given_output(&dangling).expect("") {
+                let used = self.graph.iter_output_given_op(op).expect("")
+                    .any(|x| seeded.contains(x)
+                         || self.graph.iter_op_given_input(x).expect("").next().is_some());
+                if used {
+                    unused.push(dangling);
+                }
+            }
+        }
+        unused
+    }
+
     /// If output_grad contains ticked output,
     /// then the tensor supplied has leading dimension representing time.
     pub fn bptt(&mut self,
 		output_grad: &BTreeMap<GenKey, Tensor>,
     ) -> Result<(), BTreeSet<GenKey>> {
+        if !self.loops.is_empty() {
+            return self.bptt_loop(output_grad);
+        }
 	let max_tick = self.max_bptt_tick;
         
         self.data_grad.clear();
@@ -358,6 +547,9 @@ impl Net {
             self.data_grad.entry(*i).or_insert_with(Tensor::new);
         }
 
+        let mut unused = self.unused_outputs(&output);
+        output.append(&mut unused);
+
 	// store the ticked counts.
 	let mut tick = BTreeMap::new();
         for item in &self.tick_data {
@@ -436,7 +628,7 @@ impl Net {
                             let new_output = Tensor::zeros(&size[1..]);
                             a = new_output;
 			} else {
-			    a = self.data_grad.get(input_id).expect("").ref_copy();
+			    a = Tensor::new();
 			}
                         input_grad.push(a);
                     }
@@ -460,6 +652,14 @@ impl Net {
 			    //println!("{:?}, {:?}", result.size(), self.data_grad.get(input_id).expect("").size());
 
                             self.data_grad.get(input_id).expect("").swap(&all);
+			} else {
+			    // data used by more than one op sums the gradient from each.
+			    let sum = self.data_grad.get(input_id).expect("");
+			    if sum.numel() == 0 {
+				sum.swap(&input_grad[index]);
+			    } else if input_grad[index].numel() > 0 {
+				sum.swap(&sum.add(&input_grad[index]));
+			    }
 			}
                     }
 
@@ -470,6 +670,175 @@ impl Net {
 	Ok(())
     }
 
+    /// Back propagation through the ticks of the last loop run,
+    /// output_grad is applied at the last tick,
+    /// so the loss is the value rerun leaves, the one at the last tick.
+    /// Build the loss on over_tick for a loss over all ticks.
+    /// Parameter gradients and gradients of data used at every tick
+    /// are summed over ticks, ticked data gets the gradient at each tick,
+    /// loop targets get the gradient at the first tick.
+    fn bptt_loop(&mut self, output_grad: &BTreeMap<GenKey, Tensor>) -> Result<(), BTreeSet<GenKey>> {
+        if self.tick_value.is_empty() {
+            let input: Vec<GenKey> = self.get_input_edge_data().into_iter().collect();
+            self.eval_loop(&input)?;
+        }
+        let ticks = self.tick_value.values().next().map_or(0, |x| x.len());
+        if ticks == 0 {
+            return Ok(());
+        }
+        let first = ticks.saturating_sub(self.max_bptt_tick);
+        let ids: Vec<GenKey> = self.graph.iter_data().copied().collect();
+        let constant: Vec<GenKey> = self
+            .get_input_edge_data()
+            .into_iter()
+            .filter(|x| !self.tick_data.contains(x) && !self.loops.contains_key(x))
+            .collect();
+
+        let mut start: Vec<GenKey> = output_grad.keys().copied().collect();
+        let mut unused = self.unused_outputs(&start);
+        start.append(&mut unused);
+        // other data without downstream op get a zero gradient,
+        // so the gradient passes through the loop and the branches off it.
+        let dangling: Vec<GenKey> = self
+            .get_output_edge_data()
+            .into_iter()
+            .filter(|x| {
+                !start.contains(x) && self.graph.iter_op_given_output(x).expect("").next().is_some()
+            })
+            .collect();
+        start.extend(&dangling);
+
+        let mut carry: BTreeMap<GenKey, Tensor> = BTreeMap::new();
+        let mut param_grad: BTreeMap<GenKey, Vec<Tensor>> = BTreeMap::new();
+        let mut constant_grad: BTreeMap<GenKey, Tensor> = BTreeMap::new();
+        let mut tick_grad: BTreeMap<GenKey, Vec<Tensor>> = BTreeMap::new();
+
+        for tick in (first..ticks).rev() {
+            for id in &ids {
+                if !self.tick_data.contains(id) {
+                    self.data.get(id).expect("").swap(&self.tick_value[id][tick].clone());
+                }
+            }
+            self.data_grad.clear();
+            for id in &ids {
+                self.data_grad.insert(*id, Tensor::new());
+            }
+            // the loss only counts at the last tick.
+            for (k, v) in output_grad {
+                let grad = if tick == ticks - 1 { v.clone() } else { v.zeros_like() };
+                self.data_grad.insert(*k, grad);
+            }
+            for id in &dangling {
+                self.data_grad.insert(*id, self.tick_value[id][tick].zeros_like());
+            }
+
+            let done = RefCell::new(BTreeSet::new());
+            self.graph
+                .walk(&start, Direction::Backward, |output_grads, input_grads, op| {
+                    let inputs: Vec<Tensor> = input_grads
+                        .iter()
+                        .map(|x| self.get_tick_tensor(*x, tick).expect(""))
+                        .collect();
+                    let output_grad: Vec<Tensor> = output_grads
+                        .iter()
+                        .map(|x| {
+                            let grad = self.data_grad.get(x).expect("").ref_copy();
+                            match carry.get(x) {
+                                Some(c) if grad.numel() == 0 => c.clone(),
+                                Some(c) => grad.add(c),
+                                None => grad,
+                            }
+                        })
+                        .collect();
+                    let input_grad: Vec<Tensor> = input_grads.iter().map(|_| Tensor::new()).collect();
+                    self.ops.get(op).expect("").grad(&inputs, &output_grad, &input_grad);
+                    // data used by more than one op sums the gradient from each.
+                    for (id, grad) in input_grads.iter().zip(input_grad.iter()) {
+                        let sum = self.data_grad.get(id).expect("");
+                        if sum.numel() == 0 {
+                            sum.swap(grad);
+                        } else if grad.numel() > 0 {
+                            sum.swap(&sum.add(grad));
+                        }
+                    }
+                    done.borrow_mut().insert(*op);
+                    true
+                })?;
+
+            for op in done.into_inner() {
+                let grads = self.ops.get(&op).expect("").get_grads();
+                match param_grad.get_mut(&op) {
+                    Some(sum) => {
+                        for (s, g) in sum.iter_mut().zip(grads.iter()) {
+                            *s = s.add(g);
+                        }
+                    }
+                    None => {
+                        param_grad.insert(op, grads.to_vec());
+                    }
+                }
+            }
+            for id in &constant {
+                let grad = self.data_grad[id].clone();
+                if grad.numel() == 0 {
+                    continue;
+                }
+                let sum = match constant_grad.get(id) {
+                    Some(s) => s.add(&grad),
+                    None => grad,
+                };
+                constant_grad.insert(*id, sum);
+            }
+            for id in &self.tick_data {
+                let mut grad = self.data_grad[id].clone();
+                if grad.numel() == 0 {
+                    grad = self.get_tick_tensor(*id, tick).expect("").zeros_like();
+                }
+                tick_grad.entry(*id).or_default().push(grad);
+            }
+            carry.clear();
+            for (to, from) in &self.loops {
+                let grad = self.data_grad[to].clone();
+                if grad.numel() == 0 {
+                    continue;
+                }
+                let sum = match carry.get(from) {
+                    Some(s) => s.add(&grad),
+                    None => grad,
+                };
+                carry.insert(*from, sum);
+            }
+        }
+
+        for (op, sum) in param_grad {
+            self.ops.get(&op).expect("").set_grads(&sum);
+        }
+        for (id, grad) in constant_grad {
+            self.data_grad.insert(id, grad);
+        }
+        for (id, mut grad) in tick_grad {
+            // ticks before the truncation have no gradient.
+            for tick in (0..first).rev() {
+                grad.push(self.get_tick_tensor(id, tick).expect("").zeros_like());
+            }
+            grad.reverse();
+            for tick in ticks..self.data.get(&id).expect("").size()[0] {
+                grad.push(self.get_tick_tensor(id, tick).expect("").zeros_like());
+            }
+            let all: Vec<Tensor> = grad.iter().map(|x| x.unsqueeze(0)).collect();
+            self.data_grad.insert(id, all[0].cat(&all[1..], 0));
+        }
+
+        // restore the data as after the loop run.
+        for id in &ids {
+            if !self.tick_data.contains(id) {
+                let tick = if self.loops.contains_key(id) { 0 } else { ticks - 1 };
+                self.data.get(id).expect("").swap(&self.tick_value[id][tick].clone());
+            }
+        }
+        Ok(())
+    }
+
     /// Iterate over all ops, no order guarantee
     /// Used for optimizer.
     pub fn visit_op<F>(&mut self, closure: F, allow: Option<Vec<GenKey>>, skip: Option<Vec<GenKey>>)
@@ -497,6 +866,11 @@ impl Net {
         }
     }
 
+    /// Switch all ops between training and evaluation.
+    pub fn set_training(&mut self, training: bool) {
+        self.visit_op(|x| x.set_training(training), None, None);
+    }
+
     pub fn visit_data<F>(&mut self, closure: F)
     where
         F: Fn(GenKey, &Tensor),
@@ -514,14 +888,11 @@ impl Net {
         original_keys: &[GenKey],
     ) -> Result<Vec<GenKey>, AutoDiffError> {
         let mut data_key_map = BTreeMap::new();
-        let mut ret_keys = Vec::new();
         for key in other.get_data().iter_key() {
             let new_key = self.add_tensor(other.get_tensor(key)?);
-            if original_keys.contains(&key) {
-                ret_keys.push(new_key);
-            }
             data_key_map.insert(key, new_key);
         }
+        let ret_keys = original_keys.iter().map(|x| data_key_map[x]).collect();
 
         let mut op_key_map = BTreeMap::new();
         for key in other.get_ops().iter_key() {
@@ -529,6 +900,29 @@ impl Net {
             op_key_map.insert(key, new_key);
         }
 
+        for key in &other.tick_data {
+            self.tick_data.insert(data_key_map[key]);
+        }
+        for (to, from) in &other.loops {
+            self.loops.insert(data_key_map[to], data_key_map[from]);
+        }
+        for (id, reg) in &other.tick_register {
+            let at = reg
+                .at
+                .iter()
+                .map(|(k, (p, v))| (*k, (data_key_map[p], data_key_map[v])))
+                .collect();
+            self.tick_register.insert(
+                data_key_map[id],
+                TickRegister {
+                    register: data_key_map[&reg.register],
+                    index: data_key_map[&reg.index],
+                    stack: data_key_map[&reg.stack],
+                    at,
+                },
+            );
+        }
+
         self.graph.append(&other.graph, data_key_map, op_key_map)?;
 
         Ok(ret_keys)
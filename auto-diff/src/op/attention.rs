@@ -0,0 +1,629 @@
+use super::local::transpose_last;
+use super::{scalar_like, Op, OpCall, OpHandle, OpTrait, Softmax};
+use tensor_rs::tensor::Tensor;
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::err::AutoDiffError;
+use crate::var::Var;
+
+#[cfg(feature = "use-serde")]
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "use-serde")]
+use std::any::Any;
+
+// ScaledDotProductAttention
+// MultiheadAttention
+
+/// 1 where the query attends the key and 0 otherwise,
+/// with the same size as the [..., L, S] scores.
+/// The mask broadcasts over the leading dims,
+/// causal drops keys after the query.
+fn keep_mask(scores: &Tensor, mask: Option<&Tensor>, causal: bool) -> Option<Tensor> {
+    if mask.is_none() && !causal {
+        return None;
+    }
+    let size = scores.size();
+    let n = size.len();
+    let (l, s) = (size[n - 2], size[n - 1]);
+    let mut keep = scores.ones_like();
+    if let Some(mask) = mask {
+        let mask_size = mask.size();
+        if mask_size.len() > n
+            || mask_size
+                .iter()
+                .rev()
+                .zip(size.iter().rev())
+                .any(|(m, d)| m != d && *m != 1)
+        {
+            panic!(
+                "attention expects a mask broadcasting to {:?}, get {:?}",
+                size, mask_size
+            );
+        }
+        let mut full = vec![1; n - mask_size.len()];
+        full.extend_from_slice(&mask_size);
+        let repeat: Vec<usize> = size.iter().zip(full.iter()).map(|(d, m)| d / m).collect();
+        keep = keep.mul(&mask.ne(&mask.zeros_like()).reshape(&full).repeat(&repeat));
+    }
+    if causal {
+        let mut tril = Tensor::fill(&[l, s], &scalar_like(scores, 1.));
+        for i in 0..l {
+            for j in (i + 1)..s {
+                tril.set_f64(&[i, j], 0.);
+            }
+        }
+        let mut full = vec![1; n - 2];
+        full.extend_from_slice(&[l, s]);
+        let mut repeat = size[..n - 2].to_vec();
+        repeat.extend_from_slice(&[1, 1]);
+        keep = keep.mul(&tril.reshape(&full).repeat(&repeat));
+    }
+    Some(keep)
+}
+
+/// q k^T / sqrt(E) on [..., L, E] and [..., S, E],
+/// with the dropped keys at -1e9.
+fn scores(q: &Tensor, k: &Tensor, keep: Option<&Tensor>) -> Tensor {
+    let e = q.size()[q.size().len() - 1];
+    let scores = q
+        .matmul(&transpose_last(k))
+        .mul(&scalar_like(q, 1. / (e as f64).sqrt()));
+    if let Some(keep) = keep {
+        // conditional_select takes the scores where the condition is non-negative.
+        let cond = keep.sub(&scalar_like(keep, 0.5));
+        let dropped = Tensor::fill(&scores.size(), &scalar_like(&scores, -1e9));
+        cond.conditional_select(&scores, &dropped)
+    } else {
+        scores
+    }
+}
+
+/// softmax(q k^T / sqrt(E)) v on [..., L, E], [..., S, E], [..., S, Ev],
+/// return the output and the attention weights.
+fn attention(q: &Tensor, k: &Tensor, v: &Tensor, keep: Option<&Tensor>) -> (Tensor, Tensor) {
+    let scores = scores(q, k, keep);
+    let p = Tensor::new();
+    Softmax::new(scores.size().len() - 1).apply(&[scores], &[p.ref_copy()]);
+    (p.matmul(v), p)
+}
+
+/// Gradients of q, k and v, given the attention weights p.
+fn attention_grad(
+    q: &Tensor,
+    k: &Tensor,
+    v: &Tensor,
+    p: &Tensor,
+    keep: Option<&Tensor>,
+    output_grad: &Tensor,
+) -> (Tensor, Tensor, Tensor) {
+    let e = q.size()[q.size().len() - 1];
+    let scale = scalar_like(q, 1. / (e as f64).sqrt());
+    let dv = transpose_last(p).matmul(output_grad);
+    let dp = output_grad.matmul(&transpose_last(v));
+    let scores = scores(q, k, keep);
+    let ds = Tensor::new();
+    Softmax::new(scores.size().len() - 1).grad(&[scores], &[dp], &[ds.ref_copy()]);
+    let ds = match keep {
+        Some(keep) => ds.mul(keep),
+        None => ds,
+    }
+    .mul(&scale);
+    (ds.matmul(k), transpose_last(&ds).matmul(q), dv)
+}
+
+/// softmax(q k^T / sqrt(E) + mask) v, along the last two dims,
+/// the leading dims are batch dims.
+/// The inputs are query [..., L, E], key [..., S, E], value [..., S, Ev],
+/// and a mask broadcasting to [..., L, S] if has_mask,
+/// where 0 drops the key for the query.
+/// Pass the same var more than once for self-attention.
+/// causal drops the keys after the query.
+#[cfg_attr(feature = "use-serde", derive(Serialize, Deserialize))]
+pub struct ScaledDotProductAttention {
+    causal: bool,
+    has_mask: bool,
+    #[cfg_attr(feature = "use-serde", serde(skip))]
+    handle: OpHandle,
+}
+impl ScaledDotProductAttention {
+    pub fn new(causal: bool, has_mask: bool) -> ScaledDotProductAttention {
+        ScaledDotProductAttention {
+            causal,
+            has_mask,
+            handle: OpHandle::new(),
+        }
+    }
+
+    fn mask<'a>(&self, input: &'a [Tensor]) -> Option<&'a Tensor> {
+        if self.has_mask {
+            Some(&input[3])
+        } else {
+            None
+        }
+    }
+
+    handle_method!();
+}
+impl OpCall for ScaledDotProductAttention {
+    fn call(&mut self, inputs: &[&Var]) -> Result<Vec<Var>, AutoDiffError> {
+        let new_one = ScaledDotProductAttention {
+            causal: self.causal,
+            has_mask: self.has_mask,
+            handle: OpHandle::new(),
+        };
+
+        let op = Op::new(Rc::new(RefCell::new(Box::new(new_one))));
+
+        inputs[0].called_with(op, &inputs[1..inputs.len()])
+    }
+}
+impl OpTrait for ScaledDotProductAttention {
+    fn get_name(&self) -> &'static str {
+        "ScaledDotProductAttention"
+    }
+    fn get_input_size(&self) -> usize {
+        if self.has_mask {
+            4
+        } else {
+            3
+        }
+    }
+    fn get_output_size(&self) -> usize {
+        1
+    }
+    fn apply(&self, input: &[Tensor], output: &[Tensor]) {
+        let (q, k, v) = (&input[0], &input[1], &input[2]);
+        let keep = keep_mask(&q.matmul(&transpose_last(k)), self.mask(input), self.causal);
+        output[0].swap(&attention(q, k, v, keep.as_ref()).0);
+    }
+    fn grad(&self, input: &[Tensor], output_grad: &[Tensor], input_grad: &[Tensor]) {
+        let (q, k, v) = (&input[0], &input[1], &input[2]);
+        let keep = keep_mask(&q.matmul(&transpose_last(k)), self.mask(input), self.causal);
+        let (_, p) = attention(q, k, v, keep.as_ref());
+        let (dq, dk, dv) = attention_grad(q, k, v, &p, keep.as_ref(), &output_grad[0]);
+        input_grad[0].swap(&dq);
+        input_grad[1].swap(&dk);
+        input_grad[2].swap(&dv);
+        if let Some(mask) = self.mask(input) {
+            input_grad[3].swap(&mask.zeros_like());
+        }
+    }
+    fn get_values(&self) -> Vec<Tensor> {
+        Vec::new()
+    }
+    fn get_grads(&self) -> Vec<Tensor> {
+        Vec::new()
+    }
+    fn set_values(&self, _v: &[Tensor]) {}
+    #[cfg(feature = "use-serde")]
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+/// Attention over num_heads heads, with [B, L, E] query and [B, S, E] key and value.
+/// The query, key and value are projected by [E, E] weights,
+/// each head takes E/num_heads of the projected features,
+/// and the heads are joined and projected by the output weight.
+/// The inputs are the query, key and value,
+/// pass the same var more than once for self-attention.
+/// causal drops the keys after the query.
+#[cfg_attr(feature = "use-serde", derive(Serialize, Deserialize))]
+pub struct MultiheadAttention {
+    embed_dim: usize,
+    num_heads: usize,
+    bias_option: bool,
+    causal: bool,
+    weight: [Tensor; 4],
+    bias: [Tensor; 4],
+    weight_grad: [Tensor; 4],
+    bias_grad: [Tensor; 4],
+    #[cfg_attr(feature = "use-serde", serde(skip))]
+    handle: OpHandle,
+}
+impl MultiheadAttention {
+    pub fn new(embed_dim: usize, num_heads: usize, bias: bool, causal: bool) -> MultiheadAttention {
+        if num_heads == 0 || !embed_dim.is_multiple_of(num_heads) {
+            panic!(
+                "MultiheadAttention expects embed_dim divisible by num_heads, get {:?}, {:?}",
+                embed_dim, num_heads
+            );
+        }
+        MultiheadAttention {
+            embed_dim,
+            num_heads,
+            bias_option: bias,
+            causal,
+            weight: [0; 4].map(|_| Tensor::zeros(&[embed_dim, embed_dim])),
+            bias: [0; 4].map(|_| Tensor::zeros(&[embed_dim])),
+            weight_grad: [0; 4].map(|_| Tensor::new()),
+            bias_grad: [0; 4].map(|_| Tensor::new()),
+            handle: OpHandle::new(),
+        }
+    }
+
+    /// The query, key, value and output projection, [E, E] each.
+    pub fn weight(&self) -> &[Tensor; 4] {
+        &self.weight
+    }
+    pub fn set_weight(&self, var: [Var; 4]) {
+        for (w, v) in self.weight.iter().zip(var.iter()) {
+            w.swap(&v.val());
+        }
+    }
+    pub fn bias(&self) -> &[Tensor; 4] {
+        &self.bias
+    }
+    pub fn set_bias(&self, var: [Var; 4]) {
+        for (b, v) in self.bias.iter().zip(var.iter()) {
+            b.swap(&v.val());
+        }
+    }
+
+    fn project(&self, x: &Tensor, i: usize) -> Tensor {
+        let ret = x.matmul(&self.weight[i]);
+        if self.bias_option {
+            ret.add(&self.bias[i])
+        } else {
+            ret
+        }
+    }
+
+    /// [B, L, E] to [B, H, L, E/H].
+    fn split_heads(&self, x: &Tensor) -> Tensor {
+        let size = x.size();
+        if size.len() != 3 || size[2] != self.embed_dim {
+            panic!(
+                "MultiheadAttention expects [B, L, {}] input, get {:?}",
+                self.embed_dim, size
+            );
+        }
+        x.reshape(&[
+            size[0],
+            size[1],
+            self.num_heads,
+            self.embed_dim / self.num_heads,
+        ])
+        .permute(&[0, 2, 1, 3])
+    }
+
+    /// [B, H, L, E/H] to [B, L, E].
+    fn join_heads(&self, x: &Tensor) -> Tensor {
+        let size = x.size();
+        x.permute(&[0, 2, 1, 3])
+            .reshape(&[size[0], size[2], self.embed_dim])
+    }
+
+    /// Set the gradient of projection i, and return the input gradient.
+    fn project_grad(&self, x: &Tensor, grad: &Tensor, i: usize) -> Tensor {
+        let size = x.size();
+        let population = size[0] * size[1];
+        let flat_x = x.reshape(&[population, self.embed_dim]);
+        let flat_g = grad.reshape(&[population, self.embed_dim]);
+        self.weight_grad[i].swap(&flat_x.permute(&[1, 0]).matmul(&flat_g));
+        if self.bias_option {
+            self.bias_grad[i].swap(&flat_g.sum(Some(&[0]), false));
+        }
+        grad.matmul(&self.weight[i].permute(&[1, 0]))
+    }
+
+    handle_method!();
+}
+impl OpCall for MultiheadAttention {
+    fn call(&mut self, inputs: &[&Var]) -> Result<Vec<Var>, AutoDiffError> {
+        let new_one = MultiheadAttention {
+            embed_dim: self.embed_dim,
+            num_heads: self.num_heads,
+            bias_option: self.bias_option,
+            causal: self.causal,
+            weight: [0, 1, 2, 3].map(|i| self.weight[i].ref_copy()),
+            bias: [0, 1, 2, 3].map(|i| self.bias[i].ref_copy()),
+            weight_grad: [0, 1, 2, 3].map(|i| self.weight_grad[i].ref_copy()),
+            bias_grad: [0, 1, 2, 3].map(|i| self.bias_grad[i].ref_copy()),
+            handle: OpHandle::new(),
+        };
+
+        let op = Op::new(Rc::new(RefCell::new(Box::new(new_one))));
+
+        inputs[0].called_with(op, &inputs[1..inputs.len()])
+    }
+}
+impl OpTrait for MultiheadAttention {
+    fn get_name(&self) -> &'static str {
+        "MultiheadAttention"
+    }
+    fn get_input_size(&self) -> usize {
+        3
+    }
+    fn get_output_size(&self) -> usize {
+        1
+    }
+    fn apply(&self, input: &[Tensor], output: &[Tensor]) {
+        let (q, k, v) = (&input[0], &input[1], &input[2]);
+        let qh = self.split_heads(&self.project(q, 0));
+        let kh = self.split_heads(&self.project(k, 1));
+        let vh = self.split_heads(&self.project(v, 2));
+        let keep = keep_mask(&qh.matmul(&transpose_last(&kh)), None, self.causal);
+        let (heads, _) = attention(&qh, &kh, &vh, keep.as_ref());
+        output[0].swap(&self.project(&self.join_heads(&heads), 3));
+    }
+    fn grad(&self, input: &[Tensor], output_grad: &[Tensor], input_grad: &[Tensor]) {
+        let (q, k, v) = (&input[0], &input[1], &input[2]);
+        let qh = self.split_heads(&self.project(q, 0));
+        let kh = self.split_heads(&self.project(k, 1));
+        let vh = self.split_heads(&self.project(v, 2));
+        let keep = keep_mask(&qh.matmul(&transpose_last(&kh)), None, self.causal);
+        let (heads, p) = attention(&qh, &kh, &vh, keep.as_ref());
+
+        let d_joined = self.project_grad(&self.join_heads(&heads), &output_grad[0], 3);
+        let (dqh, dkh, dvh) = attention_grad(
+            &qh,
+            &kh,
+            &vh,
+            &p,
+            keep.as_ref(),
+            &self.split_heads(&d_joined),
+        );
+        input_grad[0].swap(&self.project_grad(q, &self.join_heads(&dqh), 0));
+        input_grad[1].swap(&self.project_grad(k, &self.join_heads(&dkh), 1));
+        input_grad[2].swap(&self.project_grad(v, &self.join_heads(&dvh), 2));
+    }
+    fn get_values(&self) -> Vec<Tensor> {
+        let mut ret: Vec<Tensor> = self.weight.iter().map(|x| x.ref_copy()).collect();
+        if self.bias_option {
+            ret.extend(self.bias.iter().map(|x| x.ref_copy()));
+        }
+        ret
+    }
+    fn set_values(&self, v: &[Tensor]) {
+        for (w, x) in self.weight.iter().zip(v.iter()) {
+            w.swap(&x.clone());
+        }
+        if self.bias_option {
+            for (b, x) in self.bias.iter().zip(v[4..].iter()) {
+                b.swap(&x.clone());
+            }
+        }
+    }
+    fn get_grads(&self) -> Vec<Tensor> {
+        let mut ret: Vec<Tensor> = self.weight_grad.iter().map(|x| x.ref_copy()).collect();
+        if self.bias_option {
+            ret.extend(self.bias_grad.iter().map(|x| x.ref_copy()));
+        }
+        ret
+    }
+    fn set_grads(&self, v: &[Tensor]) {
+        for (w, x) in self.weight_grad.iter().zip(v.iter()) {
+            w.swap(&x.clone());
+        }
+        if self.bias_option {
+            for (b, x) in self.bias_grad.iter().zip(v[4..].iter()) {
+                b.swap(&x.clone());
+            }
+        }
+    }
+    #[cfg(feature = "use-serde")]
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::op::_gradient_checker;
+    use rand::prelude::StdRng;
+    use rand::SeedableRng;
+
+    fn apply(op: &dyn OpTrait, input: &[Tensor]) -> Tensor {
+        let output = Tensor::new();
+        op.apply(input, &[output.ref_copy()]);
+        output
+    }
+
+    #[test]
+    fn scaled_dot_product_attention() {
+        // the query matches the first key.
+        let q = Tensor::from_vec_f64(&[10., 0.], &[1, 2]);
+        let k = Tensor::from_vec_f64(&[10., 0., 0., 10.], &[2, 2]);
+        let v = Tensor::from_vec_f64(&[1., 2., 3., 4.], &[2, 2]);
+        let op = ScaledDotProductAttention::new(false, false);
+        let output = apply(&op, &[q.ref_copy(), k.ref_copy(), v.ref_copy()]);
+        assert!(
+            output
+                .sub(&v.get_patch(&[(0, 1), (0, 2)], None))
+                .abs()
+                .max(None, false)
+                .get_scale_f64()
+                < 1e-10
+        );
+
+        // the mask drops the first key.
+        let op = ScaledDotProductAttention::new(false, true);
+        let mask = Tensor::from_vec_f64(&[0., 1.], &[1, 2]);
+        let output = apply(&op, &[q, k, v.ref_copy(), mask]);
+        assert_eq!(output, Tensor::from_vec_f64(&[3., 4.], &[1, 2]));
+
+        // the first query only sees the first key.
+        let op = ScaledDotProductAttention::new(true, false);
+        let x = Tensor::from_vec_f64(&[0., 1., 2., 3.], &[1, 2, 2]);
+        let output = apply(&op, &[x.ref_copy(), x.ref_copy(), x]);
+        assert_eq!(
+            output.get_patch(&[(0, 1), (0, 1), (0, 2)], None),
+            Tensor::from_vec_f64(&[0., 1.], &[1, 1, 2])
+        );
+
+        let mut rng = StdRng::seed_from_u64(1);
+        let input = [
+            Tensor::uniform_f64(&mut rng, &[2, 3, 4], -1., 1.),
+            Tensor::uniform_f64(&mut rng, &[2, 5, 4], -1., 1.),
+            Tensor::uniform_f64(&mut rng, &[2, 5, 3], -1., 1.),
+        ];
+        assert!(_gradient_checker(
+            &mut ScaledDotProductAttention::new(false, false),
+            &input,
+            None,
+            Some(Tensor::from_vec_f64(&[1e-6], &[1])),
+            Some(Tensor::from_vec_f64(&[1e-10], &[1])),
+            Some(&[Tensor::uniform_f64(&mut rng, &[2, 3, 3], -1., 1.)])
+        ));
+        let mask = Tensor::from_vec_f64(
+            &[1., 0., 1., 1., 0., 1., 1., 0., 1., 1., 0., 0., 1., 1., 1.],
+            &[3, 5],
+        );
+        let mut masked = input.to_vec();
+        masked.push(mask);
+        assert!(_gradient_checker(
+            &mut ScaledDotProductAttention::new(false, true),
+            &masked,
+            Some(&[true, true, true, false]),
+            Some(Tensor::from_vec_f64(&[1e-6], &[1])),
+            Some(Tensor::from_vec_f64(&[1e-10], &[1])),
+            Some(&[Tensor::uniform_f64(&mut rng, &[2, 3, 3], -1., 1.)])
+        ));
+        assert!(_gradient_checker(
+            &mut ScaledDotProductAttention::new(true, false),
+            &[
+                Tensor::uniform_f64(&mut rng, &[2, 4, 3], -1., 1.),
+                Tensor::uniform_f64(&mut rng, &[2, 4, 3], -1., 1.),
+                Tensor::uniform_f64(&mut rng, &[2, 4, 2], -1., 1.)
+            ],
+            None,
+            Some(Tensor::from_vec_f64(&[1e-6], &[1])),
+            Some(Tensor::from_vec_f64(&[1e-10], &[1])),
+            Some(&[Tensor::uniform_f64(&mut rng, &[2, 4, 2], -1., 1.)])
+        ));
+        assert!(_gradient_checker(
+            &mut ScaledDotProductAttention::new(false, false),
+            &[
+                Tensor::uniform_f64(&mut rng, &[2, 4, 3], -1., 1.),
+                Tensor::uniform_f64(&mut rng, &[2, 5, 3], -1., 1.),
+                Tensor::uniform_f64(&mut rng, &[2, 5, 3], -1., 1.)
+            ],
+            None,
+            Some(Tensor::from_vec_f64(&[1e-6], &[1])),
+            Some(Tensor::from_vec_f64(&[1e-10], &[1])),
+            Some(&[Tensor::uniform_f64(&mut rng, &[2, 4, 3], -1., 1.)])
+        ));
+    }
+
+    #[test]
+    fn scaled_dot_product_attention_var() {
+        let q = Var::new(&[10., 0.], &[1, 2]);
+        let kv = Var::new(&[10., 0., 0., 10.], &[2, 2]);
+        let output = q
+            .scaled_dot_product_attention(&kv, &kv, None, false)
+            .unwrap();
+        let loss = output.sum(None, false).unwrap();
+        loss.bp().unwrap();
+        assert_eq!(kv.grad().unwrap().val().size(), [2, 2]);
+        loss.rerun().unwrap();
+        assert!((output.val().get_f64(&[0, 0]) - 10.).abs() < 1e-10);
+
+        // the query is also the key, its gradient sums both uses.
+        let x = Var::new(&[1., 0., 0.5, 1., -1., 0.5], &[3, 2]);
+        let v = Var::new(&[1., 2., 3., 4., 5., 6.], &[3, 2]);
+        let output = x.scaled_dot_product_attention(&x, &v, None, false).unwrap();
+        let loss = output.sum(None, false).unwrap();
+        loss.bp().unwrap();
+        let op = ScaledDotProductAttention::new(false, false);
+        let input_grad = [Tensor::new(), Tensor::new(), Tensor::new()];
+        op.grad(
+            &[x.val(), x.val(), v.val()],
+            &[output.val().ones_like()],
+            &input_grad,
+        );
+        let expected = input_grad[0].add(&input_grad[1]);
+        assert!(
+            x.grad()
+                .unwrap()
+                .val()
+                .sub(&expected)
+                .abs()
+                .max(None, false)
+                .get_scale_f64()
+                < 1e-10
+        );
+        assert!(
+            v.grad()
+                .unwrap()
+                .val()
+                .sub(&input_grad[2])
+                .abs()
+                .max(None, false)
+                .get_scale_f64()
+                < 1e-10
+        );
+        let before = output.val();
+        loss.rerun().unwrap();
+        assert_eq!(output.val(), before);
+    }
+
+    #[test]
+    fn multihead_attention() {
+        let mut rng = StdRng::seed_from_u64(2);
+        let mut op = MultiheadAttention::new(4, 2, true, false);
+        let values: Vec<Tensor> = op
+            .get_values()
+            .iter()
+            .map(|v| Tensor::uniform_f64(&mut rng, &v.size(), -1., 1.))
+            .collect();
+        op.set_values(&values);
+
+        // heads with identity projections are attention on the split features.
+        // set_weight swaps the values out of the vars.
+        let identity = || {
+            Var::new(
+                &[
+                    1., 0., 0., 0., 0., 1., 0., 0., 0., 0., 1., 0., 0., 0., 0., 1.,
+                ],
+                &[4, 4],
+            )
+        };
+        let plain = MultiheadAttention::new(4, 2, false, true);
+        plain.set_weight([identity(), identity(), identity(), identity()]);
+        let x = Tensor::uniform_f64(&mut rng, &[1, 3, 4], -1., 1.);
+        let output = apply(&plain, &[x.ref_copy(), x.ref_copy(), x.ref_copy()]);
+        let sdpa = ScaledDotProductAttention::new(true, false);
+        for h in 0..2 {
+            let part = x.get_patch(&[(0, 1), (0, 3), (2 * h, 2 * h + 2)], None);
+            let expected = apply(&sdpa, &[part.ref_copy(), part.ref_copy(), part]);
+            let got = output.get_patch(&[(0, 1), (0, 3), (2 * h, 2 * h + 2)], None);
+            assert!(got.sub(&expected).abs().max(None, false).get_scale_f64() < 1e-10);
+        }
+
+        assert!(_gradient_checker(
+            &mut op,
+            &[
+                Tensor::uniform_f64(&mut rng, &[2, 3, 4], -1., 1.),
+                Tensor::uniform_f64(&mut rng, &[2, 5, 4], -1., 1.),
+                Tensor::uniform_f64(&mut rng, &[2, 5, 4], -1., 1.),
+            ],
+            None,
+            Some(Tensor::from_vec_f64(&[1e-6], &[1])),
+            Some(Tensor::from_vec_f64(&[1e-10], &[1])),
+            Some(&[Tensor::uniform_f64(&mut rng, &[2, 3, 4], -1., 1.)])
+        ));
+        let mut op = MultiheadAttention::new(4, 2, false, true);
+        let values: Vec<Tensor> = op
+            .get_values()
+            .iter()
+            .map(|v| Tensor::uniform_f64(&mut rng, &v.size(), -1., 1.))
+            .collect();
+        op.set_values(&values);
+        assert!(_gradient_checker(
+            &mut op,
+            &[
+                Tensor::uniform_f64(&mut rng, &[2, 3, 4], -1., 1.),
+                Tensor::uniform_f64(&mut rng, &[2, 3, 4], -1., 1.),
+                Tensor::uniform_f64(&mut rng, &[2, 3, 4], -1., 1.),
+            ],
+            None,
+            Some(Tensor::from_vec_f64(&[1e-6], &[1])),
+            Some(Tensor::from_vec_f64(&[1e-10], &[1])),
+            Some(&[Tensor::uniform_f64(&mut rng, &[2, 3, 4], -1., 1.)])
+        ));
+    }
+}
@@ -1,4 +1,5 @@
 #![allow(clippy::too_many_arguments)]
+use super::pooling::PoolSize;
 use super::{Op, OpCall, OpHandle, OpTrait};
 use crate::err::AutoDiffError;
 use crate::var::Var;
@@ -11,159 +12,678 @@ use serde::{Deserialize, Serialize};
 #[cfg(feature = "use-serde")]
 use std::any::Any;
 
+/// Convolution with input and output channels split into groups,
+/// group i of the output only sees group i of the input.
+fn grouped_conv(
+    input: &Tensor,
+    weight: &Tensor,
+    groups: usize,
+    stride: &[usize],
+    padding: &[usize],
+    dilation: &[usize],
+    padding_mode: PaddingMode,
+) -> Tensor {
+    if groups == 1 {
+        return input.conv_gen(weight, stride, padding, dilation, padding_mode);
+    }
+    let outputs: Vec<Tensor> = input
+        .chunk(groups, 1)
+        .iter()
+        .zip(weight.chunk(groups, 0).iter())
+        .map(|(x, w)| x.conv_gen(w, stride, padding, dilation, padding_mode))
+        .collect();
+    outputs[0].cat(&outputs[1..], 1)
+}
+
+/// Return the gradient for the weight and the input of grouped_conv.
+fn grouped_conv_grad(
+    input: &Tensor,
+    weight: &Tensor,
+    groups: usize,
+    stride: &[usize],
+    padding: &[usize],
+    dilation: &[usize],
+    padding_mode: PaddingMode,
+    output_grad: &Tensor,
+) -> (Tensor, Tensor) {
+    if groups == 1 {
+        return input.conv_grad_gen(weight, stride, padding, dilation, padding_mode, output_grad);
+    }
+    let (w_grads, x_grads): (Vec<Tensor>, Vec<Tensor>) = input
+        .chunk(groups, 1)
+        .iter()
+        .zip(weight.chunk(groups, 0).iter())
+        .zip(output_grad.chunk(groups, 1).iter())
+        .map(|((x, w), g)| x.conv_grad_gen(w, stride, padding, dilation, padding_mode, g))
+        .unzip();
+    (
+        w_grads[0].cat(&w_grads[1..], 0),
+        x_grads[0].cat(&x_grads[1..], 1),
+    )
+}
+
+macro_rules! conv {
+    ($a:ident, $b:expr, $t:ty, $dims:expr) => {
+        #[cfg_attr(feature = "use-serde", derive(Serialize, Deserialize))]
+        pub struct $a {
+            in_channels: usize,
+            out_channels: usize,
+            kernel_size: $t,
+            stride: $t,
+            padding: $t,
+            dilation: $t,
+            groups: usize,
+            bias_option: bool,
+            padding_mode: PaddingMode,
+
+            weight: Tensor,
+            bias: Tensor,
+            weight_grad: Tensor,
+            bias_grad: Tensor,
+            #[cfg_attr(feature = "use-serde", serde(skip))]
+            handle: OpHandle,
+        }
+        impl $a {
+            pub fn new(
+                in_channels: usize,
+                out_channels: usize,
+                kernel_size: $t,
+                stride: $t,
+                padding: $t,
+                dilation: $t,
+                bias: bool,
+                padding_mode: PaddingMode,
+            ) -> $a {
+                let mut weight_size = vec![out_channels, in_channels];
+                weight_size.append(&mut kernel_size.to_vec());
+                $a {
+                    in_channels,
+                    out_channels,
+                    kernel_size,
+                    stride,
+                    padding,
+                    dilation,
+                    groups: 1,
+                    bias_option: bias,
+                    padding_mode,
+
+                    weight: Tensor::empty(&weight_size),
+                    bias: Tensor::empty(&[out_channels]),
+                    weight_grad: Tensor::empty(&weight_size),
+                    bias_grad: Tensor::empty(&[out_channels]),
+
+                    handle: OpHandle::new(),
+                }
+            }
+
+            /// Split the channels into groups, the default is 1.
+            /// in_channels and out_channels have to be divisible by groups.
+            /// groups == in_channels gives a depthwise convolution.
+            pub fn with_groups(mut self, groups: usize) -> $a {
+                if groups == 0 || self.in_channels % groups != 0 || self.out_channels % groups != 0
+                {
+                    panic!(
+                        "{} expects channels divisible by groups, get {:?}, {:?}, {:?}",
+                        $b, self.in_channels, self.out_channels, groups
+                    );
+                }
+                let mut weight_size = vec![self.out_channels, self.in_channels / groups];
+                weight_size.append(&mut self.kernel_size.to_vec());
+                self.groups = groups;
+                self.weight = Tensor::empty(&weight_size);
+                self.weight_grad = Tensor::empty(&weight_size);
+                self
+            }
+
+            pub fn weight(&self) -> &Tensor {
+                &self.weight
+            }
+
+            pub fn set_weight(&self, var: Var) {
+                self.weight.swap(&var.val());
+            }
+
+            pub fn bias(&self) -> &Tensor {
+                &self.bias
+            }
+
+            pub fn set_bias(&self, var: Var) {
+                self.bias.swap(&var.val());
+            }
+
+            handle_method!();
+        }
+
+        impl OpCall for $a {
+            fn call(&mut self, inputs: &[&Var]) -> Result<Vec<Var>, AutoDiffError> {
+                let new_one = $a {
+                    in_channels: self.in_channels,
+                    out_channels: self.out_channels,
+                    kernel_size: self.kernel_size,
+                    stride: self.stride,
+                    padding: self.padding,
+                    dilation: self.dilation,
+                    groups: self.groups,
+                    bias_option: self.bias_option,
+                    padding_mode: self.padding_mode,
+
+                    weight: self.weight.ref_copy(),
+                    bias: self.bias.ref_copy(),
+                    weight_grad: self.weight_grad.ref_copy(),
+                    bias_grad: self.bias_grad.ref_copy(),
+
+                    handle: OpHandle::new(),
+                };
+
+                let op = Op::new(Rc::new(RefCell::new(Box::new(new_one))));
+
+                inputs[0].called_with(op, &inputs[1..inputs.len()])
+            }
+        }
+
+        impl OpTrait for $a {
+            fn get_name(&self) -> &'static str {
+                ($b)
+            }
+            fn get_input_size(&self) -> usize {
+                1
+            }
+            fn get_output_size(&self) -> usize {
+                1
+            }
+            /// Forward pass
+            fn apply(&self, input: &[Tensor], output: &[Tensor]) {
+                if self.weight.size()[2..] != self.kernel_size.to_vec()[..] {
+                    panic!(
+                        "{} expects weight with kernel_size {:?}, get {:?}",
+                        $b,
+                        self.kernel_size.to_vec(),
+                        self.weight.size()
+                    );
+                }
+                let input_size = input[0].size();
+                if input_size.len() != $dims + 2 || input_size[1] != self.in_channels {
+                    panic!(
+                        "{} expects (N, {:?}, ...) input with {} dims, get {:?}",
+                        $b,
+                        self.in_channels,
+                        $dims + 2,
+                        input_size
+                    );
+                }
+                let conv_output = grouped_conv(
+                    &input[0],
+                    &self.weight,
+                    self.groups,
+                    &self.stride.to_vec(),
+                    &self.padding.to_vec(),
+                    &self.dilation.to_vec(),
+                    self.padding_mode,
+                );
+
+                if self.bias_option {
+                    let mut bias_size = vec![1; $dims + 2];
+                    bias_size[1] = self.out_channels;
+                    output[0].swap(&conv_output.add(&self.bias.reshape(&bias_size)));
+                } else {
+                    output[0].swap(&conv_output);
+                }
+            }
+
+            /// Given the forward input value and backward output_grad,
+            /// Update weight gradient.
+            /// return backward input gradeint.
+            fn grad(&self, input: &[Tensor], output_grad: &[Tensor], input_grad: &[Tensor]) {
+                let (w_grad, d_grad) = grouped_conv_grad(
+                    &input[0],
+                    &self.weight,
+                    self.groups,
+                    &self.stride.to_vec(),
+                    &self.padding.to_vec(),
+                    &self.dilation.to_vec(),
+                    self.padding_mode,
+                    &output_grad[0],
+                );
+                self.weight_grad.swap(&w_grad);
+                input_grad[0].swap(&d_grad);
+
+                if self.bias_option {
+                    // The bias is added at every output position, so its
+                    // gradient sums output_grad over the batch and spatial dims.
+                    let mut reduced_dims = vec![0];
+                    reduced_dims.extend(2..$dims + 2);
+                    self.bias_grad
+                        .swap(&output_grad[0].sum(Some(&reduced_dims), false));
+                }
+            }
+
+            /// access weight values
+            fn get_values(&self) -> Vec<Tensor> {
+                vec![self.weight.ref_copy(), self.bias.ref_copy()]
+            }
+            fn set_values(&self, v: &[Tensor]) {
+                self.weight.data_copy(&v[0]);
+                self.bias.data_copy(&v[1]);
+            }
+            /// access gradient values
+            fn get_grads(&self) -> Vec<Tensor> {
+                vec![self.weight_grad.ref_copy(), self.bias_grad.ref_copy()]
+            }
+            fn set_grads(&self, v: &[Tensor]) {
+                self.weight_grad.data_copy(&v[0]);
+                self.bias_grad.data_copy(&v[1]);
+            }
+            #[cfg(feature = "use-serde")]
+            fn as_any(&self) -> &dyn Any {
+                self
+            }
+        }
+    };
+}
+
+conv!(Conv1d, "Conv1d", usize, 1);
+conv!(Conv2d, "Conv2d", (usize, usize), 2);
+conv!(Conv3d, "Conv3d", (usize, usize, usize), 3);
+
+/// Zeros in the given size, with the element type of like.
+fn zeros_in(like: &Tensor, size: &[usize]) -> Tensor {
+    like.zeros_like()
+        .sum(None, false)
+        .reshape(&vec![1; size.len()])
+        .repeat(size)
+}
+
+// Transposed convolution is the input gradient of the convolution
+// with the same weight, taking the transposed input as its output gradient.
+macro_rules! conv_transpose {
+    ($a:ident, $b:expr, $t:ty, $dims:expr) => {
+        #[cfg_attr(feature = "use-serde", derive(Serialize, Deserialize))]
+        pub struct $a {
+            in_channels: usize,
+            out_channels: usize,
+            kernel_size: $t,
+            stride: $t,
+            padding: $t,
+            output_padding: $t,
+            dilation: $t,
+            groups: usize,
+            bias_option: bool,
+
+            weight: Tensor,
+            bias: Tensor,
+            weight_grad: Tensor,
+            bias_grad: Tensor,
+            #[cfg_attr(feature = "use-serde", serde(skip))]
+            handle: OpHandle,
+        }
+        impl $a {
+            /// output_padding adds to one side of the output,
+            /// it has to be smaller than stride.
+            /// in_channels and out_channels have to be divisible by groups.
+            pub fn new(
+                in_channels: usize,
+                out_channels: usize,
+                kernel_size: $t,
+                stride: $t,
+                padding: $t,
+                output_padding: $t,
+                dilation: $t,
+                groups: usize,
+                bias: bool,
+            ) -> $a {
+                if groups == 0 || in_channels % groups != 0 || out_channels % groups != 0 {
+                    panic!(
+                        "{} expects channels divisible by groups, get {:?}, {:?}, {:?}",
+                        $b, in_channels, out_channels, groups
+                    );
+                }
+                if output_padding
+                    .to_vec()
+                    .iter()
+                    .zip(stride.to_vec().iter())
+                    .any(|(p, s)| p >= s)
+                {
+                    panic!(
+                        "{} expects output_padding smaller than stride, get {:?}, {:?}",
+                        $b,
+                        output_padding.to_vec(),
+                        stride.to_vec()
+                    );
+                }
+                let mut weight_size = vec![in_channels, out_channels / groups];
+                weight_size.append(&mut kernel_size.to_vec());
+                $a {
+                    in_channels,
+                    out_channels,
+                    kernel_size,
+                    stride,
+                    padding,
+                    output_padding,
+                    dilation,
+                    groups,
+                    bias_option: bias,
+
+                    weight: Tensor::empty(&weight_size),
+                    bias: Tensor::empty(&[out_channels]),
+                    weight_grad: Tensor::empty(&weight_size),
+                    bias_grad: Tensor::empty(&[out_channels]),
+
+                    handle: OpHandle::new(),
+                }
+            }
+
+            pub fn weight(&self) -> &Tensor {
+                &self.weight
+            }
+
+            pub fn set_weight(&self, var: Var) {
+                self.weight.swap(&var.val());
+            }
+
+            pub fn bias(&self) -> &Tensor {
+                &self.bias
+            }
+
+            pub fn set_bias(&self, var: Var) {
+                self.bias.swap(&var.val());
+            }
+
+            /// (L_in - 1)*stride - 2*padding + dilation*(kernel_size - 1) + output_padding + 1
+            fn output_size(&self, input_size: &[usize]) -> Vec<usize> {
+                let mut ret = vec![input_size[0], self.out_channels];
+                for i in 0..$dims {
+                    let k = self.kernel_size.to_vec()[i];
+                    let s = self.stride.to_vec()[i];
+                    let p = self.padding.to_vec()[i];
+                    let d = self.dilation.to_vec()[i];
+                    let o = self.output_padding.to_vec()[i];
+                    let full = (input_size[i + 2] - 1) * s + d * (k - 1) + o + 1;
+                    if full <= 2 * p {
+                        panic!("{} gets empty output for input {:?}", $b, input_size);
+                    }
+                    ret.push(full - 2 * p);
+                }
+                ret
+            }
+
+            handle_method!();
+        }
+
+        impl OpCall for $a {
+            fn call(&mut self, inputs: &[&Var]) -> Result<Vec<Var>, AutoDiffError> {
+                let new_one = $a {
+                    in_channels: self.in_channels,
+                    out_channels: self.out_channels,
+                    kernel_size: self.kernel_size,
+                    stride: self.stride,
+                    padding: self.padding,
+                    output_padding: self.output_padding,
+                    dilation: self.dilation,
+                    groups: self.groups,
+                    bias_option: self.bias_option,
+
+                    weight: self.weight.ref_copy(),
+                    bias: self.bias.ref_copy(),
+                    weight_grad: self.weight_grad.ref_copy(),
+                    bias_grad: self.bias_grad.ref_copy(),
+
+                    handle: OpHandle::new(),
+                };
+
+                let op = Op::new(Rc::new(RefCell::new(Box::new(new_one))));
+
+                inputs[0].called_with(op, &inputs[1..inputs.len()])
+            }
+        }
+
+        impl OpTrait for $a {
+            fn get_name(&self) -> &'static str {
+                ($b)
+            }
+            fn get_input_size(&self) -> usize {
+                1
+            }
+            fn get_output_size(&self) -> usize {
+                1
+            }
+            /// Forward pass
+            fn apply(&self, input: &[Tensor], output: &[Tensor]) {
+                if self.weight.size()[2..] != self.kernel_size.to_vec()[..] {
+                    panic!(
+                        "{} expects weight with kernel_size {:?}, get {:?}",
+                        $b,
+                        self.kernel_size.to_vec(),
+                        self.weight.size()
+                    );
+                }
+                let input_size = input[0].size();
+                if input_size.len() != $dims + 2 || input_size[1] != self.in_channels {
+                    panic!(
+                        "{} expects (N, {:?}, ...) input with {} dims, get {:?}",
+                        $b,
+                        self.in_channels,
+                        $dims + 2,
+                        input_size
+                    );
+                }
+                let placeholder = zeros_in(&input[0], &self.output_size(&input_size));
+                let (_, conv_output) = grouped_conv_grad(
+                    &placeholder,
+                    &self.weight,
+                    self.groups,
+                    &self.stride.to_vec(),
+                    &self.padding.to_vec(),
+                    &self.dilation.to_vec(),
+                    PaddingMode::Zeros,
+                    &input[0],
+                );
+
+                if self.bias_option {
+                    let mut bias_size = vec![1; $dims + 2];
+                    bias_size[1] = self.out_channels;
+                    output[0].swap(&conv_output.add(&self.bias.reshape(&bias_size)));
+                } else {
+                    output[0].swap(&conv_output);
+                }
+            }
+
+            /// Given the forward input value and backward output_grad,
+            /// Update weight gradient.
+            /// return backward input gradeint.
+            fn grad(&self, input: &[Tensor], output_grad: &[Tensor], input_grad: &[Tensor]) {
+                let d_grad = grouped_conv(
+                    &output_grad[0],
+                    &self.weight,
+                    self.groups,
+                    &self.stride.to_vec(),
+                    &self.padding.to_vec(),
+                    &self.dilation.to_vec(),
+                    PaddingMode::Zeros,
+                );
+                let (w_grad, _) = grouped_conv_grad(
+                    &output_grad[0],
+                    &self.weight,
+                    self.groups,
+                    &self.stride.to_vec(),
+                    &self.padding.to_vec(),
+                    &self.dilation.to_vec(),
+                    PaddingMode::Zeros,
+                    &input[0],
+                );
+                self.weight_grad.swap(&w_grad);
+                input_grad[0].swap(&d_grad);
+
+                if self.bias_option {
+                    let mut reduced_dims = vec![0];
+                    reduced_dims.extend(2..$dims + 2);
+                    self.bias_grad
+                        .swap(&output_grad[0].sum(Some(&reduced_dims), false));
+                }
+            }
+
+            /// access weight values
+            fn get_values(&self) -> Vec<Tensor> {
+                vec![self.weight.ref_copy(), self.bias.ref_copy()]
+            }
+            fn set_values(&self, v: &[Tensor]) {
+                self.weight.data_copy(&v[0]);
+                self.bias.data_copy(&v[1]);
+            }
+            /// access gradient values
+            fn get_grads(&self) -> Vec<Tensor> {
+                vec![self.weight_grad.ref_copy(), self.bias_grad.ref_copy()]
+            }
+            fn set_grads(&self, v: &[Tensor]) {
+                self.weight_grad.data_copy(&v[0]);
+                self.bias_grad.data_copy(&v[1]);
+            }
+            #[cfg(feature = "use-serde")]
+            fn as_any(&self) -> &dyn Any {
+                self
+            }
+        }
+    };
+}
+
+conv_transpose!(ConvTranspose1d, "ConvTranspose1d", usize, 1);
+conv_transpose!(ConvTranspose2d, "ConvTranspose2d", (usize, usize), 2);
+
+// ConvTranspose3d
+
+/// Extract sliding blocks from a (N, C, H, W) input,
+/// the output is (N, C*kernel_size.0*kernel_size.1, L),
+/// L is the number of blocks.
 #[cfg_attr(feature = "use-serde", derive(Serialize, Deserialize))]
-pub struct Conv1d {
-    alpha: f32,
+pub struct Unfold {
+    kernel_size: (usize, usize),
+    stride: (usize, usize),
+    padding: (usize, usize),
+    dilation: (usize, usize),
     #[cfg_attr(feature = "use-serde", serde(skip))]
     handle: OpHandle,
 }
-impl Conv1d {
-    pub fn new(alpha: f32) -> Conv1d {
-        Conv1d {
-            alpha,
+impl Unfold {
+    pub fn new(
+        kernel_size: (usize, usize),
+        stride: (usize, usize),
+        padding: (usize, usize),
+        dilation: (usize, usize),
+    ) -> Unfold {
+        Unfold {
+            kernel_size,
+            stride,
+            padding,
+            dilation,
             handle: OpHandle::new(),
         }
     }
     handle_method!();
 }
-impl OpTrait for Conv1d {
+impl OpCall for Unfold {
+    fn call(&mut self, inputs: &[&Var]) -> Result<Vec<Var>, AutoDiffError> {
+        let new_one = Unfold::new(self.kernel_size, self.stride, self.padding, self.dilation);
+
+        let op = Op::new(Rc::new(RefCell::new(Box::new(new_one))));
+
+        inputs[0].called_with(op, &inputs[1..inputs.len()])
+    }
+}
+impl OpTrait for Unfold {
     fn get_name(&self) -> &'static str {
-        "Conv1d"
+        "Unfold"
     }
     fn get_input_size(&self) -> usize {
-        2
+        1
     }
     fn get_output_size(&self) -> usize {
         1
     }
-    /// Forward pass
     fn apply(&self, input: &[Tensor], output: &[Tensor]) {
-        unimplemented!();
+        if input[0].size().len() != 4 {
+            panic!(
+                "Unfold expects (N, C, H, W) input, get {:?}",
+                input[0].size()
+            );
+        }
+        output[0].swap(&input[0].unfold(
+            &self.kernel_size.to_vec(),
+            &self.stride.to_vec(),
+            &self.padding.to_vec(),
+            &self.dilation.to_vec(),
+        ));
     }
-
-    /// Given the forward input value and backward output_grad,
-    /// Update weight gradient.
-    /// return backward input gradeint.
     fn grad(&self, input: &[Tensor], output_grad: &[Tensor], input_grad: &[Tensor]) {
-        unimplemented!();
+        input_grad[0].swap(&output_grad[0].fold(
+            &input[0].size()[2..],
+            &self.kernel_size.to_vec(),
+            &self.stride.to_vec(),
+            &self.padding.to_vec(),
+            &self.dilation.to_vec(),
+        ));
     }
-
-    /// access weight values
     fn get_values(&self) -> Vec<Tensor> {
         Vec::new()
     }
-    fn set_values(&self, v: &[Tensor]) {}
-    /// access gradient values
     fn get_grads(&self) -> Vec<Tensor> {
         Vec::new()
     }
+    fn set_values(&self, _v: &[Tensor]) {}
     #[cfg(feature = "use-serde")]
     fn as_any(&self) -> &dyn Any {
         self
     }
 }
 
-// Conv2d
-
+/// Sum (N, C*kernel_size.0*kernel_size.1, L) sliding blocks
+/// into a (N, C, output_size.0, output_size.1) output,
+/// overlapping values are added up.
 #[cfg_attr(feature = "use-serde", derive(Serialize, Deserialize))]
-pub struct Conv2d {
-    in_channels: usize,
-    out_channels: usize,
+pub struct Fold {
+    output_size: (usize, usize),
     kernel_size: (usize, usize),
     stride: (usize, usize),
     padding: (usize, usize),
     dilation: (usize, usize),
-    groups: usize,
-    bias_option: bool,
-    padding_mode: PaddingMode,
-
-    weight: Tensor,
-    bias: Tensor,
-    weight_grad: Tensor,
-    bias_grad: Tensor,
     #[cfg_attr(feature = "use-serde", serde(skip))]
     handle: OpHandle,
 }
-impl Conv2d {
+impl Fold {
     pub fn new(
-        in_channels: usize,
-        out_channels: usize,
+        output_size: (usize, usize),
         kernel_size: (usize, usize),
         stride: (usize, usize),
         padding: (usize, usize),
         dilation: (usize, usize),
-        bias: bool,
-        padding_mode: PaddingMode,
-    ) -> Conv2d {
-        Conv2d {
-            in_channels,
-            out_channels,
+    ) -> Fold {
+        Fold {
+            output_size,
             kernel_size,
             stride,
             padding,
             dilation,
-            groups: 1,
-            bias_option: bias,
-            padding_mode,
-
-            weight: Tensor::empty(&[out_channels, in_channels, kernel_size.0, kernel_size.1]),
-            bias: Tensor::empty(&[out_channels]),
-            weight_grad: Tensor::empty(&[out_channels, in_channels, kernel_size.0, kernel_size.1]),
-            bias_grad: Tensor::empty(&[out_channels]),
-
             handle: OpHandle::new(),
         }
     }
-
-    pub fn weight(&self) -> &Tensor {
-        &self.weight
-    }
-
-    pub fn set_weight(&self, var: Var) {
-        self.weight.swap(&var.val());
-    }
-
-    pub fn bias(&self) -> &Tensor {
-        &self.bias
-    }
-
-    pub fn set_bias(&self, var: Var) {
-        self.bias.swap(&var.val());
-    }
-
     handle_method!();
 }
-
-impl OpCall for Conv2d {
+impl OpCall for Fold {
     fn call(&mut self, inputs: &[&Var]) -> Result<Vec<Var>, AutoDiffError> {
-        let new_one = Conv2d {
-            in_channels: self.in_channels,
-            out_channels: self.out_channels,
-            kernel_size: self.kernel_size,
-            stride: self.stride,
-            padding: self.padding,
-            dilation: self.dilation,
-            groups: self.groups,
-            bias_option: self.bias_option,
-            padding_mode: self.padding_mode,
-
-            weight: self.weight.ref_copy(),
-            bias: self.bias.ref_copy(),
-            weight_grad: self.weight_grad.ref_copy(),
-            bias_grad: self.bias_grad.ref_copy(),
-
-            handle: OpHandle::new(),
-        };
+        let new_one = Fold::new(
+            self.output_size,
+            self.kernel_size,
+            self.stride,
+            self.padding,
+            self.dilation,
+        );
 
         let op = Op::new(Rc::new(RefCell::new(Box::new(new_one))));
 
         inputs[0].called_with(op, &inputs[1..inputs.len()])
     }
 }
-
-impl OpTrait for Conv2d {
+impl OpTrait for Fold {
     fn get_name(&self) -> &'static str {
-        "Conv2d"
+        "Fold"
     }
     fn get_input_size(&self) -> usize {
         1
@@ -171,94 +691,363 @@ impl OpTrait for Conv2d {
     fn get_output_size(&self) -> usize {
         1
     }
-    /// Forward pass
     fn apply(&self, input: &[Tensor], output: &[Tensor]) {
-        if self.groups > 1 {
-            unimplemented!();
-        }
-        if self.weight.size()[2] != self.kernel_size.0
-            || self.weight.size()[3] != self.kernel_size.1
-        {
-            panic!("this is conv2d");
-        }
-        let input_size = input[0].size();
-        if input_size[1] != self.in_channels {
-            panic!(
-                "conv2d expect the same input channel: input: {:?}, config: {:?}",
-                input_size[1], self.in_channels
-            );
-        }
-        let conv_output = input[0].conv2d(
-            &self.weight,
-            self.stride,
-            self.padding,
-            self.dilation,
-            self.padding_mode,
-        );
-        //println!("conv_output: {:?}, {:?}, {:?}, {:?}, {:?}, {:?}", self.weight.size(), self.stride, self.padding, self.dilation, conv_output.size(), input[0].size());
-        if conv_output.size()[1] != self.out_channels {
-            panic!(
-                "conv2d expect the same input channel {:?}, {:?}",
-                input_size[1], self.in_channels
-            );
-        }
+        output[0].swap(&input[0].fold(
+            &self.output_size.to_vec(),
+            &self.kernel_size.to_vec(),
+            &self.stride.to_vec(),
+            &self.padding.to_vec(),
+            &self.dilation.to_vec(),
+        ));
+    }
+    fn grad(&self, _input: &[Tensor], output_grad: &[Tensor], input_grad: &[Tensor]) {
+        input_grad[0].swap(&output_grad[0].unfold(
+            &self.kernel_size.to_vec(),
+            &self.stride.to_vec(),
+            &self.padding.to_vec(),
+            &self.dilation.to_vec(),
+        ));
+    }
+    fn get_values(&self) -> Vec<Tensor> {
+        Vec::new()
+    }
+    fn get_grads(&self) -> Vec<Tensor> {
+        Vec::new()
+    }
+    fn set_values(&self, _v: &[Tensor]) {}
+    #[cfg(feature = "use-serde")]
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
 
-        if self.bias_option {
-            //println!("{:?}, {:?}", self.weight.size(), self.bias.size());
-            let expanded_bias = self.bias.unsqueeze(1).unsqueeze(2).repeat(&[
-                1,
-                conv_output.size()[2],
-                conv_output.size()[3],
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::op::_gradient_checker;
+    use rand::prelude::StdRng;
+    use rand::SeedableRng;
+
+    macro_rules! init {
+        ($rng:ident, $op:expr) => {{
+            let op = $op;
+            op.set_values(&[
+                Tensor::uniform_f64(&mut $rng, &op.weight().size(), -1., 1.),
+                Tensor::uniform_f64(&mut $rng, &op.bias().size(), -1., 1.),
             ]);
-            //println!("conv_output: {:?}, expanded_bias.size() {:?}", conv_output.size(), expanded_bias.size());
-            let ret = conv_output.add(&expanded_bias);
-            output[0].swap(&ret);
-        } else {
-            output[0].swap(&conv_output);
-        }
+            op
+        }};
     }
 
-    /// Given the forward input value and backward output_grad,
-    /// Update weight gradient.
-    /// return backward input gradeint.
-    fn grad(&self, input: &[Tensor], output_grad: &[Tensor], input_grad: &[Tensor]) {
-        let (w_grad, d_grad) = input[0].conv2d_grad(
-            &self.weight,
-            self.stride,
-            self.padding,
-            self.dilation,
-            self.padding_mode,
-            &output_grad[0],
+    #[test]
+    fn conv1d() {
+        let mut rng = StdRng::seed_from_u64(1);
+        let mut op = init!(rng, Conv1d::new(2, 3, 3, 1, 1, 1, true, PaddingMode::Zeros));
+        let output = Tensor::new();
+        op.apply(
+            &[Tensor::uniform_f64(&mut rng, &[2, 2, 5], -1., 1.)],
+            &[output.ref_copy()],
         );
-        self.weight_grad.swap(&w_grad);
-        input_grad[0].swap(&d_grad);
+        assert_eq!(output.size(), [2, 3, 5]);
+        assert!(_gradient_checker(
+            &mut op,
+            &[Tensor::uniform_f64(&mut rng, &[2, 2, 5], -1., 1.)],
+            None,
+            Some(Tensor::from_vec_f64(&[1e-6], &[1])),
+            Some(Tensor::from_vec_f64(&[1e-10], &[1])),
+            Some(&[Tensor::uniform_f64(&mut rng, &[2, 3, 5], -1., 1.)])
+        ));
 
-        if self.bias_option {
-            self.bias_grad
-                .swap(&output_grad[0].mean(Some(&[0, 2, 3]), false));
-        }
+        let mut op = init!(
+            rng,
+            Conv1d::new(2, 2, 2, 2, 0, 2, false, PaddingMode::Zeros)
+        );
+        assert!(_gradient_checker(
+            &mut op,
+            &[Tensor::uniform_f64(&mut rng, &[1, 2, 7], -1., 1.)],
+            None,
+            Some(Tensor::from_vec_f64(&[1e-6], &[1])),
+            Some(Tensor::from_vec_f64(&[1e-10], &[1])),
+            Some(&[Tensor::uniform_f64(&mut rng, &[1, 2, 3], -1., 1.)])
+        ));
     }
 
-    /// access weight values
-    fn get_values(&self) -> Vec<Tensor> {
-        vec![self.weight.ref_copy(), self.bias.ref_copy()]
+    #[test]
+    fn conv2d() {
+        let mut rng = StdRng::seed_from_u64(2);
+        let mut op = init!(
+            rng,
+            Conv2d::new(
+                2,
+                3,
+                (3, 2),
+                (1, 1),
+                (1, 0),
+                (1, 1),
+                true,
+                PaddingMode::Zeros
+            )
+        );
+        assert!(_gradient_checker(
+            &mut op,
+            &[Tensor::uniform_f64(&mut rng, &[2, 2, 4, 3], -1., 1.)],
+            None,
+            Some(Tensor::from_vec_f64(&[1e-6], &[1])),
+            Some(Tensor::from_vec_f64(&[1e-10], &[1])),
+            Some(&[Tensor::uniform_f64(&mut rng, &[2, 3, 4, 2], -1., 1.)])
+        ));
     }
-    fn set_values(&self, v: &[Tensor]) {
-        self.weight.data_copy(&v[0]);
-        self.bias.data_copy(&v[1]);
+
+    #[test]
+    fn conv2d_bias_grad() {
+        let op = Conv2d::new(
+            1,
+            2,
+            (1, 1),
+            (1, 1),
+            (0, 0),
+            (1, 1),
+            true,
+            PaddingMode::Zeros,
+        );
+        op.set_values(&[Tensor::zeros(&[2, 1, 1, 1]), Tensor::ones(&[2])]);
+        let input = Tensor::ones(&[2, 1, 3, 2]);
+        let output = Tensor::new();
+        op.apply(&[input.ref_copy()], &[output.ref_copy()]);
+        // Each bias shows up at 2 * 3 * 2 = 12 output positions.
+        assert_eq!(output.sum(None, false).get_scale_f64(), 24.);
+
+        op.grad(&[input], &[output.ones_like()], &[Tensor::new()]);
+        // The mean over the batch and spatial dims gave 1 here.
+        assert_eq!(op.get_grads()[1], Tensor::fill_f64(&[2], 12.));
     }
-    /// access gradient values
-    fn get_grads(&self) -> Vec<Tensor> {
-        vec![self.weight_grad.ref_copy(), self.bias_grad.ref_copy()]
+
+    #[test]
+    fn conv3d() {
+        let mut rng = StdRng::seed_from_u64(3);
+        let mut op = init!(
+            rng,
+            Conv3d::new(
+                2,
+                2,
+                (2, 2, 2),
+                (1, 1, 1),
+                (0, 1, 0),
+                (1, 1, 1),
+                true,
+                PaddingMode::Zeros
+            )
+        );
+        let output = Tensor::new();
+        op.apply(
+            &[Tensor::uniform_f64(&mut rng, &[1, 2, 3, 3, 3], -1., 1.)],
+            &[output.ref_copy()],
+        );
+        assert_eq!(output.size(), [1, 2, 2, 4, 2]);
+        assert!(_gradient_checker(
+            &mut op,
+            &[Tensor::uniform_f64(&mut rng, &[1, 2, 3, 3, 3], -1., 1.)],
+            None,
+            Some(Tensor::from_vec_f64(&[1e-6], &[1])),
+            Some(Tensor::from_vec_f64(&[1e-10], &[1])),
+            Some(&[Tensor::uniform_f64(&mut rng, &[1, 2, 2, 4, 2], -1., 1.)])
+        ));
     }
-    #[cfg(feature = "use-serde")]
-    fn as_any(&self) -> &dyn Any {
-        self
+
+    #[test]
+    fn grouped_conv() {
+        let mut rng = StdRng::seed_from_u64(4);
+        let mut op = init!(
+            rng,
+            Conv2d::new(
+                4,
+                6,
+                (2, 2),
+                (1, 1),
+                (0, 0),
+                (1, 1),
+                true,
+                PaddingMode::Zeros
+            )
+            .with_groups(2)
+        );
+        assert_eq!(op.weight().size(), [6, 2, 2, 2]);
+        assert!(_gradient_checker(
+            &mut op,
+            &[Tensor::uniform_f64(&mut rng, &[2, 4, 3, 3], -1., 1.)],
+            None,
+            Some(Tensor::from_vec_f64(&[1e-6], &[1])),
+            Some(Tensor::from_vec_f64(&[1e-10], &[1])),
+            Some(&[Tensor::uniform_f64(&mut rng, &[2, 6, 2, 2], -1., 1.)])
+        ));
+
+        // The second group only sees the second half of the channels.
+        let input = Tensor::uniform_f64(&mut rng, &[1, 4, 3, 3], -1., 1.);
+        let output = Tensor::new();
+        op.apply(&[input.ref_copy()], &[output.ref_copy()]);
+        let second = Conv2d::new(
+            2,
+            3,
+            (2, 2),
+            (1, 1),
+            (0, 0),
+            (1, 1),
+            true,
+            PaddingMode::Zeros,
+        );
+        second.set_values(&[
+            op.weight().chunk(2, 0)[1].clone(),
+            op.bias().chunk(2, 0)[1].clone(),
+        ]);
+        let expected = Tensor::new();
+        second.apply(&[input.chunk(2, 1)[1].clone()], &[expected.ref_copy()]);
+        assert_eq!(output.chunk(2, 1)[1], expected);
+
+        // depthwise
+        let mut op = init!(
+            rng,
+            Conv1d::new(3, 3, 3, 1, 1, 1, true, PaddingMode::Zeros).with_groups(3)
+        );
+        assert_eq!(op.weight().size(), [3, 1, 3]);
+        assert!(_gradient_checker(
+            &mut op,
+            &[Tensor::uniform_f64(&mut rng, &[2, 3, 4], -1., 1.)],
+            None,
+            Some(Tensor::from_vec_f64(&[1e-6], &[1])),
+            Some(Tensor::from_vec_f64(&[1e-10], &[1])),
+            Some(&[Tensor::uniform_f64(&mut rng, &[2, 3, 4], -1., 1.)])
+        ));
+    }
+
+    #[test]
+    fn conv_transpose() {
+        let mut rng = StdRng::seed_from_u64(5);
+        let mut op = init!(rng, ConvTranspose1d::new(2, 3, 3, 2, 1, 1, 1, 1, true));
+        let output = Tensor::new();
+        op.apply(
+            &[Tensor::uniform_f64(&mut rng, &[2, 2, 4], -1., 1.)],
+            &[output.ref_copy()],
+        );
+        assert_eq!(output.size(), [2, 3, 8]);
+        assert!(_gradient_checker(
+            &mut op,
+            &[Tensor::uniform_f64(&mut rng, &[2, 2, 4], -1., 1.)],
+            None,
+            Some(Tensor::from_vec_f64(&[1e-6], &[1])),
+            Some(Tensor::from_vec_f64(&[1e-10], &[1])),
+            Some(&[Tensor::uniform_f64(&mut rng, &[2, 3, 8], -1., 1.)])
+        ));
+
+        let mut op = init!(
+            rng,
+            ConvTranspose2d::new(4, 2, (2, 3), (2, 1), (0, 1), (1, 0), (1, 2), 2, false)
+        );
+        assert_eq!(op.weight().size(), [4, 1, 2, 3]);
+        assert!(_gradient_checker(
+            &mut op,
+            &[Tensor::uniform_f64(&mut rng, &[1, 4, 2, 3], -1., 1.)],
+            None,
+            Some(Tensor::from_vec_f64(&[1e-6], &[1])),
+            Some(Tensor::from_vec_f64(&[1e-10], &[1])),
+            Some(&[Tensor::uniform_f64(&mut rng, &[1, 2, 5, 5], -1., 1.)])
+        ));
+
+        // Undoes the shape change of the matching convolution.
+        let conv = Conv2d::new(
+            2,
+            2,
+            (3, 3),
+            (2, 2),
+            (1, 1),
+            (1, 1),
+            false,
+            PaddingMode::Zeros,
+        );
+        let convt = ConvTranspose2d::new(2, 2, (3, 3), (2, 2), (1, 1), (1, 1), (1, 1), 1, false);
+        let input = Tensor::uniform_f64(&mut rng, &[1, 2, 6, 6], -1., 1.);
+        conv.set_values(&[
+            Tensor::uniform_f64(&mut rng, &conv.weight().size(), -1., 1.),
+            Tensor::uniform_f64(&mut rng, &[2], -1., 1.),
+        ]);
+        convt.set_values(&[
+            conv.weight().clone(),
+            Tensor::uniform_f64(&mut rng, &[2], -1., 1.),
+        ]);
+        let output = Tensor::new();
+        conv.apply(&[input.ref_copy()], &[output.ref_copy()]);
+        let restored = Tensor::new();
+        convt.apply(&[output.ref_copy()], &[restored.ref_copy()]);
+        assert_eq!(restored.size(), input.size());
+
+        // <conv(x), y> == <x, conv_transpose(y)>
+        let y = Tensor::uniform_f64(&mut rng, &[1, 2, 3, 3], -1., 1.);
+        let lhs = output.mul(&y).sum(None, false).get_scale_f64();
+        let back = Tensor::new();
+        convt.apply(&[y], &[back.ref_copy()]);
+        let rhs = input.mul(&back).sum(None, false).get_scale_f64();
+        assert!((lhs - rhs).abs() < 1e-10);
+    }
+
+    #[test]
+    fn unfold_fold() {
+        let mut rng = StdRng::seed_from_u64(6);
+        let mut unfold = Unfold::new((2, 3), (1, 2), (1, 1), (2, 1));
+        let input = Tensor::uniform_f64(&mut rng, &[2, 2, 4, 5], -1., 1.);
+        let output = Tensor::new();
+        unfold.apply(&[input.ref_copy()], &[output.ref_copy()]);
+        assert_eq!(output.size(), [2, 12, 12]);
+        assert!(_gradient_checker(
+            &mut unfold,
+            &[input.ref_copy()],
+            None,
+            Some(Tensor::from_vec_f64(&[1e-6], &[1])),
+            Some(Tensor::from_vec_f64(&[1e-10], &[1])),
+            Some(&[Tensor::uniform_f64(&mut rng, &[2, 12, 12], -1., 1.)])
+        ));
+
+        let mut fold = Fold::new((4, 5), (2, 3), (1, 2), (1, 1), (2, 1));
+        assert!(_gradient_checker(
+            &mut fold,
+            &[Tensor::uniform_f64(&mut rng, &[2, 12, 12], -1., 1.)],
+            None,
+            Some(Tensor::from_vec_f64(&[1e-6], &[1])),
+            Some(Tensor::from_vec_f64(&[1e-10], &[1])),
+            Some(&[Tensor::uniform_f64(&mut rng, &[2, 2, 4, 5], -1., 1.)])
+        ));
+
+        // <unfold(x), y> == <x, fold(y)>
+        let y = Tensor::uniform_f64(&mut rng, &[2, 12, 12], -1., 1.);
+        let folded = Tensor::new();
+        fold.apply(&[y.ref_copy()], &[folded.ref_copy()]);
+        let lhs = output.mul(&y).sum(None, false).get_scale_f64();
+        let rhs = input.mul(&folded).sum(None, false).get_scale_f64();
+        assert!((lhs - rhs).abs() < 1e-10);
+
+        // Convolution as a matrix product on the blocks.
+        let conv = init!(
+            rng,
+            Conv2d::new(
+                2,
+                3,
+                (2, 3),
+                (1, 2),
+                (1, 1),
+                (2, 1),
+                false,
+                PaddingMode::Zeros
+            )
+        );
+        let conv_output = Tensor::new();
+        conv.apply(&[input.ref_copy()], &[conv_output.ref_copy()]);
+        for n in 0..2 {
+            let blocks = output.get_patch(&[(n, n + 1), (0, 12), (0, 12)], None);
+            let product = conv
+                .weight()
+                .reshape(&[3, 12])
+                .mm(&blocks.reshape(&[12, 12]));
+            let expected = conv_output.get_patch(&[(n, n + 1), (0, 3), (0, 4), (0, 3)], None);
+            let diff = product.sub(&expected.reshape(&[3, 12])).abs();
+            assert!(diff.max(None, false).get_scale_f64() < 1e-10);
+        }
     }
 }
-// Conv3d
-// ConvTranspose1d
-// ConvTranspose2d
-// ConvTranspose3d
-// Unfold
-// Fold
@@ -0,0 +1,306 @@
+use super::{scalar_like, Op, OpCall, OpHandle, OpTrait, TrainingFlag};
+use tensor_rs::tensor::Tensor;
+
+use rand::prelude::StdRng;
+use rand::SeedableRng;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::err::AutoDiffError;
+use crate::var::Var;
+
+#[cfg(feature = "use-serde")]
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "use-serde")]
+use std::any::Any;
+
+// Dropout
+// Dropout2d
+// Dropout3d
+// AlphaDropout
+// FeatureAlphaDropout
+
+/// 1 with probability keep and 0 otherwise, with the same type as like.
+fn bernoulli_like(rng: &mut StdRng, like: &Tensor, size: &[usize], keep: f64) -> Tensor {
+    let uniform = like.uniform_like(rng, size, 0., 1.);
+    // uniform is in [0, 1).
+    uniform.add(&scalar_like(&uniform, keep)).floor()
+}
+
+// The rng is not saved, a deserialized op starts over from seed 0.
+#[cfg(feature = "use-serde")]
+fn default_rng() -> Rc<RefCell<StdRng>> {
+    Rc::new(RefCell::new(StdRng::seed_from_u64(0)))
+}
+
+// The op saves the mask of the last forward pass for the backward pass.
+// The op placed in the graph by OpCall shares the rng, so every call
+// draws a new mask, and the train/eval flag with the op it is copied from.
+// $mask_size gives the mask size, which broadcasts to the input.
+// $forward turns (input, 0/1 mask, p) into (output, saved mask),
+// the backward pass multiplies the output gradient by the saved mask.
+macro_rules! dropout {
+    ($a:ident, $b:expr, $mask_size:expr, $forward:expr) => {
+        #[cfg_attr(feature = "use-serde", derive(Serialize, Deserialize))]
+        pub struct $a {
+            p: f64,
+            #[cfg_attr(feature = "use-serde", serde(skip, default = "default_rng"))]
+            rng: Rc<RefCell<StdRng>>,
+            #[cfg_attr(feature = "use-serde", serde(skip))]
+            mask: Tensor,
+            #[cfg_attr(feature = "use-serde", serde(skip))]
+            training: TrainingFlag,
+            #[cfg_attr(feature = "use-serde", serde(skip))]
+            handle: OpHandle,
+        }
+        impl $a {
+            /// p is the probability to drop an element.
+            pub fn new(p: f64, seed: u64) -> $a {
+                if !(0. ..=1.).contains(&p) {
+                    panic!("{} expects p in [0, 1], get {:?}", $b, p);
+                }
+                $a {
+                    p,
+                    rng: Rc::new(RefCell::new(StdRng::seed_from_u64(seed))),
+                    mask: Tensor::new(),
+                    training: TrainingFlag::new(true),
+                    handle: OpHandle::new(),
+                }
+            }
+
+            /// Drop elements.
+            pub fn train(&self) {
+                self.training.set(true);
+            }
+            /// Pass the input through.
+            pub fn eval(&self) {
+                self.training.set(false);
+            }
+            pub fn is_training(&self) -> bool {
+                self.training.get()
+            }
+
+            handle_method!();
+        }
+        impl OpCall for $a {
+            fn call(&mut self, inputs: &[&Var]) -> Result<Vec<Var>, AutoDiffError> {
+                let new_one = $a {
+                    p: self.p,
+                    rng: self.rng.clone(),
+                    mask: Tensor::new(),
+                    training: self.training.ref_copy(),
+                    handle: OpHandle::new(),
+                };
+
+                let op = Op::new(Rc::new(RefCell::new(Box::new(new_one))));
+
+                inputs[0].called_with(op, &inputs[1..inputs.len()])
+            }
+        }
+        impl OpTrait for $a {
+            fn get_name(&self) -> &'static str {
+                ($b)
+            }
+            fn get_input_size(&self) -> usize {
+                1
+            }
+            fn get_output_size(&self) -> usize {
+                1
+            }
+            fn apply(&self, input: &[Tensor], output: &[Tensor]) {
+                if !self.training.get() {
+                    // an empty mask passes the gradient through.
+                    self.mask.swap(&Tensor::new());
+                    output[0].swap(&input[0].clone());
+                    return;
+                }
+                let mask_size = ($mask_size)(&input[0].size());
+                let mask = bernoulli_like(
+                    &mut self.rng.borrow_mut(),
+                    &input[0],
+                    &mask_size,
+                    1. - self.p,
+                );
+                let (result, saved): (Tensor, Tensor) = ($forward)(&input[0], &mask, self.p);
+                self.mask.swap(&saved);
+                output[0].swap(&result);
+            }
+            fn grad(&self, _input: &[Tensor], output_grad: &[Tensor], input_grad: &[Tensor]) {
+                if self.mask.numel() == 0 {
+                    input_grad[0].swap(&output_grad[0].clone());
+                } else {
+                    input_grad[0].swap(&output_grad[0].mul(&self.mask));
+                }
+            }
+            fn set_training(&self, training: bool) {
+                self.training.set(training);
+            }
+            fn get_values(&self) -> Vec<Tensor> {
+                Vec::new()
+            }
+            fn get_grads(&self) -> Vec<Tensor> {
+                Vec::new()
+            }
+            fn set_values(&self, _v: &[Tensor]) {}
+            #[cfg(feature = "use-serde")]
+            fn as_any(&self) -> &dyn Any {
+                self
+            }
+        }
+    };
+}
+
+/// Scale the kept elements by 1/(1-p).
+fn scaled_mask(input: &Tensor, mask: &Tensor, p: f64) -> (Tensor, Tensor) {
+    let scale = if p < 1. { 1. / (1. - p) } else { 0. };
+    let saved = mask.mul(&scalar_like(mask, scale));
+    (input.mul(&saved), saved)
+}
+
+dropout!(
+    Dropout,
+    "Dropout",
+    (|size: &[usize]| size.to_vec()),
+    scaled_mask
+);
+
+// Drop whole (H, W) channels of a (N, C, H, W) or (C, H, W) input.
+dropout!(
+    Dropout2d,
+    "Dropout2d",
+    (|size: &[usize]| {
+        if size.len() != 3 && size.len() != 4 {
+            panic!(
+                "Dropout2d expects (N, C, H, W) or (C, H, W) input, get {:?}",
+                size
+            );
+        }
+        let mut ret = size[..size.len() - 2].to_vec();
+        ret.extend_from_slice(&[1, 1]);
+        ret
+    }),
+    scaled_mask
+);
+
+// Dropped elements are set to the negative saturation value of SELU,
+// then an affine transform keeps the mean and variance of the input.
+dropout!(
+    AlphaDropout,
+    "AlphaDropout",
+    (|size: &[usize]| size.to_vec()),
+    (|input: &Tensor, mask: &Tensor, p: f64| {
+        let alpha = -1.758_099_340_847_376_6;
+        let keep = 1. - p;
+        let (a, b) = if keep > 0. {
+            let a = (keep + alpha * alpha * keep * p).powf(-0.5);
+            (a, -a * alpha * p)
+        } else {
+            (0., 0.)
+        };
+        let saved = mask.mul(&scalar_like(mask, a));
+        let dropped = scalar_like(mask, 1.).sub(mask);
+        let offset = dropped
+            .mul(&scalar_like(mask, a * alpha))
+            .add(&scalar_like(mask, b));
+        (input.mul(&saved).add(&offset), saved)
+    })
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn apply(op: &dyn OpTrait, input: &Tensor) -> Tensor {
+        let output = Tensor::new();
+        op.apply(&[input.ref_copy()], &[output.ref_copy()]);
+        output
+    }
+
+    fn grad(op: &dyn OpTrait, input: &Tensor, output_grad: &Tensor) -> Tensor {
+        let input_grad = Tensor::new();
+        op.grad(
+            &[input.ref_copy()],
+            &[output_grad.ref_copy()],
+            &[input_grad.ref_copy()],
+        );
+        input_grad
+    }
+
+    #[test]
+    fn dropout() {
+        let op = Dropout::new(0.25, 3);
+        let input = Tensor::ones(&[20, 50]);
+        let output = apply(&op, &input);
+        let kept = output
+            .ne(&input.zeros_like())
+            .sum(None, false)
+            .get_scale_f64();
+        assert!((kept / 1000. - 0.75).abs() < 0.05);
+        // kept elements are scaled, so the mean is about the same.
+        assert!((output.mean(None, false).get_scale_f64() - 1.).abs() < 0.1);
+        assert_eq!(grad(&op, &input, &input), output);
+        // every forward pass draws a new mask.
+        assert!(apply(&op, &input) != output);
+
+        op.eval();
+        assert_eq!(apply(&op, &input), input);
+        assert_eq!(grad(&op, &input, &input), input);
+
+        let op = Dropout::new(1., 3);
+        assert_eq!(apply(&op, &input), input.zeros_like());
+    }
+
+    #[test]
+    fn dropout2d() {
+        let op = Dropout2d::new(0.5, 1);
+        let input = Tensor::ones(&[4, 8, 3, 3]);
+        let output = apply(&op, &input);
+        // a channel is either kept as a whole or dropped.
+        let per_channel = output.sum(Some(&[2, 3]), false);
+        let whole = per_channel
+            .eq_t(&per_channel.zeros_like())
+            .add(&per_channel.eq_t(&Tensor::fill_f64(&[4, 8], 18.)));
+        assert_eq!(whole, Tensor::ones(&[4, 8]));
+        assert_eq!(grad(&op, &input, &input), output);
+    }
+
+    #[test]
+    fn alpha_dropout() {
+        let mut rng = StdRng::seed_from_u64(5);
+        let input = Tensor::normal_f64(&mut rng, &[100, 100], 0., 1.);
+        let op = AlphaDropout::new(0.2, 7);
+        let output = apply(&op, &input);
+        // keeps zero mean and unit variance.
+        assert!(output.mean(None, false).get_scale_f64().abs() < 0.05);
+        assert!((output.std(None, false).get_scale_f64() - 1.).abs() < 0.05);
+
+        let op = AlphaDropout::new(0., 7);
+        assert_eq!(apply(&op, &input), input);
+    }
+
+    #[test]
+    fn train_eval_graph() {
+        let mut dropout = Dropout::new(0.5, 2);
+        let mut batch_norm = crate::op::BatchNorm1d::new(4, 1e-5, 0.1, true);
+        let input = Var::ones(&[3, 4]);
+        let x = dropout.call(&[&input]).unwrap().pop().unwrap();
+        let output = batch_norm
+            .call(&[&x])
+            .unwrap()
+            .pop()
+            .unwrap()
+            .sum(None, false)
+            .unwrap();
+
+        output.set_training(false);
+        assert!(!dropout.is_training() && !batch_norm.is_training());
+        output.rerun().unwrap();
+        assert_eq!(x.val(), input.val());
+        let first = output.val();
+        output.rerun().unwrap();
+        assert_eq!(output.val(), first);
+
+        output.set_training(true);
+        assert!(dropout.is_training() && batch_norm.is_training());
+    }
+}
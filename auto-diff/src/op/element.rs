@@ -318,7 +318,7 @@ mod tests {
     fn test_range_data(op: &mut dyn OpTrait) {
         for i in 0..10 {
             let zero = Tensor::from_vec_f64(&vec![(i as f64 / 10.0 - 0.51)], &vec![1]);
-            let good_grad = _gradient_checker(op, &[zero], None, None, None);
+            let good_grad = _gradient_checker(op, &[zero], None, None, None, None);
             assert_eq!(good_grad, true);
         }
     }
@@ -394,7 +394,7 @@ mod tests {
         let mut op = Log::new();
         for i in 0..10 {
             let zero = Tensor::from_vec_f64(&vec![(i as f64 / 10.0 + 0.51)], &vec![1]);
-            let good_grad = _gradient_checker(&mut op, &[zero], None, None, None);
+            let good_grad = _gradient_checker(&mut op, &[zero], None, None, None, None);
             assert_eq!(good_grad, true);
         }
     }
@@ -404,7 +404,7 @@ mod tests {
         let mut op = Log10::new();
         for i in 0..10 {
             let zero = Tensor::from_vec_f64(&vec![(i as f64 / 10.0 + 0.51)], &vec![1]);
-            let good_grad = _gradient_checker(&mut op, &[zero], None, None, None);
+            let good_grad = _gradient_checker(&mut op, &[zero], None, None, None, None);
             assert_eq!(good_grad, true);
         }
     }
@@ -414,7 +414,7 @@ mod tests {
         let mut op = Log1p::new();
         for i in 0..10 {
             let zero = Tensor::from_vec_f64(&vec![(i as f64 / 10.0 - 0.51)], &vec![1]);
-            let good_grad = _gradient_checker(&mut op, &[zero], None, None, None);
+            let good_grad = _gradient_checker(&mut op, &[zero], None, None, None, None);
             assert_eq!(good_grad, true);
         }
     }
@@ -424,7 +424,7 @@ mod tests {
         let mut op = Log1pexp::new();
         for i in 0..10 {
             let zero = Tensor::from_vec_f64(&vec![(i as f64 / 10.0 - 0.51)], &vec![1]);
-            let good_grad = _gradient_checker(&mut op, &[zero], None, None, None);
+            let good_grad = _gradient_checker(&mut op, &[zero], None, None, None, None);
             assert_eq!(good_grad, true);
         }
     }
@@ -434,7 +434,7 @@ mod tests {
         let mut op = Log2::new();
         for i in 0..10 {
             let zero = Tensor::from_vec_f64(&vec![(i as f64 / 10.0 + 0.51)], &vec![1]);
-            let good_grad = _gradient_checker(&mut op, &[zero], None, None, None);
+            let good_grad = _gradient_checker(&mut op, &[zero], None, None, None, None);
             assert_eq!(good_grad, true);
         }
     }
@@ -450,7 +450,7 @@ mod tests {
         let mut op = Reciprocal::new();
         for i in 0..10 {
             let zero = Tensor::from_vec_f64(&vec![(i as f64 / 10.0 + 0.51)], &vec![1]);
-            let good_grad = _gradient_checker(&mut op, &[zero], None, None, None);
+            let good_grad = _gradient_checker(&mut op, &[zero], None, None, None, None);
             assert_eq!(good_grad, true);
         }
     }
@@ -466,7 +466,7 @@ mod tests {
         let mut op = Rsqrt::new();
         for i in 0..10 {
             let zero = Tensor::from_vec_f64(&vec![(i as f64 / 10.0 + 0.51)], &vec![1]);
-            let good_grad = _gradient_checker(&mut op, &[zero], None, None, None);
+            let good_grad = _gradient_checker(&mut op, &[zero], None, None, None, None);
             assert_eq!(good_grad, true);
         }
     }
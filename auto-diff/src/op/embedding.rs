@@ -0,0 +1,303 @@
+use super::{Op, OpCall, OpHandle, OpTrait};
+use tensor_rs::tensor::Tensor;
+
+use std::cell::RefCell;
+use std::collections::BTreeSet;
+use std::rc::Rc;
+
+use crate::err::AutoDiffError;
+use crate::var::Var;
+
+#[cfg(feature = "use-serde")]
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "use-serde")]
+use std::any::Any;
+
+// Embedding
+// EmbeddingBag
+
+/// Look up rows of a [num_embeddings, dim] weight.
+/// The input holds the row ids, with the same element type as the weight,
+/// the output has the input size plus a trailing dim.
+///
+/// The weight gradient only has the rows in the last input,
+/// [get_grad_rows](OpTrait::get_grad_rows) lists them for the optimizer.
+/// The padding_idx row never gets gradient.
+/// With max_norm, rows looked up with a larger norm are rescaled in place.
+#[cfg_attr(feature = "use-serde", derive(Serialize, Deserialize))]
+pub struct Embedding {
+    num_embeddings: usize,
+    dim: usize,
+    padding_idx: Option<usize>,
+    max_norm: Option<f64>,
+    weight: Tensor,
+    weight_grad: Tensor,
+    #[cfg_attr(feature = "use-serde", serde(skip))]
+    grad_rows: Rc<RefCell<Vec<usize>>>,
+    #[cfg_attr(feature = "use-serde", serde(skip))]
+    handle: OpHandle,
+}
+impl Embedding {
+    pub fn new(
+        num_embeddings: usize,
+        dim: usize,
+        padding_idx: Option<usize>,
+        max_norm: Option<f64>,
+    ) -> Embedding {
+        if let Some(idx) = padding_idx {
+            if idx >= num_embeddings {
+                panic!(
+                    "Embedding expects padding_idx within num_embeddings, get {:?}, {:?}",
+                    idx, num_embeddings
+                );
+            }
+        }
+        Embedding {
+            num_embeddings,
+            dim,
+            padding_idx,
+            max_norm,
+            weight: Tensor::zeros(&[num_embeddings, dim]),
+            weight_grad: Tensor::new(),
+            grad_rows: Rc::new(RefCell::new(Vec::new())),
+            handle: OpHandle::new(),
+        }
+    }
+
+    pub fn weight(&self) -> &Tensor {
+        &self.weight
+    }
+
+    pub fn set_weight(&self, var: Var) {
+        self.weight.swap(&var.val());
+    }
+
+    /// Check the ids and return them in order.
+    fn ids(&self, input: &Tensor) -> Vec<usize> {
+        let flat = input.reshape(&[input.numel()]);
+        (0..input.numel())
+            .map(|i| {
+                let v = flat.get_f64(&[i]);
+                if v < 0. || v.fract() != 0. || v as usize >= self.num_embeddings {
+                    panic!(
+                        "Embedding expects ids in 0..{:?}, get {:?}",
+                        self.num_embeddings, v
+                    );
+                }
+                v as usize
+            })
+            .collect()
+    }
+
+    fn renorm(&self, ids: &[usize], max_norm: f64) {
+        let mut weight = self.weight.ref_copy();
+        for id in ids.iter().collect::<BTreeSet<_>>() {
+            let norm = (0..self.dim)
+                .map(|j| weight.get_f64(&[*id, j]).powi(2))
+                .sum::<f64>()
+                .sqrt();
+            if norm > max_norm {
+                let scale = max_norm / (norm + 1e-7);
+                for j in 0..self.dim {
+                    let v = weight.get_f64(&[*id, j]);
+                    weight.set_f64(&[*id, j], v * scale);
+                }
+            }
+        }
+    }
+
+    handle_method!();
+}
+
+impl OpCall for Embedding {
+    fn call(&mut self, inputs: &[&Var]) -> Result<Vec<Var>, AutoDiffError> {
+        let new_one = Embedding {
+            num_embeddings: self.num_embeddings,
+            dim: self.dim,
+            padding_idx: self.padding_idx,
+            max_norm: self.max_norm,
+            weight: self.weight.ref_copy(),
+            weight_grad: self.weight_grad.ref_copy(),
+            grad_rows: self.grad_rows.clone(),
+            handle: OpHandle::new(),
+        };
+
+        let op = Op::new(Rc::new(RefCell::new(Box::new(new_one))));
+
+        inputs[0].called_with(op, &inputs[1..inputs.len()])
+    }
+}
+
+impl OpTrait for Embedding {
+    fn get_name(&self) -> &'static str {
+        "Embedding"
+    }
+    fn get_input_size(&self) -> usize {
+        1
+    }
+    fn get_output_size(&self) -> usize {
+        1
+    }
+    fn apply(&self, input: &[Tensor], output: &[Tensor]) {
+        if self.weight.size() != [self.num_embeddings, self.dim] {
+            panic!(
+                "Embedding expects weight of size {:?}, get {:?}",
+                [self.num_embeddings, self.dim],
+                self.weight.size()
+            );
+        }
+        let ids = self.ids(&input[0]);
+        if let Some(max_norm) = self.max_norm {
+            self.renorm(&ids, max_norm);
+        }
+        let mut output_size = input[0].size();
+        output_size.push(self.dim);
+        let rows = self
+            .weight
+            .index_select(0, &input[0].reshape(&[input[0].numel()]));
+        output[0].swap(&rows.reshape(&output_size));
+    }
+    fn grad(&self, input: &[Tensor], output_grad: &[Tensor], input_grad: &[Tensor]) {
+        let ids = self.ids(&input[0]);
+        let mut weight_grad = self.weight_grad.ref_copy();
+        if weight_grad.size() != self.weight.size() {
+            weight_grad.swap(&self.weight.zeros_like());
+            self.grad_rows.borrow_mut().clear();
+        }
+        // only clear the rows from the last pass.
+        for id in self.grad_rows.borrow().iter() {
+            for j in 0..self.dim {
+                weight_grad.set_f64(&[*id, j], 0.);
+            }
+        }
+
+        let grad = output_grad[0].reshape(&[ids.len(), self.dim]);
+        let mut rows = BTreeSet::new();
+        for (i, id) in ids.iter().enumerate() {
+            if Some(*id) == self.padding_idx {
+                continue;
+            }
+            rows.insert(*id);
+            for j in 0..self.dim {
+                let v = weight_grad.get_f64(&[*id, j]) + grad.get_f64(&[i, j]);
+                weight_grad.set_f64(&[*id, j], v);
+            }
+        }
+        *self.grad_rows.borrow_mut() = rows.into_iter().collect();
+
+        // ids are not differentiable.
+        input_grad[0].swap(&input[0].zeros_like());
+    }
+    fn get_values(&self) -> Vec<Tensor> {
+        vec![self.weight.clone()]
+    }
+    fn set_values(&self, v: &[Tensor]) {
+        self.weight.swap(&v[0].clone());
+    }
+    fn get_grads(&self) -> Vec<Tensor> {
+        vec![self.weight_grad.ref_copy()]
+    }
+    fn set_grads(&self, v: &[Tensor]) {
+        self.weight_grad.swap(&v[0].clone());
+    }
+    fn get_grad_rows(&self) -> Option<Vec<usize>> {
+        Some(self.grad_rows.borrow().clone())
+    }
+    fn step_grad_rows(&self, lr: f64) {
+        let mut weight = self.weight.ref_copy();
+        for id in self.grad_rows.borrow().iter() {
+            for j in 0..self.dim {
+                let v = weight.get_f64(&[*id, j]) - lr * self.weight_grad.get_f64(&[*id, j]);
+                weight.set_f64(&[*id, j], v);
+            }
+        }
+    }
+    #[cfg(feature = "use-serde")]
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::optim::{Optimizer, SGD};
+
+    #[test]
+    fn embedding() {
+        let op = Embedding::new(5, 2, Some(0), None);
+        op.set_weight(Var::new(&[0., 0., 1., 2., 3., 4., 5., 6., 7., 8.], &[5, 2]));
+        let input = Tensor::from_vec_f64(&[1., 3., 0., 3.], &[2, 2]);
+        let output = Tensor::new();
+        op.apply(&[input.ref_copy()], &[output.ref_copy()]);
+        assert_eq!(
+            output,
+            Tensor::from_vec_f64(&[1., 2., 5., 6., 0., 0., 5., 6.], &[2, 2, 2])
+        );
+
+        let input_grad = Tensor::new();
+        op.grad(
+            &[input.ref_copy()],
+            &[Tensor::ones(&[2, 2, 2])],
+            &[input_grad.ref_copy()],
+        );
+        // row 3 is looked up twice, the padding row has no gradient.
+        assert_eq!(
+            op.get_grads()[0],
+            Tensor::from_vec_f64(&[0., 0., 1., 1., 0., 0., 2., 2., 0., 0.], &[5, 2])
+        );
+        assert_eq!(op.get_grad_rows(), Some(vec![1, 3]));
+
+        // rows from the last pass are cleared.
+        op.grad(
+            &[Tensor::from_vec_f64(&[2.], &[1])],
+            &[Tensor::ones(&[1, 2])],
+            &[input_grad.ref_copy()],
+        );
+        assert_eq!(
+            op.get_grads()[0],
+            Tensor::from_vec_f64(&[0., 0., 0., 0., 1., 1., 0., 0., 0., 0.], &[5, 2])
+        );
+        assert_eq!(op.get_grad_rows(), Some(vec![2]));
+    }
+
+    #[test]
+    fn embedding_max_norm() {
+        let op = Embedding::new(3, 2, None, Some(1.));
+        op.set_weight(Var::new(&[3., 4., 0.3, 0.4, 6., 8.], &[3, 2]));
+        let output = Tensor::new();
+        op.apply(
+            &[Tensor::from_vec_f64(&[0., 1.], &[2])],
+            &[output.ref_copy()],
+        );
+        // row 2 is not looked up, so not rescaled.
+        let expected = Tensor::from_vec_f64(&[0.6, 0.8, 0.3, 0.4, 6., 8.], &[3, 2]);
+        assert!(
+            op.weight()
+                .sub(&expected)
+                .abs()
+                .max(None, false)
+                .get_scale_f64()
+                < 1e-6
+        );
+    }
+
+    #[test]
+    fn embedding_sgd() {
+        let mut op = Embedding::new(4, 3, None, None);
+        op.set_weight(Var::ones(&[4, 3]));
+        let input = Var::new(&[2., 0., 2.], &[3]);
+        let output = op.call(&[&input]).unwrap().pop().unwrap();
+        let loss = output.sum(None, false).unwrap();
+        loss.bp().unwrap();
+        let mut opt = SGD::new(0.5);
+        opt.step(loss.dump_net());
+        assert_eq!(
+            *op.weight(),
+            Tensor::from_vec_f64(
+                &[0.5, 0.5, 0.5, 1., 1., 1., 0., 0., 0., 1., 1., 1.],
+                &[4, 3]
+            )
+        );
+    }
+}
@@ -16,6 +16,59 @@ use serde::{Deserialize, Serialize};
 #[cfg(feature = "use-serde")]
 use std::any::Any;
 
+/// Scatter value into a zero tensor shaped like template, at index along dim.
+/// Positions hit more than once accumulate, which spread alone does not do,
+/// so spread is applied one slice of dim at a time.
+fn spread_add(template: &Tensor, dim: usize, index: &Tensor, value: &Tensor) -> Tensor {
+    let mut ret = template.zeros_like();
+    let value = value.reshape(&index.size());
+    let mut range: Vec<(usize, usize)> = index.size().iter().map(|x| (0, *x)).collect();
+    for i in 0..index.size()[dim] {
+        range[dim] = (i, i + 1);
+        let slice = template
+            .zeros_like()
+            .spread(dim, &index.get_patch(&range, None), &value.get_patch(&range, None));
+        ret = ret.add(&slice);
+    }
+    ret
+}
+
+/// Expand a 1-D index along dim, so that index_select
+/// can be treated as a gather with the result of this function.
+fn select_to_gather_index(index: &Tensor, dim: usize, size: &[usize]) -> Tensor {
+    let mut view = vec![1; size.len()];
+    view[dim] = index.numel();
+    let mut times = size.to_vec();
+    times[dim] = 1;
+    index.reshape(&view).repeat(&times)
+}
+
+/// Build a 1-D index tensor with the same element type as like.
+fn index_like(like: &Tensor, index: &[usize]) -> Tensor {
+    let mut ret = like.zeros_like().sum(None, false).repeat(&[index.len()]);
+    for (i, v) in index.iter().enumerate() {
+        ret.set_f64(&[i], *v as f64);
+    }
+    ret
+}
+
+/// Gradient of the multi-output ops, outputs that are not used
+/// downstream come back with an empty gradient, and are filled with zeros.
+fn cat_output_grad(pieces: &[Tensor], output_grad: &[Tensor], dim: usize) -> Tensor {
+    let grads: Vec<Tensor> = pieces
+        .iter()
+        .zip(output_grad.iter())
+        .map(|(p, g)| {
+            if g.size().is_empty() {
+                p.zeros_like()
+            } else {
+                g.reshape(&p.size())
+            }
+        })
+        .collect();
+    grads[0].cat(&grads[1..], dim)
+}
+
 #[cfg_attr(feature = "use-serde", derive(Serialize, Deserialize))]
 pub struct Cat {
     #[cfg_attr(feature = "use-serde", serde(skip))]
@@ -71,8 +124,8 @@ impl OpTrait for Cat {
             splits.push(i.size()[self.dim]);
         }
         let result = output_grad[0].split(&splits, self.dim);
-        for i in result {
-            input_grad[0].swap(&i);
+        for (i, g) in input_grad.iter().zip(result.iter()) {
+            i.swap(g);
         }
     }
     fn get_values(&self) -> Vec<Tensor> {
@@ -92,10 +145,11 @@ one_to_vec_op_with_paras!(
     Chunk,
     "Chunk",
     1,
-    1, // TODO, this is dependent on the number of output.
+    (|op: &Chunk| op.chunks),
     chunk,
-    (|input: &[Tensor], output_grad: &[Tensor], input_grad: &[Tensor]| {
-        unimplemented!();
+    (|input: &[Tensor], output_grad: &[Tensor], input_grad: &[Tensor], chunks: usize, dim: usize| {
+        let pieces = input[0].chunk(chunks, dim);
+        input_grad[0].swap(&cat_output_grad(&pieces, output_grad, dim));
     }),
     chunks: usize,
     dim: usize
@@ -139,7 +193,7 @@ impl OpTrait for Gather {
         "Gather"
     }
     fn get_input_size(&self) -> usize {
-        1
+        2
     }
     fn get_output_size(&self) -> usize {
         1
@@ -148,7 +202,8 @@ impl OpTrait for Gather {
         output[0].swap(&input[0].gather(self.dim, &input[1]));
     }
     fn grad(&self, input: &[Tensor], output_grad: &[Tensor], input_grad: &[Tensor]) {
-        unimplemented!();
+        input_grad[0].swap(&spread_add(&input[0], self.dim, &input[1], &output_grad[0]));
+        input_grad[1].swap(&input[1].zeros_like());
     }
     fn get_values(&self) -> Vec<Tensor> {
         Vec::new()
@@ -201,7 +256,7 @@ impl OpTrait for IndexSelect {
         "Index_select"
     }
     fn get_input_size(&self) -> usize {
-        1
+        2
     }
     fn get_output_size(&self) -> usize {
         1
@@ -210,7 +265,9 @@ impl OpTrait for IndexSelect {
         output[0].swap(&input[0].index_select(self.dim, &input[1]));
     }
     fn grad(&self, input: &[Tensor], output_grad: &[Tensor], input_grad: &[Tensor]) {
-        unimplemented!();
+        let index = select_to_gather_index(&input[1], self.dim, &output_grad[0].size());
+        input_grad[0].swap(&spread_add(&input[0], self.dim, &index, &output_grad[0]));
+        input_grad[1].swap(&input[1].zeros_like());
     }
     fn get_values(&self) -> Vec<Tensor> {
         Vec::new()
@@ -263,7 +320,7 @@ impl OpTrait for IndexExclude {
         "Index_exclude"
     }
     fn get_input_size(&self) -> usize {
-        1
+        2
     }
     fn get_output_size(&self) -> usize {
         1
@@ -272,7 +329,16 @@ impl OpTrait for IndexExclude {
         output[0].swap(&input[0].index_exclude(self.dim, &input[1]));
     }
     fn grad(&self, input: &[Tensor], output_grad: &[Tensor], input_grad: &[Tensor]) {
-        unimplemented!();
+        let excluded: Vec<usize> = (0..input[1].numel())
+            .map(|i| input[1].get_f64(&[i]) as usize)
+            .collect();
+        let kept: Vec<usize> = (0..input[0].size()[self.dim])
+            .filter(|x| !excluded.contains(x))
+            .collect();
+        let index = index_like(&input[1], &kept);
+        let index = select_to_gather_index(&index, self.dim, &output_grad[0].size());
+        input_grad[0].swap(&spread_add(&input[0], self.dim, &index, &output_grad[0]));
+        input_grad[1].swap(&input[1].zeros_like());
     }
     fn get_values(&self) -> Vec<Tensor> {
         Vec::new()
@@ -334,7 +400,7 @@ impl OpTrait for Reshape {
         output[0].swap(&input[0].reshape(&self.new_shape));
     }
     fn grad(&self, input: &[Tensor], output_grad: &[Tensor], input_grad: &[Tensor]) {
-        unimplemented!();
+        input_grad[0].swap(&output_grad[0].reshape(&input[0].size()));
     }
     fn get_values(&self) -> Vec<Tensor> {
         Vec::new()
@@ -402,7 +468,8 @@ impl OpTrait for Split {
         }
     }
     fn grad(&self, input: &[Tensor], output_grad: &[Tensor], input_grad: &[Tensor]) {
-        unimplemented!();
+        let pieces = input[0].split(&self.sections, self.dim);
+        input_grad[0].swap(&cat_output_grad(&pieces, output_grad, self.dim));
     }
     fn get_values(&self) -> Vec<Tensor> {
         Vec::new()
@@ -425,7 +492,7 @@ one_to_1_op_with_paras!(
     1,
     squeeze,
     (|input: &[Tensor], output_grad: &[Tensor], input_grad: &[Tensor]| {
-        unimplemented!();
+        input_grad[0].swap(&output_grad[0].reshape(&input[0].size()));
     }),
     dim: Option<usize>
 );
@@ -437,8 +504,12 @@ many_to_1_op_with_paras!(
     2, // TODO, this is dependent on the number of input.
     1,
     stack,
-    (|input: &[Tensor], output_grad: &[Tensor], input_grad: &[Tensor]| {
-        unimplemented!();
+    (|input: &[Tensor], output_grad: &[Tensor], input_grad: &[Tensor], dim: usize| {
+        let sections = vec![1; input.len()];
+        let grads = output_grad[0].split(&sections, dim);
+        for ((i, g), x) in input_grad.iter().zip(grads.iter()).zip(input.iter()) {
+            i.swap(&g.reshape(&x.size()));
+        }
     }),
     dim: usize
 );
@@ -448,7 +519,7 @@ new_element_op!(
     "T",
     t,
     (|input: &[Tensor], output_grad: &[Tensor], input_grad: &[Tensor]| {
-        unimplemented!();
+        input_grad[0].swap(&output_grad[0].t());
     })
 );
 
@@ -499,7 +570,10 @@ impl OpTrait for Take {
         output[0].swap(&input[0].take(&self.sizes))
     }
     fn grad(&self, input: &[Tensor], output_grad: &[Tensor], input_grad: &[Tensor]) {
-        unimplemented!();
+        let flat = input[0].reshape(&[input[0].numel()]);
+        let index = index_like(&flat, &self.sizes);
+        let ret = spread_add(&flat, 0, &index, &output_grad[0].reshape(&[self.sizes.len()]));
+        input_grad[0].swap(&ret.reshape(&input[0].size()));
     }
     fn get_values(&self) -> Vec<Tensor> {
         Vec::new()
@@ -561,7 +635,11 @@ impl OpTrait for Permute {
         output[0].swap(&input[0].permute(&self.sizes))
     }
     fn grad(&self, input: &[Tensor], output_grad: &[Tensor], input_grad: &[Tensor]) {
-        unimplemented!();
+        let mut reverse = vec![0; self.sizes.len()];
+        for (i, j) in self.sizes.iter().enumerate() {
+            reverse[*j] = i;
+        }
+        input_grad[0].swap(&output_grad[0].permute(&reverse));
     }
     fn get_values(&self) -> Vec<Tensor> {
         Vec::new()
@@ -584,7 +662,7 @@ one_to_1_op_with_paras!(
     1,
     unsqueeze,
     (|input: &[Tensor], output_grad: &[Tensor], input_grad: &[Tensor]| {
-        unimplemented!();
+        input_grad[0].swap(&output_grad[0].reshape(&input[0].size()));
     }),
     dim: usize
 );
@@ -629,11 +707,16 @@ impl OpTrait for ConditionalSelect {
     fn get_output_size(&self) -> usize {
         1
     }
+    /// The first input is the condition, the element comes from
+    /// the second input where it is non-negative, otherwise from the third.
     fn apply(&self, input: &[Tensor], output: &[Tensor]) {
-        output[0].swap(&input[0].conditional_select(&input[0], &input[1]));
+        output[0].swap(&input[0].conditional_select(&input[1], &input[2]));
     }
     fn grad(&self, input: &[Tensor], output_grad: &[Tensor], input_grad: &[Tensor]) {
-        unimplemented!();
+        let zeros = output_grad[0].zeros_like();
+        input_grad[0].swap(&input[0].zeros_like());
+        input_grad[1].swap(&input[0].conditional_select(&output_grad[0], &zeros));
+        input_grad[2].swap(&input[0].conditional_select(&zeros, &output_grad[0]));
     }
     fn get_values(&self) -> Vec<Tensor> {
         Vec::new()
@@ -700,7 +783,19 @@ impl OpTrait for Repeat {
         output[0].swap(&input[0].repeat(&self.sizes))
     }
     fn grad(&self, input: &[Tensor], output_grad: &[Tensor], input_grad: &[Tensor]) {
-        unimplemented!();
+        // view each dim as [repeat, size], then sum over the repeats.
+        let mut tiled = Vec::new();
+        let mut tile_dim = Vec::new();
+        for (i, (r, s)) in self.sizes.iter().zip(input[0].size().iter()).enumerate() {
+            tiled.push(*r);
+            tiled.push(*s);
+            tile_dim.push(2 * i);
+        }
+        let ret = output_grad[0]
+            .reshape(&tiled)
+            .sum(Some(&tile_dim), false)
+            .reshape(&input[0].size());
+        input_grad[0].swap(&ret);
     }
     fn get_values(&self) -> Vec<Tensor> {
         Vec::new()
@@ -714,3 +809,133 @@ impl OpTrait for Repeat {
         self
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::op::_gradient_checker;
+
+    fn input_grad_of(op: &dyn OpTrait, input: &[Tensor], output_grad: &[Tensor]) -> Vec<Tensor> {
+        let input_grad: Vec<Tensor> = input.iter().map(|_| Tensor::new()).collect();
+        op.grad(input, output_grad, &input_grad);
+        input_grad
+    }
+
+    #[test]
+    fn cat() {
+        let a = Tensor::from_vec_f64(&[1., 2., 3., 4.], &[2, 2]);
+        let b = Tensor::from_vec_f64(&[5., 6.], &[2, 1]);
+        let g = Tensor::from_vec_f64(&[1., 2., 3., 4., 5., 6.], &[2, 3]);
+        let grad = input_grad_of(&Cat::new(1), &[a, b], &[g]);
+        assert_eq!(grad[0], Tensor::from_vec_f64(&[1., 2., 4., 5.], &[2, 2]));
+        assert_eq!(grad[1], Tensor::from_vec_f64(&[3., 6.], &[2, 1]));
+    }
+
+    #[test]
+    fn split_chunk() {
+        let a = Tensor::from_vec_f64(&[1., 2., 3., 4., 5., 6.], &[2, 3]);
+        let g = [
+            Tensor::from_vec_f64(&[1., 2.], &[2, 1]),
+            Tensor::from_vec_f64(&[3., 4., 5., 6.], &[2, 2]),
+        ];
+        let grad = input_grad_of(&Split::new(&[1, 2], 1), &[a.ref_copy()], &g);
+        assert_eq!(grad[0], Tensor::from_vec_f64(&[1., 3., 4., 2., 5., 6.], &[2, 3]));
+
+        // the unused output has no gradient.
+        let g = [Tensor::from_vec_f64(&[1., 2., 3.], &[1, 3]), Tensor::new()];
+        let grad = input_grad_of(&Chunk::new(2, 0), &[a], &g);
+        assert_eq!(grad[0], Tensor::from_vec_f64(&[1., 2., 3., 0., 0., 0.], &[2, 3]));
+    }
+
+    #[test]
+    fn gather() {
+        let a = Tensor::from_vec_f64(&[1., 2., 3., 4.], &[2, 2]);
+        let index = Tensor::from_vec_f64(&[0., 0., 1., 0.], &[2, 2]);
+        let g = Tensor::from_vec_f64(&[1., 2., 3., 4.], &[2, 2]);
+        let grad = input_grad_of(&Gather::new(1), &[a.ref_copy(), index], &[g]);
+        assert_eq!(grad[0], Tensor::from_vec_f64(&[3., 0., 4., 3.], &[2, 2]));
+
+        let mut op = Gather::new(0);
+        let index = Tensor::from_vec_f64(&[1.], &[1, 1]);
+        let a = Tensor::from_vec_f64(&[1., 2.], &[2, 1]);
+        let good_grad = _gradient_checker(&mut op, &[a, index], Some(&[true, false]), None, None, None);
+        assert!(good_grad);
+    }
+
+    #[test]
+    fn index_select_exclude() {
+        let a = Tensor::from_vec_f64(&[1., 2., 3., 4., 5., 6.], &[3, 2]);
+        let g = Tensor::from_vec_f64(&[1., 2., 3., 4., 5., 6.], &[3, 2]);
+        let index = Tensor::from_vec_f64(&[2., 0., 2.], &[3]);
+        let grad = input_grad_of(&IndexSelect::new(0), &[a.ref_copy(), index], &[g]);
+        assert_eq!(grad[0], Tensor::from_vec_f64(&[3., 4., 0., 0., 6., 8.], &[3, 2]));
+
+        let g = Tensor::from_vec_f64(&[1., 2., 3., 4.], &[2, 2]);
+        let index = Tensor::from_vec_f64(&[1.], &[1]);
+        let grad = input_grad_of(&IndexExclude::new(0), &[a, index], &[g]);
+        assert_eq!(grad[0], Tensor::from_vec_f64(&[1., 2., 0., 0., 3., 4.], &[3, 2]));
+    }
+
+    #[test]
+    fn take() {
+        let a = Tensor::from_vec_f64(&[1., 2., 3., 4.], &[2, 2]);
+        let g = Tensor::from_vec_f64(&[1., 2., 3.], &[3]);
+        let grad = input_grad_of(&Take::new(&[3, 0, 3]), &[a.ref_copy()], &[g]);
+        assert_eq!(grad[0], Tensor::from_vec_f64(&[2., 0., 0., 4.], &[2, 2]));
+
+        let mut op = Take::new(&[2]);
+        let good_grad = _gradient_checker(&mut op, &[a], None, None, None, None);
+        assert!(good_grad);
+    }
+
+    #[test]
+    fn squeeze_unsqueeze_t() {
+        let a = Tensor::from_vec_f64(&[1., 2., 3.], &[1, 3, 1]);
+        let grad = input_grad_of(&Squeeze::new(None), &[a.ref_copy()], &[a.reshape(&[3])]);
+        assert_eq!(grad[0], a);
+        let grad = input_grad_of(&Unsqueeze::new(0), &[a.ref_copy()], &[a.reshape(&[1, 1, 3, 1])]);
+        assert_eq!(grad[0], a);
+
+        let b = Tensor::from_vec_f64(&[1., 2., 3., 4., 5., 6.], &[2, 3]);
+        let grad = input_grad_of(&T::new(), &[b.ref_copy()], &[b.t()]);
+        assert_eq!(grad[0], b);
+    }
+
+    #[test]
+    fn stack() {
+        let a = Tensor::from_vec_f64(&[1., 2., 3.], &[3]);
+        let b = Tensor::from_vec_f64(&[4., 5., 6.], &[3]);
+        let g = Tensor::from_vec_f64(&[1., 2., 3., 4., 5., 6.], &[3, 2]);
+        let grad = input_grad_of(&Stack::new(1), &[a, b], &[g]);
+        assert_eq!(grad[0], Tensor::from_vec_f64(&[1., 3., 5.], &[3]));
+        assert_eq!(grad[1], Tensor::from_vec_f64(&[2., 4., 6.], &[3]));
+    }
+
+    #[test]
+    fn conditional_select() {
+        let mut op = ConditionalSelect::new();
+        for cond in [-1., 1.] {
+            let c = Tensor::from_vec_f64(&[cond], &[1]);
+            let x = Tensor::from_vec_f64(&[2.], &[1]);
+            let y = Tensor::from_vec_f64(&[-3.], &[1]);
+            let good_grad = _gradient_checker(&mut op, &[c, x, y], Some(&[false, true, true]), None, None, None);
+            assert!(good_grad);
+        }
+    }
+
+    #[test]
+    fn permute_reshape_repeat() {
+        let a = Tensor::from_vec_f64(&[1., 2., 3., 4., 5., 6.], &[1, 2, 3]);
+        let g = a.permute(&[2, 0, 1]);
+        let grad = input_grad_of(&Permute::new(&[2, 0, 1]), &[a.ref_copy()], &[g]);
+        assert_eq!(grad[0], a);
+
+        let g = a.reshape(&[3, 2]);
+        let grad = input_grad_of(&Reshape::new(&[3, 2]), &[a.ref_copy()], &[g]);
+        assert_eq!(grad[0], a);
+
+        let g = Tensor::ones(&[2, 2, 6]);
+        let grad = input_grad_of(&Repeat::new(&[2, 1, 2]), &[a.ref_copy()], &[g]);
+        assert_eq!(grad[0], Tensor::fill_f64(&[1, 2, 3], 4.));
+    }
+}
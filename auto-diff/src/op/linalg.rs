@@ -1,4 +1,4 @@
-use super::{OpHandle, OpTrait};
+use super::{grad_or_zeros, scalar_like, OpHandle, OpTrait};
 use tensor_rs::tensor::Tensor;
 
 #[cfg(feature = "use-serde")]
@@ -6,6 +6,34 @@ use serde::{Deserialize, Serialize};
 #[cfg(feature = "use-serde")]
 use std::any::Any;
 
+/// A square mask like the given matrix,
+/// with `lower` below the diagonal, `diag` on it and zero above.
+fn triangle(like: &Tensor, lower: f64, diag: f64) -> Tensor {
+    let mut ret = like.zeros_like();
+    let n = like.size()[0];
+    for i in 0..n {
+        for j in 0..i {
+            ret.set_f64(&[i, j], lower);
+        }
+        ret.set_f64(&[i, i], diag);
+    }
+    ret
+}
+
+/// Solve ax = b column by column, b can be a vector or a matrix.
+fn solve(a: &Tensor, b: &Tensor) -> Tensor {
+    let n = b.size()[0];
+    let cols = if b.size().len() == 1 { 1 } else { b.size()[1] };
+    let b2 = b.reshape(&[n, cols]);
+    let x: Vec<Tensor> = (0..cols)
+        .map(|i| {
+            a.lu_solve(&b2.get_patch(&[(0, n), (i, i + 1)], None))
+                .expect("lu_solve() does not get a result.")
+        })
+        .collect();
+    x[0].cat(&x[1..], 1).reshape(&b.size())
+}
+
 #[cfg_attr(feature = "use-serde", derive(Serialize, Deserialize))]
 pub struct NormalizeUnit {
     #[cfg_attr(feature = "use-serde", serde(skip))]
@@ -38,7 +66,11 @@ impl OpTrait for NormalizeUnit {
         output[0].swap(&input[0].normalize_unit());
     }
     fn grad(&self, input: &[Tensor], output_grad: &[Tensor], input_grad: &[Tensor]) {
-        unimplemented!();
+        // y = x/|x|, dx = (dy - y<dy, y>)/|x|.
+        let norm = input[0].mul(&input[0]).sum(None, false).sqrt();
+        let y = input[0].div(&norm);
+        let proj = output_grad[0].mul(&y).sum(None, false);
+        input_grad[0].swap(&output_grad[0].sub(&y.mul(&proj)).div(&norm));
     }
     fn get_values(&self) -> Vec<Tensor> {
         Vec::new()
@@ -90,7 +122,10 @@ impl OpTrait for Det {
         output[0].swap(&input[0].det().expect("det() does not get a result."));
     }
     fn grad(&self, input: &[Tensor], output_grad: &[Tensor], input_grad: &[Tensor]) {
-        unimplemented!();
+        // d(det(A))/dA = det(A)A^-T.
+        let det = input[0].det().expect("det() does not get a result.");
+        let inv = input[0].inv().expect("inv() does not get a result.");
+        input_grad[0].swap(&inv.t().mul(&det).mul(&output_grad[0]));
     }
     fn get_values(&self) -> Vec<Tensor> {
         Vec::new()
@@ -142,7 +177,9 @@ impl OpTrait for Inv {
         output[0].swap(&input[0].inv().expect("inv() does not get a result."));
     }
     fn grad(&self, input: &[Tensor], output_grad: &[Tensor], input_grad: &[Tensor]) {
-        unimplemented!();
+        // dA = -A^-T dY A^-T.
+        let inv_t = input[0].inv().expect("inv() does not get a result.").t();
+        input_grad[0].swap(&inv_t.matmul(&output_grad[0]).matmul(&inv_t).neg());
     }
     fn get_values(&self) -> Vec<Tensor> {
         Vec::new()
@@ -194,7 +231,7 @@ impl OpTrait for Tr {
         output[0].swap(&input[0].tr());
     }
     fn grad(&self, input: &[Tensor], output_grad: &[Tensor], input_grad: &[Tensor]) {
-        unimplemented!();
+        input_grad[0].swap(&triangle(&input[0], 0., 1.).mul(&output_grad[0]));
     }
     fn get_values(&self) -> Vec<Tensor> {
         Vec::new()
@@ -213,3 +250,502 @@ impl Default for Tr {
         Self::new()
     }
 }
+
+#[cfg_attr(feature = "use-serde", derive(Serialize, Deserialize))]
+pub struct Solve {
+    #[cfg_attr(feature = "use-serde", serde(skip))]
+    handle: OpHandle,
+}
+impl Solve {
+    pub fn new() -> Solve {
+        Solve {
+            handle: OpHandle::new(),
+        }
+    }
+    fn get_handle(&self) -> &OpHandle {
+        &self.handle
+    }
+    fn get_handle_mut(&mut self) -> &mut OpHandle {
+        &mut self.handle
+    }
+}
+impl OpTrait for Solve {
+    fn get_name(&self) -> &'static str {
+        "Solve"
+    }
+    fn get_input_size(&self) -> usize {
+        2
+    }
+    fn get_output_size(&self) -> usize {
+        1
+    }
+    /// Solve ax = b, a is the first input and b is the second,
+    /// b is either a vector or a matrix.
+    fn apply(&self, input: &[Tensor], output: &[Tensor]) {
+        output[0].swap(&solve(&input[0], &input[1]));
+    }
+    fn grad(&self, input: &[Tensor], output_grad: &[Tensor], input_grad: &[Tensor]) {
+        // db = a^-T dx, da = -db x^T.
+        let n = input[1].size()[0];
+        let x = solve(&input[0], &input[1]).reshape(&[n, input[1].numel() / n]);
+        let grad_b = solve(&input[0].t(), &output_grad[0]);
+        let grad_a = grad_b.reshape(&x.size()).matmul(&x.t()).neg();
+        input_grad[0].swap(&grad_a);
+        input_grad[1].swap(&grad_b);
+    }
+    fn get_values(&self) -> Vec<Tensor> {
+        Vec::new()
+    }
+    fn get_grads(&self) -> Vec<Tensor> {
+        Vec::new()
+    }
+    fn set_values(&self, _v: &[Tensor]) {}
+    #[cfg(feature = "use-serde")]
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+impl Default for Solve {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg_attr(feature = "use-serde", derive(Serialize, Deserialize))]
+pub struct Cholesky {
+    #[cfg_attr(feature = "use-serde", serde(skip))]
+    handle: OpHandle,
+}
+impl Cholesky {
+    pub fn new() -> Cholesky {
+        Cholesky {
+            handle: OpHandle::new(),
+        }
+    }
+    fn get_handle(&self) -> &OpHandle {
+        &self.handle
+    }
+    fn get_handle_mut(&mut self) -> &mut OpHandle {
+        &mut self.handle
+    }
+}
+impl OpTrait for Cholesky {
+    fn get_name(&self) -> &'static str {
+        "Cholesky"
+    }
+    fn get_input_size(&self) -> usize {
+        1
+    }
+    fn get_output_size(&self) -> usize {
+        1
+    }
+    /// The output is the upper triangular r, with a = r^T r.
+    fn apply(&self, input: &[Tensor], output: &[Tensor]) {
+        output[0].swap(&input[0].cholesky().expect("cholesky() does not get a result."));
+    }
+    /// The input is taken as symmetric, so is the gradient.
+    fn grad(&self, input: &[Tensor], output_grad: &[Tensor], input_grad: &[Tensor]) {
+        // With l = r^T, phi = tril(l^T dl) with half diagonal,
+        // s = l^-T phi l^-1 and da = (s + s^T)/2.
+        let r = input[0].cholesky().expect("cholesky() does not get a result.");
+        let phi = r.matmul(&output_grad[0].t()).mul(&triangle(&r, 1., 0.5));
+        let r_inv = r.inv().expect("inv() does not get a result.");
+        let s = r_inv.matmul(&phi).matmul(&r_inv.t());
+        input_grad[0].swap(&s.add(&s.t()).div(&Tensor::int_n(&[1], 2)));
+    }
+    fn get_values(&self) -> Vec<Tensor> {
+        Vec::new()
+    }
+    fn get_grads(&self) -> Vec<Tensor> {
+        Vec::new()
+    }
+    fn set_values(&self, _v: &[Tensor]) {}
+    #[cfg(feature = "use-serde")]
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+impl Default for Cholesky {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg_attr(feature = "use-serde", derive(Serialize, Deserialize))]
+pub struct QR {
+    #[cfg_attr(feature = "use-serde", serde(skip))]
+    handle: OpHandle,
+}
+impl QR {
+    pub fn new() -> QR {
+        QR {
+            handle: OpHandle::new(),
+        }
+    }
+    fn get_handle(&self) -> &OpHandle {
+        &self.handle
+    }
+    fn get_handle_mut(&mut self) -> &mut OpHandle {
+        &mut self.handle
+    }
+}
+impl OpTrait for QR {
+    fn get_name(&self) -> &'static str {
+        "QR"
+    }
+    fn get_input_size(&self) -> usize {
+        1
+    }
+    fn get_output_size(&self) -> usize {
+        2
+    }
+    /// The outputs are q and r.
+    fn apply(&self, input: &[Tensor], output: &[Tensor]) {
+        let [q, r] = input[0].qr().expect("qr() does not get a result.");
+        output[0].swap(&q);
+        output[1].swap(&r);
+    }
+    /// The gradient is for the reduced decomposition of a
+    /// square or tall matrix.
+    fn grad(&self, input: &[Tensor], output_grad: &[Tensor], input_grad: &[Tensor]) {
+        let size = input[0].size();
+        if size[0] < size[1] {
+            panic!("QR grad needs rows >= columns, got {:?}", size);
+        }
+        // m = r dr^T - dq^T q,
+        // da = (dq + q copyltu(m)) r^-T.
+        let [q, r] = input[0].qr().expect("qr() does not get a result.");
+        let grad_q = grad_or_zeros(&output_grad[0], &q);
+        let grad_r = grad_or_zeros(&output_grad[1], &r);
+        let m = r.matmul(&grad_r.t()).sub(&grad_q.t().matmul(&q));
+        let copyltu = m.mul(&triangle(&m, 1., 1.)).add(&m.mul(&triangle(&m, 1., 0.)).t());
+        let r_inv = r.inv().expect("inv() does not get a result.");
+        input_grad[0].swap(&grad_q.add(&q.matmul(&copyltu)).matmul(&r_inv.t()));
+    }
+    fn get_values(&self) -> Vec<Tensor> {
+        Vec::new()
+    }
+    fn get_grads(&self) -> Vec<Tensor> {
+        Vec::new()
+    }
+    fn set_values(&self, _v: &[Tensor]) {}
+    #[cfg(feature = "use-serde")]
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+impl Default for QR {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg_attr(feature = "use-serde", derive(Serialize, Deserialize))]
+pub struct Slogdet {
+    #[cfg_attr(feature = "use-serde", serde(skip))]
+    handle: OpHandle,
+}
+impl Slogdet {
+    pub fn new() -> Slogdet {
+        Slogdet {
+            handle: OpHandle::new(),
+        }
+    }
+    fn get_handle(&self) -> &OpHandle {
+        &self.handle
+    }
+    fn get_handle_mut(&mut self) -> &mut OpHandle {
+        &mut self.handle
+    }
+}
+impl OpTrait for Slogdet {
+    fn get_name(&self) -> &'static str {
+        "Slogdet"
+    }
+    fn get_input_size(&self) -> usize {
+        1
+    }
+    fn get_output_size(&self) -> usize {
+        2
+    }
+    /// The outputs are the sign and the log of the absolute value of det.
+    /// Both come from the pivots of a row-pivoted elimination,
+    /// so det itself, which can overflow, is never formed.
+    /// A singular input gets sign 0 and log -inf.
+    fn apply(&self, input: &[Tensor], output: &[Tensor]) {
+        let size = input[0].size();
+        if size.len() != 2 || size[0] != size[1] {
+            panic!("{} expects a square matrix, get {:?}", self.get_name(), size);
+        }
+        let n = size[0];
+        let mut a = input[0].get_raw_f64();
+        let mut sign = 1.;
+        let mut logdet = 0.;
+        for i in 0..n {
+            let p = (i..n)
+                .max_by(|x, y| a[x * n + i].abs().total_cmp(&a[y * n + i].abs()))
+                .expect("");
+            if a[p * n + i] == 0. {
+                sign = 0.;
+                logdet = f64::NEG_INFINITY;
+                break;
+            }
+            // each row swap flips the sign of det.
+            if p != i {
+                for k in 0..n {
+                    a.swap(i * n + k, p * n + k);
+                }
+                sign = -sign;
+            }
+            let pivot = a[i * n + i];
+            sign *= pivot.signum();
+            logdet += pivot.abs().ln();
+            for j in i + 1..n {
+                let m = a[j * n + i] / pivot;
+                for k in i..n {
+                    a[j * n + k] -= m * a[i * n + k];
+                }
+            }
+        }
+        output[0].swap(&scalar_like(&input[0], sign));
+        output[1].swap(&scalar_like(&input[0], logdet));
+    }
+    fn grad(&self, input: &[Tensor], output_grad: &[Tensor], input_grad: &[Tensor]) {
+        // d(log|det(A)|)/dA = A^-T, the sign is piecewise constant.
+        let inv = input[0].inv().expect("inv() does not get a result.");
+        let grad = grad_or_zeros(&output_grad[1], &output_grad[0]);
+        input_grad[0].swap(&inv.t().mul(&grad));
+    }
+    fn get_values(&self) -> Vec<Tensor> {
+        Vec::new()
+    }
+    fn get_grads(&self) -> Vec<Tensor> {
+        Vec::new()
+    }
+    fn set_values(&self, _v: &[Tensor]) {}
+    #[cfg(feature = "use-serde")]
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+impl Default for Slogdet {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::op::_gradient_checker;
+
+    /// Cholesky of (a + a^T)/2, so a can be perturbed one entry at a time.
+    struct SymmetricCholesky(Cholesky);
+    impl SymmetricCholesky {
+        fn symmetric(a: &Tensor) -> Tensor {
+            a.add(&a.t()).div(&Tensor::int_n(&[1], 2))
+        }
+    }
+    impl OpTrait for SymmetricCholesky {
+        fn get_name(&self) -> &'static str {
+            "SymmetricCholesky"
+        }
+        fn get_input_size(&self) -> usize {
+            1
+        }
+        fn get_output_size(&self) -> usize {
+            1
+        }
+        fn apply(&self, input: &[Tensor], output: &[Tensor]) {
+            self.0.apply(&[Self::symmetric(&input[0])], output);
+        }
+        fn grad(&self, input: &[Tensor], output_grad: &[Tensor], input_grad: &[Tensor]) {
+            let grad = Tensor::new();
+            self.0.grad(&[Self::symmetric(&input[0])], output_grad, &[grad.ref_copy()]);
+            input_grad[0].swap(&Self::symmetric(&grad));
+        }
+        fn get_values(&self) -> Vec<Tensor> {
+            Vec::new()
+        }
+        fn get_grads(&self) -> Vec<Tensor> {
+            Vec::new()
+        }
+        fn set_values(&self, _v: &[Tensor]) {}
+        #[cfg(feature = "use-serde")]
+        fn as_any(&self) -> &dyn Any {
+            self
+        }
+    }
+
+    #[test]
+    fn det_tr() {
+        let a = Tensor::from_vec_f64(&[2., 1., 0.5, 1., 3., 0.2, 0.1, 0.4, 1.5], &[3, 3]);
+        let mut op = Det::new();
+        assert!(_gradient_checker(
+            &mut op,
+            &[a.ref_copy()],
+            None,
+            None,
+            None,
+            None
+        ));
+        let mut op = Tr::new();
+        assert!(_gradient_checker(
+            &mut op,
+            &[a],
+            None,
+            None,
+            None,
+            None
+        ));
+    }
+
+    #[test]
+    fn inv_normalize_unit() {
+        let a = Tensor::from_vec_f64(&[2., 1., 0.5, 1., 3., 0.2, 0.1, 0.4, 1.5], &[3, 3]);
+        let g = Tensor::from_vec_f64(&[0.3, -1., 0.2, 0.5, 0.1, -0.7, 1., 0.4, -0.2], &[3, 3]);
+        assert!(_gradient_checker(
+            &mut Inv::new(),
+            &[a.ref_copy()],
+            None,
+            Some(Tensor::from_vec_f64(&[1e-6], &[1])),
+            Some(Tensor::from_vec_f64(&[1e-8], &[1])),
+            Some(&[g.ref_copy()])
+        ));
+        assert!(_gradient_checker(
+            &mut NormalizeUnit::new(),
+            &[a],
+            None,
+            Some(Tensor::from_vec_f64(&[1e-6], &[1])),
+            Some(Tensor::from_vec_f64(&[1e-8], &[1])),
+            Some(&[g])
+        ));
+    }
+
+    #[test]
+    fn solve() {
+        let a = Tensor::from_vec_f64(&[2., 1., 0.5, 1., 3., 0.2, 0.1, 0.4, 1.5], &[3, 3]);
+        let b = Tensor::from_vec_f64(&[1., -2., 0.5, 0.3, 2., 1.], &[3, 2]);
+        let x = Tensor::new();
+        Solve::new().apply(&[a.ref_copy(), b.ref_copy()], &[x.ref_copy()]);
+        assert!(a.matmul(&x).sub(&b).abs().max(None, false).get_scale_f64() < 1e-10);
+
+        let g = Tensor::from_vec_f64(&[0.3, -1., 0.2, 0.5, 0.1, -0.7], &[3, 2]);
+        assert!(_gradient_checker(
+            &mut Solve::new(),
+            &[a.ref_copy(), b],
+            None,
+            Some(Tensor::from_vec_f64(&[1e-6], &[1])),
+            Some(Tensor::from_vec_f64(&[1e-8], &[1])),
+            Some(&[g])
+        ));
+
+        let b = Tensor::from_vec_f64(&[1., -2., 0.5], &[3]);
+        let g = Tensor::from_vec_f64(&[0.3, -1., 0.2], &[3]);
+        assert!(_gradient_checker(
+            &mut Solve::new(),
+            &[a, b],
+            None,
+            Some(Tensor::from_vec_f64(&[1e-6], &[1])),
+            Some(Tensor::from_vec_f64(&[1e-8], &[1])),
+            Some(&[g])
+        ));
+    }
+
+    #[test]
+    fn cholesky() {
+        let a = Tensor::from_vec_f64(&[4., 12., -16., 12., 37., -43., -16., -43., 98.], &[3, 3]);
+        let g = Tensor::from_vec_f64(&[0.3, -1., 0.2, 0.5, 0.1, -0.7, 1., 0.4, -0.2], &[3, 3]);
+        assert!(_gradient_checker(
+            &mut SymmetricCholesky(Cholesky::new()),
+            &[a],
+            None,
+            Some(Tensor::from_vec_f64(&[1e-6], &[1])),
+            Some(Tensor::from_vec_f64(&[1e-8], &[1])),
+            Some(&[g])
+        ));
+    }
+
+    #[test]
+    fn qr() {
+        let a = Tensor::from_vec_f64(&[2., 1., 0.5, 1., 3., 0.2, 0.1, 0.4, 1.5, 0.7, -0.3, 1.], &[4, 3]);
+        let gq = Tensor::from_vec_f64(&[0.3, -1., 0.2, 0.5, 0.1, -0.7, 1., 0.4, -0.2, 0.2, 0.8, -0.5], &[4, 3]);
+        let gr = Tensor::from_vec_f64(&[0.1, 0.7, -0.4, 0.2, -0.6, 0.9, 0.3, 0.1, 0.5], &[3, 3]);
+        assert!(_gradient_checker(
+            &mut QR::new(),
+            &[a.ref_copy()],
+            None,
+            Some(Tensor::from_vec_f64(&[1e-6], &[1])),
+            Some(Tensor::from_vec_f64(&[1e-8], &[1])),
+            Some(&[gq.ref_copy(), gr.ref_copy()])
+        ));
+        assert!(_gradient_checker(
+            &mut QR::new(),
+            &[a.ref_copy()],
+            None,
+            Some(Tensor::from_vec_f64(&[1e-6], &[1])),
+            Some(Tensor::from_vec_f64(&[1e-8], &[1])),
+            Some(&[gq, gr.zeros_like()])
+        ));
+        assert!(_gradient_checker(
+            &mut QR::new(),
+            &[a],
+            None,
+            Some(Tensor::from_vec_f64(&[1e-6], &[1])),
+            Some(Tensor::from_vec_f64(&[1e-8], &[1])),
+            Some(&[Tensor::zeros(&[4, 3]), gr])
+        ));
+    }
+
+    #[test]
+    fn slogdet() {
+        let a = Tensor::from_vec_f64(&[1., 3., 0.2, 0.5, 1., 2., 0.1, 0.4, 1.5], &[3, 3]);
+        let output = [Tensor::new(), Tensor::new()];
+        Slogdet::new().apply(&[a.ref_copy()], &output);
+        let det = a.det().unwrap().get_scale_f64();
+        assert!(det < 0.);
+        assert_eq!(output[0].get_scale_f64(), -1.);
+        assert!((output[1].get_scale_f64() - det.abs().ln()).abs() < 1e-10);
+
+        let g = [Tensor::from_vec_f64(&[0.], &[1]), Tensor::from_vec_f64(&[1.5], &[1])];
+        assert!(_gradient_checker(
+            &mut Slogdet::new(),
+            &[a],
+            None,
+            Some(Tensor::from_vec_f64(&[1e-6], &[1])),
+            Some(Tensor::from_vec_f64(&[1e-8], &[1])),
+            Some(&g)
+        ));
+    }
+
+    #[test]
+    fn slogdet_pivot() {
+        // the first pivot is zero, a row swap is needed.
+        let a = Tensor::from_vec_f64(&[0., 1., 1., 0.], &[2, 2]);
+        let output = [Tensor::new(), Tensor::new()];
+        Slogdet::new().apply(&[a], &output);
+        assert_eq!(output[0].get_scale_f64(), -1.);
+        assert_eq!(output[1].get_scale_f64(), 0.);
+
+        let a = Tensor::from_vec_f64(&[0.5, 2., 1., 1., 3., 0.2, 4., 0.4, 1.5], &[3, 3]);
+        Slogdet::new().apply(&[a.ref_copy()], &output);
+        let det = a.det().unwrap().get_scale_f64();
+        assert_eq!(output[0].get_scale_f64(), det.signum());
+        assert!((output[1].get_scale_f64() - det.abs().ln()).abs() < 1e-10);
+    }
+
+    #[test]
+    fn slogdet_large() {
+        // det is 1e600, which overflows f64.
+        let n = 200;
+        let mut a = Tensor::eye(n, n).mul(&Tensor::from_vec_f64(&[1e3], &[1]));
+        a.set_f64(&[0, 0], -1e3);
+        assert!(a.det().unwrap().get_scale_f64().is_infinite());
+
+        let output = [Tensor::new(), Tensor::new()];
+        Slogdet::new().apply(&[a], &output);
+        assert_eq!(output[0].get_scale_f64(), -1.);
+        let expected = n as f64 * 1e3_f64.ln();
+        assert!((output[1].get_scale_f64() - expected).abs() < 1e-9);
+    }
+}
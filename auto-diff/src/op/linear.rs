@@ -129,33 +129,38 @@ impl OpTrait for Linear {
         if inputs.is_empty() {
             panic!("Expect one input tensor");
         }
-        if inputs[0].size()[1] != self.weight.size()[0] {
+        let (in_dim, out_dim) = (self.weight.size()[0], self.weight.size()[1]);
+        if inputs[0].size()[inputs[0].size().len() - 1] != in_dim {
             panic!(
                 "Expect input dimension matches weight dimension {:?}, {:?}",
                 inputs[0].size(),
                 self.weight.size()
             );
         }
-        if inputs[0].size()[0] != output_grad[0].size()[0] {
+        let mut expected_size = inputs[0].size();
+        let last = expected_size.len() - 1;
+        expected_size[last] = out_dim;
+        if output_grad[0].size() != expected_size {
             panic!(
-                "Expect input population matches output gradient population {:?}, {:?}",
-                inputs[0].size(),
-                output_grad[0].size()
-            );
-        }
-        if output_grad[0].size()[1] != self.weight.size()[1] {
-            panic!(
-                "Expect output gradient dimension matches weight dimension {:?}, {:?}",
+                "Expect output gradient size matches input size and weight dimension {:?}, {:?}, {:?}",
                 output_grad[0].size(),
+                inputs[0].size(),
                 self.weight.size()
             );
         }
 
-        input_grad[0].swap(&output_grad[0].matmul(&self.weight.permute(&[1, 0])));
-        self.weight_grad
-            .swap(&inputs[0].outer(&output_grad[0], Some(true)));
+        // Fold the leading dims into one population dim.
+        let population = inputs[0].numel() / in_dim;
+        let x = inputs[0].reshape(&[population, in_dim]);
+        let g = output_grad[0].reshape(&[population, out_dim]);
+
+        input_grad[0].swap(
+            &g.matmul(&self.weight.permute(&[1, 0]))
+                .reshape(&inputs[0].size()),
+        );
+        self.weight_grad.swap(&x.permute(&[1, 0]).matmul(&g));
         if self.bias_option {
-            self.bias_grad.swap(&output_grad[0].mean(Some(&[0]), false));
+            self.bias_grad.swap(&g.sum(Some(&[0]), false));
         }
     }
 
@@ -180,6 +185,12 @@ impl OpTrait for Linear {
         }
         ret
     }
+    fn set_grads(&self, v: &[Tensor]) {
+        self.weight_grad.swap(&v[0].clone());
+        if self.bias_option {
+            self.bias_grad.swap(&v[1].clone());
+        }
+    }
 
     #[cfg(feature = "use-serde")]
     fn as_any(&self) -> &dyn Any {
@@ -208,19 +219,44 @@ impl BiLinear {
         out_features: Option<usize>,
         bias: bool,
     ) -> BiLinear {
+        let (weight, bias_tensor) = match (in1_features, in2_features, out_features) {
+            (Some(d1), Some(d2), Some(d3)) => (Tensor::zeros(&[d1, d2, d3]), Tensor::zeros(&[d3])),
+            _ => (Tensor::new(), Tensor::new()),
+        };
         BiLinear {
             in1_fea: in1_features,
             in2_fea: in2_features,
             out_fea: out_features,
             bias_option: bias,
-            weight: Tensor::new(),
-            bias: Tensor::new(),
+            weight,
+            bias: bias_tensor,
             weight_grad: Tensor::new(),
             bias_grad: Tensor::new(),
             handle: OpHandle::new(),
         }
     }
 
+    /// Check the inputs against the [in1, in2, out] weight,
+    /// return the population, i.e. the product of the leading dims.
+    fn population(&self, inputs: &[Tensor]) -> usize {
+        if inputs.len() != 2 {
+            panic!("BiLinear expect two inputs.");
+        }
+        let weight_size = self.weight.size();
+        let (size1, size2) = (inputs[0].size(), inputs[1].size());
+        if weight_size.len() != 3
+            || size1[size1.len() - 1] != weight_size[0]
+            || size2[size2.len() - 1] != weight_size[1]
+            || size1[..size1.len() - 1] != size2[..size2.len() - 1]
+        {
+            panic!(
+                "Expect inputs [.., in1], [.., in2] match weight [in1, in2, out], {:?}, {:?}, {:?}",
+                size1, size2, weight_size
+            );
+        }
+        inputs[0].numel() / weight_size[0]
+    }
+
     pub fn weight(&self) -> &Tensor {
         &self.weight
     }
@@ -273,30 +309,70 @@ impl OpTrait for BiLinear {
         1
     }
 
+    /// y = x1^T W x2 + b, for each of the leading dims.
     fn apply(&self, inputs: &[Tensor], outputs: &[Tensor]) {
-        unimplemented!();
+        let population = self.population(inputs);
+        let weight_size = self.weight.size();
+        let (in1, in2, out) = (weight_size[0], weight_size[1], weight_size[2]);
+
+        let x1 = inputs[0].reshape(&[population, in1]);
+        let x2 = inputs[1].reshape(&[population, in2]);
+        let ret = x1
+            .outer(&x2, None)
+            .reshape(&[population, in1 * in2])
+            .matmul(&self.weight.reshape(&[in1 * in2, out]));
+        let ret = if self.bias_option {
+            ret.add(&self.bias)
+        } else {
+            ret
+        };
+
+        let mut ret_size = inputs[0].size();
+        let last = ret_size.len() - 1;
+        ret_size[last] = out;
+        outputs[0].swap(&ret.reshape(&ret_size));
     }
 
     fn grad(&self, inputs: &[Tensor], output_grad: &[Tensor], input_grad: &[Tensor]) {
-        if inputs.is_empty() {
-            panic!("Expect one input tensor");
-        }
-        if inputs[0].size()[1] != self.weight.size()[0] {
+        let population = self.population(inputs);
+        let weight_size = self.weight.size();
+        let (in1, in2, out) = (weight_size[0], weight_size[1], weight_size[2]);
+        if output_grad[0].numel() != population * out {
             panic!(
-                "Expect input1 dimension matches weight dimension {:?}, {:?}",
+                "Expect output gradient matches inputs and weight, {:?}, {:?}, {:?}",
+                output_grad[0].size(),
                 inputs[0].size(),
-                self.weight.size()
-            );
-        }
-        if self.weight.size()[1] != inputs[1].size()[0] {
-            panic!(
-                "Expect weight dimension matches input2 dimension {:?}, {:?}",
-                self.weight.size(),
-                inputs[1].size()
+                weight_size
             );
         }
 
-        unimplemented!();
+        let x1 = inputs[0].reshape(&[population, in1]);
+        let x2 = inputs[1].reshape(&[population, in2]);
+        let g = output_grad[0].reshape(&[population, out]);
+
+        // gradient on the outer product x1 x2^T.
+        let pair = x1.outer(&x2, None).reshape(&[population, in1 * in2]);
+        let pair_grad = g
+            .matmul(&self.weight.reshape(&[in1 * in2, out]).permute(&[1, 0]))
+            .reshape(&[population, in1, in2]);
+
+        input_grad[0].swap(
+            &pair_grad
+                .mul(&x2.reshape(&[population, 1, in2]))
+                .sum(Some(&[2]), false)
+                .reshape(&inputs[0].size()),
+        );
+        input_grad[1].swap(
+            &pair_grad
+                .mul(&x1.reshape(&[population, in1, 1]))
+                .sum(Some(&[1]), false)
+                .reshape(&inputs[1].size()),
+        );
+        self.weight_grad
+            .swap(&pair.permute(&[1, 0]).matmul(&g).reshape(&[in1, in2, out]));
+        if self.bias_option {
+            self.bias_grad.swap(&g.sum(Some(&[0]), false));
+        }
     }
 
     fn get_values(&self) -> Vec<Tensor> {
@@ -320,9 +396,89 @@ impl OpTrait for BiLinear {
         }
         ret
     }
+    fn set_grads(&self, v: &[Tensor]) {
+        self.weight_grad.swap(&v[0].clone());
+        if self.bias_option {
+            self.bias_grad.swap(&v[1].clone());
+        }
+    }
 
     #[cfg(feature = "use-serde")]
     fn as_any(&self) -> &dyn Any {
         self
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::op::_gradient_checker;
+    use rand::prelude::StdRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn linear() {
+        let mut rng = StdRng::seed_from_u64(1);
+        let mut op = Linear::new(Some(3), Some(2), true);
+        op.set_values(&[
+            Tensor::uniform_f64(&mut rng, &[3, 2], -1., 1.),
+            Tensor::uniform_f64(&mut rng, &[2], -1., 1.),
+        ]);
+        let input = Tensor::uniform_f64(&mut rng, &[2, 4, 3], -1., 1.);
+        let output = Tensor::new();
+        op.apply(&[input.ref_copy()], &[output.ref_copy()]);
+        assert_eq!(output.size(), [2, 4, 2]);
+        assert!(_gradient_checker(
+            &mut op,
+            &[input],
+            None,
+            Some(Tensor::from_vec_f64(&[1e-6], &[1])),
+            Some(Tensor::from_vec_f64(&[1e-10], &[1])),
+            Some(&[Tensor::uniform_f64(&mut rng, &[2, 4, 2], -1., 1.)])
+        ));
+        assert!(_gradient_checker(
+            &mut op,
+            &[Tensor::uniform_f64(&mut rng, &[5, 3], -1., 1.)],
+            None,
+            Some(Tensor::from_vec_f64(&[1e-6], &[1])),
+            Some(Tensor::from_vec_f64(&[1e-10], &[1])),
+            Some(&[Tensor::uniform_f64(&mut rng, &[5, 2], -1., 1.)])
+        ));
+    }
+
+    #[test]
+    fn bilinear() {
+        let mut rng = StdRng::seed_from_u64(2);
+        let mut op = BiLinear::new(Some(3), Some(2), Some(4), true);
+        op.set_values(&[
+            Tensor::uniform_f64(&mut rng, &[3, 2, 4], -1., 1.),
+            Tensor::uniform_f64(&mut rng, &[4], -1., 1.),
+        ]);
+        let inputs = [
+            Tensor::uniform_f64(&mut rng, &[2, 5, 3], -1., 1.),
+            Tensor::uniform_f64(&mut rng, &[2, 5, 2], -1., 1.),
+        ];
+        let output = Tensor::new();
+        op.apply(&inputs, &[output.ref_copy()]);
+        assert_eq!(output.size(), [2, 5, 4]);
+        assert!(_gradient_checker(
+            &mut op,
+            &inputs,
+            None,
+            Some(Tensor::from_vec_f64(&[1e-6], &[1])),
+            Some(Tensor::from_vec_f64(&[1e-10], &[1])),
+            Some(&[Tensor::uniform_f64(&mut rng, &[2, 5, 4], -1., 1.)])
+        ));
+
+        let op = BiLinear::new(Some(2), Some(2), Some(1), false);
+        op.set_values(&[Tensor::from_vec_f64(&[1., 2., 3., 4.], &[2, 2, 1])]);
+        op.apply(
+            &[
+                Tensor::from_vec_f64(&[1., 0.], &[1, 2]),
+                Tensor::from_vec_f64(&[0., 1.], &[1, 2]),
+            ],
+            &[output.ref_copy()],
+        );
+        assert_eq!(output, Tensor::from_vec_f64(&[2.], &[1, 1]));
+    }
+}
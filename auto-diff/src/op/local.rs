@@ -2,19 +2,50 @@
 use super::macros::new_binary_op;
 use super::{OpHandle, OpTrait};
 use tensor_rs::tensor::Tensor;
+use tensor_rs::tensor_impl::gen_tensor::MatmulLayout;
 
 #[cfg(feature = "use-serde")]
 use serde::{Deserialize, Serialize};
 #[cfg(feature = "use-serde")]
 use std::any::Any;
 
+/// Sum a gradient over the dimensions that were broadcast in the forward pass,
+/// so it comes back with the shape of the original operand.
+pub(crate) fn reduce_to_shape(grad: &Tensor, shape: &[usize]) -> Tensor {
+    let grad_size = grad.size();
+    if grad_size == shape {
+        return grad.clone();
+    }
+    let lead = grad_size.len().saturating_sub(shape.len());
+    let offset = shape.len().saturating_sub(grad_size.len());
+    let mut dims = Vec::new();
+    for (i, s) in grad_size.iter().enumerate() {
+        if i < lead || (shape[i - lead + offset] == 1 && *s != 1) {
+            dims.push(i);
+        }
+    }
+    if dims.is_empty() {
+        grad.reshape(shape)
+    } else {
+        grad.sum(Some(&dims), false).reshape(shape)
+    }
+}
+
+/// Swap the last two dimensions.
+pub(crate) fn transpose_last(x: &Tensor) -> Tensor {
+    let n = x.size().len();
+    let mut perm: Vec<usize> = (0..n).collect();
+    perm.swap(n - 2, n - 1);
+    x.permute(&perm)
+}
+
 new_binary_op!(
     Add,
     "Add",
     (|a: &[Tensor], b: &[Tensor]| b[0].swap(&a[0].add(&a[1]))),
     (|input: &[Tensor], output_grad: &[Tensor], input_grad: &[Tensor]| {
-        let x = input[0].ones_like().mul(&output_grad[0]);
-        let y = input[1].ones_like().mul(&output_grad[0]);
+        let x = reduce_to_shape(&output_grad[0], &input[0].size());
+        let y = reduce_to_shape(&output_grad[0], &input[1].size());
         input_grad[0].swap(&x);
         input_grad[1].swap(&y);
     })
@@ -24,8 +55,8 @@ new_binary_op!(
     "Sub",
     (|a: &[Tensor], b: &[Tensor]| b[0].swap(&a[0].sub(&a[1]))),
     (|input: &[Tensor], output_grad: &[Tensor], input_grad: &[Tensor]| {
-        let x = input[0].ones_like().mul(&output_grad[0]);
-        let y = input[1].ones_like().neg().mul(&output_grad[0]);
+        let x = reduce_to_shape(&output_grad[0], &input[0].size());
+        let y = reduce_to_shape(&output_grad[0].neg(), &input[1].size());
         input_grad[0].swap(&x);
         input_grad[1].swap(&y);
     })
@@ -35,8 +66,8 @@ new_binary_op!(
     "Mul",
     (|a: &[Tensor], b: &[Tensor]| b[0].swap(&a[0].mul(&a[1]))),
     (|input: &[Tensor], output_grad: &[Tensor], input_grad: &[Tensor]| {
-        let x = input[1].mul(&output_grad[0]);
-        let y = input[0].mul(&output_grad[0]);
+        let x = reduce_to_shape(&output_grad[0].mul(&input[1]), &input[0].size());
+        let y = reduce_to_shape(&output_grad[0].mul(&input[0]), &input[1].size());
         input_grad[0].swap(&x);
         input_grad[1].swap(&y);
     })
@@ -46,12 +77,13 @@ new_binary_op!(
     "Div",
     (|a: &[Tensor], b: &[Tensor]| b[0].swap(&a[0].div(&a[1]))),
     (|input: &[Tensor], output_grad: &[Tensor], input_grad: &[Tensor]| {
-        let x = input[1].reciprocal().mul(&output_grad[0]);
-        let y = input[0]
-            .neg()
+        let x = reduce_to_shape(&output_grad[0].div(&input[1]), &input[0].size());
+        let y = output_grad[0]
+            .mul(&input[0])
             .div(&input[1])
             .div(&input[1])
-            .mul(&output_grad[0]);
+            .neg();
+        let y = reduce_to_shape(&y, &input[1].size());
         input_grad[0].swap(&x);
         input_grad[1].swap(&y);
     })
@@ -62,20 +94,87 @@ new_binary_op!(
     "Matmul",
     (|a: &[Tensor], b: &[Tensor]| b[0].swap(&a[0].matmul(&a[1]))),
     (|input: &[Tensor], output_grad: &[Tensor], input_grad: &[Tensor]| {
-        input_grad[0].swap(&input[1].outer(&output_grad[0], Some(true)));
-        input_grad[1].swap(&input[0].outer(&output_grad[0], Some(true)));
-    })
-);
+        // Vectors are promoted to matrices as in the forward pass,
+        // then da = dc b^T and db = a^T dc, summed over broadcast batches.
+        let a_size = input[0].size();
+        let b_size = input[1].size();
+        let a = if a_size.len() == 1 {input[0].reshape(&[1, a_size[0]])} else {input[0].ref_copy()};
+        let b = if b_size.len() == 1 {input[1].reshape(&[b_size[0], 1])} else {input[1].ref_copy()};
+        let layout = MatmulLayout::new(&a.size(), &b.size()).expect("bad size for matmul");
+        let g = output_grad[0].reshape(&layout.dim);
 
-new_binary_op!(
-    Outer,
-    "Outer",
-    (|a: &[Tensor], b: &[Tensor]| b[0].swap(&a[0].outer(&a[1], None))),
-    (|input: &[Tensor], output_grad: &[Tensor], input_grad: &[Tensor]| {
-        unimplemented!();
+        let x = reduce_to_shape(&g.matmul(&transpose_last(&b)), &a.size());
+        let y = reduce_to_shape(&transpose_last(&a).matmul(&g), &b.size());
+        input_grad[0].swap(&x.reshape(&a_size));
+        input_grad[1].swap(&y.reshape(&b_size));
     })
 );
 
+#[cfg_attr(feature = "use-serde", derive(Serialize, Deserialize))]
+pub struct Outer {
+    #[cfg_attr(feature = "use-serde", serde(skip))]
+    handle: OpHandle,
+    avg: Option<bool>,
+}
+impl Outer {
+    pub fn new(avg: Option<bool>) -> Outer {
+        Outer {
+            handle: OpHandle::new(),
+            avg,
+        }
+    }
+    fn get_handle(&self) -> &OpHandle {
+        &self.handle
+    }
+    fn get_handle_mut(&mut self) -> &mut OpHandle {
+        &mut self.handle
+    }
+}
+impl OpTrait for Outer {
+    fn get_name(&self) -> &'static str {
+        "Outer"
+    }
+    fn get_input_size(&self) -> usize {
+        2
+    }
+    fn get_output_size(&self) -> usize {
+        1
+    }
+    fn apply(&self, input: &[Tensor], output: &[Tensor]) {
+        output[0].swap(&input[0].outer(&input[1], self.avg));
+    }
+    fn grad(&self, input: &[Tensor], output_grad: &[Tensor], input_grad: &[Tensor]) {
+        let a_size = input[0].size();
+        let b_size = input[1].size();
+        if self.avg == Some(true) {
+            // c = sum_k a_k b_k^T/n, with dc shared by all k.
+            let n = input[0].numel() / a_size[a_size.len() - 1];
+            let n = Tensor::int_n(&[1], n as isize);
+            input_grad[0].swap(&input[1].matmul(&output_grad[0].t()).div(&n));
+            input_grad[1].swap(&input[0].matmul(&output_grad[0]).div(&n));
+        } else {
+            // c_k = a_k b_k^T, da_k = dc_k b_k, db_k = dc_k^T a_k.
+            let mut b_col = b_size.clone();
+            b_col.push(1);
+            let mut a_row = a_size.clone();
+            a_row.insert(a_row.len() - 1, 1);
+            input_grad[0].swap(&output_grad[0].matmul(&input[1].reshape(&b_col)).reshape(&a_size));
+            input_grad[1].swap(&input[0].reshape(&a_row).matmul(&output_grad[0]).reshape(&b_size));
+        }
+    }
+    fn get_values(&self) -> Vec<Tensor> {
+        Vec::new()
+    }
+    fn get_grads(&self) -> Vec<Tensor> {
+        Vec::new()
+    }
+    fn set_values(&self, _v: &[Tensor]) {}
+    #[cfg(feature = "use-serde")]
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -88,8 +187,101 @@ mod tests {
         for i in 0..10 {
             let zero = Tensor::from_vec_f64(&vec![(i - 5) as f64], &vec![1]);
             let zero2 = zero.clone();
-            let good_grad = _gradient_checker(&mut op, &[zero, zero2], None, None, None);
+            let good_grad = _gradient_checker(&mut op, &[zero, zero2], None, None, None, None);
             assert_eq!(good_grad, true);
         }
     }
+
+    #[test]
+    fn matmul_grad() {
+        let cases = [
+            (vec![2, 3], vec![3, 2]),
+            (vec![3], vec![3, 2]),
+            (vec![2, 3], vec![3]),
+            (vec![3], vec![3]),
+            (vec![2, 2, 3], vec![3, 2]),
+            (vec![2, 1, 2, 3], vec![3, 3, 1]),
+        ];
+        for (a_size, b_size) in cases.iter() {
+            let a_numel = a_size.iter().product::<usize>();
+            let b_numel = b_size.iter().product::<usize>();
+            let a = Tensor::from_vec_f64(&(0..a_numel).map(|x| (x as f64) * 0.3 - 1.).collect::<Vec<_>>(), a_size);
+            let b = Tensor::from_vec_f64(&(0..b_numel).map(|x| 1.2 - (x as f64) * 0.2).collect::<Vec<_>>(), b_size);
+
+            // weight the output, so the checker sees a single element.
+            let output = a.matmul(&b);
+            let w = Tensor::from_vec_f64(&(0..output.numel()).map(|x| (x % 3) as f64 - 0.5).collect::<Vec<_>>(), &output.size());
+            let input_grad = [Tensor::new(), Tensor::new()];
+            Matmul::new().grad(&[a.ref_copy(), b.ref_copy()], &[w.ref_copy()], &input_grad);
+            assert_eq!(input_grad[0].size(), a.size());
+            assert_eq!(input_grad[1].size(), b.size());
+
+            let delta = 1e-6;
+            for (index, x) in [a.ref_copy(), b.ref_copy()].iter().enumerate() {
+                for i in 0..x.numel() {
+                    let pos = x.index2dimpos(i);
+                    let mut y = x.clone();
+                    y.set_f64(&pos, x.get_f64(&pos) + delta);
+                    let shifted = if index == 0 { y.matmul(&b) } else { a.matmul(&y) };
+                    let numeric = shifted.sub(&output).mul(&w).sum(None, false).get_scale_f64() / delta;
+                    assert!((numeric - input_grad[index].get_f64(&pos)).abs() < 1e-4);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn outer_grad() {
+        let cases = [(vec![3], vec![2]), (vec![4, 3], vec![4, 2]), (vec![2, 2, 3], vec![2, 2, 2])];
+        for avg in [None, Some(true)] {
+            for (a_size, b_size) in cases.iter() {
+                let a_numel = a_size.iter().product::<usize>();
+                let b_numel = b_size.iter().product::<usize>();
+                let a = Tensor::from_vec_f64(&(0..a_numel).map(|x| (x as f64) * 0.3 - 1.).collect::<Vec<_>>(), a_size);
+                let b = Tensor::from_vec_f64(&(0..b_numel).map(|x| 1.2 - (x as f64) * 0.2).collect::<Vec<_>>(), b_size);
+
+                let op = Outer::new(avg);
+                let output = a.outer(&b, avg);
+                let w = Tensor::from_vec_f64(&(0..output.numel()).map(|x| (x % 3) as f64 - 0.5).collect::<Vec<_>>(), &output.size());
+                let input_grad = [Tensor::new(), Tensor::new()];
+                op.grad(&[a.ref_copy(), b.ref_copy()], &[w.ref_copy()], &input_grad);
+                assert_eq!(input_grad[0].size(), a.size());
+                assert_eq!(input_grad[1].size(), b.size());
+
+                let delta = 1e-6;
+                for (index, x) in [a.ref_copy(), b.ref_copy()].iter().enumerate() {
+                    for i in 0..x.numel() {
+                        let pos = x.index2dimpos(i);
+                        let mut y = x.clone();
+                        y.set_f64(&pos, x.get_f64(&pos) + delta);
+                        let shifted = if index == 0 { y.outer(&b, avg) } else { a.outer(&y, avg) };
+                        let numeric = shifted.sub(&output).mul(&w).sum(None, false).get_scale_f64() / delta;
+                        assert!((numeric - input_grad[index].get_f64(&pos)).abs() < 1e-4);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn broadcast_grad() {
+        let a = Tensor::from_vec_f64(&[1., 2., 3., 4., 5., 6.], &[2, 3]);
+        let b = Tensor::from_vec_f64(&[1., 2., 4.], &[3]);
+        let output_grad = Tensor::ones(&[2, 3]);
+
+        let input_grad = [Tensor::new(), Tensor::new()];
+        Add::new().grad(&[a.ref_copy(), b.ref_copy()], &[output_grad.ref_copy()], &input_grad);
+        assert_eq!(input_grad[0], Tensor::ones(&[2, 3]));
+        assert_eq!(input_grad[1], Tensor::from_vec_f64(&[2., 2., 2.], &[3]));
+
+        let input_grad = [Tensor::new(), Tensor::new()];
+        Mul::new().grad(&[a.ref_copy(), b.ref_copy()], &[output_grad.ref_copy()], &input_grad);
+        assert_eq!(input_grad[0], Tensor::from_vec_f64(&[1., 2., 4., 1., 2., 4.], &[2, 3]));
+        assert_eq!(input_grad[1], Tensor::from_vec_f64(&[5., 7., 9.], &[3]));
+
+        let s = Tensor::from_vec_f64(&[2.], &[1]);
+        let input_grad = [Tensor::new(), Tensor::new()];
+        Div::new().grad(&[a.ref_copy(), s.ref_copy()], &[output_grad.ref_copy()], &input_grad);
+        assert_eq!(input_grad[1], Tensor::from_vec_f64(&[-21. / 4.], &[1]));
+    }
 }
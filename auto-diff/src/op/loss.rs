@@ -1,6 +1,12 @@
-use super::{OpHandle, OpTrait};
+use super::{scalar_like, Op, OpCall, OpHandle, OpTrait};
 use tensor_rs::tensor::Tensor;
 
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::err::AutoDiffError;
+use crate::var::Var;
+
 #[cfg(feature = "use-serde")]
 use serde::{Deserialize, Serialize};
 #[cfg(feature = "use-serde")]
@@ -9,24 +15,129 @@ use std::any::Any;
 //
 // Common Cost function
 //
+/// How the elementwise losses are combined into the output.
+#[cfg_attr(feature = "use-serde", derive(Serialize, Deserialize))]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
 pub enum Reduction {
+    /// Keep the elementwise losses.
     None,
+    /// Average over the elementwise losses.
+    #[default]
     Mean,
+    /// Sum over the elementwise losses.
     Sum,
 }
 
-// L1Loss
+/// Apply the reduction to the elementwise loss.
+fn reduce(loss: &Tensor, reduction: Reduction) -> Tensor {
+    match reduction {
+        Reduction::None => loss.ref_copy(),
+        Reduction::Mean => loss.mean(None, false),
+        Reduction::Sum => loss.sum(None, false),
+    }
+}
+
+/// The gradient of the reduced output on each element of loss.
+fn reduce_grad(loss: &Tensor, output_grad: &Tensor, reduction: Reduction) -> Tensor {
+    match reduction {
+        Reduction::None => output_grad.ref_copy(),
+        Reduction::Mean => {
+            Tensor::fill(&loss.size(), &output_grad.reshape(&[1])).div(&loss.numel_tensor())
+        }
+        Reduction::Sum => Tensor::fill(&loss.size(), &output_grad.reshape(&[1])),
+    }
+}
+
+/// Panic unless the prediction and the target have the same size.
+fn check_same_shape(name: &str, input: &[Tensor]) {
+    if input.len() != 2 {
+        panic!("{} expect two input, get {}", name, input.len());
+    }
+    if !input[0].same_shape(&input[1]) {
+        panic!(
+            "{} expect two input have the same shape, get {:?}, {:?}",
+            name,
+            input[0].size(),
+            input[1].size()
+        );
+    }
+}
+
+/// L1Loss, |x - y|.
+/// Prediction comes first, target comes second.
+#[cfg_attr(feature = "use-serde", derive(Serialize, Deserialize))]
+pub struct L1Loss {
+    reduction: Reduction,
+    #[cfg_attr(feature = "use-serde", serde(skip))]
+    handle: OpHandle,
+}
+impl L1Loss {
+    pub fn new(reduction: Reduction) -> L1Loss {
+        L1Loss {
+            reduction,
+            handle: OpHandle::new(),
+        }
+    }
+    handle_method!();
+}
+impl OpTrait for L1Loss {
+    fn get_name(&self) -> &'static str {
+        "L1Loss"
+    }
+    fn get_input_size(&self) -> usize {
+        2
+    }
+    fn get_output_size(&self) -> usize {
+        1
+    }
+    fn apply(&self, input: &[Tensor], output: &[Tensor]) {
+        check_same_shape(self.get_name(), input);
+        let loss = input[0].sub(&input[1]).abs();
+        output[0].swap(&reduce(&loss, self.reduction));
+    }
+    fn grad(&self, input: &[Tensor], output_grad: &[Tensor], input_grad: &[Tensor]) {
+        let diff = input[0].sub(&input[1]);
+        let g = diff
+            .sign()
+            .mul(&reduce_grad(&diff, &output_grad[0], self.reduction));
+        input_grad[1].swap(&g.neg());
+        input_grad[0].swap(&g);
+    }
+
+    fn get_values(&self) -> Vec<Tensor> {
+        Vec::new()
+    }
+    fn set_values(&self, _v: &[Tensor]) {}
+
+    fn get_grads(&self) -> Vec<Tensor> {
+        Vec::new()
+    }
+    #[cfg(feature = "use-serde")]
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+impl Default for L1Loss {
+    fn default() -> Self {
+        Self::new(Reduction::Mean)
+    }
+}
 
-/// MSELoss
-/// The left-most dimension is the N.
+/// MSELoss, (x - y)^2.
+/// Prediction comes first, target comes second.
 #[cfg_attr(feature = "use-serde", derive(Serialize, Deserialize))]
 pub struct MSELoss {
+    reduction: Reduction,
     #[cfg_attr(feature = "use-serde", serde(skip))]
     handle: OpHandle,
 }
 impl MSELoss {
     pub fn new() -> MSELoss {
+        Self::new_with_reduction(Reduction::Mean)
+    }
+    pub fn new_with_reduction(reduction: Reduction) -> MSELoss {
         MSELoss {
+            reduction,
             handle: OpHandle::new(),
         }
     }
@@ -43,16 +154,11 @@ impl OpTrait for MSELoss {
         1
     }
     fn apply(&self, input: &[Tensor], output: &[Tensor]) {
-        let tmp = input[0].sub(&input[1]);
-        let tmp2 = tmp.mul(&tmp);
-        let tmp3 = tmp2.sum(None, false);
-        let ret = tmp3.div(&input[0].get_n().mul(&input[0].get_c()));
-        output[0].swap(&ret);
+        check_same_shape(self.get_name(), input);
+        let diff = input[0].sub(&input[1]);
+        output[0].swap(&reduce(&diff.mul(&diff), self.reduction));
     }
     fn grad(&self, input: &[Tensor], output_grad: &[Tensor], input_grad: &[Tensor]) {
-        if input.len() < 2 {
-            panic!("MSELoss expect two input, get {}", input.len());
-        }
         if input_grad.len() < 2 {
             panic!(
                 "MSELoss expect two input gradient tensor, get {}",
@@ -65,23 +171,16 @@ impl OpTrait for MSELoss {
                 output_grad.len()
             );
         }
-        if !input[0].same_shape(&input[1]) {
-            panic!(
-                "MSELoss expect two input have the same shape, get {:?}, {:?}",
-                input[0].size(),
-                input[1].size()
-            );
-        }
+        check_same_shape(self.get_name(), input);
 
-        let tmp1 = input[0].sub(&input[1]);
-        let tmp2 = tmp1.div(&input[0].numel_tensor());
-        let tmp3 = tmp2.mul(&output_grad[0]);
-        input_grad[0].swap(&tmp3);
-
-        let tmp1 = input[1].sub(&input[0]);
-        let tmp2 = tmp1.div(&input[0].numel_tensor());
-        let tmp3 = tmp2.mul(&output_grad[0]);
-        input_grad[1].swap(&tmp3);
+        let diff = input[0].sub(&input[1]);
+        let g = diff.mul(&scalar_like(&diff, 2.)).mul(&reduce_grad(
+            &diff,
+            &output_grad[0],
+            self.reduction,
+        ));
+        input_grad[1].swap(&g.neg());
+        input_grad[0].swap(&g);
     }
 
     fn get_values(&self) -> Vec<Tensor> {
@@ -103,19 +202,187 @@ impl Default for MSELoss {
     }
 }
 
-/// CrossEntropyLoss
+/// Repeat a tensor with size 1 on dim 1 to c classes.
+fn repeat_class(t: &Tensor, c: usize) -> Tensor {
+    let mut sizes = vec![1; t.size().len()];
+    sizes[1] = c;
+    t.repeat(&sizes)
+}
+
+/// The weighted target distribution over the classes on dim 1 of input,
+/// and the per-sample divisor for the mean reduction.
+/// The target is either class indices, with one dim less than the input,
+/// or probabilities (one-hot included) with the input size.
+fn class_target(
+    name: &str,
+    input: &Tensor,
+    target: &Tensor,
+    weight: Option<&Tensor>,
+    ignore_index: Option<usize>,
+    label_smoothing: f64,
+) -> (Tensor, Tensor) {
+    let size = input.size();
+    if size.len() < 2 {
+        panic!(
+            "{} expect class scores on dim 1, get input size {:?}",
+            name, size
+        );
+    }
+    let c = size[1];
+    let class_weight = weight.map(|w| {
+        if w.numel() != c {
+            panic!(
+                "{} has {} class weights, but {} classes",
+                name,
+                w.numel(),
+                c
+            );
+        }
+        let mut kept = vec![1; size.len()];
+        kept[1] = c;
+        let mut repeat = size.clone();
+        repeat[1] = 1;
+        w.reshape(&kept).repeat(&repeat)
+    });
+
+    let (dist, keep, index) = if target.size().len() + 1 == size.len() {
+        let keep = match ignore_index {
+            Some(i) => target.ne(&Tensor::fill(
+                &target.size(),
+                &scalar_like(target, i as f64),
+            )),
+            None => target.ones_like(),
+        };
+        let index = target.mul(&keep).unsqueeze(1);
+        let dist = input.zeros_like().spread(1, &index, &index.ones_like());
+        (dist, keep, Some(index))
+    } else if target.size() == size {
+        let keep = input.sum(Some(&[1]), false).ones_like();
+        (target.ref_copy(), keep, None)
+    } else {
+        panic!(
+            "{} expect class indices or probabilities for input size {:?}, get target size {:?}",
+            name,
+            size,
+            target.size()
+        );
+    };
+
+    let dist = if label_smoothing > 0. {
+        dist.mul(&scalar_like(&dist, 1. - label_smoothing))
+            .add(&scalar_like(&dist, label_smoothing / c as f64))
+    } else {
+        dist
+    };
+    let (dist, divisor) = match (class_weight, index) {
+        (Some(w), Some(index)) => {
+            let divisor = w.gather(1, &index).reshape(&keep.size()).mul(&keep);
+            (dist.mul(&w), divisor)
+        }
+        (Some(w), None) => (dist.mul(&w), keep.ref_copy()),
+        (None, _) => (dist, keep.ref_copy()),
+    };
+    (dist.mul(&repeat_class(&keep.unsqueeze(1), c)), divisor)
+}
+
+/// Apply the reduction, with the mean taken over the divisor.
+fn reduce_by(loss: &Tensor, divisor: &Tensor, reduction: Reduction) -> Tensor {
+    match reduction {
+        Reduction::Mean => loss.sum(None, false).div(&divisor.sum(None, false)),
+        _ => reduce(loss, reduction),
+    }
+}
+
+/// The gradient of reduce_by on each element of loss.
+fn reduce_by_grad(
+    loss: &Tensor,
+    divisor: &Tensor,
+    output_grad: &Tensor,
+    reduction: Reduction,
+) -> Tensor {
+    match reduction {
+        Reduction::Mean => {
+            Tensor::fill(&loss.size(), &output_grad.reshape(&[1])).div(&divisor.sum(None, false))
+        }
+        _ => reduce_grad(loss, output_grad, reduction),
+    }
+}
+
+/// CrossEntropyLoss, -sum_c(w_c * t_c * log(softmax(x)_c)), classes on dim 1.
+/// The target t is either class indices, with one dim less than the input,
+/// or probabilities (one-hot included) with the input size.
+/// Options: class weights w, an ignored class index
+/// and label smoothing, which mixes t with the uniform distribution.
+/// With class indices, the mean is taken over the weights of the target classes.
 #[cfg_attr(feature = "use-serde", derive(Serialize, Deserialize))]
 pub struct CrossEntropyLoss {
+    weight: Option<Tensor>,
+    ignore_index: Option<usize>,
+    label_smoothing: f64,
+    reduction: Reduction,
     #[cfg_attr(feature = "use-serde", serde(skip))]
     handle: OpHandle,
 }
 impl CrossEntropyLoss {
     pub fn new() -> CrossEntropyLoss {
+        Self::new_with_reduction(Reduction::Mean)
+    }
+    pub fn new_with_reduction(reduction: Reduction) -> CrossEntropyLoss {
         CrossEntropyLoss {
+            weight: None,
+            ignore_index: None,
+            label_smoothing: 0.,
+            reduction,
             handle: OpHandle::new(),
         }
     }
+    /// Per-class weights, size [C].
+    pub fn set_weight(&mut self, weight: Tensor) -> &mut Self {
+        self.weight = Some(weight);
+        self
+    }
+    /// Samples with this target class are left out.
+    pub fn set_ignore_index(&mut self, ignore_index: usize) -> &mut Self {
+        self.ignore_index = Some(ignore_index);
+        self
+    }
+    pub fn set_label_smoothing(&mut self, label_smoothing: f64) -> &mut Self {
+        if !(0. ..=1.).contains(&label_smoothing) {
+            panic!(
+                "label_smoothing should be in [0, 1], get {}",
+                label_smoothing
+            );
+        }
+        self.label_smoothing = label_smoothing;
+        self
+    }
     handle_method!();
+
+    fn target(&self, input: &[Tensor]) -> (Tensor, Tensor) {
+        class_target(
+            self.get_name(),
+            &input[0],
+            &input[1],
+            self.weight.as_ref(),
+            self.ignore_index,
+            self.label_smoothing,
+        )
+    }
+}
+impl OpCall for CrossEntropyLoss {
+    fn call(&mut self, inputs: &[&Var]) -> Result<Vec<Var>, AutoDiffError> {
+        let new_one = CrossEntropyLoss {
+            weight: self.weight.as_ref().map(|w| w.ref_copy()),
+            ignore_index: self.ignore_index,
+            label_smoothing: self.label_smoothing,
+            reduction: self.reduction,
+            handle: OpHandle::new(),
+        };
+
+        let op = Op::new(Rc::new(RefCell::new(Box::new(new_one))));
+
+        inputs[0].called_with(op, &inputs[1..inputs.len()])
+    }
 }
 impl OpTrait for CrossEntropyLoss {
     fn get_name(&self) -> &'static str {
@@ -133,43 +400,30 @@ impl OpTrait for CrossEntropyLoss {
         if input.len() != 2 {
             panic!("{} expect two input, get {}", self.get_name(), input.len());
         }
-        if input[0].size().len() != (input[1].size().len() + 1) {
-            panic!(
-                "{} expect dim+1 and dim, get {}, {}, for now, no one-hot encoding support",
-                self.get_name(),
-                input[0].size().len(),
-                input[1].size().len()
-            );
-        }
-
-        let class_index = input[1].unsqueeze(1);
-        let class_score = input[0].gather(1, &class_index);
-        let val = class_score
-            .neg()
-            .add(&input[0].logsumexp(Some(&[1]), true))
-            .mean(None, false);
-        output[0].swap(&val);
+        let (dist, divisor) = self.target(input);
+        let c = input[0].size()[1];
+        let log_p = input[0].sub(&repeat_class(&input[0].logsumexp(Some(&[1]), true), c));
+        let loss = dist.mul(&log_p).sum(Some(&[1]), false).neg();
+        output[0].swap(&reduce_by(&loss, &divisor, self.reduction));
     }
 
     /// Given the forward input value and backward output_grad,
     /// Update weight gradient.
     /// return backward input gradeint.
     fn grad(&self, input: &[Tensor], output_grad: &[Tensor], input_grad: &[Tensor]) {
-        let n = input[0].size()[0];
-        let d = input[0].size()[1];
-        let common = input[0]
-            .sub(&input[0].logsumexp(Some(&[1]), true).repeat(&[1, d]))
+        let (dist, divisor) = self.target(input);
+        let c = input[0].size()[1];
+        let p = input[0]
+            .sub(&repeat_class(&input[0].logsumexp(Some(&[1]), true), c))
             .exp();
-
-        let class_index = input[1].unsqueeze(1);
-        let zeros = Tensor::zeros(&[n, d]);
-        let subone = Tensor::ones(&[n, 1]).neg();
-        let class_score = zeros.spread(1, &class_index, &subone);
-        input_grad[0].swap(
-            &(class_score.add(&common))
-                .mul(&output_grad[0])
-                .div(&Tensor::int_n(&[1], n.try_into().expect(""))),
-        )
+        let total = repeat_class(&dist.sum(Some(&[1]), true), c);
+        let sample_grad = reduce_by_grad(&divisor, &divisor, &output_grad[0], self.reduction);
+        let g = p
+            .mul(&total)
+            .sub(&dist)
+            .mul(&repeat_class(&sample_grad.unsqueeze(1), c));
+        input_grad[0].swap(&g);
+        input_grad[1].swap(&input[1].zeros_like());
     }
 
     /// access weight values
@@ -191,85 +445,294 @@ impl Default for CrossEntropyLoss {
         Self::new()
     }
 }
-// CTCLoss
-// NLLLoss
-// PoissonNLLLoss
-// KLDivLoss
-// BCELoss
+/// log(exp(a) + exp(b)), with -inf for log(0).
+fn log_add(a: f64, b: f64) -> f64 {
+    if a == f64::NEG_INFINITY {
+        return b;
+    }
+    if b == f64::NEG_INFINITY {
+        return a;
+    }
+    let m = a.max(b);
+    m + ((a - m).exp() + (b - m).exp()).ln()
+}
 
-/// This loss combines a Sigmoid layer and the BCELoss in one single class.
-/// This version is more numerically stable than using a plain Sigmoid followed
-/// by a BCELoss as, by combining the operations into one layer,
-/// we take advantage of the log-sum-exp trick for numerical stability.
-///
-/// -y log (1/(1 + exp(-x))) - (1-y) log(1 - 1/(1 + exp(-x)))
-///
-/// Prediction comes first, label comes second.
+/// The forward-backward pass of CTC for one sample,
+/// returning log(p(target | input)) and log(alpha * beta / y) for each
+/// (t, s), with s on the target interleaved with blanks.
+fn ctc_alpha_beta(
+    lp: &dyn Fn(usize, usize) -> f64,
+    labels: &[usize],
+    input_length: usize,
+) -> (f64, Vec<Vec<f64>>) {
+    let l = labels.len();
+    let ninf = f64::NEG_INFINITY;
+    // a transition may skip the blank between two different labels.
+    let can_skip = |s: usize| s >= 2 && labels[s] != labels[s - 2];
+
+    let mut alpha = vec![vec![ninf; l]; input_length];
+    alpha[0][0] = lp(0, labels[0]);
+    if l > 1 {
+        alpha[0][1] = lp(0, labels[1]);
+    }
+    for t in 1..input_length {
+        for s in 0..l {
+            let mut v = alpha[t - 1][s];
+            if s >= 1 {
+                v = log_add(v, alpha[t - 1][s - 1]);
+            }
+            if can_skip(s) {
+                v = log_add(v, alpha[t - 1][s - 2]);
+            }
+            alpha[t][s] = v + lp(t, labels[s]);
+        }
+    }
+
+    let mut beta = vec![vec![ninf; l]; input_length];
+    let last = input_length - 1;
+    beta[last][l - 1] = lp(last, labels[l - 1]);
+    if l > 1 {
+        beta[last][l - 2] = lp(last, labels[l - 2]);
+    }
+    for t in (0..last).rev() {
+        for s in 0..l {
+            let mut v = beta[t + 1][s];
+            if s + 1 < l {
+                v = log_add(v, beta[t + 1][s + 1]);
+            }
+            if s + 2 < l && can_skip(s + 2) {
+                v = log_add(v, beta[t + 1][s + 2]);
+            }
+            beta[t][s] = v + lp(t, labels[s]);
+        }
+    }
+
+    let mut log_likelihood = alpha[last][l - 1];
+    if l > 1 {
+        log_likelihood = log_add(log_likelihood, alpha[last][l - 2]);
+    }
+    let occupancy = (0..input_length)
+        .map(|t| {
+            (0..l)
+                .map(|s| alpha[t][s] + beta[t][s] - lp(t, labels[s]))
+                .collect()
+        })
+        .collect();
+    (log_likelihood, occupancy)
+}
+
+/// CTCLoss, -log(p(target | input)), summed over all the alignments
+/// of the target to the input, with repeats and blanks collapsed.
+/// The inputs are log-probabilities [T, N, C],
+/// targets, either [N, S] padded or all the targets concatenated,
+/// input lengths [N] and target lengths [N].
+/// The mean reduction divides each loss by its target length
+/// before averaging over the batch.
+/// Alignments that are impossible give an infinite loss,
+/// or 0 with zero_infinity, and no gradient.
 #[cfg_attr(feature = "use-serde", derive(Serialize, Deserialize))]
-pub struct BCEWithLogitsLoss {
+pub struct CTCLoss {
+    blank: usize,
+    reduction: Reduction,
+    zero_infinity: bool,
     #[cfg_attr(feature = "use-serde", serde(skip))]
     handle: OpHandle,
 }
-impl BCEWithLogitsLoss {
-    pub fn new() -> BCEWithLogitsLoss {
-        BCEWithLogitsLoss {
+impl CTCLoss {
+    pub fn new(blank: usize, reduction: Reduction, zero_infinity: bool) -> CTCLoss {
+        CTCLoss {
+            blank,
+            reduction,
+            zero_infinity,
             handle: OpHandle::new(),
         }
     }
     handle_method!();
+
+    /// The targets of each sample, interleaved with blanks.
+    fn labels(&self, input: &[Tensor]) -> Vec<Vec<usize>> {
+        let size = input[0].size();
+        if size.len() != 3 {
+            panic!(
+                "{} expect log-probabilities [T, N, C], get {:?}",
+                self.get_name(),
+                size
+            );
+        }
+        let (t_max, n, c) = (size[0], size[1], size[2]);
+        if self.blank >= c {
+            panic!(
+                "{} blank {} is not a class in {}",
+                self.get_name(),
+                self.blank,
+                c
+            );
+        }
+        let targets = input[1].get_raw_f64();
+        let input_lengths = input[2].get_raw_f64();
+        let target_lengths = input[3].get_raw_f64();
+        if input_lengths.len() != n || target_lengths.len() != n {
+            panic!(
+                "{} expect {} input and target lengths, get {} and {}",
+                self.get_name(),
+                n,
+                input_lengths.len(),
+                target_lengths.len()
+            );
+        }
+        let padded = input[1].size().len() == 2;
+        let mut offset = 0;
+        (0..n)
+            .map(|i| {
+                let input_length = input_lengths[i] as usize;
+                if input_length == 0 || input_length > t_max {
+                    panic!(
+                        "{} get input length {} for T = {}",
+                        self.get_name(),
+                        input_length,
+                        t_max
+                    );
+                }
+                let target_length = target_lengths[i] as usize;
+                let start = if padded {
+                    let s = input[1].size()[1];
+                    if target_length > s {
+                        panic!(
+                            "{} get target length {} for S = {}",
+                            self.get_name(),
+                            target_length,
+                            s
+                        );
+                    }
+                    i * s
+                } else {
+                    if offset + target_length > targets.len() {
+                        panic!(
+                            "{} get target lengths over the {} targets",
+                            self.get_name(),
+                            targets.len()
+                        );
+                    }
+                    offset
+                };
+                offset += target_length;
+                let mut labels = vec![self.blank];
+                for v in &targets[start..start + target_length] {
+                    let label = *v as usize;
+                    if label >= c || label == self.blank {
+                        panic!(
+                            "{} expect target classes in {} other than blank {}, get {}",
+                            self.get_name(),
+                            c,
+                            self.blank,
+                            label
+                        );
+                    }
+                    labels.push(label);
+                    labels.push(self.blank);
+                }
+                labels
+            })
+            .collect()
+    }
+
+    /// The gradient of the reduced output on each sample loss.
+    fn sample_grad(&self, input: &[Tensor], output_grad: &Tensor) -> Vec<f64> {
+        let n = input[0].size()[1];
+        let target_lengths = input[3].get_raw_f64();
+        match self.reduction {
+            Reduction::None => output_grad.get_raw_f64(),
+            Reduction::Sum => vec![output_grad.get_scale_f64(); n],
+            Reduction::Mean => target_lengths
+                .iter()
+                .map(|l| output_grad.get_scale_f64() / (l.max(1.) * n as f64))
+                .collect(),
+        }
+    }
 }
-impl OpTrait for BCEWithLogitsLoss {
+impl OpTrait for CTCLoss {
     fn get_name(&self) -> &'static str {
-        "BCEWithLogitsLoss"
+        "CTCLoss"
     }
     fn get_input_size(&self) -> usize {
-        2
+        4
     }
     fn get_output_size(&self) -> usize {
         1
     }
-    /// The first is the prediction, the second input is the label
-    /// ORDER IS IMPORTANT, SECOND ARGUMENT WON'T GET GRADEINT.
+    /// Only the log-probabilities get gradient.
     fn apply(&self, input: &[Tensor], output: &[Tensor]) {
-        if input.len() != self.get_input_size() {
-            panic!("{} expect two input, get {}", self.get_name(), input.len());
+        if input.len() != 4 {
+            panic!("{} expect four input, get {}", self.get_name(), input.len());
         }
-        let ret_all = input[1]
-            .mul(&input[0].neg().log1pexp())
-            .add(&(input[1].neg().add(&input[1].ones_like())).mul(&input[0].log1pexp()));
-        let tmp3 = ret_all.sum(None, false);
-        let ret = tmp3.div(&input[0].get_n().mul(&input[0].get_c()));
-        output[0].swap(&ret);
+        let labels = self.labels(input);
+        let (n, c) = (input[0].size()[1], input[0].size()[2]);
+        let log_probs = input[0].get_raw_f64();
+        let input_lengths = input[2].get_raw_f64();
+        let target_lengths = input[3].get_raw_f64();
+
+        let mut loss = input[0].sum(Some(&[0, 2]), false).zeros_like();
+        for (i, labels) in labels.iter().enumerate() {
+            let lp = |t: usize, k: usize| log_probs[(t * n + i) * c + k];
+            let (log_likelihood, _) = ctc_alpha_beta(&lp, labels, input_lengths[i] as usize);
+            let mut v = -log_likelihood;
+            if v.is_infinite() && self.zero_infinity {
+                v = 0.;
+            }
+            if self.reduction == Reduction::Mean {
+                v /= target_lengths[i].max(1.);
+            }
+            loss.set_f64(&[i], v);
+        }
+        let loss = match self.reduction {
+            Reduction::None => loss,
+            _ => reduce(&loss, self.reduction),
+        };
+        output[0].swap(&loss);
     }
 
-    /// Given the forward input value and backward output_grad,
-    /// Update weight gradient.
-    /// return backward input gradeint.
+    /// -exp(log(sum_s(alpha * beta / y)) - log(p)) on the class of s,
+    /// the gradient on the log-probabilities themselves.
     fn grad(&self, input: &[Tensor], output_grad: &[Tensor], input_grad: &[Tensor]) {
-        // ddx y log (1 + exp(-x)) = -y  / (1 + exp(x))
-        // ddx (1-y) log (1 + exp(x)) = (1-y) / (1 + exp(-x))
-        let ones = Tensor::ones_like(&input[0]);
-        let tmp1 = input[1].neg().div(&input[0].exp().add(&ones));
-        let tmp2 = input[1]
-            .neg()
-            .add(&ones)
-            .div(&input[0].neg().exp().add(&ones));
-        let tmp3 = tmp1.add(&tmp2);
-        let tmp4 = tmp3.mul(&output_grad[0]);
+        let labels = self.labels(input);
+        let (n, c) = (input[0].size()[1], input[0].size()[2]);
+        let log_probs = input[0].get_raw_f64();
+        let input_lengths = input[2].get_raw_f64();
+        let sample_grad = self.sample_grad(input, &output_grad[0]);
 
-        let zeros = Tensor::zeros_like(&input[0]);
-        input_grad[0].swap(&tmp4);
-        input_grad[1].swap(&zeros);
+        let mut g = input[0].zeros_like();
+        for (i, labels) in labels.iter().enumerate() {
+            let lp = |t: usize, k: usize| log_probs[(t * n + i) * c + k];
+            let (log_likelihood, occupancy) =
+                ctc_alpha_beta(&lp, labels, input_lengths[i] as usize);
+            if log_likelihood == f64::NEG_INFINITY {
+                // no alignment is possible, there is nothing to improve.
+                continue;
+            }
+            for (t, row) in occupancy.iter().enumerate() {
+                let mut by_class = vec![f64::NEG_INFINITY; c];
+                for (s, v) in row.iter().enumerate() {
+                    by_class[labels[s]] = log_add(by_class[labels[s]], *v);
+                }
+                for (k, v) in by_class.iter().enumerate() {
+                    if *v != f64::NEG_INFINITY {
+                        let d = -(v - log_likelihood).exp() * sample_grad[i];
+                        g.set_f64(&[t, i, k], d);
+                    }
+                }
+            }
+        }
+        input_grad[0].swap(&g);
+        for (k, i) in input.iter().enumerate().skip(1) {
+            input_grad[k].swap(&i.zeros_like());
+        }
     }
 
-    /// access weight values
     fn get_values(&self) -> Vec<Tensor> {
         Vec::new()
     }
-
     fn set_values(&self, _v: &[Tensor]) {}
 
-    /// access gradient values
     fn get_grads(&self) -> Vec<Tensor> {
         Vec::new()
     }
@@ -278,56 +741,1642 @@ impl OpTrait for BCEWithLogitsLoss {
         self
     }
 }
-impl Default for BCEWithLogitsLoss {
+impl Default for CTCLoss {
     fn default() -> Self {
-        Self::new()
+        Self::new(0, Reduction::Mean, false)
     }
 }
 
-// MarginRankingLoss
-// HingeEmbeddingLoss
-// MultiLabelMarginLoss
-// SmoothL1Loss
-// SoftMarginLoss
-// MultiLabelSoftMarginLoss
-// CosineEmbeddingLoss
-// MultiMarginLoss
-// TripletMarginLoss
+/// NLLLoss, -sum_c(w_c * t_c * x_c), with log-probabilities x on dim 1.
+/// The target t is either class indices, with one dim less than the input,
+/// or probabilities (one-hot included) with the input size.
+/// With class indices, the mean is taken over the weights of the target classes.
+#[cfg_attr(feature = "use-serde", derive(Serialize, Deserialize))]
+pub struct NLLLoss {
+    weight: Option<Tensor>,
+    ignore_index: Option<usize>,
+    reduction: Reduction,
+    #[cfg_attr(feature = "use-serde", serde(skip))]
+    handle: OpHandle,
+}
+impl NLLLoss {
+    pub fn new(reduction: Reduction) -> NLLLoss {
+        NLLLoss {
+            weight: None,
+            ignore_index: None,
+            reduction,
+            handle: OpHandle::new(),
+        }
+    }
+    /// Per-class weights, size [C].
+    pub fn set_weight(&mut self, weight: Tensor) -> &mut Self {
+        self.weight = Some(weight);
+        self
+    }
+    /// Samples with this target class are left out.
+    pub fn set_ignore_index(&mut self, ignore_index: usize) -> &mut Self {
+        self.ignore_index = Some(ignore_index);
+        self
+    }
+    handle_method!();
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::op::_gradient_checker;
+    fn target(&self, input: &[Tensor]) -> (Tensor, Tensor) {
+        class_target(
+            self.get_name(),
+            &input[0],
+            &input[1],
+            self.weight.as_ref(),
+            self.ignore_index,
+            0.,
+        )
+    }
+}
+impl OpCall for NLLLoss {
+    fn call(&mut self, inputs: &[&Var]) -> Result<Vec<Var>, AutoDiffError> {
+        let new_one = NLLLoss {
+            weight: self.weight.as_ref().map(|w| w.ref_copy()),
+            ignore_index: self.ignore_index,
+            reduction: self.reduction,
+            handle: OpHandle::new(),
+        };
 
-    #[test]
-    fn test_cross_entropy_loss() {
-        let a = Tensor::from_vec_f64(&vec![1., 2., 3., 4., 5., 6.], &vec![3, 2]);
-        let b = Tensor::from_vec_f64(&vec![0., 0., 1.], &vec![3]);
-        let c = CrossEntropyLoss::new();
-        let d = Tensor::new();
-        c.apply(&[a.ref_copy(), b.ref_copy()], &[d.ref_copy()]);
-        assert!((d.get_scale_f64() - 0.97992826).abs() < 0.001);
+        let op = Op::new(Rc::new(RefCell::new(Box::new(new_one))));
 
-        let a = Tensor::from_vec_f64(&vec![0.1, 0.1, 10., 10., 0.1, 0.1], &[2, 3]);
-        let b = Tensor::from_vec_f64(&vec![2., 0.], &vec![2]);
-        let c = CrossEntropyLoss::new();
-        let d = Tensor::new();
-        c.apply(&[a.ref_copy(), b.ref_copy()], &[d.ref_copy()]);
-        println!("{:?}", d);
+        inputs[0].called_with(op, &inputs[1..inputs.len()])
+    }
+}
+impl OpTrait for NLLLoss {
+    fn get_name(&self) -> &'static str {
+        "NLLLoss"
+    }
+    fn get_input_size(&self) -> usize {
+        2
+    }
+    fn get_output_size(&self) -> usize {
+        1
+    }
+    /// The first is the log-probabilities, the second input is the label
+    /// ORDER IS IMPORTANT, SECOND ARGUMENT WON'T GET GRADEINT.
+    fn apply(&self, input: &[Tensor], output: &[Tensor]) {
+        if input.len() != 2 {
+            panic!("{} expect two input, get {}", self.get_name(), input.len());
+        }
+        let (dist, divisor) = self.target(input);
+        let loss = dist.mul(&input[0]).sum(Some(&[1]), false).neg();
+        output[0].swap(&reduce_by(&loss, &divisor, self.reduction));
+    }
 
-        let a = Tensor::from_vec_f64(&vec![0.1, 0.1, 10., 10., 0.1, 0.1], &[2, 3]);
-        let b = Tensor::from_vec_f64(&vec![0., 2.], &vec![2]);
-        let mut c = CrossEntropyLoss::new();
-        let d = Tensor::new();
-        c.apply(&[a.ref_copy(), b.ref_copy()], &[d.ref_copy()]);
-        println!("{:?}", d);
+    fn grad(&self, input: &[Tensor], output_grad: &[Tensor], input_grad: &[Tensor]) {
+        let (dist, divisor) = self.target(input);
+        let c = input[0].size()[1];
+        let sample_grad = reduce_by_grad(&divisor, &divisor, &output_grad[0], self.reduction);
+        let g = dist.neg().mul(&repeat_class(&sample_grad.unsqueeze(1), c));
+        input_grad[0].swap(&g);
+        input_grad[1].swap(&input[1].zeros_like());
+    }
 
-        assert!(_gradient_checker(
-            &mut c,
-            &[a, b],
-            Some(&[true, false]),
-            None,
-            None
-        ));
+    /// access weight values
+    fn get_values(&self) -> Vec<Tensor> {
+        Vec::new()
+    }
+    fn set_values(&self, _v: &[Tensor]) {}
+    /// access gradient values
+    fn get_grads(&self) -> Vec<Tensor> {
+        Vec::new()
+    }
+    #[cfg(feature = "use-serde")]
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+impl Default for NLLLoss {
+    fn default() -> Self {
+        Self::new(Reduction::Mean)
+    }
+}
+
+// PoissonNLLLoss
+
+/// KLDivLoss, t * (log(t) - x), with log-probabilities x
+/// and target probabilities t, or log-probabilities if log_target is set.
+/// Elements with t = 0 contribute 0.
+#[cfg_attr(feature = "use-serde", derive(Serialize, Deserialize))]
+pub struct KLDivLoss {
+    log_target: bool,
+    reduction: Reduction,
+    #[cfg_attr(feature = "use-serde", serde(skip))]
+    handle: OpHandle,
+}
+impl KLDivLoss {
+    pub fn new(log_target: bool, reduction: Reduction) -> KLDivLoss {
+        KLDivLoss {
+            log_target,
+            reduction,
+            handle: OpHandle::new(),
+        }
+    }
+    handle_method!();
+
+    /// The target as probabilities and as log-probabilities,
+    /// with log(0) replaced by 0.
+    fn target(&self, target: &Tensor) -> (Tensor, Tensor) {
+        if self.log_target {
+            (target.exp(), target.ref_copy())
+        } else {
+            let zero = target.zeros_like();
+            let positive = target.gt(&zero);
+            let safe = target.add(&positive.ones_like().sub(&positive));
+            (target.ref_copy(), safe.log())
+        }
+    }
+}
+impl OpTrait for KLDivLoss {
+    fn get_name(&self) -> &'static str {
+        "KLDivLoss"
+    }
+    fn get_input_size(&self) -> usize {
+        2
+    }
+    fn get_output_size(&self) -> usize {
+        1
+    }
+    fn apply(&self, input: &[Tensor], output: &[Tensor]) {
+        check_same_shape(self.get_name(), input);
+        let (t, log_t) = self.target(&input[1]);
+        let loss = t.mul(&log_t.sub(&input[0]));
+        output[0].swap(&reduce(&loss, self.reduction));
+    }
+
+    /// -t on the input, t * (log(t) - x + 1) on the target,
+    /// where the target is log(t) for log_target.
+    fn grad(&self, input: &[Tensor], output_grad: &[Tensor], input_grad: &[Tensor]) {
+        let (t, log_t) = self.target(&input[1]);
+        let r = reduce_grad(&input[0], &output_grad[0], self.reduction);
+        input_grad[0].swap(&t.neg().mul(&r));
+        let dt = log_t.sub(&input[0]).add(&t.ones_like());
+        let dt = if self.log_target {
+            t.mul(&dt)
+        } else {
+            let positive = t.gt(&t.zeros_like());
+            positive.mul(&dt)
+        };
+        input_grad[1].swap(&dt.mul(&r));
+    }
+
+    fn get_values(&self) -> Vec<Tensor> {
+        Vec::new()
+    }
+    fn set_values(&self, _v: &[Tensor]) {}
+
+    fn get_grads(&self) -> Vec<Tensor> {
+        Vec::new()
+    }
+    #[cfg(feature = "use-serde")]
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+impl Default for KLDivLoss {
+    fn default() -> Self {
+        Self::new(false, Reduction::Mean)
+    }
+}
+
+/// BCELoss, -(y log(p) + (1 - y) log(1 - p)), on probabilities p.
+/// The logs are clamped at -100, as p can be 0 or 1.
+/// Prediction comes first, label comes second.
+#[cfg_attr(feature = "use-serde", derive(Serialize, Deserialize))]
+pub struct BCELoss {
+    reduction: Reduction,
+    #[cfg_attr(feature = "use-serde", serde(skip))]
+    handle: OpHandle,
+}
+impl BCELoss {
+    pub fn new(reduction: Reduction) -> BCELoss {
+        BCELoss {
+            reduction,
+            handle: OpHandle::new(),
+        }
+    }
+    handle_method!();
+}
+impl OpTrait for BCELoss {
+    fn get_name(&self) -> &'static str {
+        "BCELoss"
+    }
+    fn get_input_size(&self) -> usize {
+        2
+    }
+    fn get_output_size(&self) -> usize {
+        1
+    }
+    /// The first is the prediction, the second input is the label
+    /// ORDER IS IMPORTANT, SECOND ARGUMENT WON'T GET GRADEINT.
+    fn apply(&self, input: &[Tensor], output: &[Tensor]) {
+        check_same_shape(self.get_name(), input);
+        let (p, y) = (&input[0], &input[1]);
+        let floor = Tensor::fill(&p.size(), &scalar_like(p, -100.));
+        let one = p.ones_like();
+        let loss = y
+            .mul(&p.log().max_pair(&floor))
+            .add(&one.sub(y).mul(&one.sub(p).log().max_pair(&floor)))
+            .neg();
+        output[0].swap(&reduce(&loss, self.reduction));
+    }
+
+    /// (p - y) / (p (1 - p)), with the denominator kept from 0.
+    fn grad(&self, input: &[Tensor], output_grad: &[Tensor], input_grad: &[Tensor]) {
+        let (p, y) = (&input[0], &input[1]);
+        let one = p.ones_like();
+        let eps = Tensor::fill(&p.size(), &scalar_like(p, 1e-12));
+        let g = p
+            .sub(y)
+            .div(&p.mul(&one.sub(p)).max_pair(&eps))
+            .mul(&reduce_grad(p, &output_grad[0], self.reduction));
+        input_grad[0].swap(&g);
+        input_grad[1].swap(&y.zeros_like());
+    }
+
+    fn get_values(&self) -> Vec<Tensor> {
+        Vec::new()
+    }
+    fn set_values(&self, _v: &[Tensor]) {}
+
+    fn get_grads(&self) -> Vec<Tensor> {
+        Vec::new()
+    }
+    #[cfg(feature = "use-serde")]
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+impl Default for BCELoss {
+    fn default() -> Self {
+        Self::new(Reduction::Mean)
+    }
+}
+
+/// This loss combines a Sigmoid layer and the BCELoss in one single class.
+/// This version is more numerically stable than using a plain Sigmoid followed
+/// by a BCELoss as, by combining the operations into one layer,
+/// we take advantage of the log-sum-exp trick for numerical stability.
+///
+/// -y log (1/(1 + exp(-x))) - (1-y) log(1 - 1/(1 + exp(-x)))
+///
+/// Prediction comes first, label comes second.
+#[cfg_attr(feature = "use-serde", derive(Serialize, Deserialize))]
+pub struct BCEWithLogitsLoss {
+    reduction: Reduction,
+    #[cfg_attr(feature = "use-serde", serde(skip))]
+    handle: OpHandle,
+}
+impl BCEWithLogitsLoss {
+    pub fn new() -> BCEWithLogitsLoss {
+        Self::new_with_reduction(Reduction::Mean)
+    }
+    pub fn new_with_reduction(reduction: Reduction) -> BCEWithLogitsLoss {
+        BCEWithLogitsLoss {
+            reduction,
+            handle: OpHandle::new(),
+        }
+    }
+    handle_method!();
+}
+impl OpTrait for BCEWithLogitsLoss {
+    fn get_name(&self) -> &'static str {
+        "BCEWithLogitsLoss"
+    }
+    fn get_input_size(&self) -> usize {
+        2
+    }
+    fn get_output_size(&self) -> usize {
+        1
+    }
+    /// The first is the prediction, the second input is the label
+    /// ORDER IS IMPORTANT, SECOND ARGUMENT WON'T GET GRADEINT.
+    fn apply(&self, input: &[Tensor], output: &[Tensor]) {
+        check_same_shape(self.get_name(), input);
+        let ret_all = input[1]
+            .mul(&input[0].neg().log1pexp())
+            .add(&(input[1].neg().add(&input[1].ones_like())).mul(&input[0].log1pexp()));
+        output[0].swap(&reduce(&ret_all, self.reduction));
+    }
+
+    /// Given the forward input value and backward output_grad,
+    /// Update weight gradient.
+    /// return backward input gradeint.
+    fn grad(&self, input: &[Tensor], output_grad: &[Tensor], input_grad: &[Tensor]) {
+        // ddx y log (1 + exp(-x)) = -y  / (1 + exp(x))
+        // ddx (1-y) log (1 + exp(x)) = (1-y) / (1 + exp(-x))
+        let ones = Tensor::ones_like(&input[0]);
+        let tmp1 = input[1].neg().div(&input[0].exp().add(&ones));
+        let tmp2 = input[1]
+            .neg()
+            .add(&ones)
+            .div(&input[0].neg().exp().add(&ones));
+        let tmp3 = tmp1.add(&tmp2);
+        let tmp4 = tmp3.mul(&reduce_grad(&input[0], &output_grad[0], self.reduction));
+
+        let zeros = Tensor::zeros_like(&input[0]);
+        input_grad[0].swap(&tmp4);
+        input_grad[1].swap(&zeros);
+    }
+
+    /// access weight values
+    fn get_values(&self) -> Vec<Tensor> {
+        Vec::new()
+    }
+
+    fn set_values(&self, _v: &[Tensor]) {}
+
+    /// access gradient values
+    fn get_grads(&self) -> Vec<Tensor> {
+        Vec::new()
+    }
+    #[cfg(feature = "use-serde")]
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+impl Default for BCEWithLogitsLoss {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// SmoothL1Loss
+
+/// |d| < threshold as a 0/1 mask, with d = x - y.
+fn within(diff: &Tensor, threshold: f64) -> Tensor {
+    diff.abs()
+        .lt(&Tensor::fill(&diff.size(), &scalar_like(diff, threshold)))
+}
+
+/// SmoothL1Loss, 0.5 * d^2 / beta if |d| < beta, |d| - 0.5 * beta otherwise,
+/// with d = x - y. It is L1Loss when beta is 0.
+/// Prediction comes first, target comes second.
+#[cfg_attr(feature = "use-serde", derive(Serialize, Deserialize))]
+pub struct SmoothL1Loss {
+    beta: f64,
+    reduction: Reduction,
+    #[cfg_attr(feature = "use-serde", serde(skip))]
+    handle: OpHandle,
+}
+impl SmoothL1Loss {
+    pub fn new(beta: f64, reduction: Reduction) -> SmoothL1Loss {
+        if beta < 0. {
+            panic!("SmoothL1Loss expects non-negative beta, get {}", beta);
+        }
+        SmoothL1Loss {
+            beta,
+            reduction,
+            handle: OpHandle::new(),
+        }
+    }
+    handle_method!();
+}
+impl OpTrait for SmoothL1Loss {
+    fn get_name(&self) -> &'static str {
+        "SmoothL1Loss"
+    }
+    fn get_input_size(&self) -> usize {
+        2
+    }
+    fn get_output_size(&self) -> usize {
+        1
+    }
+    fn apply(&self, input: &[Tensor], output: &[Tensor]) {
+        check_same_shape(self.get_name(), input);
+        let diff = input[0].sub(&input[1]);
+        let loss = if self.beta == 0. {
+            diff.abs()
+        } else {
+            let small = within(&diff, self.beta);
+            let quad = diff.mul(&diff).mul(&scalar_like(&diff, 0.5 / self.beta));
+            let lin = diff.abs().sub(&scalar_like(&diff, 0.5 * self.beta));
+            small
+                .mul(&quad)
+                .add(&small.ones_like().sub(&small).mul(&lin))
+        };
+        output[0].swap(&reduce(&loss, self.reduction));
+    }
+    fn grad(&self, input: &[Tensor], output_grad: &[Tensor], input_grad: &[Tensor]) {
+        let diff = input[0].sub(&input[1]);
+        let slope = if self.beta == 0. {
+            diff.sign()
+        } else {
+            let small = within(&diff, self.beta);
+            small
+                .mul(&diff.mul(&scalar_like(&diff, 1. / self.beta)))
+                .add(&small.ones_like().sub(&small).mul(&diff.sign()))
+        };
+        let g = slope.mul(&reduce_grad(&diff, &output_grad[0], self.reduction));
+        input_grad[1].swap(&g.neg());
+        input_grad[0].swap(&g);
+    }
+
+    fn get_values(&self) -> Vec<Tensor> {
+        Vec::new()
+    }
+    fn set_values(&self, _v: &[Tensor]) {}
+
+    fn get_grads(&self) -> Vec<Tensor> {
+        Vec::new()
+    }
+    #[cfg(feature = "use-serde")]
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+impl Default for SmoothL1Loss {
+    fn default() -> Self {
+        Self::new(1., Reduction::Mean)
+    }
+}
+
+/// HuberLoss, 0.5 * d^2 if |d| < delta, delta * (|d| - 0.5 * delta) otherwise,
+/// with d = x - y. It is SmoothL1Loss with beta = delta, scaled by delta.
+/// Prediction comes first, target comes second.
+#[cfg_attr(feature = "use-serde", derive(Serialize, Deserialize))]
+pub struct HuberLoss {
+    delta: f64,
+    reduction: Reduction,
+    #[cfg_attr(feature = "use-serde", serde(skip))]
+    handle: OpHandle,
+}
+impl HuberLoss {
+    pub fn new(delta: f64, reduction: Reduction) -> HuberLoss {
+        if delta <= 0. {
+            panic!("HuberLoss expects positive delta, get {}", delta);
+        }
+        HuberLoss {
+            delta,
+            reduction,
+            handle: OpHandle::new(),
+        }
+    }
+    handle_method!();
+}
+impl OpTrait for HuberLoss {
+    fn get_name(&self) -> &'static str {
+        "HuberLoss"
+    }
+    fn get_input_size(&self) -> usize {
+        2
+    }
+    fn get_output_size(&self) -> usize {
+        1
+    }
+    fn apply(&self, input: &[Tensor], output: &[Tensor]) {
+        check_same_shape(self.get_name(), input);
+        let diff = input[0].sub(&input[1]);
+        let small = within(&diff, self.delta);
+        let quad = diff.mul(&diff).mul(&scalar_like(&diff, 0.5));
+        let lin = diff
+            .abs()
+            .sub(&scalar_like(&diff, 0.5 * self.delta))
+            .mul(&scalar_like(&diff, self.delta));
+        let loss = small
+            .mul(&quad)
+            .add(&small.ones_like().sub(&small).mul(&lin));
+        output[0].swap(&reduce(&loss, self.reduction));
+    }
+    fn grad(&self, input: &[Tensor], output_grad: &[Tensor], input_grad: &[Tensor]) {
+        let diff = input[0].sub(&input[1]);
+        let small = within(&diff, self.delta);
+        let slope = small.mul(&diff).add(
+            &small
+                .ones_like()
+                .sub(&small)
+                .mul(&diff.sign())
+                .mul(&scalar_like(&diff, self.delta)),
+        );
+        let g = slope.mul(&reduce_grad(&diff, &output_grad[0], self.reduction));
+        input_grad[1].swap(&g.neg());
+        input_grad[0].swap(&g);
+    }
+
+    fn get_values(&self) -> Vec<Tensor> {
+        Vec::new()
+    }
+    fn set_values(&self, _v: &[Tensor]) {}
+
+    fn get_grads(&self) -> Vec<Tensor> {
+        Vec::new()
+    }
+    #[cfg(feature = "use-serde")]
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+impl Default for HuberLoss {
+    fn default() -> Self {
+        Self::new(1., Reduction::Mean)
+    }
+}
+
+/// Panic unless all the inputs have the same size.
+fn check_all_same_shape(name: &str, input: &[Tensor], n: usize) {
+    if input.len() != n {
+        panic!("{} expect {} input, get {}", name, n, input.len());
+    }
+    for i in &input[1..] {
+        if !input[0].same_shape(i) {
+            panic!(
+                "{} expect inputs with the same shape, get {:?}, {:?}",
+                name,
+                input[0].size(),
+                i.size()
+            );
+        }
+    }
+}
+
+/// MarginRankingLoss, max(0, -y * (x1 - x2) + margin),
+/// with inputs x1, x2 and the label y, 1 if x1 should rank higher, -1 otherwise.
+#[cfg_attr(feature = "use-serde", derive(Serialize, Deserialize))]
+pub struct MarginRankingLoss {
+    margin: f64,
+    reduction: Reduction,
+    #[cfg_attr(feature = "use-serde", serde(skip))]
+    handle: OpHandle,
+}
+impl MarginRankingLoss {
+    pub fn new(margin: f64, reduction: Reduction) -> MarginRankingLoss {
+        MarginRankingLoss {
+            margin,
+            reduction,
+            handle: OpHandle::new(),
+        }
+    }
+    handle_method!();
+
+    /// -y * (x1 - x2) + margin
+    fn violation(&self, input: &[Tensor]) -> Tensor {
+        input[2]
+            .mul(&input[0].sub(&input[1]))
+            .neg()
+            .add(&scalar_like(&input[0], self.margin))
+    }
+}
+impl OpTrait for MarginRankingLoss {
+    fn get_name(&self) -> &'static str {
+        "MarginRankingLoss"
+    }
+    fn get_input_size(&self) -> usize {
+        3
+    }
+    fn get_output_size(&self) -> usize {
+        1
+    }
+    /// The label comes last and gets no gradient.
+    fn apply(&self, input: &[Tensor], output: &[Tensor]) {
+        check_all_same_shape(self.get_name(), input, 3);
+        let violation = self.violation(input);
+        let loss = violation.max_pair(&violation.zeros_like());
+        output[0].swap(&reduce(&loss, self.reduction));
+    }
+    fn grad(&self, input: &[Tensor], output_grad: &[Tensor], input_grad: &[Tensor]) {
+        let violation = self.violation(input);
+        let active = violation.gt(&violation.zeros_like());
+        let g =
+            input[2]
+                .mul(&active)
+                .mul(&reduce_grad(&violation, &output_grad[0], self.reduction));
+        input_grad[0].swap(&g.neg());
+        input_grad[1].swap(&g);
+        input_grad[2].swap(&input[2].zeros_like());
+    }
+
+    fn get_values(&self) -> Vec<Tensor> {
+        Vec::new()
+    }
+    fn set_values(&self, _v: &[Tensor]) {}
+
+    fn get_grads(&self) -> Vec<Tensor> {
+        Vec::new()
+    }
+    #[cfg(feature = "use-serde")]
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+impl Default for MarginRankingLoss {
+    fn default() -> Self {
+        Self::new(0., Reduction::Mean)
+    }
+}
+
+/// HingeEmbeddingLoss, x if y is 1, max(0, margin - x) if y is -1,
+/// with input x, usually a distance, and the label y.
+#[cfg_attr(feature = "use-serde", derive(Serialize, Deserialize))]
+pub struct HingeEmbeddingLoss {
+    margin: f64,
+    reduction: Reduction,
+    #[cfg_attr(feature = "use-serde", serde(skip))]
+    handle: OpHandle,
+}
+impl HingeEmbeddingLoss {
+    pub fn new(margin: f64, reduction: Reduction) -> HingeEmbeddingLoss {
+        HingeEmbeddingLoss {
+            margin,
+            reduction,
+            handle: OpHandle::new(),
+        }
+    }
+    handle_method!();
+
+    /// Masks of the positive and negative labels, and margin - x.
+    fn parts(&self, input: &[Tensor]) -> (Tensor, Tensor, Tensor) {
+        let zero = input[1].zeros_like();
+        let positive = input[1].gt(&zero);
+        let negative = input[1].lt(&zero);
+        let gap = input[0].neg().add(&scalar_like(&input[0], self.margin));
+        (positive, negative, gap)
+    }
+}
+impl OpTrait for HingeEmbeddingLoss {
+    fn get_name(&self) -> &'static str {
+        "HingeEmbeddingLoss"
+    }
+    fn get_input_size(&self) -> usize {
+        2
+    }
+    fn get_output_size(&self) -> usize {
+        1
+    }
+    /// The label comes last and gets no gradient.
+    fn apply(&self, input: &[Tensor], output: &[Tensor]) {
+        check_same_shape(self.get_name(), input);
+        let (positive, negative, gap) = self.parts(input);
+        let loss = positive
+            .mul(&input[0])
+            .add(&negative.mul(&gap.max_pair(&gap.zeros_like())));
+        output[0].swap(&reduce(&loss, self.reduction));
+    }
+    fn grad(&self, input: &[Tensor], output_grad: &[Tensor], input_grad: &[Tensor]) {
+        let (positive, negative, gap) = self.parts(input);
+        let g = positive
+            .sub(&negative.mul(&gap.gt(&gap.zeros_like())))
+            .mul(&reduce_grad(&input[0], &output_grad[0], self.reduction));
+        input_grad[0].swap(&g);
+        input_grad[1].swap(&input[1].zeros_like());
+    }
+
+    fn get_values(&self) -> Vec<Tensor> {
+        Vec::new()
+    }
+    fn set_values(&self, _v: &[Tensor]) {}
+
+    fn get_grads(&self) -> Vec<Tensor> {
+        Vec::new()
+    }
+    #[cfg(feature = "use-serde")]
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+impl Default for HingeEmbeddingLoss {
+    fn default() -> Self {
+        Self::new(1., Reduction::Mean)
+    }
+}
+
+// MultiLabelMarginLoss
+// SoftMarginLoss
+// MultiLabelSoftMarginLoss
+/// Repeat a tensor reduced over the last dim back to the size of like.
+fn repeat_last(reduced: &Tensor, like: &Tensor) -> Tensor {
+    let size = like.size();
+    let mut sizes = vec![1; size.len()];
+    sizes[size.len() - 1] = size[size.len() - 1];
+    reduced.unsqueeze(size.len() - 1).repeat(&sizes)
+}
+
+/// CosineEmbeddingLoss, 1 - cos(x1, x2) if y is 1,
+/// max(0, cos(x1, x2) - margin) if y is -1.
+/// The cosine is taken over the last dim of x1 and x2,
+/// the label y has the remaining leading dims.
+#[cfg_attr(feature = "use-serde", derive(Serialize, Deserialize))]
+pub struct CosineEmbeddingLoss {
+    margin: f64,
+    reduction: Reduction,
+    #[cfg_attr(feature = "use-serde", serde(skip))]
+    handle: OpHandle,
+}
+impl CosineEmbeddingLoss {
+    pub fn new(margin: f64, reduction: Reduction) -> CosineEmbeddingLoss {
+        CosineEmbeddingLoss {
+            margin,
+            reduction,
+            handle: OpHandle::new(),
+        }
+    }
+    handle_method!();
+
+    /// The cosine, and the squared norms kept from 0.
+    fn cosine(&self, input: &[Tensor]) -> (Tensor, Tensor, Tensor) {
+        let last = input[0].size().len() - 1;
+        let eps = scalar_like(&input[0], 1e-12);
+        let sq1 = input[0].mul(&input[0]).sum(Some(&[last]), false).add(&eps);
+        let sq2 = input[1].mul(&input[1]).sum(Some(&[last]), false).add(&eps);
+        let dot = input[0].mul(&input[1]).sum(Some(&[last]), false);
+        let cos = dot.div(&sq1.mul(&sq2).sqrt());
+        (cos, sq1, sq2)
+    }
+}
+impl OpTrait for CosineEmbeddingLoss {
+    fn get_name(&self) -> &'static str {
+        "CosineEmbeddingLoss"
+    }
+    fn get_input_size(&self) -> usize {
+        3
+    }
+    fn get_output_size(&self) -> usize {
+        1
+    }
+    /// The label comes last and gets no gradient.
+    fn apply(&self, input: &[Tensor], output: &[Tensor]) {
+        if input.len() != 3 {
+            panic!(
+                "{} expect three input, get {}",
+                self.get_name(),
+                input.len()
+            );
+        }
+        check_same_shape(self.get_name(), &input[..2]);
+        let (cos, _, _) = self.cosine(input);
+        let label = input[2].reshape(&cos.size());
+        let zero = label.zeros_like();
+        let gap = cos.sub(&scalar_like(&cos, self.margin));
+        let loss = label
+            .gt(&zero)
+            .mul(&cos.ones_like().sub(&cos))
+            .add(&label.lt(&zero).mul(&gap.max_pair(&zero)));
+        output[0].swap(&reduce(&loss, self.reduction));
+    }
+
+    /// d cos / d x1 = x2 / (|x1| |x2|) - cos * x1 / |x1|^2, and the same for x2.
+    fn grad(&self, input: &[Tensor], output_grad: &[Tensor], input_grad: &[Tensor]) {
+        let (cos, sq1, sq2) = self.cosine(input);
+        let label = input[2].reshape(&cos.size());
+        let zero = label.zeros_like();
+        let gap = cos.sub(&scalar_like(&cos, self.margin));
+        let d_cos = label
+            .lt(&zero)
+            .mul(&gap.gt(&zero))
+            .sub(&label.gt(&zero))
+            .mul(&reduce_grad(&cos, &output_grad[0], self.reduction));
+
+        let inv = sq1.mul(&sq2).sqrt().reciprocal();
+        let g1 = input[1]
+            .mul(&repeat_last(&inv, &input[0]))
+            .sub(&input[0].mul(&repeat_last(&cos.div(&sq1), &input[0])));
+        let g2 = input[0]
+            .mul(&repeat_last(&inv, &input[0]))
+            .sub(&input[1].mul(&repeat_last(&cos.div(&sq2), &input[0])));
+        let d_cos = repeat_last(&d_cos, &input[0]);
+        input_grad[0].swap(&g1.mul(&d_cos));
+        input_grad[1].swap(&g2.mul(&d_cos));
+        input_grad[2].swap(&input[2].zeros_like());
+    }
+
+    fn get_values(&self) -> Vec<Tensor> {
+        Vec::new()
+    }
+    fn set_values(&self, _v: &[Tensor]) {}
+
+    fn get_grads(&self) -> Vec<Tensor> {
+        Vec::new()
+    }
+    #[cfg(feature = "use-serde")]
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+impl Default for CosineEmbeddingLoss {
+    fn default() -> Self {
+        Self::new(0., Reduction::Mean)
+    }
+}
+
+// MultiMarginLoss
+/// The p-norm distance over the last dim, ||x1 - x2 + eps||_p,
+/// returned with the difference.
+fn pairwise_distance(x1: &Tensor, x2: &Tensor, p: f64) -> (Tensor, Tensor) {
+    let last = x1.size().len() - 1;
+    let diff = x1.sub(x2).add(&scalar_like(x1, 1e-6));
+    let norm = diff
+        .abs()
+        .pow_f64(p)
+        .sum(Some(&[last]), false)
+        .pow_f64(1. / p);
+    (diff, norm)
+}
+
+/// The gradient of the p-norm on the difference,
+/// sign(d) * |d|^(p - 1) / norm^(p - 1).
+fn pairwise_distance_grad(diff: &Tensor, norm: &Tensor, p: f64) -> Tensor {
+    let scale = norm.pow_f64(p - 1.);
+    diff.sign()
+        .mul(&diff.abs().pow_f64(p - 1.))
+        .div(&repeat_last(&scale, diff))
+}
+
+/// TripletMarginLoss, max(d(a, p) - d(a, n) + margin, 0),
+/// with anchor a, positive p, negative n and the p-norm distance
+/// over the last dim.
+/// With swap, d(a, n) is replaced by d(p, n) when that is smaller.
+#[cfg_attr(feature = "use-serde", derive(Serialize, Deserialize))]
+pub struct TripletMarginLoss {
+    margin: f64,
+    p: f64,
+    swap: bool,
+    reduction: Reduction,
+    #[cfg_attr(feature = "use-serde", serde(skip))]
+    handle: OpHandle,
+}
+impl TripletMarginLoss {
+    pub fn new(margin: f64, p: f64, swap: bool, reduction: Reduction) -> TripletMarginLoss {
+        if p < 1. {
+            panic!("TripletMarginLoss expects p >= 1, get {}", p);
+        }
+        TripletMarginLoss {
+            margin,
+            p,
+            swap,
+            reduction,
+            handle: OpHandle::new(),
+        }
+    }
+    handle_method!();
+
+    /// The distances (d(a, p), d(a, n), d(p, n)) with the differences,
+    /// and the mask of samples using d(p, n).
+    fn distances(&self, input: &[Tensor]) -> ([(Tensor, Tensor); 3], Tensor) {
+        let ap = pairwise_distance(&input[0], &input[1], self.p);
+        let an = pairwise_distance(&input[0], &input[2], self.p);
+        let pn = pairwise_distance(&input[1], &input[2], self.p);
+        let swapped = if self.swap {
+            pn.1.lt(&an.1)
+        } else {
+            an.1.zeros_like()
+        };
+        ([ap, an, pn], swapped)
+    }
+}
+impl OpTrait for TripletMarginLoss {
+    fn get_name(&self) -> &'static str {
+        "TripletMarginLoss"
+    }
+    fn get_input_size(&self) -> usize {
+        3
+    }
+    fn get_output_size(&self) -> usize {
+        1
+    }
+    /// The inputs are anchor, positive and negative.
+    fn apply(&self, input: &[Tensor], output: &[Tensor]) {
+        check_all_same_shape(self.get_name(), input, 3);
+        let ([ap, an, pn], swapped) = self.distances(input);
+        let negative = swapped
+            .mul(&pn.1)
+            .add(&swapped.ones_like().sub(&swapped).mul(&an.1));
+        let violation = ap.1.sub(&negative).add(&scalar_like(&ap.1, self.margin));
+        let loss = violation.max_pair(&violation.zeros_like());
+        output[0].swap(&reduce(&loss, self.reduction));
+    }
+    fn grad(&self, input: &[Tensor], output_grad: &[Tensor], input_grad: &[Tensor]) {
+        let ([ap, an, pn], swapped) = self.distances(input);
+        let negative = swapped
+            .mul(&pn.1)
+            .add(&swapped.ones_like().sub(&swapped).mul(&an.1));
+        let violation = ap.1.sub(&negative).add(&scalar_like(&ap.1, self.margin));
+        let r = violation.gt(&violation.zeros_like()).mul(&reduce_grad(
+            &violation,
+            &output_grad[0],
+            self.reduction,
+        ));
+        let use_pn = repeat_last(&swapped.mul(&r), &input[0]);
+        let use_an = repeat_last(&swapped.ones_like().sub(&swapped).mul(&r), &input[0]);
+        let r = repeat_last(&r, &input[0]);
+
+        let g_ap = pairwise_distance_grad(&ap.0, &ap.1, self.p);
+        let g_an = pairwise_distance_grad(&an.0, &an.1, self.p);
+        let g_pn = pairwise_distance_grad(&pn.0, &pn.1, self.p);
+        input_grad[0].swap(&g_ap.mul(&r).sub(&g_an.mul(&use_an)));
+        input_grad[1].swap(&g_ap.mul(&r).neg().sub(&g_pn.mul(&use_pn)));
+        input_grad[2].swap(&g_an.mul(&use_an).add(&g_pn.mul(&use_pn)));
+    }
+
+    fn get_values(&self) -> Vec<Tensor> {
+        Vec::new()
+    }
+    fn set_values(&self, _v: &[Tensor]) {}
+
+    fn get_grads(&self) -> Vec<Tensor> {
+        Vec::new()
+    }
+    #[cfg(feature = "use-serde")]
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+impl Default for TripletMarginLoss {
+    fn default() -> Self {
+        Self::new(1., 2., false, Reduction::Mean)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::op::_gradient_checker;
+
+    // uneven weights on the output, so Reduction::None is checked elementwise.
+    fn output_weight(op: &dyn OpTrait, input: &[Tensor]) -> Tensor {
+        let output = Tensor::new();
+        op.apply(input, &[output.ref_copy()]);
+        let size = output.size();
+        let n: usize = size.iter().product();
+        let weight: Vec<f64> = (0..n).map(|i| 0.5 + 0.25 * i as f64).collect();
+        Tensor::from_vec_f64(&weight, &size)
+    }
+
+    #[test]
+    fn test_cross_entropy_loss() {
+        let a = Tensor::from_vec_f64(&vec![1., 2., 3., 4., 5., 6.], &vec![3, 2]);
+        let b = Tensor::from_vec_f64(&vec![0., 0., 1.], &vec![3]);
+        let c = CrossEntropyLoss::new();
+        let d = Tensor::new();
+        c.apply(&[a.ref_copy(), b.ref_copy()], &[d.ref_copy()]);
+        assert!((d.get_scale_f64() - 0.97992826).abs() < 0.001);
+
+        let a = Tensor::from_vec_f64(&vec![0.1, 0.1, 10., 10., 0.1, 0.1], &[2, 3]);
+        let b = Tensor::from_vec_f64(&vec![2., 0.], &vec![2]);
+        let c = CrossEntropyLoss::new();
+        let d = Tensor::new();
+        c.apply(&[a.ref_copy(), b.ref_copy()], &[d.ref_copy()]);
+        println!("{:?}", d);
+
+        let a = Tensor::from_vec_f64(&vec![0.1, 0.1, 10., 10., 0.1, 0.1], &[2, 3]);
+        let b = Tensor::from_vec_f64(&vec![0., 2.], &vec![2]);
+        let mut c = CrossEntropyLoss::new();
+        let d = Tensor::new();
+        c.apply(&[a.ref_copy(), b.ref_copy()], &[d.ref_copy()]);
+        println!("{:?}", d);
+
+        assert!(_gradient_checker(
+            &mut c,
+            &[a, b],
+            Some(&[true, false]),
+            None,
+            None,
+            None
+        ));
+    }
+
+    #[test]
+    fn test_cross_entropy_loss_reduction() {
+        let a = Tensor::from_vec_f64(&[1., 2., 3., 4., 5., 6.], &[3, 2]);
+        let b = Tensor::from_vec_f64(&[0., 0., 1.], &[3]);
+        let d = Tensor::new();
+        CrossEntropyLoss::new_with_reduction(Reduction::None)
+            .apply(&[a.ref_copy(), b.ref_copy()], &[d.ref_copy()]);
+        assert_eq!(d.size(), vec![3]);
+        let mean = d.mean(None, false).get_scale_f64();
+        assert!((mean - 0.97992826).abs() < 0.001);
+
+        let mut c = CrossEntropyLoss::new_with_reduction(Reduction::Sum);
+        c.apply(&[a.ref_copy(), b.ref_copy()], &[d.ref_copy()]);
+        assert!((d.get_scale_f64() - 3. * 0.97992826).abs() < 0.001);
+        assert!(_gradient_checker(
+            &mut c,
+            &[a.ref_copy(), b.ref_copy()],
+            Some(&[true, false]),
+            None,
+            None,
+            None
+        ));
+
+        let mut c = CrossEntropyLoss::new_with_reduction(Reduction::None);
+        let input = [a, b];
+        let weight = output_weight(&c, &input);
+        assert!(_gradient_checker(
+            &mut c,
+            &input,
+            Some(&[true, false]),
+            Some(Tensor::from_vec_f64(&[1e-6], &[1])),
+            None,
+            Some(&[weight])
+        ));
+    }
+
+    #[test]
+    fn test_mse_loss() {
+        let a = Tensor::from_vec_f64(&[1., 2., 3., 4., 5., 6.], &[3, 2]);
+        let b = Tensor::from_vec_f64(&[2., 1., 3., 6., 5., 4.5], &[3, 2]);
+        let d = Tensor::new();
+        MSELoss::new_with_reduction(Reduction::None)
+            .apply(&[a.ref_copy(), b.ref_copy()], &[d.ref_copy()]);
+        assert_eq!(
+            d,
+            Tensor::from_vec_f64(&[1., 1., 0., 4., 0., 2.25], &[3, 2])
+        );
+
+        for reduction in [Reduction::None, Reduction::Mean, Reduction::Sum] {
+            let mut c = MSELoss::new_with_reduction(reduction);
+            let input = [a.ref_copy(), b.ref_copy()];
+            let weight = output_weight(&c, &input);
+            assert!(_gradient_checker(
+                &mut c,
+                &input,
+                None,
+                Some(Tensor::from_vec_f64(&[1e-6], &[1])),
+                None,
+                Some(&[weight])
+            ));
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "the same shape")]
+    fn test_mse_loss_shape() {
+        // [3, 1] - [3] would broadcast to [3, 3].
+        let a = Tensor::from_vec_f64(&[1., 2., 3.], &[3, 1]);
+        let b = Tensor::from_vec_f64(&[1., 2., 3.], &[3]);
+        MSELoss::new().apply(&[a, b], &[Tensor::new()]);
+    }
+
+    #[test]
+    fn test_bce_with_logits_loss() {
+        let a = Tensor::from_vec_f64(&[0.5, -1., 2., -0.3], &[2, 2]);
+        let b = Tensor::from_vec_f64(&[1., 0., 0., 1.], &[2, 2]);
+        for reduction in [Reduction::None, Reduction::Mean, Reduction::Sum] {
+            let mut c = BCEWithLogitsLoss::new_with_reduction(reduction);
+            let input = [a.ref_copy(), b.ref_copy()];
+            let weight = output_weight(&c, &input);
+            assert!(_gradient_checker(
+                &mut c,
+                &input,
+                Some(&[true, false]),
+                Some(Tensor::from_vec_f64(&[1e-6], &[1])),
+                None,
+                Some(&[weight])
+            ));
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_bce_with_logits_loss_shape() {
+        let a = Tensor::from_vec_f64(&[0.5, -1., 2., -0.3], &[2, 2]);
+        let b = Tensor::from_vec_f64(&[1., 0.], &[2]);
+        BCEWithLogitsLoss::new().apply(&[a, b], &[Tensor::new()]);
+    }
+
+    #[test]
+    fn test_l1_loss() {
+        let a = Tensor::from_vec_f64(&[1., 2., 3., 4., 5., 6.], &[3, 2]);
+        let b = Tensor::from_vec_f64(&[2., 1., 3.5, 6., 5.5, 4.5], &[3, 2]);
+        let d = Tensor::new();
+        L1Loss::new(Reduction::Mean).apply(&[a.ref_copy(), b.ref_copy()], &[d.ref_copy()]);
+        assert!((d.get_scale_f64() - 6.5 / 6.).abs() < 1e-12);
+
+        for reduction in [Reduction::None, Reduction::Mean, Reduction::Sum] {
+            let mut c = L1Loss::new(reduction);
+            let input = [a.ref_copy(), b.ref_copy()];
+            let weight = output_weight(&c, &input);
+            assert!(_gradient_checker(
+                &mut c,
+                &input,
+                None,
+                Some(Tensor::from_vec_f64(&[1e-6], &[1])),
+                None,
+                Some(&[weight])
+            ));
+        }
+    }
+
+    #[test]
+    fn test_smooth_l1_loss() {
+        let a = Tensor::from_vec_f64(&[1., 2., 3., 4., 5., 6.], &[3, 2]);
+        let b = Tensor::from_vec_f64(&[2.5, 1., 3.5, 6., 5.2, 4.5], &[3, 2]);
+        let d = Tensor::new();
+        SmoothL1Loss::new(1., Reduction::None)
+            .apply(&[a.ref_copy(), b.ref_copy()], &[d.ref_copy()]);
+        let expected = Tensor::from_vec_f64(&[1., 0.5, 0.125, 1.5, 0.02, 1.], &[3, 2]);
+        assert!(d.sub(&expected).abs().max(None, false).get_scale_f64() < 1e-12);
+
+        // beta = 0 is L1Loss.
+        SmoothL1Loss::new(0., Reduction::Sum).apply(&[a.ref_copy(), b.ref_copy()], &[d.ref_copy()]);
+        assert!((d.get_scale_f64() - 6.7).abs() < 1e-12);
+
+        for beta in [0., 1., 2.] {
+            for reduction in [Reduction::None, Reduction::Mean, Reduction::Sum] {
+                let mut c = SmoothL1Loss::new(beta, reduction);
+                let input = [a.ref_copy(), b.ref_copy()];
+                let weight = output_weight(&c, &input);
+                assert!(_gradient_checker(
+                    &mut c,
+                    &input,
+                    None,
+                    Some(Tensor::from_vec_f64(&[1e-6], &[1])),
+                    None,
+                    Some(&[weight])
+                ));
+            }
+        }
+    }
+
+    #[test]
+    fn test_huber_loss() {
+        let a = Tensor::from_vec_f64(&[1., 2., 3., 4., 5., 6.], &[3, 2]);
+        let b = Tensor::from_vec_f64(&[2.5, 1., 3.5, 6., 5.2, 4.5], &[3, 2]);
+        let d = Tensor::new();
+        HuberLoss::new(2., Reduction::None).apply(&[a.ref_copy(), b.ref_copy()], &[d.ref_copy()]);
+        let expected = Tensor::from_vec_f64(&[1.125, 0.5, 0.125, 2., 0.02, 1.125], &[3, 2]);
+        assert!(d.sub(&expected).abs().max(None, false).get_scale_f64() < 1e-12);
+
+        for delta in [0.5, 1., 2.] {
+            for reduction in [Reduction::None, Reduction::Mean, Reduction::Sum] {
+                let mut c = HuberLoss::new(delta, reduction);
+                let input = [a.ref_copy(), b.ref_copy()];
+                let weight = output_weight(&c, &input);
+                assert!(_gradient_checker(
+                    &mut c,
+                    &input,
+                    None,
+                    Some(Tensor::from_vec_f64(&[1e-6], &[1])),
+                    None,
+                    Some(&[weight])
+                ));
+            }
+        }
+    }
+
+    #[test]
+    fn test_cross_entropy_loss_options() {
+        // every row has log-softmax [-ln(1 + e), -ln(1 + 1/e)].
+        let (big, small) = (1.31326169, 0.31326169);
+        let a = Tensor::from_vec_f64(&[1., 2., 3., 4., 5., 6.], &[3, 2]);
+        let b = Tensor::from_vec_f64(&[0., 0., 1.], &[3]);
+        let d = Tensor::new();
+
+        let mut c = CrossEntropyLoss::new();
+        c.set_weight(Tensor::from_vec_f64(&[1., 2.], &[2]));
+        c.apply(&[a.ref_copy(), b.ref_copy()], &[d.ref_copy()]);
+        assert!((d.get_scale_f64() - (2. * big + 2. * small) / 4.).abs() < 1e-6);
+        assert!(_gradient_checker(
+            &mut c,
+            &[a.ref_copy(), b.ref_copy()],
+            Some(&[true, false]),
+            None,
+            None,
+            None
+        ));
+
+        let mut c = CrossEntropyLoss::new();
+        c.set_ignore_index(1);
+        c.apply(&[a.ref_copy(), b.ref_copy()], &[d.ref_copy()]);
+        assert!((d.get_scale_f64() - big).abs() < 1e-6);
+        assert!(_gradient_checker(
+            &mut c,
+            &[a.ref_copy(), b.ref_copy()],
+            Some(&[true, false]),
+            None,
+            None,
+            None
+        ));
+
+        let mut c = CrossEntropyLoss::new();
+        c.set_label_smoothing(0.2);
+        c.apply(&[a.ref_copy(), b.ref_copy()], &[d.ref_copy()]);
+        let expected = (2. * (0.9 * big + 0.1 * small) + 0.1 * big + 0.9 * small) / 3.;
+        assert!((d.get_scale_f64() - expected).abs() < 1e-6);
+        assert!(_gradient_checker(
+            &mut c,
+            &[a.ref_copy(), b.ref_copy()],
+            Some(&[true, false]),
+            None,
+            None,
+            None
+        ));
+
+        // one-hot targets give the same loss as class indices.
+        let one_hot = Tensor::from_vec_f64(&[1., 0., 1., 0., 0., 1.], &[3, 2]);
+        let mut c = CrossEntropyLoss::new_with_reduction(Reduction::Sum);
+        c.apply(&[a.ref_copy(), one_hot.ref_copy()], &[d.ref_copy()]);
+        assert!((d.get_scale_f64() - (2. * big + small)).abs() < 1e-6);
+        assert!(_gradient_checker(
+            &mut c,
+            &[a.ref_copy(), one_hot],
+            Some(&[true, false]),
+            None,
+            None,
+            None
+        ));
+
+        // class scores on dim 1 with a trailing dim.
+        let a = Tensor::from_vec_f64(&[1., 3., 2., 4., 0.5, -1., 2., 0.], &[2, 2, 2]);
+        let b = Tensor::from_vec_f64(&[1., 0., 0., 1.], &[2, 2]);
+        let mut c = CrossEntropyLoss::new();
+        c.set_weight(Tensor::from_vec_f64(&[0.3, 0.7], &[2]))
+            .set_label_smoothing(0.1);
+        assert!(_gradient_checker(
+            &mut c,
+            &[a, b],
+            Some(&[true, false]),
+            None,
+            None,
+            None
+        ));
+    }
+
+    #[test]
+    fn test_cross_entropy_loss_call() {
+        let mut c = CrossEntropyLoss::new();
+        c.set_weight(Tensor::from_vec_f64(&[1., 2.], &[2]));
+        let a = Var::new(&[1., 2., 3., 4., 5., 6.], &[3, 2]);
+        let b = Var::new(&[0., 0., 1.], &[3]);
+        let loss = c.call(&[&a, &b]).unwrap().pop().unwrap();
+        assert!((loss.val().get_scale_f64() - 0.81326169).abs() < 1e-6);
+        loss.bp().unwrap();
+        assert_eq!(a.grad().unwrap().size(), vec![3, 2]);
+    }
+
+    #[test]
+    fn test_nll_loss() {
+        let a = Tensor::from_vec_f64(&[1., 2., 3., 4., 5., 6.], &[3, 2]);
+        let b = Tensor::from_vec_f64(&[0., 0., 1.], &[3]);
+        let log_p = a.sub(&a.logsumexp(Some(&[1]), true).repeat(&[1, 2]));
+        for reduction in [Reduction::Mean, Reduction::Sum] {
+            let ce = Tensor::new();
+            let mut c = CrossEntropyLoss::new_with_reduction(reduction);
+            c.set_weight(Tensor::from_vec_f64(&[1., 2.], &[2]));
+            c.apply(&[a.ref_copy(), b.ref_copy()], &[ce.ref_copy()]);
+
+            let d = Tensor::new();
+            let mut c = NLLLoss::new(reduction);
+            c.set_weight(Tensor::from_vec_f64(&[1., 2.], &[2]));
+            c.apply(&[log_p.ref_copy(), b.ref_copy()], &[d.ref_copy()]);
+            assert!((d.get_scale_f64() - ce.get_scale_f64()).abs() < 1e-12);
+            assert!(_gradient_checker(
+                &mut c,
+                &[log_p.ref_copy(), b.ref_copy()],
+                Some(&[true, false]),
+                None,
+                None,
+                None
+            ));
+        }
+
+        let d = Tensor::new();
+        let mut c = NLLLoss::new(Reduction::None);
+        c.set_ignore_index(0);
+        c.apply(&[log_p.ref_copy(), b.ref_copy()], &[d.ref_copy()]);
+        assert_eq!(d.size(), vec![3]);
+        assert_eq!(d.get_f64(&[0]), 0.);
+        assert!((d.get_f64(&[2]) - 0.31326169).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_kl_div_loss() {
+        let t = Tensor::from_vec_f64(&[0.1, 0.6, 0.3, 0.5, 0.3, 0.2], &[2, 3]);
+        let d = Tensor::new();
+        let x = Tensor::from_vec_f64(&[0.2, 0.3, 0.5, 0.6, 0.3, 0.1], &[2, 3]).log();
+        KLDivLoss::new(false, Reduction::Sum).apply(&[x.ref_copy(), t.ref_copy()], &[d.ref_copy()]);
+        let expected: f64 = [
+            (0.1, 0.2),
+            (0.6, 0.3),
+            (0.3, 0.5),
+            (0.5, 0.6),
+            (0.3, 0.3),
+            (0.2, 0.1),
+        ]
+        .iter()
+        .map(|(t, p): &(f64, f64)| t * (t / p).ln())
+        .sum();
+        assert!((d.get_scale_f64() - expected).abs() < 1e-12);
+
+        // zero targets contribute nothing.
+        let t0 = Tensor::from_vec_f64(&[0., 1., 0., 0.5, 0.5, 0.], &[2, 3]);
+        KLDivLoss::new(false, Reduction::Sum)
+            .apply(&[x.ref_copy(), t0.ref_copy()], &[d.ref_copy()]);
+        let expected = -(0.3_f64).ln() + 0.5 * (0.5_f64 / 0.6).ln() + 0.5 * (0.5_f64 / 0.3).ln();
+        assert!((d.get_scale_f64() - expected).abs() < 1e-12);
+
+        for log_target in [false, true] {
+            let target = if log_target { t.log() } else { t.ref_copy() };
+            for reduction in [Reduction::Mean, Reduction::Sum] {
+                let mut c = KLDivLoss::new(log_target, reduction);
+                assert!(_gradient_checker(
+                    &mut c,
+                    &[x.ref_copy(), target.ref_copy()],
+                    None,
+                    Some(Tensor::from_vec_f64(&[1e-6], &[1])),
+                    None,
+                    None
+                ));
+            }
+        }
+    }
+
+    #[test]
+    fn test_bce_loss() {
+        let p = Tensor::from_vec_f64(&[0.2, 0.7, 0.9, 0.4], &[2, 2]);
+        let y = Tensor::from_vec_f64(&[0., 1., 1., 0.], &[2, 2]);
+        let d = Tensor::new();
+        BCELoss::new(Reduction::Mean).apply(&[p.ref_copy(), y.ref_copy()], &[d.ref_copy()]);
+        let expected = -((0.8_f64).ln() + (0.7_f64).ln() + (0.9_f64).ln() + (0.6_f64).ln()) / 4.;
+        assert!((d.get_scale_f64() - expected).abs() < 1e-12);
+
+        // the same as BCEWithLogitsLoss on the logits.
+        let logits = p.div(&p.ones_like().sub(&p)).log();
+        let e = Tensor::new();
+        BCEWithLogitsLoss::new().apply(&[logits, y.ref_copy()], &[e.ref_copy()]);
+        assert!((d.get_scale_f64() - e.get_scale_f64()).abs() < 1e-12);
+
+        // saturated predictions stay finite.
+        let saturated = Tensor::from_vec_f64(&[0., 1., 1., 0.], &[2, 2]);
+        BCELoss::new(Reduction::Sum).apply(
+            &[saturated, y.ref_copy().ones_like().sub(&y)],
+            &[d.ref_copy()],
+        );
+        assert_eq!(d.get_scale_f64(), 400.);
+
+        for reduction in [Reduction::Mean, Reduction::Sum] {
+            let mut c = BCELoss::new(reduction);
+            assert!(_gradient_checker(
+                &mut c,
+                &[p.ref_copy(), y.ref_copy()],
+                Some(&[true, false]),
+                Some(Tensor::from_vec_f64(&[1e-6], &[1])),
+                None,
+                None
+            ));
+        }
+    }
+
+    #[test]
+    fn test_margin_ranking_loss() {
+        let x1 = Tensor::from_vec_f64(&[1., 2., 3.], &[3]);
+        let x2 = Tensor::from_vec_f64(&[2., 1., 3.], &[3]);
+        let y = Tensor::from_vec_f64(&[1., 1., -1.], &[3]);
+        let d = Tensor::new();
+        let mut c = MarginRankingLoss::new(0.5, Reduction::Sum);
+        c.apply(
+            &[x1.ref_copy(), x2.ref_copy(), y.ref_copy()],
+            &[d.ref_copy()],
+        );
+        assert_eq!(d.get_scale_f64(), 2.);
+        assert!(_gradient_checker(
+            &mut c,
+            &[x1, x2, y],
+            Some(&[true, true, false]),
+            Some(Tensor::from_vec_f64(&[1e-6], &[1])),
+            None,
+            None
+        ));
+    }
+
+    #[test]
+    fn test_hinge_embedding_loss() {
+        let x = Tensor::from_vec_f64(&[0.3, 1.2, 0.5, 2.], &[4]);
+        let y = Tensor::from_vec_f64(&[1., -1., -1., 1.], &[4]);
+        let d = Tensor::new();
+        HingeEmbeddingLoss::new(1., Reduction::Sum)
+            .apply(&[x.ref_copy(), y.ref_copy()], &[d.ref_copy()]);
+        assert!((d.get_scale_f64() - 2.8).abs() < 1e-12);
+        let mut c = HingeEmbeddingLoss::new(1., Reduction::Mean);
+        assert!(_gradient_checker(
+            &mut c,
+            &[x, y],
+            Some(&[true, false]),
+            Some(Tensor::from_vec_f64(&[1e-6], &[1])),
+            None,
+            None
+        ));
+    }
+
+    #[test]
+    fn test_cosine_embedding_loss() {
+        let x1 = Tensor::from_vec_f64(&[1., 0., 1., 1.], &[2, 2]);
+        let x2 = Tensor::from_vec_f64(&[0., 1., 1., 1.], &[2, 2]);
+        let y = Tensor::from_vec_f64(&[1., -1.], &[2]);
+        let d = Tensor::new();
+        CosineEmbeddingLoss::new(0.5, Reduction::None)
+            .apply(&[x1, x2, y.ref_copy()], &[d.ref_copy()]);
+        assert!(
+            d.sub(&Tensor::from_vec_f64(&[1., 0.5], &[2]))
+                .abs()
+                .max(None, false)
+                .get_scale_f64()
+                < 1e-9
+        );
+
+        let x1 = Tensor::from_vec_f64(&[1., 0.5, -0.3, 0.2, 2., 1.], &[2, 3]);
+        let x2 = Tensor::from_vec_f64(&[0.4, 1., 0.1, 0.3, 1.5, 1.2], &[2, 3]);
+        for reduction in [Reduction::Mean, Reduction::Sum] {
+            let mut c = CosineEmbeddingLoss::new(0.1, reduction);
+            assert!(_gradient_checker(
+                &mut c,
+                &[x1.ref_copy(), x2.ref_copy(), y.ref_copy()],
+                Some(&[true, true, false]),
+                Some(Tensor::from_vec_f64(&[1e-6], &[1])),
+                None,
+                None
+            ));
+        }
+    }
+
+    #[test]
+    fn test_triplet_margin_loss() {
+        let a = Tensor::from_vec_f64(&[0., 0.], &[1, 2]);
+        let p = Tensor::from_vec_f64(&[3., 4.], &[1, 2]);
+        let n = Tensor::from_vec_f64(&[6., 8.], &[1, 2]);
+        let d = Tensor::new();
+        TripletMarginLoss::new(6., 2., false, Reduction::Mean)
+            .apply(&[a.ref_copy(), p.ref_copy(), n.ref_copy()], &[d.ref_copy()]);
+        assert!((d.get_scale_f64() - 1.).abs() < 1e-4);
+        // the positive is closer to the negative than the anchor.
+        TripletMarginLoss::new(6., 2., true, Reduction::Mean)
+            .apply(&[a.ref_copy(), p.ref_copy(), n.ref_copy()], &[d.ref_copy()]);
+        assert!((d.get_scale_f64() - 6.).abs() < 1e-4);
+        // a fractional norm is kept as it is, d(a, n) is 2 d(a, p).
+        TripletMarginLoss::new(20., 1.5, false, Reduction::Mean).apply(&[a, p, n], &[d.ref_copy()]);
+        let expected = 20. - (3_f64.powf(1.5) + 4_f64.powf(1.5)).powf(1. / 1.5);
+        assert!((d.get_scale_f64() - expected).abs() < 1e-4);
+
+        let a = Tensor::from_vec_f64(&[0.1, 0.5, -0.3, 1., 0.2, 0.4], &[2, 3]);
+        let p = Tensor::from_vec_f64(&[0.4, 1., 0.1, 1.3, -0.5, 1.2], &[2, 3]);
+        let n = Tensor::from_vec_f64(&[1., 0.8, 0.5, 0.9, 0.1, 0.2], &[2, 3]);
+        for norm in [1., 1.5, 2., 3.] {
+            for swap in [false, true] {
+                let mut c = TripletMarginLoss::new(1., norm, swap, Reduction::Sum);
+                assert!(_gradient_checker(
+                    &mut c,
+                    &[a.ref_copy(), p.ref_copy(), n.ref_copy()],
+                    None,
+                    Some(Tensor::from_vec_f64(&[1e-6], &[1])),
+                    None,
+                    None
+                ));
+            }
+        }
+    }
+
+    #[test]
+    fn test_triplet_margin_loss_var() {
+        let a = Var::new(&[0.1, 0.5, -0.3, 1., 0.2, 0.4], &[2, 3]);
+        let p = Var::new(&[0.4, 1., 0.1, 1.3, -0.5, 1.2], &[2, 3]);
+        let n = Var::new(&[1., 0.8, 0.5, 0.9, 0.1, 0.2], &[2, 3]);
+        let loss = a
+            .triplet_margin_loss(&p, &n, 1., 2., false, Reduction::Mean)
+            .unwrap();
+        loss.bp().unwrap();
+        assert_eq!(a.grad().unwrap().size(), vec![2, 3]);
+        assert_eq!(n.grad().unwrap().size(), vec![2, 3]);
+    }
+
+    #[test]
+    fn test_ctc_loss() {
+        // T = 2, one sample, target [1]: the alignments are 11, 01 and 10.
+        let lp = Tensor::from_vec_f64(&[0.3, 0.7, 0.4, 0.6], &[2, 1, 2]).log();
+        let target = Tensor::from_vec_f64(&[1.], &[1, 1]);
+        let length = Tensor::from_vec_f64(&[2.], &[1]);
+        let d = Tensor::new();
+        CTCLoss::new(0, Reduction::Sum, false).apply(
+            &[lp, target, length, Tensor::from_vec_f64(&[1.], &[1])],
+            &[d.ref_copy()],
+        );
+        assert!((d.get_scale_f64() + (0.7 * 0.6 + 0.3 * 0.6 + 0.7 * 0.4_f64).ln()).abs() < 1e-12);
+
+        // repeated labels need a blank in between, 101 is the only alignment.
+        let lp = Tensor::from_vec_f64(&[0.2, 0.8, 0.5, 0.5, 0.1, 0.9], &[3, 1, 2]).log();
+        let target = Tensor::from_vec_f64(&[1., 1.], &[1, 2]);
+        CTCLoss::new(0, Reduction::Sum, false).apply(
+            &[
+                lp.ref_copy(),
+                target.ref_copy(),
+                Tensor::from_vec_f64(&[3.], &[1]),
+                Tensor::from_vec_f64(&[2.], &[1]),
+            ],
+            &[d.ref_copy()],
+        );
+        assert!((d.get_scale_f64() + (0.8 * 0.5 * 0.9_f64).ln()).abs() < 1e-12);
+
+        // too short for the target.
+        let c = CTCLoss::new(0, Reduction::Sum, false);
+        let input = [
+            lp.ref_copy(),
+            target.ref_copy(),
+            Tensor::from_vec_f64(&[2.], &[1]),
+            Tensor::from_vec_f64(&[2.], &[1]),
+        ];
+        c.apply(&input, &[d.ref_copy()]);
+        assert_eq!(d.get_scale_f64(), f64::INFINITY);
+        let c = CTCLoss::new(0, Reduction::Sum, true);
+        c.apply(&input, &[d.ref_copy()]);
+        assert_eq!(d.get_scale_f64(), 0.);
+        let grad: Vec<Tensor> = input.iter().map(|_| Tensor::new()).collect();
+        c.grad(&input, &[Tensor::from_vec_f64(&[1.], &[1])], &grad);
+        assert_eq!(grad[0], lp.zeros_like());
+    }
+
+    fn ctc_apply(target: &[f64], target_size: &[usize], target_length: f64) {
+        let lp = Tensor::from_vec_f64(&[0.2, 0.5, 0.3, 0.3, 0.6, 0.1], &[2, 1, 3]).log();
+        CTCLoss::new(0, Reduction::Sum, false).apply(
+            &[
+                lp,
+                Tensor::from_vec_f64(target, target_size),
+                Tensor::from_vec_f64(&[2.], &[1]),
+                Tensor::from_vec_f64(&[target_length], &[1]),
+            ],
+            &[Tensor::new()],
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "target classes")]
+    fn test_ctc_loss_target_out_of_classes() {
+        ctc_apply(&[3.], &[1, 1], 1.);
+    }
+
+    #[test]
+    #[should_panic(expected = "target classes")]
+    fn test_ctc_loss_target_blank() {
+        ctc_apply(&[0.], &[1, 1], 1.);
+    }
+
+    #[test]
+    #[should_panic(expected = "target length")]
+    fn test_ctc_loss_target_length_over_padding() {
+        ctc_apply(&[1., 2.], &[1, 2], 3.);
+    }
+
+    #[test]
+    fn test_ctc_loss_batch() {
+        // sample 0 has T = 3 and target [1, 2],
+        // with alignments 112, 122, 012, 102 and 120.
+        // sample 1 has T = 2 and target [1], with alignments 11, 01 and 10.
+        let lp = Tensor::from_vec_f64(
+            &[
+                0.2, 0.5, 0.3, 0.3, 0.6, 0.1, //
+                0.3, 0.4, 0.3, 0.4, 0.5, 0.1, //
+                0.1, 0.3, 0.6, 0.2, 0.3, 0.5,
+            ],
+            &[3, 2, 3],
+        )
+        .log();
+        let p0: f64 =
+            0.5 * 0.4 * 0.6 + 0.5 * 0.3 * 0.6 + 0.2 * 0.4 * 0.6 + 0.5 * 0.3 * 0.6 + 0.5 * 0.3 * 0.1;
+        let p1: f64 = 0.6 * 0.5 + 0.3 * 0.5 + 0.6 * 0.4;
+        let padded = Tensor::from_vec_f64(&[1., 2., 1., 0.], &[2, 2]);
+        let concatenated = Tensor::from_vec_f64(&[1., 2., 1.], &[3]);
+        let input_lengths = Tensor::from_vec_f64(&[3., 2.], &[2]);
+        let target_lengths = Tensor::from_vec_f64(&[2., 1.], &[2]);
+
+        for target in [padded, concatenated] {
+            let input = [
+                lp.ref_copy(),
+                target,
+                input_lengths.ref_copy(),
+                target_lengths.ref_copy(),
+            ];
+            let d = Tensor::new();
+            CTCLoss::new(0, Reduction::None, false).apply(&input, &[d.ref_copy()]);
+            let expected = Tensor::from_vec_f64(&[-p0.ln(), -p1.ln()], &[2]);
+            assert!(d.sub(&expected).abs().max(None, false).get_scale_f64() < 1e-12);
+
+            CTCLoss::new(0, Reduction::Mean, false).apply(&input, &[d.ref_copy()]);
+            assert!((d.get_scale_f64() - (-p0.ln() / 2. - p1.ln()) / 2.).abs() < 1e-12);
+
+            for reduction in [Reduction::Mean, Reduction::Sum] {
+                let mut c = CTCLoss::new(0, reduction, false);
+                assert!(_gradient_checker(
+                    &mut c,
+                    &input,
+                    Some(&[true, false, false, false]),
+                    Some(Tensor::from_vec_f64(&[1e-6], &[1])),
+                    Some(Tensor::from_vec_f64(&[1e-8], &[1])),
+                    None,
+                ));
+            }
+        }
+
+        // the blank can be any class, swap classes 0 and 2.
+        let swapped = lp.index_select(2, &Tensor::from_vec_f64(&[2., 1., 0.], &[3]));
+        let d = Tensor::new();
+        CTCLoss::new(2, Reduction::Sum, false).apply(
+            &[
+                swapped,
+                Tensor::from_vec_f64(&[1., 0., 1., 2.], &[2, 2]),
+                input_lengths.ref_copy(),
+                target_lengths.ref_copy(),
+            ],
+            &[d.ref_copy()],
+        );
+        assert!((d.get_scale_f64() + p0.ln() + p1.ln()).abs() < 1e-12);
     }
 }
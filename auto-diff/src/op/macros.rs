@@ -116,7 +116,7 @@ macro_rules! many_to_1_op_with_paras {
                 output[0].swap(&input[0].$c(&input[1..input.len()], $( self.$arg_name ),*))
             }
             fn grad(&self, input: &[Tensor], output_grad: &[Tensor], input_grad: &[Tensor]) {
-                $d(input, output_grad, input_grad)
+                $d(input, output_grad, input_grad, $( self.$arg_name ),*)
             }
             fn get_values(&self) -> Vec<Tensor> {
                 Vec::new()
@@ -135,7 +135,7 @@ macro_rules! many_to_1_op_with_paras {
 }
 
 macro_rules! one_to_vec_op_with_paras {
-    ($a:ident, $b:expr, $is:expr,$os:expr, $c:ident, $d: tt, $( $arg_name:ident : $ArgTy:ty ),* $(,)?) => {
+    ($a:ident, $b:expr, $is:expr,$os:tt, $c:ident, $d: tt, $( $arg_name:ident : $ArgTy:ty ),* $(,)?) => {
 	#[cfg_attr(feature = "use-serde", derive(Serialize, Deserialize))]
         pub struct $a {
 	    #[cfg_attr(feature = "use-serde", serde(skip))]
@@ -177,7 +177,7 @@ macro_rules! one_to_vec_op_with_paras {
                 $is
             }
             fn get_output_size(&self) -> usize {
-                $os
+                ($os)(self)
             }
             fn apply(&self, input: &[Tensor], output: &[Tensor]) {
                 let result = input[0].$c($( self.$arg_name ),*);
@@ -186,7 +186,7 @@ macro_rules! one_to_vec_op_with_paras {
                 }
             }
             fn grad(&self, input: &[Tensor], output_grad: &[Tensor], input_grad: &[Tensor]) {
-                $d(input, output_grad, input_grad)
+                $d(input, output_grad, input_grad, $( self.$arg_name ),*)
             }
             fn get_values(&self) -> Vec<Tensor> {
                 Vec::new()
@@ -1,5 +1,5 @@
 /// Only NCWH format is supported.
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
 use std::rc::Rc;
 
 use crate::collection::generational_index::GenKey;
@@ -41,6 +41,29 @@ pub trait OpTrait {
     fn set_values(&self, v: &[Tensor]);
     /// access gradient values
     fn get_grads(&self) -> Vec<Tensor>;
+    /// Overwrite gradient values, in the order of get_grads.
+    fn set_grads(&self, v: &[Tensor]) {
+        if !v.is_empty() {
+            panic!("{} has no gradient to set.", self.get_name());
+        }
+    }
+
+    /// Switch between training and evaluation,
+    /// only ops that behave differently, e.g. dropout, need this.
+    fn set_training(&self, _training: bool) {}
+
+    /// The rows, along dim 0, of the values that the gradient touches,
+    /// None means all of them.
+    /// Optimizers can skip the other rows.
+    fn get_grad_rows(&self) -> Option<Vec<usize>> {
+        None
+    }
+
+    /// Subtract lr times the gradient from the values, in place,
+    /// only on the rows from get_grad_rows.
+    fn step_grad_rows(&self, _lr: f64) {
+        panic!("{} has no gradient rows to step.", self.get_name());
+    }
 
     #[cfg(feature = "use-serde")]
     fn as_any(&self) -> &dyn Any;
@@ -70,6 +93,36 @@ impl Default for OpHandle {
     }
 }
 
+/// Train/eval switch of an op.
+/// The op placed in the graph by OpCall shares the flag
+/// with the op it is copied from.
+pub struct TrainingFlag {
+    training: Rc<Cell<bool>>,
+}
+impl TrainingFlag {
+    pub fn new(training: bool) -> TrainingFlag {
+        TrainingFlag {
+            training: Rc::new(Cell::new(training)),
+        }
+    }
+    pub fn get(&self) -> bool {
+        self.training.get()
+    }
+    pub fn set(&self, training: bool) {
+        self.training.set(training);
+    }
+    pub fn ref_copy(&self) -> TrainingFlag {
+        TrainingFlag {
+            training: self.training.clone(),
+        }
+    }
+}
+impl Default for TrainingFlag {
+    fn default() -> Self {
+        Self::new(true)
+    }
+}
+
 macro_rules! handle_method {
     () => {
         fn get_handle(&self) -> &OpHandle {
@@ -139,6 +192,26 @@ impl Op {
     pub fn get_grads(&self) -> Vec<Tensor> {
         self.inner_op.borrow().get_grads()
     }
+
+    /// set gradient for weight/parameters.
+    pub fn set_grads(&self, v: &[Tensor]) {
+        self.inner_op.borrow().set_grads(v);
+    }
+
+    /// Switch between training and evaluation.
+    pub fn set_training(&self, training: bool) {
+        self.inner_op.borrow().set_training(training);
+    }
+
+    /// The rows of the values that have gradient, None for all rows.
+    pub fn get_grad_rows(&self) -> Option<Vec<usize>> {
+        self.inner_op.borrow().get_grad_rows()
+    }
+
+    /// Apply values -= lr * gradient on the gradient rows only.
+    pub fn step_grad_rows(&self, lr: f64) {
+        self.inner_op.borrow().step_grad_rows(lr);
+    }
 }
 //impl Clone for Op {
 //    fn clone(&self) -> Self {
@@ -188,6 +261,23 @@ impl Op {
 //    }
 //}
 
+/// A single element tensor with the same type as like.
+pub(crate) fn scalar_like(like: &Tensor, v: f64) -> Tensor {
+    let mut ret = like.zeros_like().sum(None, false);
+    ret.set_f64(&[0], v);
+    ret
+}
+
+/// Outputs of multi-output ops may come back with an empty gradient
+/// if they are not used downstream.
+pub(crate) fn grad_or_zeros(grad: &Tensor, like: &Tensor) -> Tensor {
+    if grad.numel() == 0 {
+        like.zeros_like()
+    } else {
+        grad.clone()
+    }
+}
+
 ///
 /// Verify the gradient implementation is right.
 ///
@@ -196,15 +286,19 @@ impl Op {
 /// input_mask: may skip some data point if the element is false.
 /// step: delta that is used for numeric difference.
 /// tolerance: numeric tolerance for equality.
+/// output_weight: one weight per output, the checked quantity is
+/// sum_i <output_i, output_weight_i>. Defaults to all ones on a single output.
 ///
 /// one_input and input_mask should have the same size.
 /// step and tolerance are both scalar.
+/// The parameters exposed by get_values are checked against get_grads too.
 pub fn _gradient_checker(
     op: &mut dyn OpTrait,
     one_input: &[Tensor],
     input_mask: Option<&[bool]>,
     step: Option<Tensor>,
     tolerance: Option<Tensor>,
+    output_weight: Option<&[Tensor]>,
 ) -> bool {
     let x_mask = if let Some(val) = input_mask {
         val.to_vec()
@@ -223,10 +317,24 @@ pub fn _gradient_checker(
     };
 
     // system output
-    let output = Tensor::new();
-    op.apply(one_input, &[output.ref_copy()]);
-
-    let output = output.get_scale_f64();
+    let output_size = output_weight.map_or(1, |w| w.len());
+    let output: Vec<Tensor> = (0..output_size).map(|_| Tensor::new()).collect();
+    op.apply(one_input, &output);
+    let output_grad: Vec<Tensor> = if let Some(w) = output_weight {
+        w.iter().map(|v| v.ref_copy()).collect()
+    } else {
+        vec![output[0].ones_like()]
+    };
+    let weighted = |op: &dyn OpTrait, input: &[Tensor]| -> f64 {
+        let output: Vec<Tensor> = (0..output_size).map(|_| Tensor::new()).collect();
+        op.apply(input, &output);
+        output
+            .iter()
+            .zip(output_grad.iter())
+            .map(|(o, g)| o.mul(g).sum(None, false).get_scale_f64())
+            .sum()
+    };
+    let output = weighted(op, one_input);
 
     // get the system gradient
     let input_grad = vec![Tensor::new(); op.get_input_size()];
@@ -234,16 +342,21 @@ pub fn _gradient_checker(
     for i in &input_grad {
         input_grad_ref.push(i.ref_copy());
     }
-    let output_grad = Tensor::from_vec_f64(&[1.], &[1]);
-    op.grad(one_input, &[output_grad], &input_grad_ref);
-
-    // get the numeric gradient
-    let mut numeric_gradient = Vec::new();
-    for v in one_input {
-        numeric_gradient.push(v.zeros_like())
-    }
+    op.grad(one_input, &output_grad, &input_grad_ref);
+    let param_grad: Vec<Tensor> = op.get_grads().to_vec();
 
     let mut good_gradient = true;
+    let mut compare = |name: &str, index: usize, dimpos: &[usize], scale_gradient: f64, system_gradient: f64| {
+        if (scale_gradient - system_gradient) * (scale_gradient - system_gradient) > tol {
+            println!(
+                "{}: {}, {:?}, numeric: {:?}, imple: {:?}",
+                name, index, dimpos, scale_gradient, system_gradient
+            );
+            good_gradient = false;
+        }
+    };
+
+    // get the numeric gradient
     for (index, v) in one_input.iter().enumerate() {
         if !x_mask[index] {
             continue;
@@ -259,22 +372,28 @@ pub fn _gradient_checker(
 
             let mut right_input = one_input.to_vec();
             right_input[index] = right_tensor.ref_copy();
-            let right_output = Tensor::new();
-            op.apply(&right_input, &[right_output.ref_copy()]);
-            let right_output = right_output.get_scale_f64();
+            let right_output = weighted(op, &right_input);
 
             let scale_gradient = (right_output - output) / delta;
-            numeric_gradient[index].set_f64(&dimpos, scale_gradient);
-
             let system_gradient = input_grad[index].get_f64(&dimpos);
+            compare("input", index, &dimpos, scale_gradient, system_gradient);
+        }
+    }
+
+    let values: Vec<Tensor> = op.get_values().to_vec();
+    for (index, v) in values.iter().enumerate() {
+        for i in 0..v.numel() {
+            let dimpos = v.index2dimpos(i);
+
+            let mut right_values: Vec<Tensor> = values.to_vec();
+            right_values[index].set_f64(&dimpos, v.get_f64(&dimpos) + delta);
+            op.set_values(&right_values);
+            let right_output = weighted(op, one_input);
+            op.set_values(&values.to_vec());
 
-            if (scale_gradient - system_gradient) * (scale_gradient - system_gradient) > tol {
-                println!(
-                    "input: {:?}, numeric: {:?}, imple: {:?}",
-                    one_input[0], scale_gradient, system_gradient
-                );
-                good_gradient = false;
-            }
+            let scale_gradient = (right_output - output) / delta;
+            let system_gradient = param_grad[index].get_f64(&dimpos);
+            compare("parameter", index, &dimpos, scale_gradient, system_gradient);
         }
     }
     good_gradient
@@ -363,18 +482,42 @@ pub mod local;
 pub use local::{Add, Div, Matmul, Mul, Outer, Sub};
 
 pub mod linear;
-pub use linear::Linear;
+pub use linear::{BiLinear, Linear};
 
 pub mod nonlinear;
-pub use nonlinear::{ReLU, ELU};
+pub use nonlinear::{
+    Hardtanh, LeakyReLU, LogSoftmax, Mish, ReLU, SiLU, Sine, Softmax, Softplus, ELU, GELU, PReLU,
+};
 
 pub mod convolution;
-pub use convolution::Conv2d;
+pub use convolution::{Conv1d, Conv2d, Conv3d, ConvTranspose1d, ConvTranspose2d, Fold, Unfold};
 
 pub mod pooling;
+pub use pooling::{
+    AdaptiveAvgPool2d, AdaptiveMaxPool2d, AvgPool1d, AvgPool2d, MaxPool1d, MaxPool2d,
+};
+
+pub mod normalization;
+pub use normalization::{BatchNorm1d, BatchNorm2d, GroupNorm, InstanceNorm, LayerNorm};
+
+pub mod dropout;
+pub use dropout::{AlphaDropout, Dropout, Dropout2d};
+
+pub mod embedding;
+pub use embedding::Embedding;
+
+pub mod recurrent;
+pub use recurrent::{GRUCell, LSTMCell, RNNCell, LSTM};
+
+pub mod attention;
+pub use attention::{MultiheadAttention, ScaledDotProductAttention};
 
 pub mod loss;
-pub use loss::{BCEWithLogitsLoss, CrossEntropyLoss, MSELoss};
+pub use loss::{
+    BCELoss, BCEWithLogitsLoss, CTCLoss, CosineEmbeddingLoss, CrossEntropyLoss, HingeEmbeddingLoss,
+    HuberLoss, KLDivLoss, L1Loss, MSELoss, MarginRankingLoss, NLLLoss, Reduction, SmoothL1Loss,
+    TripletMarginLoss,
+};
 
 pub mod element;
 pub use element::{
@@ -392,7 +535,7 @@ pub use index_slicing::{
 };
 
 pub mod linalg;
-pub use linalg::{Det, Inv, NormalizeUnit, Tr};
+pub use linalg::{Cholesky, Det, Inv, NormalizeUnit, Slogdet, Solve, Tr, QR};
 
 pub mod reduction;
 pub use reduction::{Argmax, Argmin, Logsumexp, Max, Mean, Min, Prod, Std, Sum, Variance};
@@ -414,9 +557,22 @@ gen_serde_funcs!(
     Matmul,
     Outer,
     Linear,
+    BiLinear,
     ELU,
     ReLU,
+    Conv1d,
     Conv2d,
+    Conv3d,
+    ConvTranspose1d,
+    ConvTranspose2d,
+    Unfold,
+    Fold,
+    MaxPool1d,
+    MaxPool2d,
+    AvgPool1d,
+    AvgPool2d,
+    AdaptiveAvgPool2d,
+    AdaptiveMaxPool2d,
     MSELoss,
     BCEWithLogitsLoss,
     CrossEntropyLoss,
@@ -477,6 +633,10 @@ gen_serde_funcs!(
     Inv,
     NormalizeUnit,
     Tr,
+    Solve,
+    Cholesky,
+    QR,
+    Slogdet,
     Argmax,
     Argmin,
     Logsumexp,
@@ -488,5 +648,40 @@ gen_serde_funcs!(
     Max,
     Min,
     GetPatch,
-    SetPatch
+    SetPatch,
+    BatchNorm1d,
+    BatchNorm2d,
+    LayerNorm,
+    GroupNorm,
+    InstanceNorm,
+    Dropout,
+    Dropout2d,
+    AlphaDropout,
+    Embedding,
+    RNNCell,
+    LSTMCell,
+    GRUCell,
+    ScaledDotProductAttention,
+    MultiheadAttention,
+    Softmax,
+    LogSoftmax,
+    Hardtanh,
+    LeakyReLU,
+    PReLU,
+    GELU,
+    SiLU,
+    Mish,
+    Softplus,
+    Sine,
+    L1Loss,
+    SmoothL1Loss,
+    HuberLoss,
+    NLLLoss,
+    KLDivLoss,
+    BCELoss,
+    MarginRankingLoss,
+    HingeEmbeddingLoss,
+    CosineEmbeddingLoss,
+    TripletMarginLoss,
+    CTCLoss
 );
@@ -1,7 +1,13 @@
 #![allow(clippy::new_without_default)]
-use super::{OpHandle, OpTrait};
+use super::{scalar_like, Op, OpCall, OpHandle, OpTrait};
 use tensor_rs::tensor::Tensor;
 
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::err::AutoDiffError;
+use crate::var::Var;
+
 #[cfg(feature = "use-serde")]
 use serde::{Deserialize, Serialize};
 #[cfg(feature = "use-serde")]
@@ -75,11 +81,268 @@ impl OpTrait for ELU {
 }
 
 // Hardshrink
-// Hardtanh
-// LeakyReLU
+
+/// Hardtanh, clamps the input to [min_val, max_val].
+#[cfg_attr(feature = "use-serde", derive(Serialize, Deserialize))]
+pub struct Hardtanh {
+    min_val: f64,
+    max_val: f64,
+    #[cfg_attr(feature = "use-serde", serde(skip))]
+    handle: OpHandle,
+}
+impl Hardtanh {
+    pub fn new(min_val: f64, max_val: f64) -> Hardtanh {
+        if min_val > max_val {
+            panic!(
+                "Hardtanh expects min_val <= max_val, get {} > {}",
+                min_val, max_val
+            );
+        }
+        Hardtanh {
+            min_val,
+            max_val,
+            handle: OpHandle::new(),
+        }
+    }
+    handle_method!();
+}
+impl OpTrait for Hardtanh {
+    fn get_name(&self) -> &'static str {
+        "Hardtanh"
+    }
+    fn get_input_size(&self) -> usize {
+        1
+    }
+    fn get_output_size(&self) -> usize {
+        1
+    }
+    /// Forward pass
+    fn apply(&self, input: &[Tensor], output: &[Tensor]) {
+        let low = Tensor::fill(&input[0].size(), &scalar_like(&input[0], self.min_val));
+        let high = Tensor::fill(&input[0].size(), &scalar_like(&input[0], self.max_val));
+        output[0].swap(&input[0].max_pair(&low).min_pair(&high));
+    }
+
+    /// The gradient passes through strictly inside the range.
+    fn grad(&self, input: &[Tensor], output_grad: &[Tensor], input_grad: &[Tensor]) {
+        let low = Tensor::fill(&input[0].size(), &scalar_like(&input[0], self.min_val));
+        let high = Tensor::fill(&input[0].size(), &scalar_like(&input[0], self.max_val));
+        let inside = input[0].gt(&low).mul(&input[0].lt(&high));
+        input_grad[0].swap(&inside.mul(&output_grad[0]));
+    }
+
+    /// access weight values
+    fn get_values(&self) -> Vec<Tensor> {
+        Vec::new()
+    }
+    fn set_values(&self, _v: &[Tensor]) {}
+    /// access gradient values
+    fn get_grads(&self) -> Vec<Tensor> {
+        Vec::new()
+    }
+    #[cfg(feature = "use-serde")]
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+/// LeakyReLU, max(0, x) + negative_slope * min(0, x).
+#[cfg_attr(feature = "use-serde", derive(Serialize, Deserialize))]
+pub struct LeakyReLU {
+    negative_slope: f64,
+    #[cfg_attr(feature = "use-serde", serde(skip))]
+    handle: OpHandle,
+}
+impl LeakyReLU {
+    pub fn new(negative_slope: f64) -> LeakyReLU {
+        LeakyReLU {
+            negative_slope,
+            handle: OpHandle::new(),
+        }
+    }
+    handle_method!();
+}
+impl OpTrait for LeakyReLU {
+    fn get_name(&self) -> &'static str {
+        "LeakyReLU"
+    }
+    fn get_input_size(&self) -> usize {
+        1
+    }
+    fn get_output_size(&self) -> usize {
+        1
+    }
+    /// Forward pass
+    fn apply(&self, input: &[Tensor], output: &[Tensor]) {
+        let zero = input[0].zeros_like();
+        let ret = input[0].max_pair(&zero).add(
+            &input[0]
+                .min_pair(&zero)
+                .mul(&scalar_like(&input[0], self.negative_slope)),
+        );
+        output[0].swap(&ret);
+    }
+
+    /// Given the forward input value and backward output_grad,
+    /// Update weight gradient.
+    /// return backward input gradeint.
+    fn grad(&self, input: &[Tensor], output_grad: &[Tensor], input_grad: &[Tensor]) {
+        let zero = input[0].zeros_like();
+        let slope = input[0].ge(&zero).add(
+            &input[0]
+                .lt(&zero)
+                .mul(&scalar_like(&input[0], self.negative_slope)),
+        );
+        input_grad[0].swap(&slope.mul(&output_grad[0]));
+    }
+
+    /// access weight values
+    fn get_values(&self) -> Vec<Tensor> {
+        Vec::new()
+    }
+    fn set_values(&self, _v: &[Tensor]) {}
+    /// access gradient values
+    fn get_grads(&self) -> Vec<Tensor> {
+        Vec::new()
+    }
+    #[cfg(feature = "use-serde")]
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
 // LogSigmoid
 // MultiheadAttention
-// PReLU
+
+/// Repeat the PReLU weight to the input size.
+/// A single weight is shared by all elements,
+/// otherwise there is one weight per channel, which is dim 1,
+/// or dim 0 for 1-d input.
+fn prelu_weight(x: &Tensor, weight: &Tensor) -> Tensor {
+    if weight.numel() == 1 {
+        return Tensor::fill(&x.size(), &weight.reshape(&[1]));
+    }
+    let size = x.size();
+    let channel = if size.len() == 1 { 0 } else { 1 };
+    if size[channel] != weight.numel() {
+        panic!(
+            "PReLU has {} weights, but the input has {} channels",
+            weight.numel(),
+            size[channel]
+        );
+    }
+    let mut kept = vec![1; size.len()];
+    kept[channel] = size[channel];
+    let mut repeat = size.clone();
+    repeat[channel] = 1;
+    weight.reshape(&kept).repeat(&repeat)
+}
+
+/// PReLU, max(0, x) + weight * min(0, x),
+/// with a learnable weight, either a single one or one per channel.
+#[cfg_attr(feature = "use-serde", derive(Serialize, Deserialize))]
+pub struct PReLU {
+    weight: Tensor,
+    weight_grad: Tensor,
+    #[cfg_attr(feature = "use-serde", serde(skip))]
+    handle: OpHandle,
+}
+impl PReLU {
+    pub fn new(weight: Tensor) -> PReLU {
+        PReLU {
+            weight,
+            weight_grad: Tensor::new(),
+            handle: OpHandle::new(),
+        }
+    }
+
+    pub fn weight(&self) -> &Tensor {
+        &self.weight
+    }
+
+    pub fn set_weight(&self, var: Var) {
+        self.weight.swap(&var.val());
+    }
+
+    handle_method!();
+}
+
+impl OpCall for PReLU {
+    fn call(&mut self, inputs: &[&Var]) -> Result<Vec<Var>, AutoDiffError> {
+        let new_one = PReLU {
+            weight: self.weight.ref_copy(),
+            weight_grad: self.weight_grad.ref_copy(),
+            handle: OpHandle::new(),
+        };
+
+        let op = Op::new(Rc::new(RefCell::new(Box::new(new_one))));
+
+        inputs[0].called_with(op, &inputs[1..inputs.len()])
+    }
+}
+
+impl OpTrait for PReLU {
+    fn get_name(&self) -> &'static str {
+        "PReLU"
+    }
+    fn get_input_size(&self) -> usize {
+        1
+    }
+    fn get_output_size(&self) -> usize {
+        1
+    }
+    /// Forward pass
+    fn apply(&self, input: &[Tensor], output: &[Tensor]) {
+        let zero = input[0].zeros_like();
+        let ret = input[0].max_pair(&zero).add(
+            &input[0]
+                .min_pair(&zero)
+                .mul(&prelu_weight(&input[0], &self.weight)),
+        );
+        output[0].swap(&ret);
+    }
+
+    /// The weight gradient sums over all but the channel dim.
+    fn grad(&self, input: &[Tensor], output_grad: &[Tensor], input_grad: &[Tensor]) {
+        let zero = input[0].zeros_like();
+        let negative = input[0].lt(&zero);
+        let slope = input[0]
+            .ge(&zero)
+            .add(&negative.mul(&prelu_weight(&input[0], &self.weight)));
+        input_grad[0].swap(&slope.mul(&output_grad[0]));
+
+        let w_grad = negative.mul(&input[0]).mul(&output_grad[0]);
+        let size = input[0].size();
+        let w_grad = if self.weight.numel() == 1 {
+            w_grad.sum(None, false)
+        } else if size.len() == 1 {
+            w_grad
+        } else {
+            let dims: Vec<usize> = (0..size.len()).filter(|d| *d != 1).collect();
+            w_grad.sum(Some(&dims), false)
+        };
+        self.weight_grad.swap(&w_grad.reshape(&self.weight.size()));
+    }
+
+    /// access weight values
+    fn get_values(&self) -> Vec<Tensor> {
+        vec![self.weight.ref_copy()]
+    }
+    fn set_values(&self, v: &[Tensor]) {
+        self.weight.swap(&v[0].clone());
+    }
+    /// access gradient values
+    fn get_grads(&self) -> Vec<Tensor> {
+        vec![self.weight_grad.ref_copy()]
+    }
+    fn set_grads(&self, v: &[Tensor]) {
+        self.weight_grad.swap(&v[0].clone());
+    }
+    #[cfg(feature = "use-serde")]
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
 
 /// ReLU
 #[cfg_attr(feature = "use-serde", derive(Serialize, Deserialize))]
@@ -139,6 +402,85 @@ impl OpTrait for ReLU {
 // RReLU
 // SELU
 // CELU
+
+const GELU_COEFF: f64 = 0.044715;
+/// tanh(sqrt(2 / pi) * (x + 0.044715 * x^3))
+fn gelu_tanh(x: &Tensor) -> Tensor {
+    let cube = x.mul(x).mul(x).mul(&scalar_like(x, GELU_COEFF));
+    x.add(&cube)
+        .mul(&scalar_like(x, (2. / std::f64::consts::PI).sqrt()))
+        .tanh()
+}
+
+/// GELU, x * Phi(x), with the tanh approximation
+/// 0.5 * x * (1 + tanh(sqrt(2 / pi) * (x + 0.044715 * x^3))).
+#[cfg_attr(feature = "use-serde", derive(Serialize, Deserialize))]
+pub struct GELU {
+    #[cfg_attr(feature = "use-serde", serde(skip))]
+    handle: OpHandle,
+}
+impl GELU {
+    pub fn new() -> GELU {
+        GELU {
+            handle: OpHandle::new(),
+        }
+    }
+    handle_method!();
+}
+impl OpTrait for GELU {
+    fn get_name(&self) -> &'static str {
+        "GELU"
+    }
+    fn get_input_size(&self) -> usize {
+        1
+    }
+    fn get_output_size(&self) -> usize {
+        1
+    }
+    /// Forward pass
+    fn apply(&self, input: &[Tensor], output: &[Tensor]) {
+        let x = &input[0];
+        let ret = gelu_tanh(x)
+            .add(&x.ones_like())
+            .mul(x)
+            .mul(&scalar_like(x, 0.5));
+        output[0].swap(&ret);
+    }
+
+    /// Given the forward input value and backward output_grad,
+    /// Update weight gradient.
+    /// return backward input gradeint.
+    fn grad(&self, input: &[Tensor], output_grad: &[Tensor], input_grad: &[Tensor]) {
+        let x = &input[0];
+        let t = gelu_tanh(x);
+        let one = x.ones_like();
+        let du = x
+            .mul(x)
+            .mul(&scalar_like(x, 3. * GELU_COEFF))
+            .add(&one)
+            .mul(&scalar_like(x, (2. / std::f64::consts::PI).sqrt()));
+        let g = one
+            .add(&t)
+            .add(&x.mul(&one.sub(&t.mul(&t))).mul(&du))
+            .mul(&scalar_like(x, 0.5));
+        input_grad[0].swap(&g.mul(&output_grad[0]));
+    }
+
+    /// access weight values
+    fn get_values(&self) -> Vec<Tensor> {
+        Vec::new()
+    }
+    fn set_values(&self, _v: &[Tensor]) {}
+    /// access gradient values
+    fn get_grads(&self) -> Vec<Tensor> {
+        Vec::new()
+    }
+    #[cfg(feature = "use-serde")]
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
 // Sigmoid
 #[cfg_attr(feature = "use-serde", derive(Serialize, Deserialize))]
 pub struct Sigmoid {
@@ -197,7 +539,169 @@ impl OpTrait for Sigmoid {
     }
 }
 
-// Softplus
+/// SiLU, also known as Swish, x * sigmoid(x).
+#[cfg_attr(feature = "use-serde", derive(Serialize, Deserialize))]
+pub struct SiLU {
+    #[cfg_attr(feature = "use-serde", serde(skip))]
+    handle: OpHandle,
+}
+impl SiLU {
+    pub fn new() -> SiLU {
+        SiLU {
+            handle: OpHandle::new(),
+        }
+    }
+    handle_method!();
+}
+impl OpTrait for SiLU {
+    fn get_name(&self) -> &'static str {
+        "SiLU"
+    }
+    fn get_input_size(&self) -> usize {
+        1
+    }
+    fn get_output_size(&self) -> usize {
+        1
+    }
+    /// Forward pass
+    fn apply(&self, input: &[Tensor], output: &[Tensor]) {
+        output[0].swap(&input[0].mul(&input[0].sigmoid()));
+    }
+
+    /// sigmoid(x) * (1 + x * (1 - sigmoid(x)))
+    fn grad(&self, input: &[Tensor], output_grad: &[Tensor], input_grad: &[Tensor]) {
+        let s = input[0].sigmoid();
+        let one = input[0].ones_like();
+        let g = s.mul(&one.add(&input[0].mul(&one.sub(&s))));
+        input_grad[0].swap(&g.mul(&output_grad[0]));
+    }
+
+    /// access weight values
+    fn get_values(&self) -> Vec<Tensor> {
+        Vec::new()
+    }
+    fn set_values(&self, _v: &[Tensor]) {}
+    /// access gradient values
+    fn get_grads(&self) -> Vec<Tensor> {
+        Vec::new()
+    }
+    #[cfg(feature = "use-serde")]
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+/// Mish, x * tanh(softplus(x)).
+#[cfg_attr(feature = "use-serde", derive(Serialize, Deserialize))]
+pub struct Mish {
+    #[cfg_attr(feature = "use-serde", serde(skip))]
+    handle: OpHandle,
+}
+impl Mish {
+    pub fn new() -> Mish {
+        Mish {
+            handle: OpHandle::new(),
+        }
+    }
+    handle_method!();
+}
+impl OpTrait for Mish {
+    fn get_name(&self) -> &'static str {
+        "Mish"
+    }
+    fn get_input_size(&self) -> usize {
+        1
+    }
+    fn get_output_size(&self) -> usize {
+        1
+    }
+    /// Forward pass
+    fn apply(&self, input: &[Tensor], output: &[Tensor]) {
+        output[0].swap(&input[0].mul(&input[0].log1pexp().tanh()));
+    }
+
+    /// tanh(sp) + x * (1 - tanh(sp)^2) * sigmoid(x), with sp = softplus(x).
+    fn grad(&self, input: &[Tensor], output_grad: &[Tensor], input_grad: &[Tensor]) {
+        let t = input[0].log1pexp().tanh();
+        let sech2 = input[0].ones_like().sub(&t.mul(&t));
+        let g = t.add(&input[0].mul(&sech2).mul(&input[0].sigmoid()));
+        input_grad[0].swap(&g.mul(&output_grad[0]));
+    }
+
+    /// access weight values
+    fn get_values(&self) -> Vec<Tensor> {
+        Vec::new()
+    }
+    fn set_values(&self, _v: &[Tensor]) {}
+    /// access gradient values
+    fn get_grads(&self) -> Vec<Tensor> {
+        Vec::new()
+    }
+    #[cfg(feature = "use-serde")]
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+/// Softplus, log(1 + exp(beta * x)) / beta.
+#[cfg_attr(feature = "use-serde", derive(Serialize, Deserialize))]
+pub struct Softplus {
+    beta: f64,
+    #[cfg_attr(feature = "use-serde", serde(skip))]
+    handle: OpHandle,
+}
+impl Softplus {
+    pub fn new(beta: f64) -> Softplus {
+        if beta <= 0. {
+            panic!("Softplus expects a positive beta, get {:?}", beta);
+        }
+        Softplus {
+            beta,
+            handle: OpHandle::new(),
+        }
+    }
+    handle_method!();
+}
+impl OpTrait for Softplus {
+    fn get_name(&self) -> &'static str {
+        "Softplus"
+    }
+    fn get_input_size(&self) -> usize {
+        1
+    }
+    fn get_output_size(&self) -> usize {
+        1
+    }
+    /// Forward pass
+    fn apply(&self, input: &[Tensor], output: &[Tensor]) {
+        let ret = input[0]
+            .mul(&scalar_like(&input[0], self.beta))
+            .log1pexp()
+            .mul(&scalar_like(&input[0], 1. / self.beta));
+        output[0].swap(&ret);
+    }
+
+    /// sigmoid(beta * x)
+    fn grad(&self, input: &[Tensor], output_grad: &[Tensor], input_grad: &[Tensor]) {
+        let g = input[0].mul(&scalar_like(&input[0], self.beta)).sigmoid();
+        input_grad[0].swap(&g.mul(&output_grad[0]));
+    }
+
+    /// access weight values
+    fn get_values(&self) -> Vec<Tensor> {
+        Vec::new()
+    }
+    fn set_values(&self, _v: &[Tensor]) {}
+    /// access gradient values
+    fn get_grads(&self) -> Vec<Tensor> {
+        Vec::new()
+    }
+    #[cfg(feature = "use-serde")]
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
 // Softshrink
 // Softsign
 // Tanh
@@ -256,9 +760,136 @@ impl OpTrait for Sine {
 }
 
 // Softmin
-// Softmax
+
+/// Repeat a keepdim reduction along dim back to the size of x.
+fn repeat_along(x: &Tensor, reduced: &Tensor, dim: usize) -> Tensor {
+    let mut sizes = vec![1; x.size().len()];
+    sizes[dim] = x.size()[dim];
+    reduced.repeat(&sizes)
+}
+
+/// log(softmax(x)) along dim, shifted by logsumexp for stability.
+fn log_softmax(x: &Tensor, dim: usize) -> Tensor {
+    if dim >= x.size().len() {
+        panic!(
+            "softmax dim {} is out of range for input size {:?}",
+            dim,
+            x.size()
+        );
+    }
+    x.sub(&repeat_along(x, &x.logsumexp(Some(&[dim]), true), dim))
+}
+
+/// Softmax along dim, exp(x_i)/sum_j(exp(x_j)).
+#[cfg_attr(feature = "use-serde", derive(Serialize, Deserialize))]
+pub struct Softmax {
+    dim: usize,
+    #[cfg_attr(feature = "use-serde", serde(skip))]
+    handle: OpHandle,
+}
+impl Softmax {
+    pub fn new(dim: usize) -> Softmax {
+        Softmax {
+            dim,
+            handle: OpHandle::new(),
+        }
+    }
+    handle_method!();
+}
+impl OpTrait for Softmax {
+    fn get_name(&self) -> &'static str {
+        "Softmax"
+    }
+    fn get_input_size(&self) -> usize {
+        1
+    }
+    fn get_output_size(&self) -> usize {
+        1
+    }
+    /// Forward pass
+    fn apply(&self, input: &[Tensor], output: &[Tensor]) {
+        output[0].swap(&log_softmax(&input[0], self.dim).exp());
+    }
+
+    /// dx = y * (dy - sum(dy * y)), with y the softmax output.
+    fn grad(&self, input: &[Tensor], output_grad: &[Tensor], input_grad: &[Tensor]) {
+        let y = log_softmax(&input[0], self.dim).exp();
+        let dot = output_grad[0].mul(&y).sum(Some(&[self.dim]), true);
+        let ret = y.mul(&output_grad[0].sub(&repeat_along(&y, &dot, self.dim)));
+        input_grad[0].swap(&ret);
+    }
+
+    /// access weight values
+    fn get_values(&self) -> Vec<Tensor> {
+        Vec::new()
+    }
+    fn set_values(&self, _v: &[Tensor]) {}
+    /// access gradient values
+    fn get_grads(&self) -> Vec<Tensor> {
+        Vec::new()
+    }
+    #[cfg(feature = "use-serde")]
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
 // Softmax2d
-// LogSoftmax
+
+/// LogSoftmax along dim, x_i - log(sum_j(exp(x_j))).
+#[cfg_attr(feature = "use-serde", derive(Serialize, Deserialize))]
+pub struct LogSoftmax {
+    dim: usize,
+    #[cfg_attr(feature = "use-serde", serde(skip))]
+    handle: OpHandle,
+}
+impl LogSoftmax {
+    pub fn new(dim: usize) -> LogSoftmax {
+        LogSoftmax {
+            dim,
+            handle: OpHandle::new(),
+        }
+    }
+    handle_method!();
+}
+impl OpTrait for LogSoftmax {
+    fn get_name(&self) -> &'static str {
+        "LogSoftmax"
+    }
+    fn get_input_size(&self) -> usize {
+        1
+    }
+    fn get_output_size(&self) -> usize {
+        1
+    }
+    /// Forward pass
+    fn apply(&self, input: &[Tensor], output: &[Tensor]) {
+        output[0].swap(&log_softmax(&input[0], self.dim));
+    }
+
+    /// dx = dy - softmax(x) * sum(dy).
+    fn grad(&self, input: &[Tensor], output_grad: &[Tensor], input_grad: &[Tensor]) {
+        let y = log_softmax(&input[0], self.dim).exp();
+        let total = output_grad[0].sum(Some(&[self.dim]), true);
+        let ret = output_grad[0].sub(&y.mul(&repeat_along(&y, &total, self.dim)));
+        input_grad[0].swap(&ret);
+    }
+
+    /// access weight values
+    fn get_values(&self) -> Vec<Tensor> {
+        Vec::new()
+    }
+    fn set_values(&self, _v: &[Tensor]) {}
+    /// access gradient values
+    fn get_grads(&self) -> Vec<Tensor> {
+        Vec::new()
+    }
+    #[cfg(feature = "use-serde")]
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
 // AdaptiveLogSoftmaxWithLoss
 
 #[cfg(test)]
@@ -272,7 +903,7 @@ mod tests {
 
         for i in 0..10 {
             let zero = Tensor::from_vec_f64(&vec![(i - 5) as f64], &vec![1]);
-            let good_grad = _gradient_checker(&mut op, &[zero], None, None, None);
+            let good_grad = _gradient_checker(&mut op, &[zero], None, None, None, None);
             assert_eq!(good_grad, true);
         }
     }
@@ -283,7 +914,7 @@ mod tests {
 
         for i in 0..10 {
             let zero = Tensor::from_vec_f64(&vec![(i - 5) as f64], &vec![1]);
-            let good_grad = _gradient_checker(&mut op, &[zero], None, None, None);
+            let good_grad = _gradient_checker(&mut op, &[zero], None, None, None, None);
             assert_eq!(good_grad, true);
         }
     }
@@ -294,7 +925,7 @@ mod tests {
 
         for i in 0..10 {
             let zero = Tensor::from_vec_f64(&vec![(i - 5) as f64], &vec![1]);
-            let good_grad = _gradient_checker(&mut op, &[zero], None, None, None);
+            let good_grad = _gradient_checker(&mut op, &[zero], None, None, None, None);
             assert_eq!(good_grad, true);
         }
     }
@@ -305,8 +936,196 @@ mod tests {
 
         for i in 0..10 {
             let zero = Tensor::from_vec_f64(&vec![(i - 5) as f64], &vec![1]);
-            let good_grad = _gradient_checker(&mut op, &[zero], None, None, None);
+            let good_grad = _gradient_checker(&mut op, &[zero], None, None, None, None);
             assert_eq!(good_grad, true);
         }
     }
+
+    #[test]
+    fn hardtanh() {
+        let mut op = Hardtanh::new(-2., 2.);
+
+        for i in 0..10 {
+            let zero = Tensor::from_vec_f64(&[(i - 5) as f64 + 0.5], &[1]);
+            let good_grad = _gradient_checker(&mut op, &[zero], None, None, None, None);
+            assert!(good_grad);
+        }
+    }
+
+    #[test]
+    fn leaky_relu() {
+        let mut op = LeakyReLU::new(0.1);
+
+        for i in 0..10 {
+            let zero = Tensor::from_vec_f64(&[(i - 5) as f64], &[1]);
+            let good_grad = _gradient_checker(&mut op, &[zero], None, None, None, None);
+            assert!(good_grad);
+        }
+    }
+
+    #[test]
+    fn prelu() {
+        let mut op = PReLU::new(Tensor::from_vec_f64(&[0.25], &[1]));
+
+        for i in 0..10 {
+            let zero = Tensor::from_vec_f64(&[(i - 5) as f64], &[1]);
+            let good_grad = _gradient_checker(&mut op, &[zero], None, None, None, None);
+            assert!(good_grad);
+        }
+
+        // one weight per channel.
+        let mut op = PReLU::new(Tensor::from_vec_f64(&[0.1, 0.2, 0.3], &[3]));
+        let x = Tensor::from_vec_f64(
+            &[1., -2., 3., -1., 0.5, -4., -0.5, -2., 1.5, 2.5, -0.5, -3.],
+            &[2, 3, 2],
+        );
+        let weight = Tensor::from_vec_f64(
+            &[0.5, -1., 2., 1., 0.3, -0.7, 1.2, 0.1, -0.4, 0.8, 1.5, -2.],
+            &[2, 3, 2],
+        );
+        assert!(_gradient_checker(
+            &mut op,
+            &[x.ref_copy()],
+            None,
+            Some(Tensor::from_vec_f64(&[1e-6], &[1])),
+            Some(Tensor::from_vec_f64(&[1e-10], &[1])),
+            Some(&[weight.ref_copy()])
+        ));
+
+        // the slope gradient is the sum of weight * x over negative x.
+        let output = Tensor::new();
+        op.apply(&[x.ref_copy()], &[output.ref_copy()]);
+        assert!((output.get_f64(&[0, 0, 1]) + 0.2).abs() < 1e-12);
+        let expected = Tensor::from_vec_f64(
+            &[
+                -2. * -1. + -0.5 * 1.2 + -2. * 0.1,
+                -1.,
+                -4. * -0.7 + -0.5 * 1.5 + -3. * -2.,
+            ],
+            &[3],
+        );
+        let diff = op.get_grads()[0].sub(&expected).abs();
+        assert!(diff.max(None, false).get_scale_f64() < 1e-12);
+    }
+
+    #[test]
+    fn gelu() {
+        let mut op = GELU::new();
+
+        for i in 0..10 {
+            let zero = Tensor::from_vec_f64(&[(i - 5) as f64 * 0.7], &[1]);
+            let good_grad = _gradient_checker(&mut op, &[zero], None, None, None, None);
+            assert!(good_grad);
+        }
+    }
+
+    #[test]
+    fn silu() {
+        let mut op = SiLU::new();
+
+        for i in 0..10 {
+            let zero = Tensor::from_vec_f64(&[(i - 5) as f64 * 0.7], &[1]);
+            let good_grad = _gradient_checker(&mut op, &[zero], None, None, None, None);
+            assert!(good_grad);
+        }
+    }
+
+    #[test]
+    fn mish() {
+        let mut op = Mish::new();
+
+        for i in 0..10 {
+            let zero = Tensor::from_vec_f64(&[(i - 5) as f64 * 0.7], &[1]);
+            let good_grad = _gradient_checker(&mut op, &[zero], None, None, None, None);
+            assert!(good_grad);
+        }
+    }
+
+    #[test]
+    fn softplus() {
+        let mut op = Softplus::new(2.);
+
+        for i in 0..10 {
+            let zero = Tensor::from_vec_f64(&[(i - 5) as f64 * 0.7], &[1]);
+            let good_grad = _gradient_checker(&mut op, &[zero], None, None, None, None);
+            assert!(good_grad);
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn softplus_zero_beta() {
+        Softplus::new(0.);
+    }
+
+    #[test]
+    fn softmax() {
+        let x = Tensor::from_vec_f64(&[1., 2., 3., -1., 0., 4.], &[2, 3]);
+        let op = Softmax::new(1);
+        let output = Tensor::new();
+        op.apply(&[x.ref_copy()], &[output.ref_copy()]);
+        let row = output.sum(Some(&[1]), false);
+        assert!((row.get_f64(&[0]) - 1.).abs() < 1e-12);
+        assert!((row.get_f64(&[1]) - 1.).abs() < 1e-12);
+        assert!((output.get_f64(&[0, 2]) - 0.6652409557748219).abs() < 1e-12);
+
+        // large logits do not overflow.
+        let big = Tensor::from_vec_f64(&[1000., 1000.], &[2]);
+        Softmax::new(0).apply(&[big], &[output.ref_copy()]);
+        assert!((output.get_f64(&[0]) - 0.5).abs() < 1e-12);
+
+        let weight = Tensor::from_vec_f64(&[0.5, -1., 2., 1., 0.3, -0.7], &[2, 3]);
+        for dim in 0..2 {
+            assert!(_gradient_checker(
+                &mut Softmax::new(dim),
+                &[x.ref_copy()],
+                None,
+                Some(Tensor::from_vec_f64(&[1e-6], &[1])),
+                Some(Tensor::from_vec_f64(&[1e-10], &[1])),
+                Some(&[weight.ref_copy()])
+            ));
+        }
+    }
+
+    #[test]
+    fn log_softmax() {
+        let x = Tensor::from_vec_f64(
+            &[1., 2., 3., -1., 0., 4., 0.5, -2., 1.5, 2.5, -0.5, 0.],
+            &[2, 3, 2],
+        );
+        let op = LogSoftmax::new(1);
+        let output = Tensor::new();
+        op.apply(&[x.ref_copy()], &[output.ref_copy()]);
+        let expected = Softmax::new(1);
+        let softmax = Tensor::new();
+        expected.apply(&[x.ref_copy()], &[softmax.ref_copy()]);
+        assert!(
+            output
+                .exp()
+                .sub(&softmax)
+                .abs()
+                .max(None, false)
+                .get_scale_f64()
+                < 1e-12
+        );
+
+        let big = Tensor::from_vec_f64(&[1000., 0.], &[2]);
+        LogSoftmax::new(0).apply(&[big], &[output.ref_copy()]);
+        assert!((output.get_f64(&[1]) + 1000.).abs() < 1e-9);
+
+        let weight = Tensor::from_vec_f64(
+            &[0.5, -1., 2., 1., 0.3, -0.7, 1.2, 0.1, -0.4, 0.8, 1.5, -2.],
+            &[2, 3, 2],
+        );
+        for dim in 0..3 {
+            assert!(_gradient_checker(
+                &mut LogSoftmax::new(dim),
+                &[x.ref_copy()],
+                None,
+                Some(Tensor::from_vec_f64(&[1e-6], &[1])),
+                Some(Tensor::from_vec_f64(&[1e-10], &[1])),
+                Some(&[weight.ref_copy()])
+            ));
+        }
+    }
 }
@@ -1,5 +1,16 @@
+use super::{scalar_like, Op, OpCall, OpHandle, OpTrait, TrainingFlag};
 use tensor_rs::tensor::Tensor;
-use super::OpTrait;
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::err::AutoDiffError;
+use crate::var::Var;
+
+#[cfg(feature = "use-serde")]
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "use-serde")]
+use std::any::Any;
 
 // BatchNorm1d
 // BatchNorm2d
@@ -12,4 +23,681 @@ use super::OpTrait;
 // LayerNorm
 // LocalResponseNorm
 
+/// Shape to broadcast a per-channel parameter against an [N, C, ...] input.
+fn channel_shape(input: &Tensor) -> Vec<usize> {
+    let mut ret = vec![1; input.size().len()];
+    ret[1] = input.size()[1];
+    ret
+}
+
+/// All dimensions but the channel one.
+fn non_channel_dims(input: &Tensor) -> Vec<usize> {
+    (0..input.size().len()).filter(|x| *x != 1).collect()
+}
+
+/// Mean and biased variance over dims, reduced dims are kept.
+fn moments(x: &Tensor, dims: &[usize]) -> (Tensor, Tensor) {
+    let mean = x.mean(Some(dims), true);
+    let centered = x.sub(&mean);
+    let var = centered.mul(&centered).mean(Some(dims), true);
+    (mean, var)
+}
+
+/// (x - mean)/sqrt(var + eps), also return 1/sqrt(var + eps).
+fn standardize(x: &Tensor, mean: &Tensor, var: &Tensor, eps: f64) -> (Tensor, Tensor) {
+    let inv_std = var.add(&scalar_like(x, eps)).sqrt().reciprocal();
+    (x.sub(mean).mul(&inv_std), inv_std)
+}
+
+/// Gradient of standardize with batch statistics over dims,
+/// given the gradient of the standardized value.
+fn standardize_grad(x_hat: &Tensor, inv_std: &Tensor, grad: &Tensor, dims: &[usize]) -> Tensor {
+    let mean_grad = grad.mean(Some(dims), true);
+    let mean_grad_x = grad.mul(x_hat).mean(Some(dims), true);
+    grad.sub(&mean_grad).sub(&x_hat.mul(&mean_grad_x)).mul(inv_std)
+}
+
+/// Normalize an [N, C, ...] input within groups of channels.
+fn group_standardize(x: &Tensor, num_groups: usize, eps: f64) -> (Tensor, Tensor) {
+    let n = x.size()[0];
+    let grouped = x.reshape(&[n, num_groups, x.numel() / n / num_groups]);
+    let (mean, var) = moments(&grouped, &[2]);
+    standardize(&grouped, &mean, &var, eps)
+}
+
+/// Gradient of group_standardize, grad is the gradient of the normalized value.
+fn group_standardize_grad(x: &Tensor, grad: &Tensor, num_groups: usize, eps: f64) -> Tensor {
+    let (x_hat, inv_std) = group_standardize(x, num_groups, eps);
+    standardize_grad(&x_hat, &inv_std, &grad.reshape(&x_hat.size()), &[2]).reshape(&x.size())
+}
+
+macro_rules! batch_norm {
+    ($a:ident, $b:expr, $dim_check:expr) => {
+        /// Normalize each channel of an [N, C, ...] input
+        /// with the batch statistics in training mode,
+        /// and the running statistics in eval mode.
+        #[cfg_attr(feature = "use-serde", derive(Serialize, Deserialize))]
+        pub struct $a {
+            num_features: usize,
+            eps: f64,
+            momentum: f64,
+            affine: bool,
+            weight: Tensor,
+            bias: Tensor,
+            weight_grad: Tensor,
+            bias_grad: Tensor,
+            running_mean: Tensor,
+            running_var: Tensor,
+            #[cfg_attr(feature = "use-serde", serde(skip))]
+            training: TrainingFlag,
+            #[cfg_attr(feature = "use-serde", serde(skip))]
+            handle: OpHandle,
+        }
+        impl $a {
+            pub fn new(num_features: usize, eps: f64, momentum: f64, affine: bool) -> $a {
+                $a {
+                    num_features,
+                    eps,
+                    momentum,
+                    affine,
+                    weight: Tensor::ones(&[num_features]),
+                    bias: Tensor::zeros(&[num_features]),
+                    weight_grad: Tensor::new(),
+                    bias_grad: Tensor::new(),
+                    running_mean: Tensor::zeros(&[num_features]),
+                    running_var: Tensor::ones(&[num_features]),
+                    training: TrainingFlag::new(true),
+                    handle: OpHandle::new(),
+                }
+            }
+
+            pub fn weight(&self) -> &Tensor {
+                &self.weight
+            }
+            pub fn set_weight(&self, var: Var) {
+                self.weight.swap(&var.val());
+            }
+            pub fn bias(&self) -> &Tensor {
+                &self.bias
+            }
+            pub fn set_bias(&self, var: Var) {
+                self.bias.swap(&var.val());
+            }
+            pub fn running_mean(&self) -> &Tensor {
+                &self.running_mean
+            }
+            pub fn running_var(&self) -> &Tensor {
+                &self.running_var
+            }
+
+            /// Use batch statistics and update the running statistics.
+            pub fn train(&self) {
+                self.training.set(true);
+            }
+            /// Use the running statistics.
+            pub fn eval(&self) {
+                self.training.set(false);
+            }
+            pub fn is_training(&self) -> bool {
+                self.training.get()
+            }
+
+            handle_method!();
+        }
+        impl OpCall for $a {
+            fn call(&mut self, inputs: &[&Var]) -> Result<Vec<Var>, AutoDiffError> {
+                let new_one = $a {
+                    num_features: self.num_features,
+                    eps: self.eps,
+                    momentum: self.momentum,
+                    affine: self.affine,
+                    weight: self.weight.ref_copy(),
+                    bias: self.bias.ref_copy(),
+                    weight_grad: self.weight_grad.ref_copy(),
+                    bias_grad: self.bias_grad.ref_copy(),
+                    running_mean: self.running_mean.ref_copy(),
+                    running_var: self.running_var.ref_copy(),
+                    training: self.training.ref_copy(),
+                    handle: OpHandle::new(),
+                };
+
+                let op = Op::new(Rc::new(RefCell::new(Box::new(new_one))));
+
+                inputs[0].called_with(op, &inputs[1..inputs.len()])
+            }
+        }
+        impl OpTrait for $a {
+            fn get_name(&self) -> &'static str {
+                ($b)
+            }
+            fn get_input_size(&self) -> usize {
+                1
+            }
+            fn get_output_size(&self) -> usize {
+                1
+            }
+            fn apply(&self, input: &[Tensor], output: &[Tensor]) {
+                if !($dim_check)(input[0].size().len()) || input[0].size()[1] != self.num_features {
+                    panic!("{} does not accept input of size {:?}", $b, input[0].size());
+                }
+                let shape = channel_shape(&input[0]);
+                let dims = non_channel_dims(&input[0]);
+                let x_hat = if self.training.get() {
+                    let (mean, var) = moments(&input[0], &dims);
+                    let n = input[0].numel() / self.num_features;
+                    let unbiased = var.mul(&scalar_like(&var, n as f64 / (n as f64 - 1.).max(1.)));
+                    let momentum = scalar_like(&var, self.momentum);
+                    let keep = scalar_like(&var, 1. - self.momentum);
+                    self.running_mean.swap(
+                        &self.running_mean.mul(&keep).add(&mean.reshape(&[self.num_features]).mul(&momentum)));
+                    self.running_var.swap(
+                        &self.running_var.mul(&keep).add(&unbiased.reshape(&[self.num_features]).mul(&momentum)));
+                    standardize(&input[0], &mean, &var, self.eps).0
+                } else {
+                    standardize(&input[0],
+                                &self.running_mean.reshape(&shape),
+                                &self.running_var.reshape(&shape), self.eps).0
+                };
+                if self.affine {
+                    output[0].swap(&x_hat.mul(&self.weight.reshape(&shape)).add(&self.bias.reshape(&shape)));
+                } else {
+                    output[0].swap(&x_hat);
+                }
+            }
+            fn grad(&self, input: &[Tensor], output_grad: &[Tensor], input_grad: &[Tensor]) {
+                let shape = channel_shape(&input[0]);
+                let dims = non_channel_dims(&input[0]);
+                let (x_hat, inv_std) = if self.training.get() {
+                    let (mean, var) = moments(&input[0], &dims);
+                    standardize(&input[0], &mean, &var, self.eps)
+                } else {
+                    standardize(&input[0],
+                                &self.running_mean.reshape(&shape),
+                                &self.running_var.reshape(&shape), self.eps)
+                };
+                let grad = if self.affine {
+                    self.weight_grad.swap(&output_grad[0].mul(&x_hat).sum(Some(&dims), false));
+                    self.bias_grad.swap(&output_grad[0].sum(Some(&dims), false));
+                    output_grad[0].mul(&self.weight.reshape(&shape))
+                } else {
+                    output_grad[0].clone()
+                };
+                if self.training.get() {
+                    input_grad[0].swap(&standardize_grad(&x_hat, &inv_std, &grad, &dims));
+                } else {
+                    // the running statistics are constants.
+                    input_grad[0].swap(&grad.mul(&inv_std));
+                }
+            }
+            fn set_training(&self, training: bool) {
+                self.training.set(training);
+            }
+            fn get_values(&self) -> Vec<Tensor> {
+                if self.affine {
+                    vec![self.weight.clone(), self.bias.clone()]
+                } else {
+                    Vec::new()
+                }
+            }
+            fn set_values(&self, v: &[Tensor]) {
+                if self.affine {
+                    self.weight.swap(&v[0].clone());
+                    self.bias.swap(&v[1].clone());
+                }
+            }
+            fn get_grads(&self) -> Vec<Tensor> {
+                if self.affine {
+                    vec![self.weight_grad.clone(), self.bias_grad.clone()]
+                } else {
+                    Vec::new()
+                }
+            }
+            fn set_grads(&self, v: &[Tensor]) {
+                if self.affine {
+                    self.weight_grad.swap(&v[0].clone());
+                    self.bias_grad.swap(&v[1].clone());
+                }
+            }
+            #[cfg(feature = "use-serde")]
+            fn as_any(&self) -> &dyn Any {
+                self
+            }
+        }
+    };
+}
+
+batch_norm!(BatchNorm1d, "BatchNorm1d", (|d: usize| d == 2 || d == 3));
+batch_norm!(BatchNorm2d, "BatchNorm2d", (|d: usize| d == 4));
+
+/// Normalize over the last dimensions given by normalized_shape,
+/// with an elementwise affine transform of the same shape.
+#[cfg_attr(feature = "use-serde", derive(Serialize, Deserialize))]
+pub struct LayerNorm {
+    normalized_shape: Vec<usize>,
+    eps: f64,
+    elementwise_affine: bool,
+    weight: Tensor,
+    bias: Tensor,
+    weight_grad: Tensor,
+    bias_grad: Tensor,
+    #[cfg_attr(feature = "use-serde", serde(skip))]
+    handle: OpHandle,
+}
+impl LayerNorm {
+    pub fn new(normalized_shape: &[usize], eps: f64, elementwise_affine: bool) -> LayerNorm {
+        LayerNorm {
+            normalized_shape: normalized_shape.to_vec(),
+            eps,
+            elementwise_affine,
+            weight: Tensor::ones(normalized_shape),
+            bias: Tensor::zeros(normalized_shape),
+            weight_grad: Tensor::new(),
+            bias_grad: Tensor::new(),
+            handle: OpHandle::new(),
+        }
+    }
+
+    pub fn weight(&self) -> &Tensor {
+        &self.weight
+    }
+    pub fn set_weight(&self, var: Var) {
+        self.weight.swap(&var.val());
+    }
+    pub fn bias(&self) -> &Tensor {
+        &self.bias
+    }
+    pub fn set_bias(&self, var: Var) {
+        self.bias.swap(&var.val());
+    }
+
+    /// Normalized dims and leading dims of the input.
+    fn split_dims(&self, input: &Tensor) -> (Vec<usize>, Vec<usize>) {
+        let n = input.size().len();
+        let k = self.normalized_shape.len();
+        if n < k || input.size()[n - k..] != self.normalized_shape[..] {
+            panic!("LayerNorm expects input ends with {:?}, get {:?}", self.normalized_shape, input.size());
+        }
+        ((n - k..n).collect(), (0..n - k).collect())
+    }
+
+    handle_method!();
+}
+impl OpCall for LayerNorm {
+    fn call(&mut self, inputs: &[&Var]) -> Result<Vec<Var>, AutoDiffError> {
+        let new_one = LayerNorm {
+            normalized_shape: self.normalized_shape.clone(),
+            eps: self.eps,
+            elementwise_affine: self.elementwise_affine,
+            weight: self.weight.ref_copy(),
+            bias: self.bias.ref_copy(),
+            weight_grad: self.weight_grad.ref_copy(),
+            bias_grad: self.bias_grad.ref_copy(),
+            handle: OpHandle::new(),
+        };
+
+        let op = Op::new(Rc::new(RefCell::new(Box::new(new_one))));
+
+        inputs[0].called_with(op, &inputs[1..inputs.len()])
+    }
+}
+impl OpTrait for LayerNorm {
+    fn get_name(&self) -> &'static str {
+        "LayerNorm"
+    }
+    fn get_input_size(&self) -> usize {
+        1
+    }
+    fn get_output_size(&self) -> usize {
+        1
+    }
+    fn apply(&self, input: &[Tensor], output: &[Tensor]) {
+        let (dims, _) = self.split_dims(&input[0]);
+        let (mean, var) = moments(&input[0], &dims);
+        let (x_hat, _) = standardize(&input[0], &mean, &var, self.eps);
+        if self.elementwise_affine {
+            output[0].swap(&x_hat.mul(&self.weight).add(&self.bias));
+        } else {
+            output[0].swap(&x_hat);
+        }
+    }
+    fn grad(&self, input: &[Tensor], output_grad: &[Tensor], input_grad: &[Tensor]) {
+        let (dims, lead) = self.split_dims(&input[0]);
+        let (mean, var) = moments(&input[0], &dims);
+        let (x_hat, inv_std) = standardize(&input[0], &mean, &var, self.eps);
+        let grad = if self.elementwise_affine {
+            let weight_grad = output_grad[0].mul(&x_hat);
+            if lead.is_empty() {
+                self.weight_grad.swap(&weight_grad);
+                self.bias_grad.swap(&output_grad[0].clone());
+            } else {
+                self.weight_grad.swap(&weight_grad.sum(Some(&lead), false));
+                self.bias_grad.swap(&output_grad[0].sum(Some(&lead), false));
+            }
+            output_grad[0].mul(&self.weight)
+        } else {
+            output_grad[0].clone()
+        };
+        input_grad[0].swap(&standardize_grad(&x_hat, &inv_std, &grad, &dims));
+    }
+    fn get_values(&self) -> Vec<Tensor> {
+        if self.elementwise_affine {
+            vec![self.weight.clone(), self.bias.clone()]
+        } else {
+            Vec::new()
+        }
+    }
+    fn set_values(&self, v: &[Tensor]) {
+        if self.elementwise_affine {
+            self.weight.swap(&v[0].clone());
+            self.bias.swap(&v[1].clone());
+        }
+    }
+    fn get_grads(&self) -> Vec<Tensor> {
+        if self.elementwise_affine {
+            vec![self.weight_grad.clone(), self.bias_grad.clone()]
+        } else {
+            Vec::new()
+        }
+    }
+    fn set_grads(&self, v: &[Tensor]) {
+        if self.elementwise_affine {
+            self.weight_grad.swap(&v[0].clone());
+            self.bias_grad.swap(&v[1].clone());
+        }
+    }
+    #[cfg(feature = "use-serde")]
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+macro_rules! group_norm {
+    ($a:ident, $b:expr, $groups:expr, $( $arg_name:ident : $ArgTy:ty ),*) => {
+        #[cfg_attr(feature = "use-serde", derive(Serialize, Deserialize))]
+        pub struct $a {
+            $( $arg_name : $ArgTy, )*
+            num_channels: usize,
+            eps: f64,
+            affine: bool,
+            weight: Tensor,
+            bias: Tensor,
+            weight_grad: Tensor,
+            bias_grad: Tensor,
+            #[cfg_attr(feature = "use-serde", serde(skip))]
+            handle: OpHandle,
+        }
+        impl $a {
+            pub fn weight(&self) -> &Tensor {
+                &self.weight
+            }
+            pub fn set_weight(&self, var: Var) {
+                self.weight.swap(&var.val());
+            }
+            pub fn bias(&self) -> &Tensor {
+                &self.bias
+            }
+            pub fn set_bias(&self, var: Var) {
+                self.bias.swap(&var.val());
+            }
+
+            handle_method!();
+        }
+        impl OpCall for $a {
+            fn call(&mut self, inputs: &[&Var]) -> Result<Vec<Var>, AutoDiffError> {
+                let new_one = $a {
+                    $( $arg_name : self.$arg_name, )*
+                    num_channels: self.num_channels,
+                    eps: self.eps,
+                    affine: self.affine,
+                    weight: self.weight.ref_copy(),
+                    bias: self.bias.ref_copy(),
+                    weight_grad: self.weight_grad.ref_copy(),
+                    bias_grad: self.bias_grad.ref_copy(),
+                    handle: OpHandle::new(),
+                };
+
+                let op = Op::new(Rc::new(RefCell::new(Box::new(new_one))));
+
+                inputs[0].called_with(op, &inputs[1..inputs.len()])
+            }
+        }
+        impl OpTrait for $a {
+            fn get_name(&self) -> &'static str {
+                ($b)
+            }
+            fn get_input_size(&self) -> usize {
+                1
+            }
+            fn get_output_size(&self) -> usize {
+                1
+            }
+            fn apply(&self, input: &[Tensor], output: &[Tensor]) {
+                if input[0].size().len() < 2 || input[0].size()[1] != self.num_channels {
+                    panic!("{} expects {} channels, get {:?}", $b, self.num_channels, input[0].size());
+                }
+                let (x_hat, _) = group_standardize(&input[0], ($groups)(self), self.eps);
+                let x_hat = x_hat.reshape(&input[0].size());
+                if self.affine {
+                    let shape = channel_shape(&input[0]);
+                    output[0].swap(&x_hat.mul(&self.weight.reshape(&shape)).add(&self.bias.reshape(&shape)));
+                } else {
+                    output[0].swap(&x_hat);
+                }
+            }
+            fn grad(&self, input: &[Tensor], output_grad: &[Tensor], input_grad: &[Tensor]) {
+                let groups = ($groups)(self);
+                let grad = if self.affine {
+                    let shape = channel_shape(&input[0]);
+                    let dims = non_channel_dims(&input[0]);
+                    let (x_hat, _) = group_standardize(&input[0], groups, self.eps);
+                    let x_hat = x_hat.reshape(&input[0].size());
+                    self.weight_grad.swap(&output_grad[0].mul(&x_hat).sum(Some(&dims), false));
+                    self.bias_grad.swap(&output_grad[0].sum(Some(&dims), false));
+                    output_grad[0].mul(&self.weight.reshape(&shape))
+                } else {
+                    output_grad[0].clone()
+                };
+                input_grad[0].swap(&group_standardize_grad(&input[0], &grad, groups, self.eps));
+            }
+            fn get_values(&self) -> Vec<Tensor> {
+                if self.affine {
+                    vec![self.weight.clone(), self.bias.clone()]
+                } else {
+                    Vec::new()
+                }
+            }
+            fn set_values(&self, v: &[Tensor]) {
+                if self.affine {
+                    self.weight.swap(&v[0].clone());
+                    self.bias.swap(&v[1].clone());
+                }
+            }
+            fn get_grads(&self) -> Vec<Tensor> {
+                if self.affine {
+                    vec![self.weight_grad.clone(), self.bias_grad.clone()]
+                } else {
+                    Vec::new()
+                }
+            }
+            fn set_grads(&self, v: &[Tensor]) {
+                if self.affine {
+                    self.weight_grad.swap(&v[0].clone());
+                    self.bias_grad.swap(&v[1].clone());
+                }
+            }
+            #[cfg(feature = "use-serde")]
+            fn as_any(&self) -> &dyn Any {
+                self
+            }
+        }
+    };
+}
+
+group_norm!(GroupNorm, "GroupNorm", (|op: &GroupNorm| op.num_groups), num_groups: usize);
+impl GroupNorm {
+    /// Normalize an [N, C, ...] input over groups of C/num_groups channels,
+    /// with a per-channel affine transform.
+    pub fn new(num_groups: usize, num_channels: usize, eps: f64, affine: bool) -> GroupNorm {
+        if !num_channels.is_multiple_of(num_groups) {
+            panic!("GroupNorm expects num_channels {} divisible by num_groups {}", num_channels, num_groups);
+        }
+        GroupNorm {
+            num_groups,
+            num_channels,
+            eps,
+            affine,
+            weight: Tensor::ones(&[num_channels]),
+            bias: Tensor::zeros(&[num_channels]),
+            weight_grad: Tensor::new(),
+            bias_grad: Tensor::new(),
+            handle: OpHandle::new(),
+        }
+    }
+}
+
+group_norm!(InstanceNorm, "InstanceNorm", (|op: &InstanceNorm| op.num_channels),);
+impl InstanceNorm {
+    /// Normalize each channel of each sample of an [N, C, ...] input
+    /// over the remaining dims, for both 1d and 2d data.
+    pub fn new(num_features: usize, eps: f64, affine: bool) -> InstanceNorm {
+        InstanceNorm {
+            num_channels: num_features,
+            eps,
+            affine,
+            weight: Tensor::ones(&[num_features]),
+            bias: Tensor::zeros(&[num_features]),
+            weight_grad: Tensor::new(),
+            bias_grad: Tensor::new(),
+            handle: OpHandle::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::op::_gradient_checker;
+    use rand::prelude::StdRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn batch_norm() {
+        let mut rng = StdRng::seed_from_u64(1);
+        let mut op = BatchNorm1d::new(3, 1e-5, 0.1, true);
+        op.set_weight(Var::new_f64(&[1., 2., -1.], &[3]));
+        op.set_bias(Var::new_f64(&[0., 1., 0.5], &[3]));
+        assert!(_gradient_checker(
+            &mut op,
+            &[Tensor::uniform_f64(&mut rng, &[4, 3], -1., 1.)],
+            None,
+            Some(Tensor::from_vec_f64(&[1e-6], &[1])),
+            Some(Tensor::from_vec_f64(&[1e-10], &[1])),
+            Some(&[Tensor::uniform_f64(&mut rng, &[4, 3], -1., 1.)])
+        ));
+        assert!(_gradient_checker(
+            &mut op,
+            &[Tensor::uniform_f64(&mut rng, &[2, 3, 5], -1., 1.)],
+            None,
+            Some(Tensor::from_vec_f64(&[1e-6], &[1])),
+            Some(Tensor::from_vec_f64(&[1e-10], &[1])),
+            Some(&[Tensor::uniform_f64(&mut rng, &[2, 3, 5], -1., 1.)])
+        ));
+        assert!(_gradient_checker(
+            &mut BatchNorm2d::new(2, 1e-5, 0.1, false),
+            &[Tensor::uniform_f64(&mut rng, &[2, 2, 3, 3], -1., 1.)],
+            None,
+            Some(Tensor::from_vec_f64(&[1e-6], &[1])),
+            Some(Tensor::from_vec_f64(&[1e-10], &[1])),
+            Some(&[Tensor::uniform_f64(&mut rng, &[2, 2, 3, 3], -1., 1.)])
+        ));
+
+        op.eval();
+        assert!(_gradient_checker(
+            &mut op,
+            &[Tensor::uniform_f64(&mut rng, &[4, 3], -1., 1.)],
+            None,
+            Some(Tensor::from_vec_f64(&[1e-6], &[1])),
+            Some(Tensor::from_vec_f64(&[1e-10], &[1])),
+            Some(&[Tensor::uniform_f64(&mut rng, &[4, 3], -1., 1.)])
+        ));
+    }
+
+    #[test]
+    fn batch_norm_running_stats() {
+        let op = BatchNorm1d::new(2, 0., 0.5, false);
+        let input = Tensor::from_vec_f64(&[1., 2., 3., 6.], &[2, 2]);
+        let output = Tensor::new();
+        op.apply(&[input.ref_copy()], &[output.ref_copy()]);
+        assert_eq!(output, Tensor::from_vec_f64(&[-1., -1., 1., 1.], &[2, 2]));
+        assert_eq!(*op.running_mean(), Tensor::from_vec_f64(&[1., 2.], &[2]));
+        assert_eq!(*op.running_var(), Tensor::from_vec_f64(&[1.5, 4.5], &[2]));
+
+        op.eval();
+        op.apply(&[input.ref_copy()], &[output.ref_copy()]);
+        assert_eq!(*op.running_mean(), Tensor::from_vec_f64(&[1., 2.], &[2]));
+        let expected = input.sub(&op.running_mean().reshape(&[1, 2]))
+            .div(&op.running_var().reshape(&[1, 2]).sqrt());
+        assert_eq!(output, expected);
+    }
+
+    #[test]
+    fn layer_norm() {
+        let mut rng = StdRng::seed_from_u64(2);
+        let mut op = LayerNorm::new(&[3], 1e-5, true);
+        op.set_weight(Var::new_f64(&[1., 2., -1.], &[3]));
+        op.set_bias(Var::new_f64(&[0., 1., 0.5], &[3]));
+        assert!(_gradient_checker(
+            &mut op,
+            &[Tensor::uniform_f64(&mut rng, &[4, 3], -1., 1.)],
+            None,
+            Some(Tensor::from_vec_f64(&[1e-6], &[1])),
+            Some(Tensor::from_vec_f64(&[1e-10], &[1])),
+            Some(&[Tensor::uniform_f64(&mut rng, &[4, 3], -1., 1.)])
+        ));
+        assert!(_gradient_checker(
+            &mut LayerNorm::new(&[2, 3], 1e-5, false),
+            &[Tensor::uniform_f64(&mut rng, &[2, 2, 3], -1., 1.)],
+            None,
+            Some(Tensor::from_vec_f64(&[1e-6], &[1])),
+            Some(Tensor::from_vec_f64(&[1e-10], &[1])),
+            Some(&[Tensor::uniform_f64(&mut rng, &[2, 2, 3], -1., 1.)])
+        ));
+    }
+
+    #[test]
+    fn group_instance_norm() {
+        let mut rng = StdRng::seed_from_u64(3);
+        let mut op = GroupNorm::new(2, 4, 1e-5, true);
+        op.set_weight(Var::new_f64(&[1., 2., -1., 0.5], &[4]));
+        assert!(_gradient_checker(
+            &mut op,
+            &[Tensor::uniform_f64(&mut rng, &[2, 4, 3], -1., 1.)],
+            None,
+            Some(Tensor::from_vec_f64(&[1e-6], &[1])),
+            Some(Tensor::from_vec_f64(&[1e-10], &[1])),
+            Some(&[Tensor::uniform_f64(&mut rng, &[2, 4, 3], -1., 1.)])
+        ));
+        assert!(_gradient_checker(
+            &mut InstanceNorm::new(3, 1e-5, true),
+            &[Tensor::uniform_f64(&mut rng, &[2, 3, 2, 2], -1., 1.)],
+            None,
+            Some(Tensor::from_vec_f64(&[1e-6], &[1])),
+            Some(Tensor::from_vec_f64(&[1e-10], &[1])),
+            Some(&[Tensor::uniform_f64(&mut rng, &[2, 3, 2, 2], -1., 1.)])
+        ));
+    }
+
+    #[test]
+    fn train_eval_shared() {
+        let mut op = BatchNorm1d::new(2, 1e-5, 0.1, true);
+        let input = Var::new_f64(&[1., 2., 3., 6.], &[2, 2]);
+        let output = op.call(&[&input]).unwrap().pop().unwrap();
+        output.bp().unwrap();
+        assert_eq!(op.get_grads()[1], Tensor::from_vec_f64(&[2., 2.], &[2]));
 
+        // the op in the graph follows the mode of op.
+        op.eval();
+        output.rerun().unwrap();
+        assert_eq!(*op.running_mean(), Tensor::from_vec_f64(&[0.2, 0.4], &[2]));
+    }
+}
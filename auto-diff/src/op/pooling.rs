@@ -1,4 +1,8 @@
-use super::{OpHandle, OpTrait};
+use super::{Op, OpCall, OpHandle, OpTrait};
+use crate::err::AutoDiffError;
+use crate::var::Var;
+use std::cell::RefCell;
+use std::rc::Rc;
 use tensor_rs::tensor::Tensor;
 
 #[cfg(feature = "use-serde")]
@@ -6,74 +10,320 @@ use serde::{Deserialize, Serialize};
 #[cfg(feature = "use-serde")]
 use std::any::Any;
 
-// MaxPool1d
-// Maxpool2d
+/// Per dimension pooling parameters, usize for 1d and (usize, usize) for 2d.
+/// Convolution shares it, with (usize, usize, usize) for 3d.
+pub(crate) trait PoolSize: Copy {
+    fn to_vec(self) -> Vec<usize>;
+}
+impl PoolSize for usize {
+    fn to_vec(self) -> Vec<usize> {
+        vec![self]
+    }
+}
+impl PoolSize for (usize, usize) {
+    fn to_vec(self) -> Vec<usize> {
+        vec![self.0, self.1]
+    }
+}
+impl PoolSize for (usize, usize, usize) {
+    fn to_vec(self) -> Vec<usize> {
+        vec![self.0, self.1, self.2]
+    }
+}
+
+// Pooling takes the unbatched input (C, *) or the batched one (N, C, *).
+fn check_pool_input(name: &str, input: &Tensor, dims: usize) {
+    let n = input.size().len();
+    if n != dims + 1 && n != dims + 2 {
+        panic!(
+            "{} expects input with {} or {} dims, get {:?}",
+            name,
+            dims + 1,
+            dims + 2,
+            input.size()
+        );
+    }
+}
+
+macro_rules! max_pool {
+    ($a:ident, $b:expr, $t:ty, $dims:expr) => {
+        #[cfg_attr(feature = "use-serde", derive(Serialize, Deserialize))]
+        pub struct $a {
+            #[cfg_attr(feature = "use-serde", serde(skip))]
+            handle: OpHandle,
+            kernel_size: $t,
+            stride: $t,
+            padding: $t,
+            dilation: $t,
+            return_indices: bool,
+            ceil_mode: bool,
+        }
+        impl $a {
+            /// stride defaults to kernel_size.
+            /// With return_indices, the op has a second output holding
+            /// the flat index of each maximum within its input plane.
+            pub fn new(
+                kernel_size: $t,
+                stride: Option<$t>,
+                padding: $t,
+                dilation: $t,
+                return_indices: bool,
+                ceil_mode: bool,
+            ) -> $a {
+                $a {
+                    handle: OpHandle::new(),
+                    kernel_size,
+                    stride: stride.unwrap_or(kernel_size),
+                    padding,
+                    dilation,
+                    return_indices,
+                    ceil_mode,
+                }
+            }
+            handle_method!();
+        }
+        impl OpCall for $a {
+            fn call(&mut self, inputs: &[&Var]) -> Result<Vec<Var>, AutoDiffError> {
+                let new_one = $a {
+                    handle: OpHandle::new(),
+                    kernel_size: self.kernel_size,
+                    stride: self.stride,
+                    padding: self.padding,
+                    dilation: self.dilation,
+                    return_indices: self.return_indices,
+                    ceil_mode: self.ceil_mode,
+                };
+
+                let op = Op::new(Rc::new(RefCell::new(Box::new(new_one))));
+
+                inputs[0].called_with(op, &inputs[1..inputs.len()])
+            }
+        }
+        impl OpTrait for $a {
+            fn get_name(&self) -> &'static str {
+                ($b)
+            }
+            fn get_input_size(&self) -> usize {
+                1
+            }
+            fn get_output_size(&self) -> usize {
+                if self.return_indices {
+                    2
+                } else {
+                    1
+                }
+            }
+            fn apply(&self, input: &[Tensor], output: &[Tensor]) {
+                check_pool_input($b, &input[0], $dims);
+                let (values, indices) = input[0].max_pool(
+                    &self.kernel_size.to_vec(),
+                    &self.stride.to_vec(),
+                    &self.padding.to_vec(),
+                    &self.dilation.to_vec(),
+                    self.ceil_mode,
+                );
+                output[0].swap(&values);
+                if self.return_indices {
+                    output[1].swap(&indices);
+                }
+            }
+            fn grad(&self, input: &[Tensor], output_grad: &[Tensor], input_grad: &[Tensor]) {
+                // The values output can be unused when only the indices are consumed.
+                if output_grad[0].numel() == 0 {
+                    input_grad[0].swap(&input[0].zeros_like());
+                    return;
+                }
+                let ret = input[0].max_pool_grad(
+                    &self.kernel_size.to_vec(),
+                    &self.stride.to_vec(),
+                    &self.padding.to_vec(),
+                    &self.dilation.to_vec(),
+                    self.ceil_mode,
+                    &output_grad[0],
+                );
+                input_grad[0].swap(&ret);
+            }
+            fn get_values(&self) -> Vec<Tensor> {
+                Vec::new()
+            }
+            fn get_grads(&self) -> Vec<Tensor> {
+                Vec::new()
+            }
+            fn set_values(&self, _v: &[Tensor]) {}
+            #[cfg(feature = "use-serde")]
+            fn as_any(&self) -> &dyn Any {
+                self
+            }
+        }
+    };
+}
+
+max_pool!(MaxPool1d, "MaxPool1d", usize, 1);
+max_pool!(MaxPool2d, "MaxPool2d", (usize, usize), 2);
+
+// MaxPool3d
+// MaxUnpool1d
+// MaxUnpool2d
+// MaxUnpool3d
+
+macro_rules! avg_pool {
+    ($a:ident, $b:expr, $t:ty, $dims:expr) => {
+        #[cfg_attr(feature = "use-serde", derive(Serialize, Deserialize))]
+        pub struct $a {
+            #[cfg_attr(feature = "use-serde", serde(skip))]
+            handle: OpHandle,
+            kernel_size: $t,
+            stride: $t,
+            padding: $t,
+            ceil_mode: bool,
+            count_include_pad: bool,
+        }
+        impl $a {
+            /// stride defaults to kernel_size.
+            pub fn new(
+                kernel_size: $t,
+                stride: Option<$t>,
+                padding: $t,
+                ceil_mode: bool,
+                count_include_pad: bool,
+            ) -> $a {
+                $a {
+                    handle: OpHandle::new(),
+                    kernel_size,
+                    stride: stride.unwrap_or(kernel_size),
+                    padding,
+                    ceil_mode,
+                    count_include_pad,
+                }
+            }
+            handle_method!();
+        }
+        impl OpCall for $a {
+            fn call(&mut self, inputs: &[&Var]) -> Result<Vec<Var>, AutoDiffError> {
+                let new_one = $a {
+                    handle: OpHandle::new(),
+                    kernel_size: self.kernel_size,
+                    stride: self.stride,
+                    padding: self.padding,
+                    ceil_mode: self.ceil_mode,
+                    count_include_pad: self.count_include_pad,
+                };
+
+                let op = Op::new(Rc::new(RefCell::new(Box::new(new_one))));
+
+                inputs[0].called_with(op, &inputs[1..inputs.len()])
+            }
+        }
+        impl OpTrait for $a {
+            fn get_name(&self) -> &'static str {
+                ($b)
+            }
+            fn get_input_size(&self) -> usize {
+                1
+            }
+            fn get_output_size(&self) -> usize {
+                1
+            }
+            fn apply(&self, input: &[Tensor], output: &[Tensor]) {
+                check_pool_input($b, &input[0], $dims);
+                output[0].swap(&input[0].avg_pool(
+                    &self.kernel_size.to_vec(),
+                    &self.stride.to_vec(),
+                    &self.padding.to_vec(),
+                    self.ceil_mode,
+                    self.count_include_pad,
+                ));
+            }
+            fn grad(&self, input: &[Tensor], output_grad: &[Tensor], input_grad: &[Tensor]) {
+                input_grad[0].swap(&input[0].avg_pool_grad(
+                    &self.kernel_size.to_vec(),
+                    &self.stride.to_vec(),
+                    &self.padding.to_vec(),
+                    self.ceil_mode,
+                    self.count_include_pad,
+                    &output_grad[0],
+                ));
+            }
+            fn get_values(&self) -> Vec<Tensor> {
+                Vec::new()
+            }
+            fn get_grads(&self) -> Vec<Tensor> {
+                Vec::new()
+            }
+            fn set_values(&self, _v: &[Tensor]) {}
+            #[cfg(feature = "use-serde")]
+            fn as_any(&self) -> &dyn Any {
+                self
+            }
+        }
+    };
+}
+
+avg_pool!(AvgPool1d, "AvgPool1d", usize, 1);
+avg_pool!(AvgPool2d, "AvgPool2d", (usize, usize), 2);
+
+// AvgPool3d
+// FractionalMaxPool2d
+// LPPool1d
+// LPPool2d
+// AdaptiveMaxPool1d
+
 #[cfg_attr(feature = "use-serde", derive(Serialize, Deserialize))]
-pub struct MaxPool2d {
+pub struct AdaptiveMaxPool2d {
     #[cfg_attr(feature = "use-serde", serde(skip))]
     handle: OpHandle,
-    kernel_size: (usize, usize),
-    stride: (usize, usize),
-    padding: Tensor,
-    dilation: (usize, usize),
+    output_size: (usize, usize),
     return_indices: bool,
-    ceil_mode: bool,
-}
-impl MaxPool2d {
-    pub fn new(
-        kernel_size: Option<(usize, usize)>,
-        stride: Option<(usize, usize)>,
-        padding: Option<Tensor>,
-        dilation: Option<(usize, usize)>,
-        return_indices: Option<bool>,
-        ceil_mode: Option<bool>,
-    ) -> MaxPool2d {
-        let kernel_size = if let Some(v) = kernel_size { v } else { (2, 2) };
-        let stride = if let Some(v) = stride { v } else { (2, 2) };
-        let padding = if let Some(v) = padding {
-            v
-        } else {
-            Tensor::zeros(&[1])
-        };
-        let dilation = if let Some(v) = dilation { v } else { (2, 2) };
-        let return_indices = if let Some(v) = return_indices {
-            v
-        } else {
-            false
-        };
-        let ceil_mode = if let Some(v) = ceil_mode { v } else { false };
-        MaxPool2d {
+}
+impl AdaptiveMaxPool2d {
+    pub fn new(output_size: (usize, usize), return_indices: bool) -> AdaptiveMaxPool2d {
+        AdaptiveMaxPool2d {
             handle: OpHandle::new(),
-            kernel_size,
-            stride,
-            padding,
-            dilation,
+            output_size,
             return_indices,
-            ceil_mode,
         }
     }
-    fn get_handle(&self) -> &OpHandle {
-        &self.handle
-    }
-    fn get_handle_mut(&mut self) -> &mut OpHandle {
-        &mut self.handle
+    handle_method!();
+}
+impl OpCall for AdaptiveMaxPool2d {
+    fn call(&mut self, inputs: &[&Var]) -> Result<Vec<Var>, AutoDiffError> {
+        let new_one = AdaptiveMaxPool2d::new(self.output_size, self.return_indices);
+
+        let op = Op::new(Rc::new(RefCell::new(Box::new(new_one))));
+
+        inputs[0].called_with(op, &inputs[1..inputs.len()])
     }
 }
-impl OpTrait for MaxPool2d {
+impl OpTrait for AdaptiveMaxPool2d {
     fn get_name(&self) -> &'static str {
-        "MaxPool2d"
+        "AdaptiveMaxPool2d"
     }
     fn get_input_size(&self) -> usize {
         1
     }
     fn get_output_size(&self) -> usize {
-        1
+        if self.return_indices {
+            2
+        } else {
+            1
+        }
     }
     fn apply(&self, input: &[Tensor], output: &[Tensor]) {
-        unimplemented!();
+        check_pool_input("AdaptiveMaxPool2d", &input[0], 2);
+        let (values, indices) = input[0].adaptive_max_pool(&self.output_size.to_vec());
+        output[0].swap(&values);
+        if self.return_indices {
+            output[1].swap(&indices);
+        }
     }
     fn grad(&self, input: &[Tensor], output_grad: &[Tensor], input_grad: &[Tensor]) {
-        unimplemented!();
+        // The values output can be unused when only the indices are consumed.
+        if output_grad[0].numel() == 0 {
+            input_grad[0].swap(&input[0].zeros_like());
+            return;
+        }
+        let ret = input[0].adaptive_max_pool_grad(&self.output_size.to_vec(), &output_grad[0]);
+        input_grad[0].swap(&ret);
     }
     fn get_values(&self) -> Vec<Tensor> {
         Vec::new()
@@ -88,20 +338,212 @@ impl OpTrait for MaxPool2d {
     }
 }
 
-// MaxPool3d
-// MaxUnpool1d
-// MaxUnpool2d
-// MaxUnpool3d
-// AvgPool1d
-// AvgPool2d
-// AvgPool3d
-// FractionalMaxPool2d
-// LPPool1d
-// LPPool2d
-// AdaptiveMaxPool1d
-// AdaptiveMaxPool2d
 // AdaptiveMaxPool3d
 // AdaptiveAvgPool1d
-// AdaptiveAvgPool2d
+
+#[cfg_attr(feature = "use-serde", derive(Serialize, Deserialize))]
+pub struct AdaptiveAvgPool2d {
+    #[cfg_attr(feature = "use-serde", serde(skip))]
+    handle: OpHandle,
+    output_size: (usize, usize),
+}
+impl AdaptiveAvgPool2d {
+    pub fn new(output_size: (usize, usize)) -> AdaptiveAvgPool2d {
+        AdaptiveAvgPool2d {
+            handle: OpHandle::new(),
+            output_size,
+        }
+    }
+    handle_method!();
+}
+impl OpCall for AdaptiveAvgPool2d {
+    fn call(&mut self, inputs: &[&Var]) -> Result<Vec<Var>, AutoDiffError> {
+        let new_one = AdaptiveAvgPool2d::new(self.output_size);
+
+        let op = Op::new(Rc::new(RefCell::new(Box::new(new_one))));
+
+        inputs[0].called_with(op, &inputs[1..inputs.len()])
+    }
+}
+impl OpTrait for AdaptiveAvgPool2d {
+    fn get_name(&self) -> &'static str {
+        "AdaptiveAvgPool2d"
+    }
+    fn get_input_size(&self) -> usize {
+        1
+    }
+    fn get_output_size(&self) -> usize {
+        1
+    }
+    fn apply(&self, input: &[Tensor], output: &[Tensor]) {
+        check_pool_input("AdaptiveAvgPool2d", &input[0], 2);
+        output[0].swap(&input[0].adaptive_avg_pool(&self.output_size.to_vec()));
+    }
+    fn grad(&self, input: &[Tensor], output_grad: &[Tensor], input_grad: &[Tensor]) {
+        input_grad[0]
+            .swap(&input[0].adaptive_avg_pool_grad(&self.output_size.to_vec(), &output_grad[0]));
+    }
+    fn get_values(&self) -> Vec<Tensor> {
+        Vec::new()
+    }
+    fn get_grads(&self) -> Vec<Tensor> {
+        Vec::new()
+    }
+    fn set_values(&self, _v: &[Tensor]) {}
+    #[cfg(feature = "use-serde")]
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
 // AdaptiveAvgPool3d
-//
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::op::_gradient_checker;
+    use rand::prelude::StdRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn max_pool() {
+        let mut rng = StdRng::seed_from_u64(1);
+        assert!(_gradient_checker(
+            &mut MaxPool1d::new(3, Some(2), 1, 1, false, false),
+            &[Tensor::uniform_f64(&mut rng, &[2, 3, 7], -1., 1.)],
+            None,
+            Some(Tensor::from_vec_f64(&[1e-6], &[1])),
+            Some(Tensor::from_vec_f64(&[1e-10], &[1])),
+            Some(&[Tensor::uniform_f64(&mut rng, &[2, 3, 4], -1., 1.)])
+        ));
+        assert!(_gradient_checker(
+            &mut MaxPool1d::new(2, None, 0, 2, true, true),
+            &[Tensor::uniform_f64(&mut rng, &[3, 8], -1., 1.)],
+            None,
+            Some(Tensor::from_vec_f64(&[1e-6], &[1])),
+            Some(Tensor::from_vec_f64(&[1e-10], &[1])),
+            Some(&[
+                Tensor::uniform_f64(&mut rng, &[3, 4], -1., 1.),
+                Tensor::zeros(&[3, 4])
+            ])
+        ));
+        assert!(_gradient_checker(
+            &mut MaxPool2d::new((3, 3), Some((2, 2)), (1, 1), (1, 1), false, false),
+            &[Tensor::uniform_f64(&mut rng, &[2, 2, 5, 6], -1., 1.)],
+            None,
+            Some(Tensor::from_vec_f64(&[1e-6], &[1])),
+            Some(Tensor::from_vec_f64(&[1e-10], &[1])),
+            Some(&[Tensor::uniform_f64(&mut rng, &[2, 2, 3, 3], -1., 1.)])
+        ));
+        assert!(_gradient_checker(
+            &mut MaxPool2d::new((2, 3), None, (0, 1), (2, 1), true, true),
+            &[Tensor::uniform_f64(&mut rng, &[2, 7, 6], -1., 1.)],
+            None,
+            Some(Tensor::from_vec_f64(&[1e-6], &[1])),
+            Some(Tensor::from_vec_f64(&[1e-10], &[1])),
+            Some(&[
+                Tensor::uniform_f64(&mut rng, &[2, 3, 3], -1., 1.),
+                Tensor::zeros(&[2, 3, 3])
+            ])
+        ));
+
+        let input = Tensor::from_vec_f64(&[1., 5., 2., 0., 3., 4., 8., 7.], &[1, 1, 2, 4]);
+        let output = [Tensor::new(), Tensor::new()];
+        MaxPool2d::new((2, 2), None, (0, 0), (1, 1), true, false).apply(&[input], &output);
+        assert_eq!(output[0], Tensor::from_vec_f64(&[5., 8.], &[1, 1, 1, 2]));
+        assert_eq!(output[1], Tensor::from_vec_f64(&[1., 6.], &[1, 1, 1, 2]));
+    }
+
+    #[test]
+    fn avg_pool() {
+        let mut rng = StdRng::seed_from_u64(2);
+        assert!(_gradient_checker(
+            &mut AvgPool1d::new(3, Some(2), 1, false, true),
+            &[Tensor::uniform_f64(&mut rng, &[2, 3, 7], -1., 1.)],
+            None,
+            Some(Tensor::from_vec_f64(&[1e-6], &[1])),
+            Some(Tensor::from_vec_f64(&[1e-10], &[1])),
+            Some(&[Tensor::uniform_f64(&mut rng, &[2, 3, 4], -1., 1.)])
+        ));
+        assert!(_gradient_checker(
+            &mut AvgPool1d::new(2, None, 1, true, false),
+            &[Tensor::uniform_f64(&mut rng, &[3, 8], -1., 1.)],
+            None,
+            Some(Tensor::from_vec_f64(&[1e-6], &[1])),
+            Some(Tensor::from_vec_f64(&[1e-10], &[1])),
+            Some(&[Tensor::uniform_f64(&mut rng, &[3, 5], -1., 1.)])
+        ));
+        assert!(_gradient_checker(
+            &mut AvgPool2d::new((3, 3), Some((2, 2)), (1, 1), false, true),
+            &[Tensor::uniform_f64(&mut rng, &[2, 2, 5, 6], -1., 1.)],
+            None,
+            Some(Tensor::from_vec_f64(&[1e-6], &[1])),
+            Some(Tensor::from_vec_f64(&[1e-10], &[1])),
+            Some(&[Tensor::uniform_f64(&mut rng, &[2, 2, 3, 3], -1., 1.)])
+        ));
+        assert!(_gradient_checker(
+            &mut AvgPool2d::new((2, 3), None, (1, 1), true, false),
+            &[Tensor::uniform_f64(&mut rng, &[2, 7, 6], -1., 1.)],
+            None,
+            Some(Tensor::from_vec_f64(&[1e-6], &[1])),
+            Some(Tensor::from_vec_f64(&[1e-10], &[1])),
+            Some(&[Tensor::uniform_f64(&mut rng, &[2, 4, 3], -1., 1.)])
+        ));
+    }
+
+    #[test]
+    fn adaptive_pool() {
+        let mut rng = StdRng::seed_from_u64(3);
+        assert!(_gradient_checker(
+            &mut AdaptiveAvgPool2d::new((3, 2)),
+            &[Tensor::uniform_f64(&mut rng, &[2, 2, 5, 6], -1., 1.)],
+            None,
+            Some(Tensor::from_vec_f64(&[1e-6], &[1])),
+            Some(Tensor::from_vec_f64(&[1e-10], &[1])),
+            Some(&[Tensor::uniform_f64(&mut rng, &[2, 2, 3, 2], -1., 1.)])
+        ));
+        assert!(_gradient_checker(
+            &mut AdaptiveAvgPool2d::new((4, 4)),
+            &[Tensor::uniform_f64(&mut rng, &[2, 3, 3], -1., 1.)],
+            None,
+            Some(Tensor::from_vec_f64(&[1e-6], &[1])),
+            Some(Tensor::from_vec_f64(&[1e-10], &[1])),
+            Some(&[Tensor::uniform_f64(&mut rng, &[2, 4, 4], -1., 1.)])
+        ));
+        assert!(_gradient_checker(
+            &mut AdaptiveMaxPool2d::new((3, 2), false),
+            &[Tensor::uniform_f64(&mut rng, &[2, 2, 5, 6], -1., 1.)],
+            None,
+            Some(Tensor::from_vec_f64(&[1e-6], &[1])),
+            Some(Tensor::from_vec_f64(&[1e-10], &[1])),
+            Some(&[Tensor::uniform_f64(&mut rng, &[2, 2, 3, 2], -1., 1.)])
+        ));
+        assert!(_gradient_checker(
+            &mut AdaptiveMaxPool2d::new((2, 4), true),
+            &[Tensor::uniform_f64(&mut rng, &[2, 3, 7], -1., 1.)],
+            None,
+            Some(Tensor::from_vec_f64(&[1e-6], &[1])),
+            Some(Tensor::from_vec_f64(&[1e-10], &[1])),
+            Some(&[
+                Tensor::uniform_f64(&mut rng, &[2, 2, 4], -1., 1.),
+                Tensor::zeros(&[2, 2, 4])
+            ])
+        ));
+    }
+
+    #[test]
+    fn max_pool_return_indices() {
+        let x = Var::new_f64(&[1., 5., 2., 0., 3., 4., 8., 7.], &[1, 1, 2, 4]);
+        let mut op = MaxPool2d::new((2, 2), None, (0, 0), (1, 1), true, false);
+        let output = op.call(&[&x]).unwrap();
+        assert_eq!(
+            output[1].val(),
+            Tensor::from_vec_f64(&[1., 6.], &[1, 1, 1, 2])
+        );
+        output[0].sum(None, false).unwrap().bp().unwrap();
+        assert_eq!(
+            x.grad().unwrap().val(),
+            Tensor::from_vec_f64(&[0., 1., 0., 0., 0., 0., 1., 0.], &[1, 1, 2, 4])
+        );
+    }
+}
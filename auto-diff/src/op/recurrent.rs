@@ -0,0 +1,691 @@
+use super::{grad_or_zeros, Op, OpCall, OpHandle, OpTrait};
+use tensor_rs::tensor::Tensor;
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::err::AutoDiffError;
+use crate::var::Var;
+use crate::var_inner::VarInner;
+
+#[cfg(feature = "use-serde")]
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "use-serde")]
+use std::any::Any;
+
+// RNNCell
+// LSTMCell
+// GRUCell
+// LSTM
+
+/// Split the last dim into n equal parts.
+fn chunk(t: &Tensor, n: usize) -> Vec<Tensor> {
+    let size = t.size();
+    let last = size.len() - 1;
+    let step = size[last] / n;
+    (0..n)
+        .map(|i| {
+            let mut range: Vec<_> = size.iter().map(|x| (0, *x)).collect();
+            range[last] = (i * step, (i + 1) * step);
+            t.get_patch(&range, None)
+        })
+        .collect()
+}
+
+/// Derivative of sigmoid given its output.
+fn d_sigmoid(y: &Tensor) -> Tensor {
+    y.mul(&y.ones_like().sub(y))
+}
+
+/// Derivative of tanh given its output.
+fn d_tanh(y: &Tensor) -> Tensor {
+    y.ones_like().sub(&y.mul(y))
+}
+
+// The cells take (x, h, ...) of sizes [batch, input_size], [batch, hidden_size]
+// and return the states at the next step.
+// The weights are [input_size, $gates*hidden_size] and
+// [hidden_size, $gates*hidden_size], with gates side by side on the last dim.
+// $forward turns (x W_ih + b_ih, h W_hh + b_hh, inputs) into the outputs.
+// $backward turns (x W_ih + b_ih, h W_hh + b_hh, inputs, output gradients)
+// into the gradients of the two gate sums and
+// the gradients to the states apart from the ones through W_hh.
+macro_rules! recurrent_cell {
+    ($a:ident, $b:expr, $gates:expr, $states:expr, $forward:expr, $backward:expr) => {
+        #[cfg_attr(feature = "use-serde", derive(Serialize, Deserialize))]
+        pub struct $a {
+            input_size: usize,
+            hidden_size: usize,
+            bias_option: bool,
+            weight_ih: Tensor,
+            weight_hh: Tensor,
+            bias_ih: Tensor,
+            bias_hh: Tensor,
+            weight_ih_grad: Tensor,
+            weight_hh_grad: Tensor,
+            bias_ih_grad: Tensor,
+            bias_hh_grad: Tensor,
+            #[cfg_attr(feature = "use-serde", serde(skip))]
+            handle: OpHandle,
+        }
+        impl $a {
+            pub fn new(input_size: usize, hidden_size: usize, bias: bool) -> $a {
+                $a {
+                    input_size,
+                    hidden_size,
+                    bias_option: bias,
+                    weight_ih: Tensor::zeros(&[input_size, $gates * hidden_size]),
+                    weight_hh: Tensor::zeros(&[hidden_size, $gates * hidden_size]),
+                    bias_ih: Tensor::zeros(&[$gates * hidden_size]),
+                    bias_hh: Tensor::zeros(&[$gates * hidden_size]),
+                    weight_ih_grad: Tensor::new(),
+                    weight_hh_grad: Tensor::new(),
+                    bias_ih_grad: Tensor::new(),
+                    bias_hh_grad: Tensor::new(),
+                    handle: OpHandle::new(),
+                }
+            }
+
+            pub fn weight_ih(&self) -> &Tensor {
+                &self.weight_ih
+            }
+            pub fn set_weight_ih(&self, var: Var) {
+                self.weight_ih.swap(&var.val());
+            }
+            pub fn weight_hh(&self) -> &Tensor {
+                &self.weight_hh
+            }
+            pub fn set_weight_hh(&self, var: Var) {
+                self.weight_hh.swap(&var.val());
+            }
+            pub fn bias_ih(&self) -> &Tensor {
+                &self.bias_ih
+            }
+            pub fn set_bias_ih(&self, var: Var) {
+                self.bias_ih.swap(&var.val());
+            }
+            pub fn bias_hh(&self) -> &Tensor {
+                &self.bias_hh
+            }
+            pub fn set_bias_hh(&self, var: Var) {
+                self.bias_hh.swap(&var.val());
+            }
+
+            fn check(&self, input: &[Tensor]) {
+                if input.len() != 1 + $states {
+                    panic!("{} expects {} inputs, get {}", $b, 1 + $states, input.len());
+                }
+                let batch = input[0].size()[0];
+                if input[0].size() != [batch, self.input_size] {
+                    panic!(
+                        "{} expects input of size {:?}, get {:?}",
+                        $b,
+                        [batch, self.input_size],
+                        input[0].size()
+                    );
+                }
+                for state in &input[1..] {
+                    if state.size() != [batch, self.hidden_size] {
+                        panic!(
+                            "{} expects state of size {:?}, get {:?}",
+                            $b,
+                            [batch, self.hidden_size],
+                            state.size()
+                        );
+                    }
+                }
+            }
+
+            /// x W_ih + b_ih and h W_hh + b_hh.
+            fn gate_sums(&self, input: &[Tensor]) -> (Tensor, Tensor) {
+                let mut gi = input[0].matmul(&self.weight_ih);
+                let mut gh = input[1].matmul(&self.weight_hh);
+                if self.bias_option {
+                    gi = gi.add(&self.bias_ih);
+                    gh = gh.add(&self.bias_hh);
+                }
+                (gi, gh)
+            }
+
+            handle_method!();
+        }
+        impl OpCall for $a {
+            fn call(&mut self, inputs: &[&Var]) -> Result<Vec<Var>, AutoDiffError> {
+                let new_one = $a {
+                    input_size: self.input_size,
+                    hidden_size: self.hidden_size,
+                    bias_option: self.bias_option,
+                    weight_ih: self.weight_ih.ref_copy(),
+                    weight_hh: self.weight_hh.ref_copy(),
+                    bias_ih: self.bias_ih.ref_copy(),
+                    bias_hh: self.bias_hh.ref_copy(),
+                    weight_ih_grad: self.weight_ih_grad.ref_copy(),
+                    weight_hh_grad: self.weight_hh_grad.ref_copy(),
+                    bias_ih_grad: self.bias_ih_grad.ref_copy(),
+                    bias_hh_grad: self.bias_hh_grad.ref_copy(),
+                    handle: OpHandle::new(),
+                };
+
+                let op = Op::new(Rc::new(RefCell::new(Box::new(new_one))));
+
+                inputs[0].called_with(op, &inputs[1..inputs.len()])
+            }
+        }
+        impl OpTrait for $a {
+            fn get_name(&self) -> &'static str {
+                ($b)
+            }
+            fn get_input_size(&self) -> usize {
+                1 + $states
+            }
+            fn get_output_size(&self) -> usize {
+                $states
+            }
+            fn apply(&self, input: &[Tensor], output: &[Tensor]) {
+                self.check(input);
+                let (gi, gh) = self.gate_sums(input);
+                let result: Vec<Tensor> = ($forward)(&gi, &gh, input, self.hidden_size);
+                for (o, r) in output.iter().zip(result.iter()) {
+                    o.swap(r);
+                }
+            }
+            fn grad(&self, input: &[Tensor], output_grad: &[Tensor], input_grad: &[Tensor]) {
+                self.check(input);
+                let (gi, gh) = self.gate_sums(input);
+                let output_grad: Vec<Tensor> = output_grad
+                    .iter()
+                    .zip(input[1..].iter())
+                    .map(|(g, like)| grad_or_zeros(g, like))
+                    .collect();
+                let (d_gi, d_gh, state_grad): (Tensor, Tensor, Vec<Tensor>) =
+                    ($backward)(&gi, &gh, input, &output_grad, self.hidden_size);
+
+                self.weight_ih_grad
+                    .swap(&input[0].permute(&[1, 0]).matmul(&d_gi));
+                self.weight_hh_grad
+                    .swap(&input[1].permute(&[1, 0]).matmul(&d_gh));
+                if self.bias_option {
+                    self.bias_ih_grad.swap(&d_gi.sum(Some(&[0]), false));
+                    self.bias_hh_grad.swap(&d_gh.sum(Some(&[0]), false));
+                }
+
+                input_grad[0].swap(&d_gi.matmul(&self.weight_ih.permute(&[1, 0])));
+                input_grad[1].swap(
+                    &d_gh
+                        .matmul(&self.weight_hh.permute(&[1, 0]))
+                        .add(&state_grad[0]),
+                );
+                for (ig, g) in input_grad[2..].iter().zip(state_grad[1..].iter()) {
+                    ig.swap(g);
+                }
+            }
+            fn get_values(&self) -> Vec<Tensor> {
+                let mut ret = vec![self.weight_ih.ref_copy(), self.weight_hh.ref_copy()];
+                if self.bias_option {
+                    ret.push(self.bias_ih.ref_copy());
+                    ret.push(self.bias_hh.ref_copy());
+                }
+                ret
+            }
+            fn set_values(&self, v: &[Tensor]) {
+                self.weight_ih.swap(&v[0].clone());
+                self.weight_hh.swap(&v[1].clone());
+                if self.bias_option {
+                    self.bias_ih.swap(&v[2].clone());
+                    self.bias_hh.swap(&v[3].clone());
+                }
+            }
+            fn get_grads(&self) -> Vec<Tensor> {
+                let mut ret = vec![
+                    self.weight_ih_grad.ref_copy(),
+                    self.weight_hh_grad.ref_copy(),
+                ];
+                if self.bias_option {
+                    ret.push(self.bias_ih_grad.ref_copy());
+                    ret.push(self.bias_hh_grad.ref_copy());
+                }
+                ret
+            }
+            fn set_grads(&self, v: &[Tensor]) {
+                self.weight_ih_grad.swap(&v[0].clone());
+                self.weight_hh_grad.swap(&v[1].clone());
+                if self.bias_option {
+                    self.bias_ih_grad.swap(&v[2].clone());
+                    self.bias_hh_grad.swap(&v[3].clone());
+                }
+            }
+            #[cfg(feature = "use-serde")]
+            fn as_any(&self) -> &dyn Any {
+                self
+            }
+        }
+    };
+}
+
+// h' = tanh(x W_ih + b_ih + h W_hh + b_hh)
+recurrent_cell!(
+    RNNCell,
+    "RNNCell",
+    1,
+    1,
+    (|gi: &Tensor, gh: &Tensor, _input: &[Tensor], _hidden: usize| vec![gi.add(gh).tanh()]),
+    (|gi: &Tensor, gh: &Tensor, input: &[Tensor], output_grad: &[Tensor], _hidden: usize| {
+        let h = gi.add(gh).tanh();
+        let d = output_grad[0].mul(&d_tanh(&h));
+        (d.clone(), d, vec![input[1].zeros_like()])
+    })
+);
+
+// Gates i, f, g, o, with inputs (x, h, c) and outputs (h', c').
+// c' = f*c + i*g, h' = o*tanh(c')
+fn lstm_gates(gi: &Tensor, gh: &Tensor) -> Vec<Tensor> {
+    let gates = chunk(&gi.add(gh), 4);
+    vec![
+        gates[0].sigmoid(),
+        gates[1].sigmoid(),
+        gates[2].tanh(),
+        gates[3].sigmoid(),
+    ]
+}
+
+recurrent_cell!(
+    LSTMCell,
+    "LSTMCell",
+    4,
+    2,
+    (|gi: &Tensor, gh: &Tensor, input: &[Tensor], _hidden: usize| {
+        let gates = lstm_gates(gi, gh);
+        let c = gates[1].mul(&input[2]).add(&gates[0].mul(&gates[2]));
+        vec![gates[3].mul(&c.tanh()), c]
+    }),
+    (|gi: &Tensor, gh: &Tensor, input: &[Tensor], output_grad: &[Tensor], _hidden: usize| {
+        let gates = lstm_gates(gi, gh);
+        let (i, f, g, o) = (&gates[0], &gates[1], &gates[2], &gates[3]);
+        let tc = f.mul(&input[2]).add(&i.mul(g)).tanh();
+        let dh = &output_grad[0];
+        let dc = output_grad[1].add(&dh.mul(o).mul(&d_tanh(&tc)));
+        let d = dc.mul(g).mul(&d_sigmoid(i)).cat(
+            &[
+                dc.mul(&input[2]).mul(&d_sigmoid(f)),
+                dc.mul(i).mul(&d_tanh(g)),
+                dh.mul(&tc).mul(&d_sigmoid(o)),
+            ],
+            1,
+        );
+        (d.clone(), d, vec![input[1].zeros_like(), dc.mul(f)])
+    })
+);
+
+// Gates r, z, n.
+// n = tanh(x W_in + b_in + r*(h W_hn + b_hn)), h' = (1-z)*n + z*h
+recurrent_cell!(
+    GRUCell,
+    "GRUCell",
+    3,
+    1,
+    (|gi: &Tensor, gh: &Tensor, input: &[Tensor], _hidden: usize| {
+        let (si, sh) = (chunk(gi, 3), chunk(gh, 3));
+        let r = si[0].add(&sh[0]).sigmoid();
+        let z = si[1].add(&sh[1]).sigmoid();
+        let n = si[2].add(&r.mul(&sh[2])).tanh();
+        vec![n.add(&z.mul(&input[1].sub(&n)))]
+    }),
+    (|gi: &Tensor, gh: &Tensor, input: &[Tensor], output_grad: &[Tensor], _hidden: usize| {
+        let (si, sh) = (chunk(gi, 3), chunk(gh, 3));
+        let r = si[0].add(&sh[0]).sigmoid();
+        let z = si[1].add(&sh[1]).sigmoid();
+        let n = si[2].add(&r.mul(&sh[2])).tanh();
+        let dh = &output_grad[0];
+        let dn = dh.mul(&z.ones_like().sub(&z)).mul(&d_tanh(&n));
+        let dr = dn.mul(&sh[2]).mul(&d_sigmoid(&r));
+        let dz = dh.mul(&input[1].sub(&n)).mul(&d_sigmoid(&z));
+        let d_gi = dr.cat(&[dz.clone(), dn.clone()], 1);
+        let d_gh = dr.cat(&[dz, dn.mul(&r)], 1);
+        (d_gi, d_gh, vec![dh.mul(&z)])
+    })
+);
+
+/// Stacked LSTMCell run over a [T, batch, input_size] sequence by ticks.
+/// The output of a layer at a tick is the input of the next layer.
+pub struct LSTM {
+    cells: Vec<LSTMCell>,
+}
+impl LSTM {
+    pub fn new(input_size: usize, hidden_size: usize, num_layers: usize, bias: bool) -> LSTM {
+        if num_layers == 0 {
+            panic!("LSTM expects at least one layer.");
+        }
+        LSTM {
+            cells: (0..num_layers)
+                .map(|i| {
+                    let size = if i == 0 { input_size } else { hidden_size };
+                    LSTMCell::new(size, hidden_size, bias)
+                })
+                .collect(),
+        }
+    }
+
+    /// The cells from the bottom layer, to get and set weights.
+    pub fn cells(&self) -> &[LSTMCell] {
+        &self.cells
+    }
+
+    /// Tick the input and build the net for one tick,
+    /// the states start at zero and loop back with assign_to.
+    /// Return the top layer h, and (h, c) of every layer, at a tick.
+    /// The net has run over all ticks,
+    /// use over_tick to get the [T, batch, hidden_size] output,
+    /// and at_tick(-1) to get the last states.
+    /// A loss built on the returned vars counts the last tick on bp,
+    /// build it on over_tick to count every tick.
+    pub fn forward(&mut self, input: &Var) -> Result<(Var, Vec<(Var, Var)>), AutoDiffError> {
+        let size = input.val().size();
+        if size.len() != 3 {
+            return Err(AutoDiffError::new(&format!(
+                "LSTM expects [T, batch, input_size] input, get {:?}",
+                size
+            )));
+        }
+        input.tick()?;
+
+        let zero = input.val().zeros_like().sum(None, false);
+        let mut x = input.ref_copy();
+        let mut states = Vec::new();
+        for cell in &mut self.cells {
+            let init: Vec<Var> = (0..2)
+                .map(|_| {
+                    Var::set_inner(VarInner::new_tensor(Tensor::fill(
+                        &[size[1], cell.hidden_size],
+                        &zero,
+                    )))
+                })
+                .collect();
+            let mut output = cell.call(&[&x, &init[0], &init[1]])?;
+            let c = output.pop().expect("");
+            let h = output.pop().expect("");
+            h.assign_to(&init[0])?;
+            c.assign_to(&init[1])?;
+            x = h.ref_copy();
+            states.push((h, c));
+        }
+        x.rerun()?;
+        Ok((x, states))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::op::_gradient_checker;
+    use rand::prelude::StdRng;
+    use rand::SeedableRng;
+
+    fn outputs(op: &dyn OpTrait, input: &[Tensor]) -> Vec<Tensor> {
+        let output: Vec<Tensor> = (0..op.get_output_size()).map(|_| Tensor::new()).collect();
+        op.apply(input, &output);
+        output
+    }
+
+    fn set_random(rng: &mut StdRng, op: &dyn OpTrait) {
+        let values: Vec<Tensor> = op
+            .get_values()
+            .iter()
+            .map(|v| Tensor::uniform_f64(rng, &v.size(), -1., 1.))
+            .collect();
+        op.set_values(&values);
+    }
+
+    #[test]
+    fn rnn_cell() {
+        let mut rng = StdRng::seed_from_u64(1);
+        let mut op = RNNCell::new(3, 2, true);
+        op.set_weight_ih(Var::new(&[1., 0., 0., 1., 1., 1.], &[3, 2]));
+        let output = outputs(
+            &op,
+            &[
+                Tensor::from_vec_f64(&[1., 2., 0.], &[1, 3]),
+                Tensor::zeros(&[1, 2]),
+            ],
+        );
+        assert_eq!(
+            output[0],
+            Tensor::from_vec_f64(&[1f64.tanh(), 2f64.tanh()], &[1, 2])
+        );
+
+        set_random(&mut rng, &op);
+        let input = [
+            Tensor::uniform_f64(&mut rng, &[4, 3], -1., 1.),
+            Tensor::uniform_f64(&mut rng, &[4, 2], -1., 1.),
+        ];
+        assert!(_gradient_checker(
+            &mut op,
+            &input,
+            None,
+            Some(Tensor::from_vec_f64(&[1e-6], &[1])),
+            Some(Tensor::from_vec_f64(&[1e-10], &[1])),
+            Some(&[Tensor::uniform_f64(&mut rng, &[4, 2], -1., 1.)])
+        ));
+    }
+
+    #[test]
+    fn lstm_cell() {
+        let mut rng = StdRng::seed_from_u64(2);
+        let mut op = LSTMCell::new(3, 2, true);
+        // all gates are 0.5 or 0, with the zero weights.
+        let output = outputs(
+            &op,
+            &[
+                Tensor::uniform_f64(&mut rng, &[1, 3], -1., 1.),
+                Tensor::zeros(&[1, 2]),
+                Tensor::ones(&[1, 2]),
+            ],
+        );
+        assert_eq!(output[1], Tensor::fill_f64(&[1, 2], 0.5));
+        assert_eq!(output[0], Tensor::fill_f64(&[1, 2], 0.5 * 0.5f64.tanh()));
+
+        set_random(&mut rng, &op);
+        let input = [
+            Tensor::uniform_f64(&mut rng, &[4, 3], -1., 1.),
+            Tensor::uniform_f64(&mut rng, &[4, 2], -1., 1.),
+            Tensor::uniform_f64(&mut rng, &[4, 2], -1., 1.),
+        ];
+        assert!(_gradient_checker(
+            &mut op,
+            &input,
+            None,
+            Some(Tensor::from_vec_f64(&[1e-6], &[1])),
+            Some(Tensor::from_vec_f64(&[1e-10], &[1])),
+            Some(&[
+                Tensor::uniform_f64(&mut rng, &[4, 2], -1., 1.),
+                Tensor::uniform_f64(&mut rng, &[4, 2], -1., 1.)
+            ])
+        ));
+
+        let mut op = LSTMCell::new(3, 2, false);
+        set_random(&mut rng, &op);
+        assert_eq!(op.get_values().len(), 2);
+        assert!(_gradient_checker(
+            &mut op,
+            &input,
+            None,
+            Some(Tensor::from_vec_f64(&[1e-6], &[1])),
+            Some(Tensor::from_vec_f64(&[1e-10], &[1])),
+            Some(&[
+                Tensor::uniform_f64(&mut rng, &[4, 2], -1., 1.),
+                Tensor::uniform_f64(&mut rng, &[4, 2], -1., 1.)
+            ])
+        ));
+    }
+
+    #[test]
+    fn gru_cell() {
+        let mut rng = StdRng::seed_from_u64(3);
+        let mut op = GRUCell::new(3, 2, true);
+        // z is 0.5 and n is 0, with the zero weights.
+        let output = outputs(
+            &op,
+            &[
+                Tensor::uniform_f64(&mut rng, &[1, 3], -1., 1.),
+                Tensor::ones(&[1, 2]),
+            ],
+        );
+        assert_eq!(output[0], Tensor::fill_f64(&[1, 2], 0.5));
+
+        set_random(&mut rng, &op);
+        let input = [
+            Tensor::uniform_f64(&mut rng, &[4, 3], -1., 1.),
+            Tensor::uniform_f64(&mut rng, &[4, 2], -1., 1.),
+        ];
+        assert!(_gradient_checker(
+            &mut op,
+            &input,
+            None,
+            Some(Tensor::from_vec_f64(&[1e-6], &[1])),
+            Some(Tensor::from_vec_f64(&[1e-10], &[1])),
+            Some(&[Tensor::uniform_f64(&mut rng, &[4, 2], -1., 1.)])
+        ));
+    }
+
+    #[test]
+    fn rnn_cell_tick() {
+        // h' = x + h, unrolled over 3 ticks.
+        let mut cell = RNNCell::new(1, 1, false);
+        cell.set_weight_ih(Var::new(&[1.], &[1, 1]));
+        cell.set_weight_hh(Var::new(&[1.], &[1, 1]));
+        let x = Var::new(&[0.1, 0.2, 0.3], &[3, 1, 1]);
+        x.tick().unwrap();
+        let h0 = Var::new(&[0.], &[1, 1]);
+        let h = cell.call(&[&x, &h0]).unwrap().pop().unwrap();
+        h.assign_to(&h0).unwrap();
+        let loss = h.over_tick().unwrap().sum(None, false).unwrap();
+        loss.rerun().unwrap();
+
+        let mut expected = Vec::new();
+        let mut state = 0f64;
+        for v in &[0.1, 0.2, 0.3] {
+            state = (v + state).tanh();
+            expected.push(state);
+        }
+        let values = h.over_tick().unwrap().val();
+        assert_eq!(values.size(), [3, 1, 1]);
+        for (t, e) in expected.iter().enumerate() {
+            assert!((values.get_f64(&[t, 0, 0]) - e).abs() < 1e-10);
+        }
+        assert_eq!(h.at_tick(-1).unwrap().val().get_f64(&[0, 0]), expected[2]);
+        assert!(h.at_tick(3).is_err());
+        // h0 keeps the value at the first tick.
+        assert_eq!(h0.val().get_f64(&[0, 0]), 0.);
+
+        // loss is h1 + h2 + h3, check the gradient numerically.
+        loss.bp().unwrap();
+        let total = |w_ih: f64, w_hh: f64, x: &[f64]| -> f64 {
+            let mut state = 0f64;
+            let mut sum = 0.;
+            for v in x {
+                state = (w_ih * v + w_hh * state).tanh();
+                sum += state;
+            }
+            sum
+        };
+        let eps = 1e-6;
+        let seq = [0.1, 0.2, 0.3];
+        let grads = cell.get_grads();
+        let numeric = (total(1. + eps, 1., &seq) - total(1. - eps, 1., &seq)) / (2. * eps);
+        assert!((grads[0].get_f64(&[0, 0]) - numeric).abs() < 1e-6);
+        let numeric = (total(1., 1. + eps, &seq) - total(1., 1. - eps, &seq)) / (2. * eps);
+        assert!((grads[1].get_f64(&[0, 0]) - numeric).abs() < 1e-6);
+        let x_grad = x.grad().unwrap().val();
+        assert_eq!(x_grad.size(), [3, 1, 1]);
+        for t in 0..3 {
+            let mut plus = seq;
+            plus[t] += eps;
+            let mut minus = seq;
+            minus[t] -= eps;
+            let numeric = (total(1., 1., &plus) - total(1., 1., &minus)) / (2. * eps);
+            assert!((x_grad.get_f64(&[t, 0, 0]) - numeric).abs() < 1e-6);
+        }
+
+        // a loss on h is h3, the value at the last tick.
+        let last = h.sum(None, false).unwrap();
+        last.rerun().unwrap();
+        assert!((last.val().get_scale_f64() - expected[2]).abs() < 1e-10);
+        last.bp().unwrap();
+        let h3 = |w_ih: f64| (w_ih * 0.3 + (w_ih * 0.2 + (w_ih * 0.1f64).tanh()).tanh()).tanh();
+        let numeric = (h3(1. + eps) - h3(1. - eps)) / (2. * eps);
+        assert!((cell.get_grads()[0].get_f64(&[0, 0]) - numeric).abs() < 1e-6);
+
+        // at_tick passes the gradient back to its tick only.
+        let first = h.at_tick(0).unwrap().sum(None, false).unwrap();
+        first.bp().unwrap();
+        let x_grad = x.grad().unwrap().val();
+        assert!((x_grad.get_f64(&[0, 0, 0]) - (1. - expected[0].powi(2))).abs() < 1e-10);
+        assert_eq!(x_grad.get_f64(&[1, 0, 0]), 0.);
+        assert_eq!(x_grad.get_f64(&[2, 0, 0]), 0.);
+
+        // truncated to the last tick.
+        loss.dump_net().borrow_mut().set_max_bptt_tick(1);
+        loss.bp().unwrap();
+        let x_grad = x.grad().unwrap().val();
+        assert_eq!(x_grad.get_f64(&[0, 0, 0]), 0.);
+        assert_eq!(x_grad.get_f64(&[1, 0, 0]), 0.);
+        assert!(x_grad.get_f64(&[2, 0, 0]) > 0.);
+    }
+
+    #[test]
+    fn lstm() {
+        let mut rng = StdRng::seed_from_u64(4);
+        let mut lstm = LSTM::new(3, 2, 2, true);
+        for cell in lstm.cells() {
+            set_random(&mut rng, cell);
+        }
+        let seq = Tensor::uniform_f64(&mut rng, &[5, 4, 3], -1., 1.);
+        let input = Var::new(&seq.get_raw_f64(), &[5, 4, 3]);
+        let (output, states) = lstm.forward(&input).unwrap();
+        assert_eq!(states.len(), 2);
+        let over = output.over_tick().unwrap().val();
+        assert_eq!(over.size(), [5, 4, 2]);
+
+        // the same with the cells by hand.
+        let mut h = [Tensor::zeros(&[4, 2]), Tensor::zeros(&[4, 2])];
+        let mut c = [Tensor::zeros(&[4, 2]), Tensor::zeros(&[4, 2])];
+        for t in 0..5 {
+            let mut x = seq
+                .get_patch(&[(t, t + 1), (0, 4), (0, 3)], None)
+                .reshape(&[4, 3]);
+            for (l, cell) in lstm.cells().iter().enumerate() {
+                let result = outputs(cell, &[x.clone(), h[l].clone(), c[l].clone()]);
+                h[l] = result[0].clone();
+                c[l] = result[1].clone();
+                x = result[0].clone();
+            }
+            let at = over
+                .get_patch(&[(t, t + 1), (0, 4), (0, 2)], None)
+                .reshape(&[4, 2]);
+            assert!(at.sub(&x).abs().max(None, false).get_scale_f64() < 1e-10);
+        }
+        assert!(
+            states[0]
+                .1
+                .at_tick(-1)
+                .unwrap()
+                .val()
+                .sub(&c[0])
+                .abs()
+                .max(None, false)
+                .get_scale_f64()
+                < 1e-10
+        );
+
+        // train the sum of the top h over ticks down.
+        let loss = output.over_tick().unwrap().sum(None, false).unwrap();
+        let before = loss.val().get_scale_f64();
+        let mut opt = crate::optim::SGD::new(0.1);
+        for _ in 0..5 {
+            loss.bp().unwrap();
+            loss.step(&mut opt).unwrap();
+            loss.rerun().unwrap();
+        }
+        let after = loss.val().get_scale_f64();
+        assert!(after < before);
+    }
+}
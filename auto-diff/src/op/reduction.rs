@@ -73,7 +73,7 @@ macro_rules! reduce_macro {
                 }
             }
             fn grad(&self, input: &[Tensor], output_grad: &[Tensor], input_grad: &[Tensor]) {
-                $d(input, output_grad, input_grad)
+                $d(input, output_grad, input_grad, self.dim.as_deref())
             }
             fn get_values(&self) -> Vec<Tensor> {
                 Vec::new()
@@ -90,12 +90,51 @@ macro_rules! reduce_macro {
     };
 }
 
+/// Broadcast a reduced tensor back to the shape of the reduction input.
+/// Works for both keepdim and non-keepdim results,
+/// as the reduced dims are recovered from dim.
+fn expand_reduced(input: &Tensor, reduced: &Tensor, dim: Option<&[usize]>) -> Tensor {
+    let in_size = input.size();
+    let mut kept_size = Vec::with_capacity(in_size.len());
+    let mut repeat_size = Vec::with_capacity(in_size.len());
+    for (i, s) in in_size.iter().enumerate() {
+        if dim.is_none_or(|d| d.contains(&i)) {
+            kept_size.push(1);
+            repeat_size.push(*s);
+        } else {
+            kept_size.push(*s);
+            repeat_size.push(1);
+        }
+    }
+    reduced.reshape(&kept_size).repeat(&repeat_size)
+}
+
+/// Number of input elements folded into each output element.
+fn reduced_count(input: &Tensor, dim: Option<&[usize]>) -> usize {
+    input
+        .size()
+        .iter()
+        .enumerate()
+        .filter(|(i, _)| dim.is_none_or(|d| d.contains(i)))
+        .map(|(_, s)| *s)
+        .product()
+}
+
+/// Gradient for max/min, routed to the element(s) equal to the extreme value.
+/// Ties share the gradient evenly.
+fn extreme_grad(input: &Tensor, output: &Tensor, output_grad: &Tensor, dim: Option<&[usize]>) -> Tensor {
+    let mask = input.eq_t(&expand_reduced(input, output, dim));
+    let count = expand_reduced(input, &mask.sum(dim, false), dim);
+    mask.div(&count)
+        .mul(&expand_reduced(input, output_grad, dim))
+}
+
 reduce_macro!(
     Argmax,
     "Argmax",
     argmax,
-    (|input: &[Tensor], output_grad: &[Tensor], input_grad: &[Tensor]| {
-        unimplemented!();
+    (|input: &[Tensor], _output_grad: &[Tensor], input_grad: &[Tensor], _dim: Option<&[usize]>| {
+        input_grad[0].swap(&input[0].zeros_like());
     })
 );
 
@@ -103,8 +142,8 @@ reduce_macro!(
     Argmin,
     "Argmin",
     argmin,
-    (|input: &[Tensor], output_grad: &[Tensor], input_grad: &[Tensor]| {
-        unimplemented!();
+    (|input: &[Tensor], _output_grad: &[Tensor], input_grad: &[Tensor], _dim: Option<&[usize]>| {
+        input_grad[0].swap(&input[0].zeros_like());
     })
 );
 
@@ -112,8 +151,10 @@ reduce_macro!(
     Logsumexp,
     "Logsumexp",
     logsumexp,
-    (|input: &[Tensor], output_grad: &[Tensor], input_grad: &[Tensor]| {
-        unimplemented!();
+    (|input: &[Tensor], output_grad: &[Tensor], input_grad: &[Tensor], dim: Option<&[usize]>| {
+        let lse = input[0].logsumexp(dim, true);
+        let softmax = input[0].sub(&expand_reduced(&input[0], &lse, dim)).exp();
+        input_grad[0].swap(&softmax.mul(&expand_reduced(&input[0], &output_grad[0], dim)));
     })
 );
 
@@ -121,8 +162,11 @@ reduce_macro!(
     Mean,
     "Mean",
     mean,
-    (|input: &[Tensor], output_grad: &[Tensor], input_grad: &[Tensor]| {
-        unimplemented!();
+    (|input: &[Tensor], output_grad: &[Tensor], input_grad: &[Tensor], dim: Option<&[usize]>| {
+        let n = reduced_count(&input[0], dim);
+        let ret = expand_reduced(&input[0], &output_grad[0], dim)
+            .div(&Tensor::int_n(&[1], n as isize));
+        input_grad[0].swap(&ret);
     })
 );
 
@@ -130,8 +174,13 @@ reduce_macro!(
     Prod,
     "Prod",
     prod,
-    (|input: &[Tensor], output_grad: &[Tensor], input_grad: &[Tensor]| {
-        unimplemented!();
+    (|input: &[Tensor], output_grad: &[Tensor], input_grad: &[Tensor], dim: Option<&[usize]>| {
+        // d(prod)/dx_i = prod/x_i, undefined where x_i is zero.
+        let prod = input[0].prod(dim, true);
+        let ret = expand_reduced(&input[0], &prod, dim)
+            .div(&input[0])
+            .mul(&expand_reduced(&input[0], &output_grad[0], dim));
+        input_grad[0].swap(&ret);
     })
 );
 
@@ -139,8 +188,16 @@ reduce_macro!(
     Std,
     "Std",
     std,
-    (|input: &[Tensor], output_grad: &[Tensor], input_grad: &[Tensor]| {
-        unimplemented!();
+    (|input: &[Tensor], output_grad: &[Tensor], input_grad: &[Tensor], dim: Option<&[usize]>| {
+        // d(std)/dx_i = (x_i - mean)/(n*std).
+        let n = reduced_count(&input[0], dim);
+        let centered = input[0].sub(&expand_reduced(&input[0], &input[0].mean(dim, true), dim));
+        let std = expand_reduced(&input[0], &input[0].std(dim, true), dim);
+        let ret = centered
+            .div(&std)
+            .div(&Tensor::int_n(&[1], n as isize))
+            .mul(&expand_reduced(&input[0], &output_grad[0], dim));
+        input_grad[0].swap(&ret);
     })
 );
 
@@ -148,8 +205,8 @@ reduce_macro!(
     Sum,
     "Sum",
     sum,
-    (|input: &[Tensor], output_grad: &[Tensor], input_grad: &[Tensor]| {
-        unimplemented!();
+    (|input: &[Tensor], output_grad: &[Tensor], input_grad: &[Tensor], dim: Option<&[usize]>| {
+        input_grad[0].swap(&expand_reduced(&input[0], &output_grad[0], dim));
     })
 );
 
@@ -157,8 +214,15 @@ reduce_macro!(
     Variance,
     "Var",
     var,
-    (|input: &[Tensor], output_grad: &[Tensor], input_grad: &[Tensor]| {
-        unimplemented!();
+    (|input: &[Tensor], output_grad: &[Tensor], input_grad: &[Tensor], dim: Option<&[usize]>| {
+        // d(var)/dx_i = 2(x_i - mean)/n.
+        let n = reduced_count(&input[0], dim);
+        let centered = input[0].sub(&expand_reduced(&input[0], &input[0].mean(dim, true), dim));
+        let ret = centered
+            .mul(&Tensor::int_n(&[1], 2))
+            .div(&Tensor::int_n(&[1], n as isize))
+            .mul(&expand_reduced(&input[0], &output_grad[0], dim));
+        input_grad[0].swap(&ret);
     })
 );
 
@@ -166,8 +230,9 @@ reduce_macro!(
     Max,
     "Max",
     max,
-    (|input: &[Tensor], output_grad: &[Tensor], input_grad: &[Tensor]| {
-        unimplemented!();
+    (|input: &[Tensor], output_grad: &[Tensor], input_grad: &[Tensor], dim: Option<&[usize]>| {
+        let max = input[0].max(dim, true);
+        input_grad[0].swap(&extreme_grad(&input[0], &max, &output_grad[0], dim));
     })
 );
 
@@ -175,7 +240,60 @@ reduce_macro!(
     Min,
     "Min",
     min,
-    (|input: &[Tensor], output_grad: &[Tensor], input_grad: &[Tensor]| {
-        unimplemented!();
+    (|input: &[Tensor], output_grad: &[Tensor], input_grad: &[Tensor], dim: Option<&[usize]>| {
+        let min = input[0].min(dim, true);
+        input_grad[0].swap(&extreme_grad(&input[0], &min, &output_grad[0], dim));
     })
 );
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::op::_gradient_checker;
+
+    macro_rules! test_reduction_grad {
+        ($name:ident, $op:ident) => {
+            #[test]
+            fn $name() {
+                // Each case reduces to a single element, as the checker expects.
+                let cases = [
+                    (vec![2, 3], None, false),
+                    (vec![2, 3], None, true),
+                    (vec![2, 3], Some(vec![0, 1]), false),
+                    (vec![1, 6], Some(vec![1]), false),
+                    (vec![6, 1], Some(vec![0]), true),
+                    (vec![1, 2, 3], Some(vec![1, 2]), true),
+                ];
+                for (size, dim, keepdim) in cases.iter() {
+                    let input = Tensor::from_vec_f64(&[0.3, -1.2, 2.1, 0.7, 1.5, -0.4], size);
+                    let mut op = $op::new(dim.as_deref(), *keepdim);
+                    let good_grad = _gradient_checker(&mut op, &[input], None, None, None, None);
+                    assert_eq!(good_grad, true);
+                }
+            }
+        };
+    }
+
+    test_reduction_grad!(sum, Sum);
+    test_reduction_grad!(mean, Mean);
+    test_reduction_grad!(prod, Prod);
+    test_reduction_grad!(std, Std);
+    test_reduction_grad!(variance, Variance);
+    test_reduction_grad!(max, Max);
+    test_reduction_grad!(min, Min);
+    test_reduction_grad!(logsumexp, Logsumexp);
+
+    #[test]
+    fn max_min_route_to_arg() {
+        let input = Tensor::from_vec_f64(&[0.3, -1.2, 2.1, 0.7, 1.5, -0.4], &[2, 3]);
+        let output_grad = Tensor::from_vec_f64(&[1., 2.], &[2]);
+
+        let input_grad = Tensor::new();
+        Max::new(Some(&[1]), false).grad(&[input.ref_copy()], &[output_grad.ref_copy()], &[input_grad.ref_copy()]);
+        assert_eq!(input_grad, Tensor::from_vec_f64(&[0., 0., 1., 0., 2., 0.], &[2, 3]));
+
+        let input_grad = Tensor::new();
+        Min::new(Some(&[1]), true).grad(&[input], &[output_grad.reshape(&[2, 1])], &[input_grad.ref_copy()]);
+        assert_eq!(input_grad, Tensor::from_vec_f64(&[0., 1., 0., 0., 0., 2.], &[2, 3]));
+    }
+}
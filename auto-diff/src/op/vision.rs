@@ -64,7 +64,9 @@ impl OpTrait for GetPatch {
         output[0].swap(&input[0].get_patch(&self.range, step));
     }
     fn grad(&self, input: &[Tensor], output_grad: &[Tensor], input_grad: &[Tensor]) {
-        unimplemented!();
+        // scatter back to where the patch was taken, zero elsewhere.
+        let step = self.step.as_ref().map(|v| &v[..]);
+        input_grad[0].swap(&input[0].zeros_like().set_patch(&output_grad[0], &self.range, step));
     }
     fn get_values(&self) -> Vec<Tensor> {
         Vec::new()
@@ -131,7 +133,11 @@ impl OpTrait for SetPatch {
         output[0].swap(&input[0].set_patch(&input[1], &self.range, step));
     }
     fn grad(&self, input: &[Tensor], output_grad: &[Tensor], input_grad: &[Tensor]) {
-        unimplemented!();
+        // The overwritten region gets no gradient in the base tensor,
+        // it goes to the pasted value instead.
+        let step = self.step.as_ref().map(|v| &v[..]);
+        input_grad[0].swap(&output_grad[0].set_patch(&input[1].zeros_like(), &self.range, step));
+        input_grad[1].swap(&output_grad[0].get_patch(&self.range, step));
     }
     fn get_values(&self) -> Vec<Tensor> {
         Vec::new()
@@ -145,3 +151,44 @@ impl OpTrait for SetPatch {
         self
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn get_patch_grad() {
+        let a = Tensor::from_vec_f64(&(0..12).map(|x| x as f64).collect::<Vec<_>>(), &[3, 4]);
+        let op = GetPatch::new(&[(0, 3), (1, 4)], Some(&[2, 2]));
+        let output = Tensor::new();
+        op.apply(&[a.ref_copy()], &[output.ref_copy()]);
+        assert_eq!(output, Tensor::from_vec_f64(&[1., 3., 9., 11.], &[2, 2]));
+
+        let input_grad = Tensor::new();
+        let output_grad = Tensor::from_vec_f64(&[1., 2., 3., 4.], &[2, 2]);
+        op.grad(&[a], &[output_grad], &[input_grad.ref_copy()]);
+        assert_eq!(input_grad, Tensor::from_vec_f64(&[0., 1., 0., 2.,
+                                                      0., 0., 0., 0.,
+                                                      0., 3., 0., 4.], &[3, 4]));
+    }
+
+    #[test]
+    fn set_patch_grad() {
+        let a = Tensor::zeros(&[3, 4]);
+        let b = Tensor::ones(&[2, 2]);
+        let op = SetPatch::new(&[(0, 3), (0, 3)], Some(&[2, 2]));
+        let output = Tensor::new();
+        op.apply(&[a.ref_copy(), b.ref_copy()], &[output.ref_copy()]);
+        assert_eq!(output, Tensor::from_vec_f64(&[1., 0., 1., 0.,
+                                                  0., 0., 0., 0.,
+                                                  1., 0., 1., 0.], &[3, 4]));
+
+        let input_grad = [Tensor::new(), Tensor::new()];
+        let output_grad = Tensor::from_vec_f64(&(0..12).map(|x| x as f64).collect::<Vec<_>>(), &[3, 4]);
+        op.grad(&[a, b], &[output_grad], &input_grad);
+        assert_eq!(input_grad[0], Tensor::from_vec_f64(&[0., 1., 0., 3.,
+                                                         4., 5., 6., 7.,
+                                                         0., 9., 0., 11.], &[3, 4]));
+        assert_eq!(input_grad[1], Tensor::from_vec_f64(&[0., 2., 8., 10.], &[2, 2]));
+    }
+}
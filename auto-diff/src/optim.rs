@@ -72,6 +72,12 @@ impl Optimizer for SGD {
     fn step(&mut self, net: Rc<RefCell<Net>>) {
         net.borrow_mut().visit_op(
             |x| {
+                // Only update the rows with gradient.
+                if x.get_grad_rows().is_some() {
+                    x.step_grad_rows(self.lr.get_scale_f64());
+                    return;
+                }
+
                 let weights = x.get_values();
                 let grads = x.get_grads();
                 //println!("name: {}, {}, {}", x.get_name(), weights.len(), grads.len());
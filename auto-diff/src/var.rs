@@ -7,7 +7,7 @@ use std::rc::Rc;
 
 use crate::compute_graph::Net;
 use crate::err::AutoDiffError;
-use crate::op::Op;
+use crate::op::{Op, Reduction};
 use crate::optim::Optimizer;
 use crate::var_inner::VarInner;
 use tensor_rs::tensor::Tensor;
@@ -287,14 +287,16 @@ impl Var {
         ///
         matmul
     );
-    var_2_to_1!(
-        /// Outer product
+    var_2_to_1_with_para!(
+        /// Outer product of the last dimension,
+        /// leading dimensions are matched pairwise.
+        /// With avg set, the products are averaged over the leading dimensions.
         /// ```
         /// # use auto_diff::{Var, var_f64, AutoDiffError};
         /// # fn test_outer() -> Result<(), AutoDiffError> {
         /// let v1 = Var::new_f64(&[1., 2., 3.], &[3]);
         /// let v2 = Var::new_f64(&[4., 5., 6.], &[3]);
-        /// let v3 = v1.outer(&v2)?;
+        /// let v3 = v1.outer(&v2, None)?;
         /// let em = var_f64!([[4.,   5.,  6.],
         ///                    [8.,  10., 12.],
         ///                    [12., 15., 18.]]);
@@ -303,7 +305,8 @@ impl Var {
         /// # }
         /// # test_outer();
         /// ```
-        outer
+        outer,
+        avg: Option<bool>
     );
 
     // nonlinear
@@ -314,13 +317,205 @@ impl Var {
             )),
         })
     }
+    var_1_to_1_with_para!(
+        /// Clamp to [min_val, max_val].
+        hardtanh,
+        min_val: f64,
+        max_val: f64
+    );
+    var_1_to_1_with_para!(
+        /// max(0, x) + negative_slope * min(0, x)
+        leaky_relu,
+        negative_slope: f64
+    );
+    /// max(0, x) + weight * min(0, x), weight is either a single element
+    /// or one element per channel, which is dim 1 (dim 0 for 1-d input).
+    /// weight is the initial value of the op's own parameter,
+    /// which is updated by the optimizer.
+    pub fn prelu(&self, weight: &Tensor) -> Result<Var, AutoDiffError> {
+        Ok(Var {
+            var: Rc::new(RefCell::new(self.var.borrow().prelu(weight)?)),
+        })
+    }
     var_1_to_1!(relu);
+    var_1_to_1!(
+        /// GELU with the tanh approximation.
+        gelu
+    );
     var_1_to_1!(sigmoid);
+    var_1_to_1!(
+        /// x * sigmoid(x), also known as Swish.
+        silu
+    );
+    var_1_to_1!(
+        /// x * tanh(softplus(x))
+        mish
+    );
+    var_1_to_1_with_para!(
+        /// log(1 + exp(beta * x)) / beta, beta is positive.
+        softplus,
+        beta: f64
+    );
+    var_1_to_1!(
+        /// Same as sin, as an activation op.
+        sine
+    );
+    var_1_to_1_with_para!(
+        /// Softmax along dim.
+        ///
+        /// ```
+        /// # use auto_diff::{Var, var_f64, AutoDiffError};
+        /// # extern crate openblas_src;
+        /// # fn test_softmax() -> Result<(), AutoDiffError> {
+        /// let a = var_f64!([[0., 1000.], [-1000., 0.]]);
+        /// let b = a.softmax(1)?;
+        /// assert_eq!(b, var_f64!([[0., 1.], [0., 1.]]));
+        /// #   Ok(())
+        /// # }
+        /// # test_softmax();
+        /// ```
+        softmax,
+        dim: usize
+    );
+    var_1_to_1_with_para!(
+        /// Log of softmax along dim, computed without forming the softmax.
+        log_softmax,
+        dim: usize
+    );
 
     // loss
-    var_2_to_1!(mse_loss);
+    var_2_to_1!(
+        /// Mean squared error, self is the prediction.
+        mse_loss
+    );
+    var_2_to_1_with_para!(
+        /// mse_loss with a given reduction.
+        mse_loss_with_reduction,
+        reduction: Reduction
+    );
     var_2_to_1!(bce_with_logits_loss);
-    var_2_to_1!(cross_entropy_loss);
+    var_2_to_1_with_para!(
+        /// bce_with_logits_loss with a given reduction.
+        bce_with_logits_loss_with_reduction,
+        reduction: Reduction
+    );
+    var_2_to_1!(
+        /// Class scores [N, C] against class indices [N],
+        /// or probabilities [N, C].
+        /// Use CrossEntropyLoss through OpCall for class weights,
+        /// ignore_index and label smoothing.
+        cross_entropy_loss
+    );
+    var_2_to_1_with_para!(
+        /// cross_entropy_loss with a given reduction.
+        cross_entropy_loss_with_reduction,
+        reduction: Reduction
+    );
+    var_2_to_1_with_para!(
+        /// Log-probabilities [N, C] against class indices [N],
+        /// or probabilities [N, C].
+        nll_loss,
+        reduction: Reduction
+    );
+    var_2_to_1_with_para!(
+        /// Binary cross entropy on probabilities, self is the prediction.
+        bce_loss,
+        reduction: Reduction
+    );
+    var_2_to_1_with_para!(
+        /// KL divergence from self, log-probabilities, to the target.
+        kl_div_loss,
+        log_target: bool,
+        reduction: Reduction
+    );
+    var_2_to_1_with_para!(
+        /// Mean absolute error, self is the prediction.
+        l1_loss,
+        reduction: Reduction
+    );
+    var_2_to_1_with_para!(
+        /// Squared error below beta, absolute error above it.
+        smooth_l1_loss,
+        beta: f64,
+        reduction: Reduction
+    );
+    var_2_to_1_with_para!(
+        /// Squared error below delta, delta scaled absolute error above it.
+        huber_loss,
+        delta: f64,
+        reduction: Reduction
+    );
+    /// max(0, -target * (self - other) + margin),
+    /// target is 1 if self should rank higher, -1 otherwise.
+    pub fn margin_ranking_loss(
+        &self,
+        other: &Var,
+        target: &Var,
+        margin: f64,
+        reduction: Reduction,
+    ) -> Result<Var, AutoDiffError> {
+        Ok(Var {
+            var: Rc::new(RefCell::new(self.var.borrow().margin_ranking_loss(
+                &[other.var.clone(), target.var.clone()],
+                margin,
+                reduction,
+            )?)),
+        })
+    }
+    var_2_to_1_with_para!(
+        /// self if target is 1, max(0, margin - self) if target is -1.
+        hinge_embedding_loss,
+        margin: f64,
+        reduction: Reduction
+    );
+    /// 1 - cos(self, other) if target is 1,
+    /// max(0, cos(self, other) - margin) if target is -1,
+    /// with the cosine over the last dim.
+    pub fn cosine_embedding_loss(
+        &self,
+        other: &Var,
+        target: &Var,
+        margin: f64,
+        reduction: Reduction,
+    ) -> Result<Var, AutoDiffError> {
+        Ok(Var {
+            var: Rc::new(RefCell::new(self.var.borrow().cosine_embedding_loss(
+                &[other.var.clone(), target.var.clone()],
+                margin,
+                reduction,
+            )?)),
+        })
+    }
+    var_more_to_1_with_para!(
+        /// CTC loss of log-probabilities [T, N, C],
+        /// other is [targets, input lengths, target lengths].
+        ctc_loss,
+        blank: usize,
+        reduction: Reduction,
+        zero_infinity: bool
+    );
+    /// max(d(self, positive) - d(self, negative) + margin, 0),
+    /// with the p-norm distance over the last dim.
+    /// With swap, d(positive, negative) is used when it is closer.
+    pub fn triplet_margin_loss(
+        &self,
+        positive: &Var,
+        negative: &Var,
+        margin: f64,
+        p: f64,
+        swap: bool,
+        reduction: Reduction,
+    ) -> Result<Var, AutoDiffError> {
+        Ok(Var {
+            var: Rc::new(RefCell::new(self.var.borrow().triplet_margin_loss(
+                &[positive.var.clone(), negative.var.clone()],
+                margin,
+                p,
+                swap,
+                reduction,
+            )?)),
+        })
+    }
 
     //elementwise op
     var_1_to_1!(abs);
@@ -508,6 +703,36 @@ impl Var {
     var_1_to_1!(inv);
     var_1_to_1!(normalize_unit);
     var_1_to_1!(tr);
+    var_2_to_1!(
+        /// Solve self x = other, other is either a vector or a matrix.
+        solve
+    );
+    var_1_to_1!(
+        /// Upper triangular r such that self = r^T r.
+        cholesky
+    );
+    /// Return [q, r] with self = q r.
+    pub fn qr(&self) -> Result<Vec<Var>, AutoDiffError> {
+        let mut result = self.var.borrow().qr()?;
+        let mut ret = Vec::new();
+        for i in result.drain(..) {
+            ret.push(Var {
+                var: Rc::new(RefCell::new(i)),
+            });
+        }
+        Ok(ret)
+    }
+    /// Return [sign, log(abs(det))] of a square matrix.
+    pub fn slogdet(&self) -> Result<Vec<Var>, AutoDiffError> {
+        let mut result = self.var.borrow().slogdet()?;
+        let mut ret = Vec::new();
+        for i in result.drain(..) {
+            ret.push(Var {
+                var: Rc::new(RefCell::new(i)),
+            });
+        }
+        Ok(ret)
+    }
 
     // reduction
     var_1_to_1_with_para!(argmax, dim: Option<&[usize]>, keepdim: bool);
@@ -567,6 +792,129 @@ impl Var {
     );
     var_1_to_1_with_para!(view, new_shape: &[usize]);
 
+    // pooling
+    var_1_to_1_with_para!(
+        /// Max pooling over the last dim of a (C, L) or (N, C, L) input,
+        /// stride defaults to kernel_size.
+        /// Use [MaxPool1d](crate::op::MaxPool1d) to get the indices as well.
+        max_pool1d,
+        kernel_size: usize,
+        stride: Option<usize>,
+        padding: usize,
+        dilation: usize,
+        ceil_mode: bool
+    );
+    var_1_to_1_with_para!(
+        /// Max pooling over the last two dims of a (C, H, W) or (N, C, H, W) input,
+        /// stride defaults to kernel_size.
+        /// Use [MaxPool2d](crate::op::MaxPool2d) to get the indices as well.
+        ///
+        /// ```
+        /// # use auto_diff::{Var, var_f64, AutoDiffError};
+        /// # fn test_max_pool2d() -> Result<(), AutoDiffError> {
+        /// let m1 = var_f64!([[1., 5., 2., 0.],
+        ///                    [3., 4., 8., 7.]]).reshape(&[1, 1, 2, 4])?;
+        /// let m2 = m1.max_pool2d((2, 2), None, (0, 0), (1, 1), false)?;
+        /// assert_eq!(m2, var_f64!([[5., 8.]]).reshape(&[1, 1, 1, 2])?);
+        /// #   Ok(())
+        /// # }
+        /// # test_max_pool2d();
+        /// ```
+        max_pool2d,
+        kernel_size: (usize, usize),
+        stride: Option<(usize, usize)>,
+        padding: (usize, usize),
+        dilation: (usize, usize),
+        ceil_mode: bool
+    );
+    var_1_to_1_with_para!(
+        /// Average pooling over the last dim of a (C, L) or (N, C, L) input,
+        /// stride defaults to kernel_size.
+        avg_pool1d,
+        kernel_size: usize,
+        stride: Option<usize>,
+        padding: usize,
+        ceil_mode: bool,
+        count_include_pad: bool
+    );
+    var_1_to_1_with_para!(
+        /// Average pooling over the last two dims of a (C, H, W) or (N, C, H, W) input,
+        /// stride defaults to kernel_size.
+        avg_pool2d,
+        kernel_size: (usize, usize),
+        stride: Option<(usize, usize)>,
+        padding: (usize, usize),
+        ceil_mode: bool,
+        count_include_pad: bool
+    );
+    var_1_to_1_with_para!(
+        /// Average pooling to a fixed (H, W) output size.
+        adaptive_avg_pool2d,
+        output_size: (usize, usize)
+    );
+    var_1_to_1_with_para!(
+        /// Max pooling to a fixed (H, W) output size.
+        adaptive_max_pool2d,
+        output_size: (usize, usize)
+    );
+
+    // sliding blocks
+    var_1_to_1_with_para!(
+        /// Extract sliding blocks from a (N, C, H, W) input
+        /// into a (N, C*kernel_size.0*kernel_size.1, L) output,
+        /// L is the number of blocks.
+        ///
+        /// ```
+        /// # use auto_diff::{Var, var_f64, AutoDiffError};
+        /// # fn test_unfold() -> Result<(), AutoDiffError> {
+        /// let m1 = var_f64!([[1., 2., 3.],
+        ///                    [4., 5., 6.]]).reshape(&[1, 1, 2, 3])?;
+        /// let m2 = m1.unfold((2, 2), (1, 1), (0, 0), (1, 1))?;
+        /// assert_eq!(m2, var_f64!([[1., 2.], [2., 3.], [4., 5.], [5., 6.]]).reshape(&[1, 4, 2])?);
+        /// #   Ok(())
+        /// # }
+        /// # test_unfold();
+        /// ```
+        unfold,
+        kernel_size: (usize, usize),
+        stride: (usize, usize),
+        padding: (usize, usize),
+        dilation: (usize, usize)
+    );
+    var_1_to_1_with_para!(
+        /// Sum (N, C*kernel_size.0*kernel_size.1, L) sliding blocks
+        /// into a (N, C, output_size.0, output_size.1) output, the reverse of unfold.
+        fold,
+        output_size: (usize, usize),
+        kernel_size: (usize, usize),
+        stride: (usize, usize),
+        padding: (usize, usize),
+        dilation: (usize, usize)
+    );
+
+    // attention
+    /// softmax(self key^T / sqrt(E)) value along the last two dims,
+    /// self is the [..., L, E] query, key is [..., S, E], value is [..., S, Ev].
+    /// 0 in the mask, broadcasting to [..., L, S], drops the key for the query,
+    /// causal drops the keys after the query.
+    pub fn scaled_dot_product_attention(
+        &self,
+        key: &Var,
+        value: &Var,
+        mask: Option<&Var>,
+        causal: bool,
+    ) -> Result<Var, AutoDiffError> {
+        let result = self.var.borrow().scaled_dot_product_attention(
+            &key.var,
+            &value.var,
+            mask.map(|x| &x.var),
+            causal,
+        )?;
+        Ok(Var {
+            var: Rc::new(RefCell::new(result)),
+        })
+    }
+
     // innternal use
     pub fn val(&self) -> Tensor {
         self.var.borrow().val()
@@ -577,6 +925,12 @@ impl Var {
         self.var.borrow_mut().set_grad(use_gradient);
     }
 
+    /// Switch the ops in the background net, e.g. dropout,
+    /// between training and evaluation.
+    pub fn set_training(&self, training: bool) {
+        self.dump_net().borrow_mut().set_training(training);
+    }
+
     /// Reset net in the background.
     pub fn reset_net(&self) {
         self.var.borrow_mut().reset_net();
@@ -603,14 +957,54 @@ impl Var {
         self.var.borrow().rerun(None)
     }
 
-    /// Used to loop back
-    pub fn assign_to(&self, to: &Var) {}
-
-    /// Used to remove tick tag
-    pub fn over_tick(&self) -> Var {unimplemented!()}
+    /// Tag the var as a sequence with time on the leading dim,
+    /// ops see one tick of it at a time.
+    pub fn tick(&self) -> Result<(), AutoDiffError> {
+        self.var.borrow().tick()
+    }
+
+    /// Used to loop back.
+    /// The value of self at a tick becomes the value of to at the next tick,
+    /// to is an input holding the value at the first tick.
+    /// With a loop, rerun runs the net once for every tick of the ticked vars,
+    /// and bp goes back through the ticks from the loss at the last tick.
+    ///
+    /// ```
+    /// # use auto_diff::{Var, AutoDiffError};
+    /// # fn test_loop() -> Result<(), AutoDiffError> {
+    /// let x = Var::new(&[1., 2., 3.], &[3, 1]);
+    /// x.tick()?;
+    /// let h = Var::new(&[0.], &[1]);
+    /// let sum = x.ref_copy() + h.ref_copy();
+    /// sum.assign_to(&h)?;
+    /// sum.rerun()?;
+    /// assert_eq!(sum.over_tick()?, Var::new(&[1., 3., 6.], &[3, 1]));
+    /// assert_eq!(sum.at_tick(-1)?, Var::new(&[6.], &[1]));
+    /// #   Ok(())
+    /// # }
+    /// # test_loop().unwrap();
+    /// ```
+    pub fn assign_to(&self, to: &Var) -> Result<(), AutoDiffError> {
+        self.var.borrow().assign_to(&to.var.borrow())
+    }
+
+    /// The values at all ticks, stacked on the leading dim,
+    /// a loss built on it counts every tick.
+    /// It is built in the net once, later calls give the same var.
+    pub fn over_tick(&self) -> Result<Var, AutoDiffError> {
+        Ok(Var {
+            var: Rc::new(RefCell::new(self.var.borrow().over_tick()?)),
+        })
+    }
 
-    /// Used to remove tick tag
-    pub fn at_tick(&self, tick: i32) -> Var {unimplemented!()}
+    /// The value at one tick, sliced from over_tick,
+    /// negative tick counts from the last one.
+    /// It is built in the net once per tick, later calls give the same var.
+    pub fn at_tick(&self, tick: i32) -> Result<Var, AutoDiffError> {
+        Ok(Var {
+            var: Rc::new(RefCell::new(self.var.borrow().at_tick(tick)?)),
+        })
+    }
 
     /// Extract input and output from the hidden net.
     pub fn get_io_var(&self) -> Result<(Vec<Var>, Vec<Var>), AutoDiffError> {
@@ -856,7 +1250,7 @@ macro_rules! var_f64 {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::op::OpCall;
+    use crate::op::{OpCall, OpTrait};
     extern crate openblas_src;
 
     #[test]
@@ -870,6 +1264,45 @@ mod tests {
         assert_eq!(b.grad().unwrap(), Var::new(&[2., 3., 4., 5.], &[2, 2]));
     }
 
+    #[test]
+    fn add_broadcast() {
+        let a = Var::new(&[2., 3., 4., 5.], &[2, 2]);
+        let b = Var::new(&[1., 2.], &[2]);
+        let c = a.ref_copy() + b.ref_copy();
+        assert_eq!(c, Var::new(&[3., 5., 5., 7.], &[2, 2]));
+        c.bp().unwrap();
+        assert_eq!(a.grad().unwrap(), Var::new(&[1., 1., 1., 1.], &[2, 2]));
+        assert_eq!(b.grad().unwrap(), Var::new(&[2., 2.], &[2]));
+    }
+
+    #[test]
+    fn chunk_bp() {
+        let a = Var::new(&[1., 2., 3., 4.], &[2, 2]);
+        let c = a.chunk(2, 0).unwrap();
+        assert_eq!(c.len(), 2);
+        let d = c[0].ref_copy() * c[1].ref_copy();
+        d.bp().unwrap();
+        assert_eq!(a.grad().unwrap(), Var::new(&[3., 4., 1., 2.], &[2, 2]));
+    }
+
+    #[test]
+    fn qr_bp() -> Result<(), AutoDiffError> {
+        // Only r is used, q still has to pass the gradient on.
+        let a = Var::new_f64(&[2., 1., 1., 3.], &[2, 2]);
+        let qr = a.qr()?;
+        qr[1].tr()?.bp()?;
+        let expected = Tensor::new();
+        crate::op::QR::new().grad(&[a.val()], &[Tensor::new(), Tensor::eye(2, 2)], &[expected.ref_copy()]);
+        assert_eq!(a.grad()?.val(), expected);
+
+        let a = Var::new_f64(&[2., 1., 1., 3.], &[2, 2]);
+        let slogdet = a.slogdet()?;
+        slogdet[1].bp()?;
+        let diff = a.grad()?.val().sub(&a.val().inv().unwrap().t());
+        assert!(diff.abs().sum(None, false).get_scale_f64() < 1e-10);
+        Ok(())
+    }
+
     #[test]
     fn test_mul_repeat_vars() {
         let a = Var::new(&[2., 3., 4., 5.], &[2, 2]);
@@ -899,6 +1332,53 @@ mod tests {
         assert_eq!(c, Var::new(&[1.,], &vec![1]));
     }
 
+    #[test]
+    fn test_over_tick_cached() {
+        let x = Var::new(&[1., 2., 3.], &[3, 1]);
+        x.tick().unwrap();
+        let h = Var::new(&[0.], &[1]);
+        let sum = x.ref_copy() + h.ref_copy();
+        sum.assign_to(&h).unwrap();
+        sum.rerun().unwrap();
+        let last = sum.at_tick(-1).unwrap();
+        let ops = sum.dump_net().borrow().get_ops().iter_key().count();
+        for _ in 0..5 {
+            assert_eq!(sum.at_tick(-1).unwrap(), Var::new(&[6.], &[1]));
+            sum.over_tick().unwrap();
+        }
+        assert_eq!(sum.dump_net().borrow().get_ops().iter_key().count(), ops);
+
+        // the register follows the sequence length.
+        x.set(&Var::new(&[1., 2., 3., 4., 5.], &[5, 1]));
+        sum.rerun().unwrap();
+        assert_eq!(
+            sum.over_tick().unwrap(),
+            Var::new(&[1., 3., 6., 10., 15.], &[5, 1])
+        );
+        assert_eq!(last, Var::new(&[15.], &[1]));
+        x.set(&Var::new(&[1., 2.], &[2, 1]));
+        sum.rerun().unwrap();
+        assert_eq!(sum.over_tick().unwrap(), Var::new(&[1., 3.], &[2, 1]));
+        assert_eq!(last, Var::new(&[3.], &[1]));
+    }
+
+    #[test]
+    fn test_prelu() {
+        use crate::optim::SGD;
+
+        let weight = Tensor::from_vec_f64(&[0.25], &[1]);
+        let x = Var::new(&[-2., 1., -4.], &[3]);
+        let loss = x.prelu(&weight).unwrap().sum(None, false).unwrap();
+        assert_eq!(loss, Var::new(&[-0.5], &[1]));
+
+        // the slope gradient is -6, the op steps its own copy.
+        loss.bp().unwrap();
+        loss.step(&mut SGD::new_f64(0.1)).unwrap();
+        loss.rerun().unwrap();
+        assert!((loss.val().get_scale_f64() + 4.1).abs() < 1e-12);
+        assert_eq!(weight, Tensor::from_vec_f64(&[0.25], &[1]));
+    }
+
     #[test]
     fn test_linear() {
         use crate::op::Linear;
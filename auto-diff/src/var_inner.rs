@@ -5,16 +5,20 @@ use std::fmt;
 use std::rc::Rc;
 
 use crate::collection::generational_index::GenKey;
-use crate::compute_graph::Net;
+use crate::compute_graph::{Net, TickRegister};
 use crate::err::AutoDiffError;
 use crate::op::{
-    Abs, Acos, Add, ArgSort, Argmax, Argmin, Asin, Atan, BCEWithLogitsLoss, Cat, Ceil, Chunk,
-    ConditionalSelect, Cos, Cosh, CrossEntropyLoss, Det, Div, EqElem, Equal, Exp, Expm1, Floor,
-    Frac, Gather, Ge, GetPatch, Gt, IndexExclude, IndexSelect, Inv, Le, Log, Log10, Log1p,
-    Log1pexp, Log2, Logsumexp, Lt, MSELoss, Matmul, Max, MaxPair, Mean, Min, MinPair, Mul, Ne, Neg,
-    NormalizeUnit, Op, Outer, Permute, Prod, ReLU, Reciprocal, Repeat, Reshape, Round, Rsqrt,
-    SetPatch, Sigmoid, Sign, Sin, Sinh, Split, Sqrt, Squeeze, Stack, Std, Sub, Sum, Take, Tan,
-    Tanh, Tr, Trunc, Unsqueeze, Variance, View, ELU, T,
+    Abs, Acos, AdaptiveAvgPool2d, AdaptiveMaxPool2d, Add, ArgSort, Argmax, Argmin, Asin, Atan,
+    AvgPool1d, AvgPool2d, BCELoss, BCEWithLogitsLoss, CTCLoss, Cat, Ceil, Cholesky, Chunk,
+    ConditionalSelect, Cos, Cosh, CosineEmbeddingLoss, CrossEntropyLoss, Det, Div, EqElem, Equal,
+    Exp, Expm1, Floor, Fold, Frac, Gather, Ge, GetPatch, Gt, Hardtanh, HingeEmbeddingLoss,
+    HuberLoss, IndexExclude, IndexSelect, Inv, KLDivLoss, L1Loss, Le, LeakyReLU, Log, Log10, Log1p,
+    Log1pexp, Log2, LogSoftmax, Logsumexp, Lt, MSELoss, MarginRankingLoss, Matmul, Max, MaxPair,
+    MaxPool1d, MaxPool2d, Mean, Min, MinPair, Mish, Mul, NLLLoss, Ne, Neg, NormalizeUnit, Op, Outer,
+    PReLU, Permute, Prod, ReLU, Reciprocal, Reduction, Repeat, Reshape, Round, Rsqrt,
+    ScaledDotProductAttention, SetPatch, SiLU, Sigmoid, Sign, Sin, Sine, Sinh, Slogdet,
+    SmoothL1Loss, Softmax, Softplus, Solve, Split, Sqrt, Squeeze, Stack, Std, Sub, Sum, Take, Tan,
+    Tanh, Tr, TripletMarginLoss, Trunc, Unfold, Unsqueeze, Variance, View, ELU, GELU, QR, T,
 };
 use crate::optim::Optimizer;
 use tensor_rs::tensor::Tensor;
@@ -70,12 +74,15 @@ macro_rules! var_inner_1_to_1_with_para {
 
 macro_rules! var_inner_2_to_1_with_para {
     ($a:ident, $b:ident, $( $arg_name:ident : $ArgTy:ty ),* $(,)?) => {
+        var_inner_2_to_1_with_para!($a, $b::new, $( $arg_name : $ArgTy ),*);
+    };
+    ($a:ident, $b:ident :: $c:ident, $( $arg_name:ident : $ArgTy:ty ),* $(,)?) => {
         pub fn $a(&self, other: &Rc<RefCell<VarInner>>,
                   $( $arg_name : $ArgTy ),*)
                   -> Result<VarInner, AutoDiffError> {
-            let new_one = $b::new($( $arg_name ),*);
+            let new_one = $b::$c($( $arg_name ),*);
             let op = Op::new(Rc::new(RefCell::new(Box::new(new_one))));
-            let mut result = self.called_with(op, &[other.clone()])?;
+            let mut result = self.called_with(op, std::slice::from_ref(other))?;
             Ok(result.remove(0))
         }
     }
@@ -379,6 +386,115 @@ impl VarInner {
         Ok(())
     }
 
+    /// The leading dim is time, ops see one tick at a time.
+    pub fn tick(&self) -> Result<(), AutoDiffError> {
+        self.net.borrow_mut().tag_tick(&self.id)
+    }
+
+    /// Loop back, the value at a tick is the value of to at the next tick.
+    pub fn assign_to(&self, to: &VarInner) -> Result<(), AutoDiffError> {
+        if !Rc::ptr_eq(&self.net, &to.net) {
+            return Err(AutoDiffError::new("assign_to expects vars in the same net."));
+        }
+        self.net.borrow_mut().add_loop(&self.id, &to.id)
+    }
+
+    /// The values at all ticks stacked on the leading dim, as a var in the net.
+    /// It is a register shifted by one tick at each tick and looped back,
+    /// sized to the ticks of each run.
+    /// The var is kept in the net, so later calls give the same var.
+    pub fn over_tick(&self) -> Result<VarInner, AutoDiffError> {
+        if !self.net.borrow().has_loop() {
+            return Err(AutoDiffError::new(
+                "over_tick expects a loop from assign_to.",
+            ));
+        }
+        let cached = self.net.borrow().get_tick_register(self.id);
+        if let Some(reg) = cached {
+            let stack = VarInner {
+                id: reg.stack,
+                need_grad: self.need_grad,
+                net: self.net.clone(),
+            };
+            // a new var in the net clears the values over ticks.
+            if self.net.borrow().get_tick_values(stack.id).is_err() {
+                stack.rerun(None)?;
+            }
+            return Ok(stack);
+        }
+
+        if self.net.borrow().get_tick_values(self.id).is_err() {
+            self.rerun(None)?;
+        }
+        let values = self.net.borrow().get_tick_values(self.id)?;
+        if values.is_empty() {
+            return Err(AutoDiffError::new("no tick yet."));
+        }
+        // one more row than the ticks, the first row drops out at each tick.
+        let ticks = values.len();
+        let mut size = vec![ticks + 1];
+        size.extend(values[0].size());
+        let register =
+            VarInner::new_net_tensor(self.net.clone(), self.need_grad, Tensor::zeros(&size));
+        let index: Vec<f64> = (1..=ticks).map(|x| x as f64).collect();
+        let index = Rc::new(RefCell::new(VarInner::new_net_tensor(
+            self.net.clone(),
+            self.need_grad,
+            Tensor::from_vec_f64(&index, &[ticks]),
+        )));
+        let shifted = register.index_select(0, index.clone())?;
+        let this = Rc::new(RefCell::new(self.unsqueeze(0)?));
+        let next = shifted.cat(&[this], 0)?;
+        next.assign_to(&register)?;
+        let stack = next.index_select(0, index.clone())?;
+        self.net.borrow_mut().set_tick_register(
+            self.id,
+            TickRegister {
+                register: register.id,
+                index: index.borrow().id,
+                stack: stack.id,
+                at: BTreeMap::new(),
+            },
+        );
+        stack.rerun(None)?;
+        Ok(stack)
+    }
+
+    /// The value at one tick, sliced from over_tick.
+    /// Negative tick counts from the last one.
+    /// The var is kept in the net, so later calls give the same var.
+    pub fn at_tick(&self, tick: i32) -> Result<VarInner, AutoDiffError> {
+        let stack = self.over_tick()?;
+        let ticks = stack.size()[0];
+        let index = if tick < 0 { ticks as i32 + tick } else { tick };
+        if index < 0 || index as usize >= ticks {
+            return Err(AutoDiffError::new(&format!(
+                "tick {} is out of {} ticks.",
+                tick, ticks
+            )));
+        }
+        let mut reg = self.net.borrow().get_tick_register(self.id).expect("");
+        if let Some((_, slice)) = reg.at.get(&tick) {
+            return Ok(VarInner {
+                id: *slice,
+                need_grad: self.need_grad,
+                net: self.net.clone(),
+            });
+        }
+
+        let position = Rc::new(RefCell::new(VarInner::new_net_tensor(
+            self.net.clone(),
+            self.need_grad,
+            Tensor::from_vec_f64(&[index as f64], &[1]),
+        )));
+        let slice = stack
+            .index_select(0, position.clone())?
+            .reshape(&stack.size()[1..])?;
+        reg.at.insert(tick, (position.borrow().id, slice.id));
+        self.net.borrow_mut().set_tick_register(self.id, reg);
+        Ok(slice)
+    }
+
     /// Update,
     pub fn step(&self, opt: &mut dyn Optimizer) -> Result<(), AutoDiffError> {
         opt.step(self.net.clone());
@@ -453,7 +569,9 @@ impl VarInner {
                     let mut existing_net = false;
                     for set in &mut other_var_by_networks {
                         if Rc::ptr_eq(&item.borrow().net, &set[0].borrow().net) {
-                            set.push(item.clone());
+                            if !set.iter().any(|x| Rc::ptr_eq(x, &item)) {
+                                set.push(item.clone());
+                            }
                             existing_net = true;
                             break;
                         }
@@ -478,11 +596,12 @@ impl VarInner {
                 }
             }
 
+            // ops see the first tick of ticked data.
             let mut input_id = vec![self.id];
-            let mut inputs = vec![self.net.borrow().get_tensor(self.id)?];
+            let mut inputs = vec![self.net.borrow().get_tick_tensor(self.id, 0)?];
             for i in others {
                 input_id.push(i.borrow().id);
-                inputs.push(self.net.borrow().get_tensor(i.borrow().id)?);
+                inputs.push(self.net.borrow().get_tick_tensor(i.borrow().id, 0)?);
             }
 
             let mut output_id = vec![];
@@ -532,7 +651,7 @@ impl VarInner {
     var_inner_2_to_1!(mul, Mul);
     var_inner_2_to_1!(div, Div);
     var_inner_2_to_1!(matmul, Matmul);
-    var_inner_2_to_1!(outer, Outer);
+    var_inner_2_to_1_with_para!(outer, Outer, avg: Option<bool>);
 
     // nonlinear
     pub fn elu(&self, alpha: VarInner) -> Result<VarInner, AutoDiffError> {
@@ -541,13 +660,49 @@ impl VarInner {
         let mut result = self.called_with(op, &[])?;
         Ok(result.remove(0))
     }
+    var_inner_1_to_1_with_para!(hardtanh, Hardtanh, min_val: f64, max_val: f64);
+    var_inner_1_to_1_with_para!(leaky_relu, LeakyReLU, negative_slope: f64);
+    pub fn prelu(&self, weight: &Tensor) -> Result<VarInner, AutoDiffError> {
+        let new_one = PReLU::new(weight.clone());
+        let op = Op::new(Rc::new(RefCell::new(Box::new(new_one))));
+        let mut result = self.called_with(op, &[])?;
+        Ok(result.remove(0))
+    }
     var_inner_1_to_1!(relu, ReLU);
+    var_inner_1_to_1!(gelu, GELU);
     var_inner_1_to_1!(sigmoid, Sigmoid);
+    var_inner_1_to_1!(silu, SiLU);
+    var_inner_1_to_1!(mish, Mish);
+    var_inner_1_to_1_with_para!(softplus, Softplus, beta: f64);
+    var_inner_1_to_1!(sine, Sine);
+    var_inner_1_to_1_with_para!(softmax, Softmax, dim: usize);
+    var_inner_1_to_1_with_para!(log_softmax, LogSoftmax, dim: usize);
 
     // loss
     var_inner_2_to_1!(mse_loss, MSELoss);
+    var_inner_2_to_1_with_para!(mse_loss_with_reduction, MSELoss::new_with_reduction, reduction: Reduction);
     var_inner_2_to_1!(bce_with_logits_loss, BCEWithLogitsLoss);
+    var_inner_2_to_1_with_para!(bce_with_logits_loss_with_reduction, BCEWithLogitsLoss::new_with_reduction, reduction: Reduction);
     var_inner_2_to_1!(cross_entropy_loss, CrossEntropyLoss);
+    var_inner_2_to_1_with_para!(cross_entropy_loss_with_reduction, CrossEntropyLoss::new_with_reduction, reduction: Reduction);
+    var_inner_2_to_1_with_para!(nll_loss, NLLLoss, reduction: Reduction);
+    var_inner_2_to_1_with_para!(bce_loss, BCELoss, reduction: Reduction);
+    var_inner_2_to_1_with_para!(kl_div_loss, KLDivLoss, log_target: bool, reduction: Reduction);
+    var_inner_2_to_1_with_para!(l1_loss, L1Loss, reduction: Reduction);
+    var_inner_2_to_1_with_para!(smooth_l1_loss, SmoothL1Loss, beta: f64, reduction: Reduction);
+    var_inner_2_to_1_with_para!(huber_loss, HuberLoss, delta: f64, reduction: Reduction);
+    var_inner_more_to_1_with_para!(margin_ranking_loss, MarginRankingLoss, margin: f64, reduction: Reduction);
+    var_inner_2_to_1_with_para!(hinge_embedding_loss, HingeEmbeddingLoss, margin: f64, reduction: Reduction);
+    var_inner_more_to_1_with_para!(cosine_embedding_loss, CosineEmbeddingLoss, margin: f64, reduction: Reduction);
+    var_inner_more_to_1_with_para!(ctc_loss, CTCLoss, blank: usize, reduction: Reduction, zero_infinity: bool);
+    var_inner_more_to_1_with_para!(
+        triplet_margin_loss,
+        TripletMarginLoss,
+        margin: f64,
+        p: f64,
+        swap: bool,
+        reduction: Reduction
+    );
 
     // element ops
     var_inner_1_to_1!(abs, Abs);
@@ -692,6 +847,20 @@ impl VarInner {
     var_inner_1_to_1!(inv, Inv);
     var_inner_1_to_1!(normalize_unit, NormalizeUnit);
     var_inner_1_to_1!(tr, Tr);
+    var_inner_2_to_1!(solve, Solve);
+    var_inner_1_to_1!(cholesky, Cholesky);
+    pub fn qr(&self) -> Result<Vec<VarInner>, AutoDiffError> {
+        let new_one = QR::new();
+        let op = Op::new(Rc::new(RefCell::new(Box::new(new_one))));
+        let result = self.called_with(op, &[])?;
+        Ok(result)
+    }
+    pub fn slogdet(&self) -> Result<Vec<VarInner>, AutoDiffError> {
+        let new_one = Slogdet::new();
+        let op = Op::new(Rc::new(RefCell::new(Box::new(new_one))));
+        let result = self.called_with(op, &[])?;
+        Ok(result)
+    }
 
     // reduction
     var_inner_1_to_1_with_para!(argmax, Argmax, dim: Option<&[usize]>, keepdim: bool);
@@ -720,6 +889,99 @@ impl VarInner {
     );
     var_inner_1_to_1_with_para!(view, View, new_shape: &[usize]);
 
+    // pooling
+    pub fn max_pool1d(
+        &self,
+        kernel_size: usize,
+        stride: Option<usize>,
+        padding: usize,
+        dilation: usize,
+        ceil_mode: bool,
+    ) -> Result<VarInner, AutoDiffError> {
+        let new_one = MaxPool1d::new(kernel_size, stride, padding, dilation, false, ceil_mode);
+        let op = Op::new(Rc::new(RefCell::new(Box::new(new_one))));
+        let mut result = self.called_with(op, &[])?;
+        Ok(result.remove(0))
+    }
+    pub fn max_pool2d(
+        &self,
+        kernel_size: (usize, usize),
+        stride: Option<(usize, usize)>,
+        padding: (usize, usize),
+        dilation: (usize, usize),
+        ceil_mode: bool,
+    ) -> Result<VarInner, AutoDiffError> {
+        let new_one = MaxPool2d::new(kernel_size, stride, padding, dilation, false, ceil_mode);
+        let op = Op::new(Rc::new(RefCell::new(Box::new(new_one))));
+        let mut result = self.called_with(op, &[])?;
+        Ok(result.remove(0))
+    }
+    var_inner_1_to_1_with_para!(
+        avg_pool1d,
+        AvgPool1d,
+        kernel_size: usize,
+        stride: Option<usize>,
+        padding: usize,
+        ceil_mode: bool,
+        count_include_pad: bool
+    );
+    var_inner_1_to_1_with_para!(
+        avg_pool2d,
+        AvgPool2d,
+        kernel_size: (usize, usize),
+        stride: Option<(usize, usize)>,
+        padding: (usize, usize),
+        ceil_mode: bool,
+        count_include_pad: bool
+    );
+    var_inner_1_to_1_with_para!(adaptive_avg_pool2d, AdaptiveAvgPool2d, output_size: (usize, usize));
+    pub fn adaptive_max_pool2d(
+        &self,
+        output_size: (usize, usize),
+    ) -> Result<VarInner, AutoDiffError> {
+        let new_one = AdaptiveMaxPool2d::new(output_size, false);
+        let op = Op::new(Rc::new(RefCell::new(Box::new(new_one))));
+        let mut result = self.called_with(op, &[])?;
+        Ok(result.remove(0))
+    }
+
+    // sliding blocks
+    var_inner_1_to_1_with_para!(
+        unfold,
+        Unfold,
+        kernel_size: (usize, usize),
+        stride: (usize, usize),
+        padding: (usize, usize),
+        dilation: (usize, usize)
+    );
+    var_inner_1_to_1_with_para!(
+        fold,
+        Fold,
+        output_size: (usize, usize),
+        kernel_size: (usize, usize),
+        stride: (usize, usize),
+        padding: (usize, usize),
+        dilation: (usize, usize)
+    );
+
+    // attention
+    pub fn scaled_dot_product_attention(
+        &self,
+        key: &Rc<RefCell<VarInner>>,
+        value: &Rc<RefCell<VarInner>>,
+        mask: Option<&Rc<RefCell<VarInner>>>,
+        causal: bool,
+    ) -> Result<VarInner, AutoDiffError> {
+        let mut inputs = vec![key.clone(), value.clone()];
+        if let Some(mask) = mask {
+            inputs.push(mask.clone());
+        }
+        let new_one = ScaledDotProductAttention::new(causal, mask.is_some());
+        let op = Op::new(Rc::new(RefCell::new(Box::new(new_one))));
+        let mut result = self.called_with(op, &inputs)?;
+        Ok(result.remove(0))
+    }
+
     pub fn dump_net(&self) -> Rc<RefCell<Net>> {
         self.net.clone()
     }
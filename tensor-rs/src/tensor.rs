@@ -554,6 +554,11 @@ impl Tensor {
             v: Rc::new(RefCell::new(self.v.borrow().pow_f32(n))),
         }
     }
+    pub fn pow_f64(&self, n: f64) -> Tensor {
+        Tensor {
+            v: Rc::new(RefCell::new(self.v.borrow().pow_f64(n))),
+        }
+    }
     tensor_method_single_tensor_return!(reciprocal);
     tensor_method_single_tensor_return!(round);
     tensor_method_single_tensor_return!(rsqrt);
@@ -727,6 +732,14 @@ impl Tensor {
             v: Rc::new(RefCell::new(TypedTensor::uniform_f32(rng, dim, from, to)))
         }
     }
+    /// Uniform in [from, to) with the same type as self.
+    pub fn uniform_like(&self, rng: &mut StdRng,
+                        dim: &[usize],
+                        from: f64, to: f64) -> Tensor {
+        Tensor {
+            v: Rc::new(RefCell::new(self.v.borrow().uniform_like(rng, dim, from, to)))
+        }
+    }
     
 
     // conv ops
@@ -753,6 +766,128 @@ impl Tensor {
         )
     }
 
+    /// Convolution over the trailing dims of a (N, C, *) input,
+    /// with filter in (C_out, C, *).
+    pub fn conv_gen(&self, weight: &Tensor,
+                    stride: &[usize],
+                    padding: &[usize],
+                    dilation: &[usize],
+                    padding_mode: PaddingMode
+    ) -> Tensor {
+        Tensor {
+            v: Rc::new(RefCell::new(self.v.borrow().conv_gen(&weight.v.borrow(), stride, padding, dilation, padding_mode))),
+        }
+    }
+    /// Return the gradient for the weight and the input.
+    pub fn conv_grad_gen(&self, weight: &Tensor,
+                         stride: &[usize],
+                         padding: &[usize],
+                         dilation: &[usize],
+                         padding_mode: PaddingMode,
+                         output_grad: &Tensor
+    ) -> (Tensor, Tensor) {
+        let (r1, r2) = self.v.borrow().conv_grad_gen(&weight.v.borrow(), stride, padding, dilation, padding_mode, &output_grad.v.borrow());
+        (Tensor { v: Rc::new(RefCell::new(r1))},
+         Tensor { v: Rc::new(RefCell::new(r2))},
+        )
+    }
+
+    /// Extract sliding blocks, (N, C, *) to (N, C*prod(kernel_size), L).
+    pub fn unfold(&self,
+                  kernel_size: &[usize],
+                  stride: &[usize],
+                  padding: &[usize],
+                  dilation: &[usize]
+    ) -> Tensor {
+        Tensor {
+            v: Rc::new(RefCell::new(self.v.borrow().unfold(kernel_size, stride, padding, dilation))),
+        }
+    }
+    /// Sum sliding blocks back, (N, C*prod(kernel_size), L) to (N, C, *output_size).
+    pub fn fold(&self,
+                output_size: &[usize],
+                kernel_size: &[usize],
+                stride: &[usize],
+                padding: &[usize],
+                dilation: &[usize]
+    ) -> Tensor {
+        Tensor {
+            v: Rc::new(RefCell::new(self.v.borrow().fold(output_size, kernel_size, stride, padding, dilation))),
+        }
+    }
+
+    // pooling ops
+    /// Max pooling over the trailing kernel_size.len() dims,
+    /// return the pooled values and their flat indices within each input plane.
+    pub fn max_pool(&self,
+                    kernel_size: &[usize],
+                    stride: &[usize],
+                    padding: &[usize],
+                    dilation: &[usize],
+                    ceil_mode: bool
+    ) -> (Tensor, Tensor) {
+        let (r1, r2) = self.v.borrow().max_pool(kernel_size, stride, padding, dilation, ceil_mode);
+        (Tensor { v: Rc::new(RefCell::new(r1))},
+         Tensor { v: Rc::new(RefCell::new(r2))},
+        )
+    }
+    pub fn max_pool_grad(&self,
+                         kernel_size: &[usize],
+                         stride: &[usize],
+                         padding: &[usize],
+                         dilation: &[usize],
+                         ceil_mode: bool,
+                         output_grad: &Tensor
+    ) -> Tensor {
+        Tensor {
+            v: Rc::new(RefCell::new(self.v.borrow().max_pool_grad(kernel_size, stride, padding, dilation, ceil_mode, &output_grad.v.borrow()))),
+        }
+    }
+    pub fn avg_pool(&self,
+                    kernel_size: &[usize],
+                    stride: &[usize],
+                    padding: &[usize],
+                    ceil_mode: bool,
+                    count_include_pad: bool
+    ) -> Tensor {
+        Tensor {
+            v: Rc::new(RefCell::new(self.v.borrow().avg_pool(kernel_size, stride, padding, ceil_mode, count_include_pad))),
+        }
+    }
+    pub fn avg_pool_grad(&self,
+                         kernel_size: &[usize],
+                         stride: &[usize],
+                         padding: &[usize],
+                         ceil_mode: bool,
+                         count_include_pad: bool,
+                         output_grad: &Tensor
+    ) -> Tensor {
+        Tensor {
+            v: Rc::new(RefCell::new(self.v.borrow().avg_pool_grad(kernel_size, stride, padding, ceil_mode, count_include_pad, &output_grad.v.borrow()))),
+        }
+    }
+    pub fn adaptive_avg_pool(&self, output_size: &[usize]) -> Tensor {
+        Tensor {
+            v: Rc::new(RefCell::new(self.v.borrow().adaptive_avg_pool(output_size))),
+        }
+    }
+    pub fn adaptive_avg_pool_grad(&self, output_size: &[usize], output_grad: &Tensor) -> Tensor {
+        Tensor {
+            v: Rc::new(RefCell::new(self.v.borrow().adaptive_avg_pool_grad(output_size, &output_grad.v.borrow()))),
+        }
+    }
+    pub fn adaptive_max_pool(&self, output_size: &[usize]) -> (Tensor, Tensor) {
+        let (r1, r2) = self.v.borrow().adaptive_max_pool(output_size);
+        (Tensor { v: Rc::new(RefCell::new(r1))},
+         Tensor { v: Rc::new(RefCell::new(r2))},
+        )
+    }
+    pub fn adaptive_max_pool_grad(&self, output_size: &[usize], output_grad: &Tensor) -> Tensor {
+        Tensor {
+            v: Rc::new(RefCell::new(self.v.borrow().adaptive_max_pool_grad(output_size, &output_grad.v.borrow()))),
+        }
+    }
+
     pub fn inner(&self) -> Rc<RefCell<TypedTensor>> {
 	self.v.clone()
     }
@@ -820,6 +955,19 @@ pub enum PaddingMode{
 mod tests {
     use super::*;
 
+    #[test]
+    fn uniform_like() {
+        use rand::SeedableRng;
+        let mut rng = StdRng::seed_from_u64(1);
+        let a = Tensor::from_vec_f32(&[1.], &[1]);
+        let u = a.uniform_like(&mut rng, &[2, 3], 0., 1.);
+        assert_eq!(u.size(), [2, 3]);
+        // get_raw_f32 only takes an f32 tensor.
+        assert_eq!(u.get_raw_f32().len(), 6);
+        let u = Tensor::from_vec_f64(&[1.], &[1]).uniform_like(&mut rng, &[4], 2., 3.);
+        assert!(u.get_raw_f64().iter().all(|x| (2. ..3.).contains(x)));
+    }
+
     #[test]
     fn tensor_equal() {
         let a = Tensor::from_vec_f32(&vec![1., 2., 3., ], &vec![3, 1]);
@@ -853,6 +1001,17 @@ mod tests {
         assert_eq!(m3, Tensor::from_vec_f32(&vec![2.,4.,4.,6.,], &vec![2,2]));
     }
 
+    #[test]
+    fn test_broadcast() {
+        let m1 = Tensor::from_vec_f32(&[1.,2.,], &[2,1]);
+        let m2 = Tensor::from_vec_f32(&[10.,20.,30.,], &[1,3]);
+        assert_eq!(m1.add(&m2), Tensor::from_vec_f32(&[11.,21.,31.,12.,22.,32.,], &[2,3]));
+        assert_eq!(m2.sub(&m1), Tensor::from_vec_f32(&[9.,19.,29.,8.,18.,28.,], &[2,3]));
+
+        let m3 = Tensor::from_vec_f32(&[1.5,], &[1]);
+        assert_eq!(m3.gt(&m1), Tensor::from_vec_f32(&[1.,0.,], &[2,1]));
+    }
+
     #[test]
     fn test_mm() {
         let m1 = Tensor::from_vec_f32(&vec![1.,2.,3.,4.,5.,6.], &vec![3,2]);
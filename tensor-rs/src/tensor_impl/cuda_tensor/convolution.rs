@@ -68,4 +68,21 @@ impl Convolution for CudaTensor {
     ) -> (Self, Self) {
         todo!();
     }
+
+    fn unfold(&self, kernel_size: &[usize],
+              stride: &[usize],
+              padding: &[usize],
+              dilation: &[usize],
+    ) -> Self {
+        todo!();
+    }
+
+    fn fold(&self, output_size: &[usize],
+            kernel_size: &[usize],
+            stride: &[usize],
+            padding: &[usize],
+            dilation: &[usize],
+    ) -> Self {
+        todo!();
+    }
 }
@@ -6,35 +6,11 @@ impl<T> CompareTensor for GenTensor<T> where T: num_traits::Float {
     type ElementType = T;
     
     fn max_pair(&self, o: &GenTensor<T>) -> GenTensor<T> {
-        if self.size() != o.size() {
-            panic!("max needs two tensor have the same size, {:?}, {:?}", self.size(), o.size());
-        }
-        let mut ret = GenTensor::zeros(self.size());
-
-        for ((a, b), c) in self.get_data().iter().zip(o.get_data().iter()).zip(ret.get_data_mut().iter_mut()) {
-            if a >= b {
-                *c = *a;
-            } else {
-                *c = *b;
-            }
-        }
-        ret
+        self._broadcast(o, |a, b| if a >= b {*a} else {*b})
     }
     // min, 
     fn min_pair(&self, o: &GenTensor<T>) -> GenTensor<T> {
-        if self.size() != o.size() {
-            panic!("max needs two tensor have the same size, {:?}, {:?}", self.size(), o.size());
-        }
-        let mut ret = GenTensor::zeros(self.size());
-
-        for ((a, b), c) in self.get_data().iter().zip(o.get_data().iter()).zip(ret.get_data_mut().iter_mut()) {
-            if a >= b {
-                *c = *b;
-            } else {
-                *c = *a;
-            }
-        }
-        ret
+        self._broadcast(o, |a, b| if a >= b {*b} else {*a})
     }
 
     fn all(&self, f: &dyn Fn(Self::ElementType) -> bool) -> bool {
@@ -70,18 +70,6 @@ impl<T> Convolution for GenTensor<T> where T: num_traits::Float {
         }
         //println!("padded_dim: {:?}", padded_dim);
 
-        // find the coordinate of
-        // start center point in a filter in padded dimension
-        // in case filter_dim[i] is even, start_point will be the half.
-        // in case filter_dim[i] is odd, start_point will be the center.
-        let mut start_point = Vec::new();
-        for i in 0..stride.len() {
-            let half = filter_dim[2+i]/2;
-            let dilated = half*dilation[i];
-            start_point.push(dilated);
-        }
-        //println!("start_point: {:?}", start_point);
-
         let mut output_size = Vec::new();
         //println!("{:?}, {:?}", padded_dim, stride);
         for i in 0..stride.len() {
@@ -176,11 +164,9 @@ impl<T> Convolution for GenTensor<T> where T: num_traits::Float {
                     let mut current_pos = left_upper.len()-1;
                     loop {
                         left_upper[current_pos] += stride[current_pos];
-                        let mut compare_pos = padded_dim[current_pos] - start_point[current_pos]*2;
-                        if filter_dim[current_pos+2] % 2 == 0 {
-                            compare_pos += 1;
-                        }
-                        if left_upper[current_pos] >= compare_pos {
+                        let compare_pos = padded_dim[current_pos]
+                            - ((filter_dim[current_pos+2]-1)*dilation[current_pos] + 1);
+                        if left_upper[current_pos] > compare_pos {
                             left_upper[current_pos] = 0;
                             if current_pos > 0 {
                                 current_pos -= 1;
@@ -441,6 +427,135 @@ impl<T> Convolution for GenTensor<T> where T: num_traits::Float {
         
         (ret_w_grad, ret_x_grad)
     }
+
+    fn unfold(&self, kernel_size: &[usize],
+              stride: &[usize],
+              padding: &[usize],
+              dilation: &[usize],
+    ) -> Self {
+        let size = self.size();
+        if size.len() != kernel_size.len() + 2 {
+            panic!("unfold expects (N, C, *) input for {:?} dims kernel, get {:?}", kernel_size.len(), size);
+        }
+        let (blocks, block_map) = block_positions(&size[2..], kernel_size, stride, padding, dilation);
+        let plane_len: usize = size[2..].iter().product();
+        let planes = size[0]*size[1];
+        let data = self.get_data();
+
+        let mut ret = Vec::with_capacity(planes*block_map.len());
+        for p in 0..planes {
+            let base = p*plane_len;
+            for pos in block_map.iter() {
+                match pos {
+                    Some(i) => ret.push(data[base + i]),
+                    None => ret.push(T::zero()),
+                }
+            }
+        }
+        let kernel_len: usize = kernel_size.iter().product();
+        GenTensor::new_move(ret, vec![size[0], size[1]*kernel_len, blocks])
+    }
+
+    fn fold(&self, output_size: &[usize],
+            kernel_size: &[usize],
+            stride: &[usize],
+            padding: &[usize],
+            dilation: &[usize],
+    ) -> Self {
+        let size = self.size();
+        let kernel_len: usize = kernel_size.iter().product();
+        if size.len() != 3 || kernel_len == 0 || !size[1].is_multiple_of(kernel_len) {
+            panic!("fold expects (N, C*prod(kernel_size), L) input, get {:?}, {:?}", size, kernel_size);
+        }
+        let (blocks, block_map) = block_positions(output_size, kernel_size, stride, padding, dilation);
+        if size[2] != blocks {
+            panic!("fold expects {:?} blocks for output_size {:?}, get {:?}", blocks, output_size, size[2]);
+        }
+        let plane_len: usize = output_size.iter().product();
+        let channels = size[1]/kernel_len;
+        let data = self.get_data();
+
+        let mut ret_size = vec![size[0], channels];
+        ret_size.extend_from_slice(output_size);
+        let mut ret = GenTensor::zeros(&ret_size);
+        let ret_data = ret.get_data_mut();
+        for p in 0..size[0]*channels {
+            let base = p*plane_len;
+            for (j, pos) in block_map.iter().enumerate() {
+                if let Some(i) = pos {
+                    ret_data[base + i] = ret_data[base + i] + data[p*block_map.len() + j];
+                }
+            }
+        }
+        ret
+    }
+}
+
+// For each (kernel offset, block) pair, in row-major order,
+// the flat position within the plane it reads, None is padding.
+// Also return the number of blocks.
+fn block_positions(plane: &[usize],
+                   kernel_size: &[usize],
+                   stride: &[usize],
+                   padding: &[usize],
+                   dilation: &[usize],
+) -> (usize, Vec<Option<usize>>) {
+    let dims = kernel_size.len();
+    if plane.len() != dims || stride.len() != dims || padding.len() != dims || dilation.len() != dims {
+        panic!("plane, kernel_size, stride, padding, dilation should have the same # of dims, {:?}, {:?}, {:?}, {:?}, {:?}",
+               plane, kernel_size, stride, padding, dilation);
+    }
+
+    // per dim, the padded position of each (kernel offset, block) pair.
+    let mut blocks = 1;
+    let mut per_dim = Vec::with_capacity(dims);
+    for i in 0..dims {
+        if kernel_size[i] < 1 || stride[i] < 1 || dilation[i] < 1 {
+            panic!("kernel_size, stride and dilation should be at least 1, get {:?}, {:?}, {:?}", kernel_size, stride, dilation);
+        }
+        let span = dilation[i]*(kernel_size[i] - 1) + 1;
+        if plane[i] + 2*padding[i] < span {
+            panic!("sliding block {:?} is larger than the padded input {:?}", span, plane[i] + 2*padding[i]);
+        }
+        let n = (plane[i] + 2*padding[i] - span)/stride[i] + 1;
+        blocks *= n;
+        let pos: Vec<Vec<Option<usize>>> = (0..kernel_size[i]).map(|k| {
+            (0..n).map(|b| {
+                let p = b*stride[i] + k*dilation[i];
+                if p >= padding[i] && p < plane[i] + padding[i] {Some(p - padding[i])} else {None}
+            }).collect()
+        }).collect();
+        per_dim.push(pos);
+    }
+
+    let kernel_len: usize = kernel_size.iter().product();
+    let mut ret = Vec::with_capacity(kernel_len*blocks);
+    let mut kernel_index = vec![0; dims];
+    let mut block_index = vec![0; dims];
+    for k in 0..kernel_len {
+        let mut rest = k;
+        for i in (0..dims).rev() {
+            kernel_index[i] = rest % kernel_size[i];
+            rest /= kernel_size[i];
+        }
+        for b in 0..blocks {
+            let mut rest = b;
+            for i in (0..dims).rev() {
+                let n = per_dim[i][0].len();
+                block_index[i] = rest % n;
+                rest /= n;
+            }
+            let mut flat = Some(0);
+            for i in 0..dims {
+                flat = match (flat, per_dim[i][kernel_index[i]][block_index[i]]) {
+                    (Some(f), Some(p)) => Some(f*plane[i] + p),
+                    _ => None,
+                };
+            }
+            ret.push(flat);
+        }
+    }
+    (blocks, ret)
 }
 
 #[cfg(test)]
@@ -449,6 +564,39 @@ mod tests {
     use crate::tensor_trait::index_slicing::IndexSlicing;
     use super::*;
 
+    #[test]
+    fn conv_gen_dilation() {
+        let data = GenTensor::<f64>::new_raw(&[1., 2., 3., 4., 5., 6., 7.], &[1, 1, 7]);
+        let filter = GenTensor::<f64>::new_raw(&[1., 10.], &[1, 1, 2]);
+        let result = data.conv_gen(&filter, &[1], &[0], &[2], PaddingMode::Zeros);
+        assert_eq!(result, GenTensor::new_raw(&[31., 42., 53., 64., 75.], &[1, 1, 5]));
+        let result = data.conv_gen(&filter, &[2], &[0], &[2], PaddingMode::Zeros);
+        assert_eq!(result, GenTensor::new_raw(&[31., 53., 75.], &[1, 1, 3]));
+    }
+
+    #[test]
+    fn unfold_fold() {
+        let data = GenTensor::<f64>::arange(12).reshape(&[1, 1, 3, 4]);
+        let blocks = data.unfold(&[2, 2], &[1, 2], &[0, 0], &[1, 1]);
+        assert_eq!(blocks, GenTensor::new_raw(&[0., 2., 4., 6.,
+                                                1., 3., 5., 7.,
+                                                4., 6., 8., 10.,
+                                                5., 7., 9., 11.], &[1, 4, 4]));
+        // the middle row is in two blocks.
+        assert_eq!(blocks.fold(&[3, 4], &[2, 2], &[1, 2], &[0, 0], &[1, 1]),
+                   GenTensor::new_raw(&[0., 1., 2., 3.,
+                                        8., 10., 12., 14.,
+                                        8., 9., 10., 11.], &[1, 1, 3, 4]));
+
+        let data = GenTensor::<f64>::new_raw(&[1., 2., 3.], &[1, 1, 3]);
+        let blocks = data.unfold(&[2], &[1], &[1], &[2]);
+        assert_eq!(blocks, GenTensor::new_raw(&[0., 1., 2., 2., 3., 0.], &[1, 2, 3]));
+        let ones = GenTensor::<f64>::ones(&[1, 2, 3]);
+        assert_eq!(ones.fold(&[3], &[2], &[1], &[1], &[2]), GenTensor::new_raw(&[1., 2., 1.], &[1, 1, 3]));
+        let ones = GenTensor::<f64>::ones(&[1, 2, 2]);
+        assert_eq!(ones.fold(&[3], &[2], &[1], &[0], &[1]), GenTensor::new_raw(&[1., 2., 1.], &[1, 1, 3]));
+    }
+
     #[test]
     fn conv_gen() {
 
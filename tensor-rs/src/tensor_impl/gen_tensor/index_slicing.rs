@@ -95,57 +95,12 @@ impl<T> IndexSlicing for GenTensor<T> where T: num_traits::Float {
         }
         
         let mut ret = index.clone();
-
-        //let total_task: usize = index.size().iter().filter(|x| **x != dim).product();
-        let mut outer_index = vec![0; dim];
-        let mut inner_index = vec![0; index.size().len()-dim -1 ];
-
-        loop {
-            //println!("outer_index, {:?}, inner_index: {:?}", outer_index, inner_index);
-
-            let mut outer_seg: Vec<(usize, usize)> = outer_index.iter().map(|x| (*x, x+1)).collect();
-            outer_seg.push((0, self.size()[dim]));
-            let mut inner_seg: Vec<(usize, usize)> = inner_index.iter().map(|x| (*x, x+1)).collect();
-            outer_seg.append(&mut inner_seg);
-            //println!("outer_seg {:?}", outer_seg);
-            let current_scope = self.get_patch(&outer_seg, None);
-
-            for i in 0..index.size()[dim] {
-                let mut current_index = outer_index.to_vec();
-                current_index.push(i);
-                current_index.append(&mut inner_index.to_vec());
-
-                let gather_index = index.get(&current_index).to_usize().expect("");
-                ret.set(&current_index, current_scope.get_data()[gather_index]);
-            }
-            
-            let mut can_continue = false;
-            for i in 0..index.size().len()-dim-1 {
-                inner_index[dim - i - 1] += 1;
-                if inner_index[dim - i - 1] >= index.size()[index.size().len() - i -1] {
-                    inner_index[dim - i - 1] = 0;
-                } else {
-                    can_continue = true;
-                    break;
-                }
-            }
-            if can_continue {
-                continue;
-            }
-
-            for i in 0..dim {
-                outer_index[dim-i-1] += 1;
-                if outer_index[dim-i-1] >= index.size()[dim - i - 1] {
-                    outer_index[dim-i-1] = 0;
-                } else {
-                    break
-                }
-            }
-            
-            if inner_index == vec![0; index.size().len()-dim -1 ] && outer_index == vec![0; dim] {
-                break;
-            }
-        };
+        for i in 0..index.numel() {
+            let index_pos = index.index2dimpos(i);
+            let mut get_pos = index_pos.clone();
+            get_pos[dim] = index.get(&index_pos).to_usize().expect("");
+            ret.set(&index_pos, self.get(&get_pos));
+        }
         ret
     }
     fn spread(&self, dim: usize, index: &Self, value: &Self) -> Self {
@@ -507,6 +462,10 @@ mod tests {
         let r = a.gather(1, &g);
         println!("{:?}", r);
         assert_eq!(r, GenTensor::new_raw(&[1., 1., 4., 3.,], &[2, 2]));
+
+        let g = GenTensor::new_raw(&[1., 0.,], &[1, 2]);
+        let r = a.gather(0, &g);
+        assert_eq!(r, GenTensor::new_raw(&[3., 2.,], &[1, 2]));
     }
 
     #[test]
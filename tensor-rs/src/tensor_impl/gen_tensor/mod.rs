@@ -7,12 +7,123 @@ pub mod convolution;
 pub mod elemwise;
 pub mod index_slicing;
 pub mod linalg;
+pub mod pooling;
 pub mod reduction;
 pub mod rand;
 
 #[cfg(feature = "use-serde")]
 use serde::{Serialize, Deserialize};
 
+/// Shape of the result when broadcasting two shapes, NumPy style.
+///
+/// Shapes are aligned on the right, and each dimension pair has to be
+/// equal or contain a 1.
+///
+/// ```
+/// # use tensor_rs::tensor_impl::gen_tensor::*;
+/// assert_eq!(broadcast_shapes(&[4, 1, 3], &[2, 1]), Ok(vec![4, 2, 3]));
+/// assert!(broadcast_shapes(&[4, 3], &[2]).is_err());
+/// ```
+pub fn broadcast_shapes(a: &[usize], b: &[usize]) -> Result<Vec<usize>, &'static str> {
+    let n = cmp::max(a.len(), b.len());
+    let mut ret = vec![1; n];
+    for i in 0..n {
+        let x = if i < a.len() {a[a.len()-i-1]} else {1};
+        let y = if i < b.len() {b[b.len()-i-1]} else {1};
+        ret[n-i-1] = if x == y || y == 1 {
+            x
+        } else if x == 1 {
+            y
+        } else {
+            return Err("shapes cannot be broadcast together");
+        };
+    }
+    Ok(ret)
+}
+
+/// Layout of a batched matrix product, NumPy style.
+///
+/// A 1-D left operand is taken as a row vector and a 1-D right operand
+/// as a column vector, the added dimension is dropped from the result.
+/// Dimensions before the last two are batch dimensions and broadcast.
+///
+/// ```
+/// # use tensor_rs::tensor_impl::gen_tensor::*;
+/// let layout = MatmulLayout::new(&[2, 1, 3, 4], &[5, 4, 6]).unwrap();
+/// assert_eq!(layout.dim, vec![2, 5, 3, 6]);
+/// assert_eq!(layout.offsets[6], (12, 24));
+/// assert_eq!(MatmulLayout::new(&[4], &[4]).unwrap().dim, vec![1]);
+/// assert!(MatmulLayout::new(&[2, 3], &[2, 3]).is_err());
+/// ```
+#[derive(Debug, PartialEq)]
+pub struct MatmulLayout {
+    /// Rows of the left matrix.
+    pub m: usize,
+    /// Columns of the left matrix and rows of the right matrix.
+    pub k: usize,
+    /// Columns of the right matrix.
+    pub n: usize,
+    /// Start of the left and right matrix in the data, for each batch.
+    pub offsets: Vec<(usize, usize)>,
+    /// Shape of the result.
+    pub dim: Vec<usize>,
+}
+impl MatmulLayout {
+    pub fn new(a: &[usize], b: &[usize]) -> Result<MatmulLayout, &'static str> {
+        if a.is_empty() || b.is_empty() {
+            return Err("matmul does not take empty tensors");
+        }
+        let (m, k) = if a.len() == 1 {(1, a[0])} else {(a[a.len()-2], a[a.len()-1])};
+        let (kb, n) = if b.len() == 1 {(b[0], 1)} else {(b[b.len()-2], b[b.len()-1])};
+        if k != kb {
+            return Err("matmul expects matched inner size");
+        }
+        let a_batch = &a[..cmp::max(a.len(), 2)-2];
+        let b_batch = &b[..cmp::max(b.len(), 2)-2];
+        let batch = broadcast_shapes(a_batch, b_batch)?;
+
+        // stride counted in matrices, 0 along broadcast dimensions.
+        let batch_stride = |x: &[usize]| -> Vec<usize> {
+            let mut ret = vec![0; batch.len()];
+            let mut acc = 1;
+            for i in (0..x.len()).rev() {
+                if x[i] != 1 {
+                    ret[batch.len()-x.len()+i] = acc;
+                }
+                acc *= x[i];
+            }
+            ret
+        };
+        let a_stride = batch_stride(a_batch);
+        let b_stride = batch_stride(b_batch);
+
+        let mut offsets = Vec::with_capacity(batch.iter().product());
+        for index in 0..batch.iter().product() {
+            let mut rest = index;
+            let (mut a_offset, mut b_offset) = (0, 0);
+            for i in (0..batch.len()).rev() {
+                let pos = rest % batch[i];
+                rest /= batch[i];
+                a_offset += pos*a_stride[i];
+                b_offset += pos*b_stride[i];
+            }
+            offsets.push((a_offset*m*k, b_offset*k*n));
+        }
+
+        let mut dim = batch;
+        if a.len() > 1 {
+            dim.push(m);
+        }
+        if b.len() > 1 {
+            dim.push(n);
+        }
+        if dim.is_empty() {
+            dim.push(1);
+        }
+        Ok(MatmulLayout {m, k, n, offsets, dim})
+    }
+}
+
 /// Naive tensor implementation, single thread
 #[cfg_attr(feature = "use-serde", derive(Serialize, Deserialize))]
 pub struct GenTensor<T> {
@@ -700,48 +811,63 @@ impl<T> GenTensor<T> where T: num_traits::Float {
         ret
     }
     
-    pub fn _right_broadcast<F>(&self, o: &GenTensor<T>, closure: F) -> GenTensor<T>
+    /// Apply closure element-wise with NumPy-style broadcasting.
+    pub fn _broadcast<F>(&self, o: &GenTensor<T>, closure: F) -> GenTensor<T>
     where F: Fn(&T, &T) -> T {
+        if self.dim == o.dim {
+            return GenTensor {
+                d: self.d.iter().zip(o.d.iter()).map(|(v1, v2)| closure(v1, v2)).collect(),
+                dim: self.dim.clone(),
+            };
+        }
+
+        let dim = match broadcast_shapes(&self.dim, &o.dim) {
+            Ok(v) => v,
+            Err(e) => panic!("{}: {:?}, {:?}", e, self.dim, o.dim),
+        };
+        let lstride = self._broadcast_stride(&dim);
+        let rstride = o._broadcast_stride(&dim);
+        let cap = dim.iter().product();
         let mut ret = GenTensor {
-            d: Vec::with_capacity(self.d.len()),
-            dim: self.dim.clone(),
+            d: Vec::with_capacity(cap),
+            dim,
         };
-        // with same shape.
-        if self.d.len() == o.d.len() {
-            for (v1, v2) in self.d.iter().zip(o.d.iter()) {
-                ret.d.push(closure(v1, v2));
-            }
-        // right single scale
-        } else if o.dim.len() == 1 && o.dim[0] == 1{
-            for i in 0..self.d.len() {
-                ret.d.push(closure(&self.d[i], &o.d[0]));
-            }
-        } else {
-            if self.d.len() < o.d.len() {
-                panic!("right-hand broadcast only.");
-            }
-            if self.dim.len() <= o.dim.len() {
-                panic!("unmatched dimension. {}, {}", self.dim.len(), o.dim.len());
-            }
-            for i in 0..o.dim.len() {
-                if o.dim[o.dim.len()-i-1] != self.dim[self.dim.len()-i-1] {
-                    panic!("unmatched size.");
-                }
-            }
 
-            // do repeat add
-            let mut index = 0;
-            for i in 0..self.d.len() {
-                ret.d.push(closure(&self.d[i], &o.d[index]));
-                index += 1;
-                if index >= o.d.len() {
-                    index = 0;
+        let mut index = vec![0; ret.dim.len()];
+        let mut lpos = 0;
+        let mut rpos = 0;
+        for _i in 0..cap {
+            ret.d.push(closure(&self.d[lpos], &o.d[rpos]));
+
+            for j in (0..index.len()).rev() {
+                index[j] += 1;
+                lpos += lstride[j];
+                rpos += rstride[j];
+                if index[j] < ret.dim[j] {
+                    break;
                 }
+                lpos -= lstride[j]*index[j];
+                rpos -= rstride[j]*index[j];
+                index[j] = 0;
             }
         }
         ret
     }
 
+    /// Stride of self when viewed with the broadcast shape dim,
+    /// broadcast dimensions get a stride of 0.
+    fn _broadcast_stride(&self, dim: &[usize]) -> Vec<usize> {
+        let stride = self.stride();
+        let offset = dim.len() - self.dim.len();
+        (0..dim.len()).map(|i| {
+            if i < offset || self.dim[i - offset] == 1 {
+                0
+            } else {
+                stride[i - offset]
+            }
+        }).collect()
+    }
+
     pub fn log10_like(&self) -> GenTensor<T> {
 	let new_data = vec![T::from(std::f64::consts::LN_10).unwrap(); self.d.len()];
         let new_dim = self.dim.to_vec();
@@ -759,7 +885,7 @@ impl<T> GenTensor<T> where T: num_traits::Float {
         }
     }
     
-    /// element-wise add with right-hand broadcast.
+    /// element-wise add with broadcast.
     ///
     /// ```
     /// # use tensor_rs::tensor_impl::gen_tensor::*;
@@ -770,16 +896,16 @@ impl<T> GenTensor<T> where T: num_traits::Float {
     /// assert_eq!(m3.get(&vec![1,1]), 8.);
     /// ```
     pub fn add(&self, o: &GenTensor<T>) -> GenTensor<T> {
-        self._right_broadcast(o, |x, y| *x + *y)
+        self._broadcast(o, |x, y| *x + *y)
     }
     pub fn sub(&self, o: &GenTensor<T>) -> GenTensor<T> {
-        self._right_broadcast(o, |x, y| *x - *y)
+        self._broadcast(o, |x, y| *x - *y)
     }
     pub fn mul(&self, o: &GenTensor<T>) -> GenTensor<T> {
-        self._right_broadcast(o, |x, y| *x * *y)
+        self._broadcast(o, |x, y| *x * *y)
     }
     pub fn div(&self, o: &GenTensor<T>) -> GenTensor<T> {
-        self._right_broadcast(o, |x, y| *x / *y)
+        self._broadcast(o, |x, y| *x / *y)
     }
 
     /// matrix multiplication
@@ -840,44 +966,29 @@ impl<T> GenTensor<T> where T: num_traits::Float {
 
     /// matrix multiplication of two tensor
     /// This is also for dot/inner product.
+    /// Leading dimensions are batch dimensions and broadcast,
+    /// see MatmulLayout.
     pub fn matmul(&self, o: &GenTensor<T>) -> GenTensor<T> {
-        if self.size()[self.size().len()-1] != o.size()[0] {
-            panic!("matmul expect matched size {:?}, {:?}", self.dim, o.dim);
-        }
-        if self.size().len() == 1 && o.size().len() == 1 {
-            panic!("Two vector have not matched size for matmul! {:?}, {:?}", self.numel(), o.numel());
-        }
-        let inner = o.dim[0];
-        let mut cap = 1;
-        let mut odim = Vec::new();
-        let mut lloop = 1;
-        let mut rloop = 1;
-        for i in 0..self.dim.len()-1 {
-            cap *= self.dim[i];
-            odim.push(self.dim[i]);
-            lloop *= self.dim[i];
-        }
-        for i in 1..o.dim.len() {
-            cap *= o.dim[i];
-            odim.push(o.dim[i]);
-            rloop *= o.dim[i];
-        }
+        let layout = match MatmulLayout::new(&self.dim, &o.dim) {
+            Ok(v) => v,
+            Err(e) => panic!("{}: {:?}, {:?}", e, self.dim, o.dim),
+        };
+        let (m, k, n) = (layout.m, layout.k, layout.n);
 
         let mut ret = GenTensor {
-            d: Vec::with_capacity(cap),
-            dim: odim,
+            d: Vec::with_capacity(layout.offsets.len()*m*n),
+            dim: layout.dim,
         };
-        
-        let lstride = self.stride();
-        let rstride = o.stride();
-        for i in 0..lloop {
-            for j in 0..rloop {
-                let mut tsum = T::zero();
-                for k in 0..inner {
-                    tsum = tsum
-                        + self.d[i*lstride[0] + k] * o.d[k*rstride[0] + j];
+        for (l, r) in layout.offsets {
+            for i in 0..m {
+                for j in 0..n {
+                    let mut tsum = T::zero();
+                    for p in 0..k {
+                        tsum = tsum
+                            + self.d[l + i*k + p] * o.d[r + p*n + j];
+                    }
+                    ret.d.push(tsum);
                 }
-                ret.d.push(tsum);
             }
         }
         ret
@@ -1024,18 +1135,13 @@ impl<T> GenTensor<T> where T: num_traits::Float {
     /// assert_eq!(m1.eq_t(&m2).get(&vec![2,1]), 1.);
     /// ```
     pub fn eq_t(&self, o: &GenTensor<T>) -> GenTensor<T> {
-        let mut cmp = Vec::<T>::with_capacity(self.d.len());
-        for (v1, v2) in self.d.iter().zip(o.d.iter()) {
-            if (*v1-*v2).abs() < T::min_positive_value().sqrt() {
-                cmp.push(T::one(),);
+        self._broadcast(o, |a, b| {
+            if (*a-*b).abs() < T::min_positive_value().sqrt() {
+                T::one()
             } else {
-                cmp.push(T::zero());
+                T::zero()
             }
-        }
-        GenTensor {
-            d: cmp,
-            dim: self.dim.to_vec(),
-        }
+        })
     }
 
     /// true if two tensors have the same size and elements, false otherwise.
@@ -1066,35 +1172,11 @@ impl<T> GenTensor<T> where T: num_traits::Float {
     }
 
     pub fn ge(&self, o: &GenTensor<T>) -> GenTensor<T> {
-        if self.size() != o.size() {
-            panic!("max needs two tensor have the same size, {:?}, {:?}", self.dim, o.dim);
-        }
-        let mut ret = GenTensor::zeros(&self.dim);
-
-        for ((a, b), c) in self.d.iter().zip(o.d.iter()).zip(ret.d.iter_mut()) {
-            if a >= b {
-                *c = T::one();
-            } else {
-                *c = T::zero();
-            }
-        }
-        ret
+        self._broadcast(o, |a, b| if a >= b {T::one()} else {T::zero()})
     }
 
     pub fn gt(&self, o: &GenTensor<T>) -> GenTensor<T> {
-        if self.size() != o.size() {
-            panic!("max needs two tensor have the same size, {:?}, {:?}", self.dim, o.dim);
-        }
-        let mut ret = GenTensor::zeros(&self.dim);
-
-        for ((a, b), c) in self.d.iter().zip(o.d.iter()).zip(ret.d.iter_mut()) {
-            if a > b {
-                *c = T::one();
-            } else {
-                *c = T::zero();
-            }
-        }
-        ret
+        self._broadcast(o, |a, b| if a > b {T::one()} else {T::zero()})
     }
 
     //pub fn isfinite(&self, o: &GenTensor<T>) -> GenTensor<T> {
@@ -1114,35 +1196,11 @@ impl<T> GenTensor<T> where T: num_traits::Float {
     //}
     // le
     pub fn le(&self, o: &GenTensor<T>) -> GenTensor<T> {
-        if self.size() != o.size() {
-            panic!("max needs two tensor have the same size, {:?}, {:?}", self.dim, o.dim);
-        }
-        let mut ret = GenTensor::zeros(&self.dim);
-
-        for ((a, b), c) in self.d.iter().zip(o.d.iter()).zip(ret.d.iter_mut()) {
-            if a <= b {
-                *c = T::one();
-            } else {
-                *c = T::zero();
-            }
-        }
-        ret
+        self._broadcast(o, |a, b| if a <= b {T::one()} else {T::zero()})
     }
     // lt
     pub fn lt(&self, o: &GenTensor<T>) -> GenTensor<T> {
-        if self.size() != o.size() {
-            panic!("max needs two tensor have the same size, {:?}, {:?}", self.dim, o.dim);
-        }
-        let mut ret = GenTensor::zeros(&self.dim);
-
-        for ((a, b), c) in self.d.iter().zip(o.d.iter()).zip(ret.d.iter_mut()) {
-            if a < b {
-                *c = T::one();
-            } else {
-                *c = T::zero();
-            }
-        }
-        ret
+        self._broadcast(o, |a, b| if a < b {T::one()} else {T::zero()})
     }
     // max, 
     //pub fn max(&self, o: Option<&GenTensor<T>>, dim: Option<usize>, keep_dim: Option<bool>) -> GenTensor<T> {
@@ -1155,24 +1213,7 @@ impl<T> GenTensor<T> where T: num_traits::Float {
 
     // ne
     pub fn ne(&self, o: &GenTensor<T>) -> GenTensor<T> {
-        if self.size() != o.size() {
-            panic!("max needs two tensor have the same size, {:?}, {:?}", self.dim, o.dim);
-        }
-        
-
-        let data = self.d.iter().zip(
-            o.d.iter())
-            .map(|(x, y)|
-                 if *x != *y {
-                     T::one()
-                 } else {
-                     T::zero()
-                 }
-        ).collect();
-        GenTensor {
-            d: data,
-            dim: self.dim.to_vec(),
-        }
+        self._broadcast(o, |a, b| if *a != *b {T::one()} else {T::zero()})
     }
     // sort
     // topk
@@ -1356,6 +1397,45 @@ mod tests {
         }
     }
 
+    #[test]
+    fn broadcast() {
+        let a = GenTensor::<f64>::new_raw(&[1., 2., 3., 4.], &[2, 1, 2]);
+        let b = GenTensor::<f64>::new_raw(&[10., 20., 30.], &[3, 1]);
+        let c = a.add(&b);
+        assert_eq!(*c.size(), vec![2, 3, 2]);
+        assert_eq!(c, GenTensor::new_raw(&[11., 12., 21., 22., 31., 32.,
+                                           13., 14., 23., 24., 33., 34.], &[2, 3, 2]));
+        assert_eq!(b.mul(&a), a.mul(&b));
+        assert_eq!(a.ge(&GenTensor::new_raw(&[2.], &[1])),
+                   GenTensor::new_raw(&[0., 1., 1., 1.], &[2, 1, 2]));
+
+        let d = GenTensor::<f64>::zeros(&[4, 3]);
+        let result = std::panic::catch_unwind(|| d.add(&GenTensor::zeros(&[2])));
+        assert!(result.is_err());
+
+        // A column against a vector of the same length is an outer op,
+        // not element-wise.
+        let col = GenTensor::<f64>::new_raw(&[1., 2.], &[2, 1]);
+        let row = GenTensor::<f64>::new_raw(&[10., 20.], &[2]);
+        assert_eq!(col.sub(&row), GenTensor::new_raw(&[-9., -19., -8., -18.], &[2, 2]));
+    }
+
+    #[test]
+    fn matmul() {
+        let a = GenTensor::<f64>::new_raw(&[1., 2., 3., 4., 5., 6., 7., 8.], &[2, 2, 2]);
+        let b = GenTensor::<f64>::new_raw(&[1., 0., 0., 1., 0., 1., 1., 0.], &[2, 2, 2]);
+        assert_eq!(a.matmul(&b), GenTensor::new_raw(&[1., 2., 3., 4., 6., 5., 8., 7.], &[2, 2, 2]));
+
+        // the right operand broadcasts over the batch.
+        let c = GenTensor::<f64>::new_raw(&[0., 1., 1., 0.], &[2, 2]);
+        assert_eq!(a.matmul(&c), GenTensor::new_raw(&[2., 1., 4., 3., 6., 5., 8., 7.], &[2, 2, 2]));
+
+        let v = GenTensor::<f64>::new_raw(&[1., -1.], &[2]);
+        assert_eq!(a.matmul(&v), GenTensor::new_raw(&[-1., -1., -1., -1.], &[2, 2]));
+        assert_eq!(v.matmul(&c), GenTensor::new_raw(&[-1., 1.], &[2]));
+        assert_eq!(v.matmul(&v), GenTensor::new_raw(&[2.], &[1]));
+    }
+
     #[test]
     fn outer() {
         let a = GenTensor::<f32>::fill(1., &vec![10, 2]);
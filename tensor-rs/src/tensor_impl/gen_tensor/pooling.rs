@@ -0,0 +1,350 @@
+use super::GenTensor;
+use crate::tensor_trait::pooling::Pooling;
+
+/// For each output position, the flat input positions it pools over.
+/// None is padding, which still counts toward the window size.
+type Windows = Vec<Vec<Option<usize>>>;
+
+// Windows along one dim, pytorch style.
+// With ceil_mode, the last window may run over the padded input,
+// it is kept as long as it starts inside the input or the left padding.
+fn sliding_windows(len: usize, kernel: usize, stride: usize,
+                   padding: usize, dilation: usize, ceil_mode: bool) -> Windows {
+    if kernel < 1 || stride < 1 || dilation < 1 {
+        panic!("kernel_size, stride and dilation should be at least 1, get {:?}, {:?}, {:?}", kernel, stride, dilation);
+    }
+    if padding > kernel/2 {
+        panic!("padding should be at most half of kernel_size, get {:?}, {:?}", padding, kernel);
+    }
+    let span = dilation*(kernel - 1) + 1;
+    if len + 2*padding < span {
+        panic!("pooling window {:?} is larger than the padded input {:?}", span, len + 2*padding);
+    }
+    let room = len + 2*padding - span;
+    let mut out = if ceil_mode {room.div_ceil(stride) + 1} else {room/stride + 1};
+    if ceil_mode && (out - 1)*stride >= len + padding {
+        out -= 1;
+    }
+
+    (0..out).map(|i| {
+        (0..kernel).map(|k| i*stride + k*dilation)
+            .filter(|p| *p < len + 2*padding)
+            .map(|p| if p >= padding && p < len + padding {Some(p - padding)} else {None})
+            .collect()
+    }).collect()
+}
+
+// Windows along one dim, covering [floor(i*len/out), ceil((i+1)*len/out)).
+fn adaptive_windows(len: usize, out: usize) -> Windows {
+    if out < 1 || len < 1 {
+        panic!("adaptive pooling expects non-empty input and output, get {:?}, {:?}", len, out);
+    }
+    (0..out).map(|i| {
+        let start = i*len/out;
+        let end = ((i + 1)*len).div_ceil(out);
+        (start..end).map(Some).collect()
+    }).collect()
+}
+
+// Combine the windows along each dim into windows on the whole plane,
+// output positions are in row-major order.
+fn plane_windows(plane: &[usize], per_dim: &[Windows]) -> Windows {
+    let mut ret: Windows = vec![vec![Some(0)]];
+    for (n, windows) in plane.iter().zip(per_dim.iter()) {
+        ret = ret.iter().flat_map(|outer| windows.iter().map(move |inner| {
+            outer.iter().flat_map(|a| inner.iter().map(move |b| match (a, b) {
+                (Some(a), Some(b)) => Some(a*n + b),
+                _ => None,
+            })).collect()
+        })).collect();
+    }
+    ret
+}
+
+// Split the size into the leading dims and the pooled plane.
+fn split_plane(size: &[usize], dims: usize) -> (Vec<usize>, Vec<usize>) {
+    if dims == 0 || size.len() < dims {
+        panic!("pooling over {:?} dims needs an input with as many dims, get {:?}", dims, size);
+    }
+    (size[..size.len() - dims].to_vec(), size[size.len() - dims..].to_vec())
+}
+
+fn check_pool_args(size: &[usize], kernel_size: &[usize], stride: &[usize],
+                   padding: &[usize], dilation: &[usize]) {
+    if kernel_size.len() != stride.len() || kernel_size.len() != padding.len() || kernel_size.len() != dilation.len() {
+        panic!("kernel_size, stride, padding, dilation should have the same # of dims, {:?}, {:?}, {:?}, {:?}",
+               kernel_size, stride, padding, dilation);
+    }
+    split_plane(size, kernel_size.len());
+}
+
+impl<T> GenTensor<T> where T: num_traits::Float {
+    fn sliding_plane_windows(&self, kernel_size: &[usize], stride: &[usize],
+                             padding: &[usize], dilation: &[usize],
+                             ceil_mode: bool) -> (Vec<usize>, Windows) {
+        check_pool_args(self.size(), kernel_size, stride, padding, dilation);
+        let (_, plane) = split_plane(self.size(), kernel_size.len());
+        let per_dim: Vec<Windows> = (0..plane.len())
+            .map(|i| sliding_windows(plane[i], kernel_size[i], stride[i],
+                                     padding[i], dilation[i], ceil_mode))
+            .collect();
+        let out: Vec<usize> = per_dim.iter().map(|x| x.len()).collect();
+        (out, plane_windows(&plane, &per_dim))
+    }
+
+    fn adaptive_plane_windows(&self, output_size: &[usize]) -> (Vec<usize>, Windows) {
+        let (_, plane) = split_plane(self.size(), output_size.len());
+        let per_dim: Vec<Windows> = plane.iter().zip(output_size.iter())
+            .map(|(len, out)| adaptive_windows(*len, *out))
+            .collect();
+        (output_size.to_vec(), plane_windows(&plane, &per_dim))
+    }
+
+    // The output size, with the leading dims of self.
+    fn pooled_size(&self, out: &[usize]) -> Vec<usize> {
+        let (mut ret, _) = split_plane(self.size(), out.len());
+        ret.extend_from_slice(out);
+        ret
+    }
+
+    /// Per output element, the flat position in self it is taken from.
+    fn pool_argmax(&self, out: &[usize], windows: &Windows) -> Vec<usize> {
+        let (_, plane) = split_plane(self.size(), out.len());
+        let plane_len: usize = plane.iter().product();
+        let planes = self.numel().checked_div(plane_len).unwrap_or(0);
+        let data = self.get_data();
+
+        let mut ret = Vec::with_capacity(planes*windows.len());
+        for p in 0..planes {
+            let base = p*plane_len;
+            for window in windows {
+                let mut best: Option<usize> = None;
+                for i in window.iter().flatten() {
+                    let v = data[base + i];
+                    if best.is_none_or(|b| v > data[b] || v.is_nan()) {
+                        best = Some(base + i);
+                    }
+                }
+                match best {
+                    Some(v) => ret.push(v),
+                    None => panic!("pooling window only covers padding."),
+                }
+            }
+        }
+        ret
+    }
+
+    fn pool_max(&self, out: &[usize], windows: &Windows) -> (GenTensor<T>, GenTensor<T>) {
+        let (_, plane) = split_plane(self.size(), out.len());
+        let plane_len: usize = plane.iter().product();
+        let argmax = self.pool_argmax(out, windows);
+        let values = argmax.iter().map(|i| self.get_data()[*i]).collect();
+        let indices = argmax.iter().map(|i| T::from(i % plane_len).unwrap()).collect();
+        let size = self.pooled_size(out);
+        (GenTensor::new_move(values, size.clone()), GenTensor::new_move(indices, size))
+    }
+
+    fn pool_max_grad(&self, out: &[usize], windows: &Windows, output_grad: &GenTensor<T>) -> GenTensor<T> {
+        let argmax = self.pool_argmax(out, windows);
+        if output_grad.numel() != argmax.len() {
+            panic!("output_grad doesn't match the pooled size, {:?}, {:?}", output_grad.size(), self.pooled_size(out));
+        }
+        let mut ret = self.zeros_like();
+        let data = ret.get_data_mut();
+        for (i, g) in argmax.iter().zip(output_grad.get_data().iter()) {
+            data[*i] = data[*i] + *g;
+        }
+        ret
+    }
+
+    fn window_count(window: &[Option<usize>], count_include_pad: bool) -> T {
+        let n = if count_include_pad {
+            window.len()
+        } else {
+            window.iter().flatten().count()
+        };
+        T::from(n).unwrap()
+    }
+
+    fn pool_avg(&self, out: &[usize], windows: &Windows, count_include_pad: bool) -> GenTensor<T> {
+        let (_, plane) = split_plane(self.size(), out.len());
+        let plane_len: usize = plane.iter().product();
+        let planes = self.numel().checked_div(plane_len).unwrap_or(0);
+        let data = self.get_data();
+
+        let mut ret = Vec::with_capacity(planes*windows.len());
+        for p in 0..planes {
+            let base = p*plane_len;
+            for window in windows {
+                let sum = window.iter().flatten().fold(T::zero(), |acc, i| acc + data[base + i]);
+                ret.push(sum/Self::window_count(window, count_include_pad));
+            }
+        }
+        GenTensor::new_move(ret, self.pooled_size(out))
+    }
+
+    fn pool_avg_grad(&self, out: &[usize], windows: &Windows, count_include_pad: bool,
+                     output_grad: &GenTensor<T>) -> GenTensor<T> {
+        let (_, plane) = split_plane(self.size(), out.len());
+        let plane_len: usize = plane.iter().product();
+        let planes = self.numel().checked_div(plane_len).unwrap_or(0);
+        if output_grad.numel() != planes*windows.len() {
+            panic!("output_grad doesn't match the pooled size, {:?}, {:?}", output_grad.size(), self.pooled_size(out));
+        }
+        let grad = output_grad.get_data();
+
+        let mut ret = self.zeros_like();
+        let data = ret.get_data_mut();
+        for p in 0..planes {
+            let base = p*plane_len;
+            for (j, window) in windows.iter().enumerate() {
+                let g = grad[p*windows.len() + j]/Self::window_count(window, count_include_pad);
+                for i in window.iter().flatten() {
+                    data[base + i] = data[base + i] + g;
+                }
+            }
+        }
+        ret
+    }
+}
+
+impl<T> Pooling for GenTensor<T> where T: num_traits::Float {
+    fn max_pool(&self,
+                kernel_size: &[usize],
+                stride: &[usize],
+                padding: &[usize],
+                dilation: &[usize],
+                ceil_mode: bool
+    ) -> (Self, Self) {
+        let (out, windows) = self.sliding_plane_windows(kernel_size, stride, padding, dilation, ceil_mode);
+        self.pool_max(&out, &windows)
+    }
+
+    fn max_pool_grad(&self,
+                     kernel_size: &[usize],
+                     stride: &[usize],
+                     padding: &[usize],
+                     dilation: &[usize],
+                     ceil_mode: bool,
+                     output_grad: &Self
+    ) -> Self {
+        let (out, windows) = self.sliding_plane_windows(kernel_size, stride, padding, dilation, ceil_mode);
+        self.pool_max_grad(&out, &windows, output_grad)
+    }
+
+    fn avg_pool(&self,
+                kernel_size: &[usize],
+                stride: &[usize],
+                padding: &[usize],
+                ceil_mode: bool,
+                count_include_pad: bool
+    ) -> Self {
+        let dilation = vec![1; kernel_size.len()];
+        let (out, windows) = self.sliding_plane_windows(kernel_size, stride, padding, &dilation, ceil_mode);
+        self.pool_avg(&out, &windows, count_include_pad)
+    }
+
+    fn avg_pool_grad(&self,
+                     kernel_size: &[usize],
+                     stride: &[usize],
+                     padding: &[usize],
+                     ceil_mode: bool,
+                     count_include_pad: bool,
+                     output_grad: &Self
+    ) -> Self {
+        let dilation = vec![1; kernel_size.len()];
+        let (out, windows) = self.sliding_plane_windows(kernel_size, stride, padding, &dilation, ceil_mode);
+        self.pool_avg_grad(&out, &windows, count_include_pad, output_grad)
+    }
+
+    fn adaptive_avg_pool(&self, output_size: &[usize]) -> Self {
+        let (out, windows) = self.adaptive_plane_windows(output_size);
+        self.pool_avg(&out, &windows, false)
+    }
+
+    fn adaptive_avg_pool_grad(&self, output_size: &[usize], output_grad: &Self) -> Self {
+        let (out, windows) = self.adaptive_plane_windows(output_size);
+        self.pool_avg_grad(&out, &windows, false, output_grad)
+    }
+
+    fn adaptive_max_pool(&self, output_size: &[usize]) -> (Self, Self) {
+        let (out, windows) = self.adaptive_plane_windows(output_size);
+        self.pool_max(&out, &windows)
+    }
+
+    fn adaptive_max_pool_grad(&self, output_size: &[usize], output_grad: &Self) -> Self {
+        let (out, windows) = self.adaptive_plane_windows(output_size);
+        self.pool_max_grad(&out, &windows, output_grad)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tensor_impl::gen_tensor::GenTensor;
+    use super::*;
+
+    #[test]
+    fn max_pool() {
+        let data = GenTensor::<f64>::new_raw(&[1., 5., 2., 0.,
+                                               3., 4., 8., 7.,
+                                               6., 9., 1., 2.], &[1, 1, 3, 4]);
+        let (values, indices) = data.max_pool(&[2, 2], &[2, 2], &[0, 0], &[1, 1], false);
+        assert_eq!(values, GenTensor::new_raw(&[5., 8.], &[1, 1, 1, 2]));
+        assert_eq!(indices, GenTensor::new_raw(&[1., 6.], &[1, 1, 1, 2]));
+
+        // the last row is only reached with ceil_mode.
+        let (values, indices) = data.max_pool(&[2, 2], &[2, 2], &[0, 0], &[1, 1], true);
+        assert_eq!(values, GenTensor::new_raw(&[5., 8., 9., 2.], &[1, 1, 2, 2]));
+        assert_eq!(indices, GenTensor::new_raw(&[1., 6., 9., 11.], &[1, 1, 2, 2]));
+
+        let grad = data.max_pool_grad(&[2, 2], &[2, 2], &[0, 0], &[1, 1], true,
+                                      &GenTensor::new_raw(&[1., 2., 3., 4.], &[1, 1, 2, 2]));
+        assert_eq!(grad, GenTensor::new_raw(&[0., 1., 0., 0.,
+                                              0., 0., 2., 0.,
+                                              0., 3., 0., 4.], &[1, 1, 3, 4]));
+
+        let data = GenTensor::<f64>::new_raw(&[-1., -2., -3., -4., -5.], &[1, 5]);
+        let (values, indices) = data.max_pool(&[3], &[2], &[1], &[1], false);
+        assert_eq!(values, GenTensor::new_raw(&[-1., -2., -4.], &[1, 3]));
+        assert_eq!(indices, GenTensor::new_raw(&[0., 1., 3.], &[1, 3]));
+
+        let (values, _) = data.max_pool(&[2], &[1], &[0], &[3], false);
+        assert_eq!(values, GenTensor::new_raw(&[-1., -2.], &[1, 2]));
+    }
+
+    #[test]
+    fn avg_pool() {
+        let data = GenTensor::<f64>::new_raw(&[1., 2., 3., 4., 5.], &[1, 1, 5]);
+        let result = data.avg_pool(&[2], &[2], &[1], false, true);
+        assert_eq!(result, GenTensor::new_raw(&[0.5, 2.5, 4.5], &[1, 1, 3]));
+        let result = data.avg_pool(&[2], &[2], &[1], false, false);
+        assert_eq!(result, GenTensor::new_raw(&[1., 2.5, 4.5], &[1, 1, 3]));
+        let result = data.avg_pool(&[2], &[2], &[0], true, true);
+        assert_eq!(result, GenTensor::new_raw(&[1.5, 3.5, 5.], &[1, 1, 3]));
+
+        let grad = data.avg_pool_grad(&[2], &[2], &[1], false, true,
+                                      &GenTensor::new_raw(&[2., 4., 6.], &[1, 1, 3]));
+        assert_eq!(grad, GenTensor::new_raw(&[1., 2., 2., 3., 3.], &[1, 1, 5]));
+    }
+
+    #[test]
+    fn adaptive_pool() {
+        let data = GenTensor::<f64>::new_raw(&[1., 2., 3., 4., 5.], &[1, 5]);
+        let result = data.adaptive_avg_pool(&[3]);
+        assert_eq!(result, GenTensor::new_raw(&[1.5, 3., 4.5], &[1, 3]));
+        let (values, indices) = data.adaptive_max_pool(&[3]);
+        assert_eq!(values, GenTensor::new_raw(&[2., 4., 5.], &[1, 3]));
+        assert_eq!(indices, GenTensor::new_raw(&[1., 3., 4.], &[1, 3]));
+
+        let grad = data.adaptive_avg_pool_grad(&[3], &GenTensor::new_raw(&[2., 3., 4.], &[1, 3]));
+        assert_eq!(grad, GenTensor::new_raw(&[1., 2., 1., 3., 2.], &[1, 5]));
+        let grad = data.adaptive_max_pool_grad(&[3], &GenTensor::new_raw(&[2., 3., 4.], &[1, 3]));
+        assert_eq!(grad, GenTensor::new_raw(&[0., 2., 0., 3., 4.], &[1, 5]));
+
+        let data = GenTensor::<f64>::new_raw(&[1., 2., 3., 4.], &[1, 1, 2, 2]);
+        let result = data.adaptive_avg_pool(&[3, 3]);
+        assert_eq!(result, GenTensor::new_raw(&[1., 1.5, 2.,
+                                                2., 2.5, 3.,
+                                                3., 3.5, 4.], &[1, 1, 3, 3]));
+    }
+}
@@ -230,7 +230,7 @@ impl<T> ReduceTensor for GenTensor<T> where T: num_traits::Float {
                          |x| {
                              let mut min = x[0];
                              for i in x {
-                                 if min < *i {
+                                 if min > *i {
                                      min = *i;
                                  }
                              }
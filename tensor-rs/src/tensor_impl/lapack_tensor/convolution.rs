@@ -29,18 +29,6 @@ macro_rules! blas_conv {
             }
             //println!("padded_dim: {:?}", padded_dim);
         
-            // find the coordinate of
-            // start center point in a filter in padded dimension
-            // in case filter_dim[i] is even, start_point will be the half.
-            // in case filter_dim[i] is odd, start_point will be the center.
-            let mut start_point = Vec::new();
-            for i in 0..stride.len() {
-                let half = filter_dim[2+i]/2;
-                let dilated = half*dilation[i];
-                start_point.push(dilated);
-            }
-            //println!("start_point: {:?}", start_point);
-        
             let mut output_size = Vec::new();
             //println!("{:?}, {:?}", padded_dim, stride);
             for i in 0..stride.len() {
@@ -128,11 +116,9 @@ macro_rules! blas_conv {
                     let mut current_pos = left_upper.len()-1;
                     loop {
                         left_upper[current_pos] += stride[current_pos];
-                        let mut compare_pos = padded_dim[current_pos] - start_point[current_pos]*2;
-                        if filter_dim[current_pos+2] % 2 == 0 {
-                            compare_pos += 1;
-                        }
-                        if left_upper[current_pos] >= compare_pos {
+                        let compare_pos = padded_dim[current_pos]
+                            - ((filter_dim[current_pos+2]-1)*dilation[current_pos] + 1);
+                        if left_upper[current_pos] > compare_pos {
                             left_upper[current_pos] = 0;
                             if current_pos > 0 {
                                 current_pos -= 1;
@@ -3,6 +3,8 @@ use crate::tensor_impl::gen_tensor::GenTensor;
 use super::blas_api::BlasAPI;
 
 
+// Same-shape operands go through axpy,
+// anything that needs broadcasting falls back to the generic kernel.
 #[cfg(feature = "use-blas-lapack")]
 macro_rules! blas_add {
     ($a:ty, $b: ident) => {
@@ -10,39 +12,13 @@ macro_rules! blas_add {
             x: &GenTensor<$a>,
             y: &GenTensor<$a>,
         ) -> GenTensor<$a> {
-            let real_x;
-            let mut real_y = y.get_data().clone();
-            let mut real_size = x.numel();
-            let real_x_vec;
-            if x.numel() == 1 && y.numel() > 1 {
-                real_x_vec = vec![x.get_data()[0]; y.numel()];
-                real_x = &real_x_vec;
-                real_size = y.numel();
-            } else if x.numel() > 1 && y.numel() == 1 {
-                real_x = x.get_data();
-                real_y = vec![real_y[0]; x.numel()];
-                real_size = x.numel();
-            } else if x.numel() == y.numel() {
-                real_x = x.get_data();
-            } else {
-                if x.numel() < y.numel() {
-                    panic!("right-hand broadcast only.");
-                }
-                if x.size().len() <= y.size().len() {
-                    panic!("unmatched dimension. {}, {}", x.size().len(), y.size().len());
-                }
-                for i in 0..y.size().len() {
-                    if y.size()[y.size().len()-i-1] != x.size()[x.size().len()-i-1] {
-                        panic!("unmatched size.");
-                    }
-                }
-                real_x = x.get_data();
-                real_y = real_y.repeat(x.numel()/y.numel());
+            if x.size() != y.size() {
+                return x.add(y);
             }
-            
-            BlasAPI::<$a>::axpy(real_size,
+            let mut real_y = y.get_data().clone();
+            BlasAPI::<$a>::axpy(x.numel(),
                                 1.0 as $a,
-                                real_x, 1,
+                                x.get_data(), 1,
                                 &mut real_y, 1);
             GenTensor::<$a>::new_move(real_y, x.size().clone())
         }
@@ -63,52 +39,15 @@ macro_rules! blas_sub {
             x: &GenTensor<$a>,
             y: &GenTensor<$a>,
         ) -> GenTensor<$a> {
-            if x.numel() == 1 && y.numel() > 1 {
-                let mut real_x_vec = vec![x.get_data()[0]; y.numel()];
-                let real_size = y.numel();
-                BlasAPI::<$a>::axpy(real_size,
-                                    -1.0 as $a,
-                                    y.get_data(), 1,
-                                    &mut real_x_vec, 1);
-                return GenTensor::<$a>::new_move(real_x_vec, y.size().clone());
-            } else if x.numel() > 1 && y.numel() == 1 {
-                let mut real_x_vec = x.get_data().clone();
-                let real_size = x.numel();
-                BlasAPI::<$a>::axpy(real_size,
-                                    -1.0 as $a,
-                                    y.get_data(), 1,
-                                    &mut real_x_vec, 1);
-                return GenTensor::<$a>::new_move(real_x_vec, y.size().clone());
-            } else if x.size() == y.size() {
-                let mut real_x_vec = x.get_data().clone();
-                let real_size = x.numel();
-                BlasAPI::<$a>::axpy(real_size,
-                                    -1.0 as $a,
-                                    y.get_data(), 1,
-                                    &mut real_x_vec, 1);
-                return GenTensor::<$a>::new_move(real_x_vec, y.size().clone());
-            } else {
-                if x.numel() < y.numel() {
-                    panic!("right-hand broadcast only.");
-                }
-                if x.size().len() <= y.size().len() {
-                    panic!("unmatched dimension and right-hand broadcast only. {}, {}",
-			   x.size().len(), y.size().len());
-                }
-                for i in 0..y.size().len() {
-                    if y.size()[y.size().len()-i-1] != x.size()[x.size().len()-i-1] {
-                        panic!("unmatched size.");
-                    }
-                }
-                let mut real_x_vec = x.get_data().clone();
-                let real_y_vec = y.get_data().repeat(x.numel()/y.numel());
-                let real_size = x.numel();
-                BlasAPI::<$a>::axpy(real_size,
-                                    -1.0 as $a,
-                                    &real_y_vec, 1,
-                                    &mut real_x_vec, 1);
-                return GenTensor::<$a>::new_move(real_x_vec, x.size().clone());
+            if x.size() != y.size() {
+                return x.sub(y);
             }
+            let mut real_x_vec = x.get_data().clone();
+            BlasAPI::<$a>::axpy(x.numel(),
+                                -1.0 as $a,
+                                y.get_data(), 1,
+                                &mut real_x_vec, 1);
+            GenTensor::<$a>::new_move(real_x_vec, x.size().clone())
         }
     }
 }
@@ -166,5 +105,11 @@ mod tests {
         let c = sub_f64(&a, &b);
         let em = GenTensor::<f64>::new_raw(&[0.0, 0.0, 0.0, 0.0, 0.0, 0.0], &[1, 2, 3]);
         assert_eq!(c, em);
+
+        let a = GenTensor::<f64>::ones(&[2, 1]);
+        let b = GenTensor::<f64>::new_raw(&[1.0, 2.0, 3.0], &[1, 3]);
+        let c = sub_f64(&a, &b);
+        let em = GenTensor::<f64>::new_raw(&[0.0, -1.0, -2.0, 0.0, -1.0, -2.0], &[2, 3]);
+        assert_eq!(c, em);
     }
 }
@@ -7,45 +7,34 @@ pub mod reduction;
 pub mod blas_api;
 pub mod lapack_api;
 
-use crate::tensor_impl::gen_tensor::GenTensor;
+use crate::tensor_impl::gen_tensor::{GenTensor, MatmulLayout};
 use crate::tensor_impl::lapack_tensor::blas_api::BlasAPI;
 
 macro_rules! blas_matmul {
     ($a:ty, $b: ident) => {
+        /// Batched matmul as a gemm call per batch,
+        /// see MatmulLayout for the shape rules.
         pub fn $b(
             x: &GenTensor<$a>,
             y: &GenTensor<$a>,
         ) -> GenTensor<$a> {
-            if x.size()[x.size().len()-1] != y.size()[0] {
-                panic!("matmul expect matched size {:?}, {:?}", x.size(), y.size());
-            }
-            if x.size().len() == 1 && y.size().len() == 1 {
-                panic!("Two vector have not matched size for matmul! {:?}, {:?}", x.numel(), y.numel());
-            }
-            let inner = y.size()[0];
-            let mut cap = 1;
-            let mut odim = Vec::new();
-            let mut lloop = 1;
-            let mut rloop = 1;
-            for i in 0..x.size().len()-1 {
-                cap *= x.size()[i];
-                odim.push(x.size()[i]);
-                lloop *= x.size()[i];
-            }
-            for i in 1..y.size().len() {
-                cap *= y.size()[i];
-                odim.push(y.size()[i]);
-                rloop *= y.size()[i];
-            }
-            
+            let layout = match MatmulLayout::new(x.size(), y.size()) {
+                Ok(v) => v,
+                Err(e) => panic!("{}: {:?}, {:?}", e, x.size(), y.size()),
+            };
+            let (m, k, n) = (layout.m, layout.k, layout.n);
+
             let mut ret = GenTensor::<$a>::new_move(
-                vec![0.; cap], odim);
-            
-            BlasAPI::<$a>::gemm(false, false,
-                                rloop, lloop, inner,
-                                1., y.get_data(), rloop,
-                                x.get_data(), inner,
-                                1., ret.get_data_mut(), rloop,);
+                vec![0.; layout.offsets.len()*m*n], layout.dim);
+
+            // row major x*y is column major y^T*x^T.
+            for (i, (l, r)) in layout.offsets.iter().enumerate() {
+                BlasAPI::<$a>::gemm(false, false,
+                                    n, m, k,
+                                    1., &y.get_data()[*r..], n,
+                                    &x.get_data()[*l..], k,
+                                    0., &mut ret.get_data_mut()[i*m*n..], n,);
+            }
             ret
         }
     }
@@ -67,4 +56,15 @@ mod tests {
         let em = GenTensor::<f32>::new_raw(&[90.0, 96.0, 102.0, 216.0, 231.0, 246.0], &[2, 3]);
         assert_eq!(v3, em);
     }
+
+    #[test]
+    fn test_batched_matmul() {
+        let v1 = GenTensor::<f64>::new_raw(&(0..12).map(|x| x as f64).collect::<Vec<_>>(), &[2, 2, 3]);
+        let v2 = GenTensor::<f64>::new_raw(&(0..6).map(|x| x as f64).collect::<Vec<_>>(), &[1, 3, 2]);
+        assert_eq!(matmul_f64(&v1, &v2), v1.matmul(&v2));
+
+        let v3 = GenTensor::<f64>::new_raw(&[1., 2., 3.], &[3]);
+        assert_eq!(matmul_f64(&v1, &v3), v1.matmul(&v3));
+        assert_eq!(matmul_f64(&v3, &v2), v3.matmul(&v2));
+    }
 }
@@ -31,4 +31,21 @@ pub trait Convolution where Self: std::marker::Sized {
                          padding_mode: PaddingMode,
                          output_grad: &Self,
     ) -> (Self, Self);
+
+    /// Extract sliding blocks from a (N, C, *) tensor into (N, C*prod(kernel_size), L),
+    /// L is the number of blocks.
+    fn unfold(&self, kernel_size: &[usize],
+              stride: &[usize],
+              padding: &[usize],
+              dilation: &[usize],
+    ) -> Self;
+
+    /// Sum (N, C*prod(kernel_size), L) blocks back into a (N, C, *output_size) tensor,
+    /// the adjoint of unfold.
+    fn fold(&self, output_size: &[usize],
+            kernel_size: &[usize],
+            stride: &[usize],
+            padding: &[usize],
+            dilation: &[usize],
+    ) -> Self;
 }
@@ -3,5 +3,6 @@ pub mod convolution;
 pub mod elemwise;
 pub mod index_slicing;
 pub mod linalg;
+pub mod pooling;
 pub mod reduction;
 pub mod rand;
@@ -0,0 +1,51 @@
+/// Pooling over the trailing kernel_size.len() dimensions.
+/// All leading dimensions, e.g. batch and channel, are kept as is.
+pub trait Pooling where Self: std::marker::Sized {
+
+    /// Return the pooled maximums and the flat index,
+    /// within its input plane, of the element each one is taken from.
+    /// Padding never wins the maximum.
+    fn max_pool(&self,
+                kernel_size: &[usize],
+                stride: &[usize],
+                padding: &[usize],
+                dilation: &[usize],
+                ceil_mode: bool
+    ) -> (Self, Self);
+
+    /// Route output_grad back to the elements picked by max_pool.
+    fn max_pool_grad(&self,
+                     kernel_size: &[usize],
+                     stride: &[usize],
+                     padding: &[usize],
+                     dilation: &[usize],
+                     ceil_mode: bool,
+                     output_grad: &Self
+    ) -> Self;
+
+    fn avg_pool(&self,
+                kernel_size: &[usize],
+                stride: &[usize],
+                padding: &[usize],
+                ceil_mode: bool,
+                count_include_pad: bool
+    ) -> Self;
+
+    fn avg_pool_grad(&self,
+                     kernel_size: &[usize],
+                     stride: &[usize],
+                     padding: &[usize],
+                     ceil_mode: bool,
+                     count_include_pad: bool,
+                     output_grad: &Self
+    ) -> Self;
+
+    fn adaptive_avg_pool(&self, output_size: &[usize]) -> Self;
+
+    fn adaptive_avg_pool_grad(&self, output_size: &[usize], output_grad: &Self) -> Self;
+
+    /// Same as max_pool, with the windows picked from output_size.
+    fn adaptive_max_pool(&self, output_size: &[usize]) -> (Self, Self);
+
+    fn adaptive_max_pool_grad(&self, output_size: &[usize], output_grad: &Self) -> Self;
+}
@@ -16,6 +16,7 @@ use super::tensor_trait::index_slicing::IndexSlicing;
 use super::tensor_trait::convolution::{Convolution};
 use super::tensor_trait::reduction::ReduceTensor;
 use super::tensor_trait::linalg::LinearAlgbra;
+use super::tensor_trait::pooling::Pooling;
 use super::tensor_trait::rand::Random;
 
 #[cfg(feature = "use-blas-lapack")]
@@ -638,6 +639,13 @@ impl TypedTensor {
             //_ => {panic!("should have same tensor type!");},
         }
     }
+    pub fn pow_f64(&self, n: f64) -> TypedTensor {
+        match &self {
+            TypedTensor::Typef32(v1) => {TypedTensor::Typef32(v1.pow(n as f32))},
+            TypedTensor::Typef64(v1) => {TypedTensor::Typef64(v1.pow(n))},
+            //_ => {panic!("should have same tensor type!");},
+        }
+    }
     typed_tensor_method_single_tensor_return!(reciprocal);
     typed_tensor_method_single_tensor_return!(round);
     typed_tensor_method_single_tensor_return!(rsqrt);
@@ -933,6 +941,20 @@ impl TypedTensor {
                        from: f32, to: f32) -> TypedTensor {
         TypedTensor::Typef32(GenTensor::<f32>::uniform(rng, dim, from, to))
     }
+    /// Uniform in [from, to) with the same type as self.
+    pub fn uniform_like(&self, rng: &mut StdRng,
+                        dim: &[usize],
+                        from: f64, to: f64) -> TypedTensor {
+        match self {
+            TypedTensor::Typef32(_) => TypedTensor::uniform_f32(rng, dim, from as f32, to as f32),
+            TypedTensor::Typef64(_) => TypedTensor::uniform_f64(rng, dim, from, to),
+            #[cfg(feature = "use-cuda")]
+            TypedTensor::Cudaf32(_) => {
+                let v = GenTensor::<f32>::uniform(rng, dim, from as f32, to as f32);
+                TypedTensor::Cudaf32(CudaTensor::new_raw(v.get_data(), dim))
+            },
+        }
+    }
     
 
     // conv ops
@@ -984,6 +1006,177 @@ impl TypedTensor {
             _ => {panic!("should have same tensor type!");},
         }
     }
+
+    pub fn conv_gen(&self, filter: &TypedTensor,
+                    stride: &[usize],
+                    padding: &[usize],
+                    dilation: &[usize],
+                    padding_mode: PaddingMode) -> TypedTensor {
+        match (self, filter) {
+            (TypedTensor::Typef32(v1), TypedTensor::Typef32(v2)) => {
+                #[cfg(not(feature = "use-blas-lapack"))]
+                return TypedTensor::Typef32(v1.conv_gen(v2, stride, padding, dilation, padding_mode));
+                #[cfg(feature = "use-blas-lapack")]
+                return TypedTensor::Typef32(gemm_conv_f32(v1, v2, stride, padding, dilation, padding_mode));
+            },
+            (TypedTensor::Typef64(v1), TypedTensor::Typef64(v2)) => {
+                #[cfg(not(feature = "use-blas-lapack"))]
+                return TypedTensor::Typef64(v1.conv_gen(v2, stride, padding, dilation, padding_mode));
+                #[cfg(feature = "use-blas-lapack")]
+                return TypedTensor::Typef64(gemm_conv_f64(v1, v2, stride, padding, dilation, padding_mode));
+            },
+            _ => {panic!("should have same tensor type!");},
+        }
+    }
+    pub fn conv_grad_gen(&self, filter: &TypedTensor,
+                         stride: &[usize],
+                         padding: &[usize],
+                         dilation: &[usize],
+                         padding_mode: PaddingMode,
+                         output_grad: &TypedTensor
+    ) -> (TypedTensor, TypedTensor) {
+        match (self, filter, output_grad) {
+            (TypedTensor::Typef32(v1), TypedTensor::Typef32(v2), TypedTensor::Typef32(v3)) => {
+                let (r1, r2) = v1.conv_grad_gen(v2, stride, padding, dilation, padding_mode, v3);
+                (TypedTensor::Typef32(r1), TypedTensor::Typef32(r2))
+            },
+            (TypedTensor::Typef64(v1), TypedTensor::Typef64(v2), TypedTensor::Typef64(v3)) => {
+                let (r1, r2) = v1.conv_grad_gen(v2, stride, padding, dilation, padding_mode, v3);
+                (TypedTensor::Typef64(r1), TypedTensor::Typef64(r2))
+            },
+            _ => {panic!("should have same tensor type!");},
+        }
+    }
+
+    pub fn unfold(&self,
+                  kernel_size: &[usize],
+                  stride: &[usize],
+                  padding: &[usize],
+                  dilation: &[usize]) -> TypedTensor {
+        match self {
+            TypedTensor::Typef32(v1) => {TypedTensor::Typef32(v1.unfold(kernel_size, stride, padding, dilation))},
+            TypedTensor::Typef64(v1) => {TypedTensor::Typef64(v1.unfold(kernel_size, stride, padding, dilation))},
+        }
+    }
+    pub fn fold(&self,
+                output_size: &[usize],
+                kernel_size: &[usize],
+                stride: &[usize],
+                padding: &[usize],
+                dilation: &[usize]) -> TypedTensor {
+        match self {
+            TypedTensor::Typef32(v1) => {TypedTensor::Typef32(v1.fold(output_size, kernel_size, stride, padding, dilation))},
+            TypedTensor::Typef64(v1) => {TypedTensor::Typef64(v1.fold(output_size, kernel_size, stride, padding, dilation))},
+        }
+    }
+
+    // pooling ops
+    pub fn max_pool(&self,
+                    kernel_size: &[usize],
+                    stride: &[usize],
+                    padding: &[usize],
+                    dilation: &[usize],
+                    ceil_mode: bool) -> (TypedTensor, TypedTensor) {
+        match self {
+            TypedTensor::Typef32(v1) => {
+                let (r1, r2) = v1.max_pool(kernel_size, stride, padding, dilation, ceil_mode);
+                (TypedTensor::Typef32(r1), TypedTensor::Typef32(r2))
+            },
+            TypedTensor::Typef64(v1) => {
+                let (r1, r2) = v1.max_pool(kernel_size, stride, padding, dilation, ceil_mode);
+                (TypedTensor::Typef64(r1), TypedTensor::Typef64(r2))
+            },
+        }
+    }
+    pub fn max_pool_grad(&self,
+                         kernel_size: &[usize],
+                         stride: &[usize],
+                         padding: &[usize],
+                         dilation: &[usize],
+                         ceil_mode: bool,
+                         output_grad: &TypedTensor) -> TypedTensor {
+        match (self, output_grad) {
+            (TypedTensor::Typef32(v1), TypedTensor::Typef32(v2)) => {
+                TypedTensor::Typef32(v1.max_pool_grad(kernel_size, stride, padding, dilation, ceil_mode, v2))
+            },
+            (TypedTensor::Typef64(v1), TypedTensor::Typef64(v2)) => {
+                TypedTensor::Typef64(v1.max_pool_grad(kernel_size, stride, padding, dilation, ceil_mode, v2))
+            },
+            _ => {panic!("should have same tensor type!");},
+        }
+    }
+    pub fn avg_pool(&self,
+                    kernel_size: &[usize],
+                    stride: &[usize],
+                    padding: &[usize],
+                    ceil_mode: bool,
+                    count_include_pad: bool) -> TypedTensor {
+        match self {
+            TypedTensor::Typef32(v1) => {
+                TypedTensor::Typef32(v1.avg_pool(kernel_size, stride, padding, ceil_mode, count_include_pad))
+            },
+            TypedTensor::Typef64(v1) => {
+                TypedTensor::Typef64(v1.avg_pool(kernel_size, stride, padding, ceil_mode, count_include_pad))
+            },
+        }
+    }
+    pub fn avg_pool_grad(&self,
+                         kernel_size: &[usize],
+                         stride: &[usize],
+                         padding: &[usize],
+                         ceil_mode: bool,
+                         count_include_pad: bool,
+                         output_grad: &TypedTensor) -> TypedTensor {
+        match (self, output_grad) {
+            (TypedTensor::Typef32(v1), TypedTensor::Typef32(v2)) => {
+                TypedTensor::Typef32(v1.avg_pool_grad(kernel_size, stride, padding, ceil_mode, count_include_pad, v2))
+            },
+            (TypedTensor::Typef64(v1), TypedTensor::Typef64(v2)) => {
+                TypedTensor::Typef64(v1.avg_pool_grad(kernel_size, stride, padding, ceil_mode, count_include_pad, v2))
+            },
+            _ => {panic!("should have same tensor type!");},
+        }
+    }
+    pub fn adaptive_avg_pool(&self, output_size: &[usize]) -> TypedTensor {
+        match self {
+            TypedTensor::Typef32(v1) => {TypedTensor::Typef32(v1.adaptive_avg_pool(output_size))},
+            TypedTensor::Typef64(v1) => {TypedTensor::Typef64(v1.adaptive_avg_pool(output_size))},
+        }
+    }
+    pub fn adaptive_avg_pool_grad(&self, output_size: &[usize], output_grad: &TypedTensor) -> TypedTensor {
+        match (self, output_grad) {
+            (TypedTensor::Typef32(v1), TypedTensor::Typef32(v2)) => {
+                TypedTensor::Typef32(v1.adaptive_avg_pool_grad(output_size, v2))
+            },
+            (TypedTensor::Typef64(v1), TypedTensor::Typef64(v2)) => {
+                TypedTensor::Typef64(v1.adaptive_avg_pool_grad(output_size, v2))
+            },
+            _ => {panic!("should have same tensor type!");},
+        }
+    }
+    pub fn adaptive_max_pool(&self, output_size: &[usize]) -> (TypedTensor, TypedTensor) {
+        match self {
+            TypedTensor::Typef32(v1) => {
+                let (r1, r2) = v1.adaptive_max_pool(output_size);
+                (TypedTensor::Typef32(r1), TypedTensor::Typef32(r2))
+            },
+            TypedTensor::Typef64(v1) => {
+                let (r1, r2) = v1.adaptive_max_pool(output_size);
+                (TypedTensor::Typef64(r1), TypedTensor::Typef64(r2))
+            },
+        }
+    }
+    pub fn adaptive_max_pool_grad(&self, output_size: &[usize], output_grad: &TypedTensor) -> TypedTensor {
+        match (self, output_grad) {
+            (TypedTensor::Typef32(v1), TypedTensor::Typef32(v2)) => {
+                TypedTensor::Typef32(v1.adaptive_max_pool_grad(output_size, v2))
+            },
+            (TypedTensor::Typef64(v1), TypedTensor::Typef64(v2)) => {
+                TypedTensor::Typef64(v1.adaptive_max_pool_grad(output_size, v2))
+            },
+            _ => {panic!("should have same tensor type!");},
+        }
+    }
 }
 impl fmt::Display for TypedTensor {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {